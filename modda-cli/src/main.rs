@@ -8,7 +8,7 @@ use env_logger::{Env, Target};
 use log::debug;
 
 use log_settings::LogSettings;
-use modda_lib::args::{ Cli, Commands, ConfigArgs };
+use modda_lib::args::{ CacheArgs, Cli, Commands, ConfigArgs };
 use modda_lib::cache::Cache;
 use modda_lib::canon_path::CanonPath;
 use modda_lib::chitin::ensure_chitin_key;
@@ -16,9 +16,13 @@ use modda_lib::modda_context::WeiduContext;
 use modda_lib::run_weidu::check_weidu_exe;
 use modda_lib::config::{global_conf_dir, Settings};
 use modda_lib::sub::append_mod::append_mod;
-use modda_lib::sub::extract_manifest::extract_manifest;
+use modda_lib::sub::audit::audit;
+use modda_lib::sub::check::check;
+use modda_lib::sub::extract_manifest::{export_manifest, extract_manifest};
 use modda_lib::sub::install::install;
 use modda_lib::sub::invalidate::invalidate;
+use modda_lib::sub::prune_cache::prune_cache;
+use modda_lib::sub::version::version;
 use subcommands::config_show::open_global_config_dir;
 use subcommands::config_edit::edit_global_config_dir;
 use subcommands::discover::discover;
@@ -45,34 +49,47 @@ fn main() -> Result<()> {
     let current_dir = std::env::current_dir()?;
     let current_dir = CanonPath::new(current_dir)?;
 
+    let mut settings = Settings::read_settings(&current_dir)?;
+    settings.override_archive_cache(cli.cache_dir.as_deref());
+    let config = &settings.combined;
     if cli.command.wants_chitin_key() {
-        if ensure_chitin_key().is_err() {
+        let auto_game_subdir = !(cli.no_auto_game_subdir || config.no_auto_game_subdir.unwrap_or(false));
+        if ensure_chitin_key(auto_game_subdir).is_err() {
             bail!("Must be run from the game directory (where chitin.key is)");
         } else {
             debug!("chitin.key found");
         }
     }
-    let settings = Settings::read_settings(&current_dir)?;
-    let config = &settings.combined;
-    let weidu_context = WeiduContext{ config: &config, current_dir: &current_dir };
-    check_weidu_exe(&weidu_context)?;
+    let weidu_context = WeiduContext{ config: &config, current_dir: &current_dir, debug_log: None };
+    // `version` reports a missing weidu instead of failing, `check` doesn't run weidu (so it can run in CI),
+    // neither do `export` and `cache`
+    if !matches!(cli.command, Commands::Version(..) | Commands::Check(..) | Commands::Export(..) | Commands::Cache(..)) {
+        check_weidu_exe(&weidu_context)?;
+    }
     let cache = Cache::ensure_from_config(config).unwrap();
 
     match cli.command {
         Commands::Install(ref install_opts) => install(install_opts, &config, &current_dir, &cache),
-        Commands::Search(ref search_opts) => search(search_opts),
+        Commands::Search(ref search_opts) => search(search_opts, &current_dir),
         Commands::ListComponents(ref params) => sub_list_components(params, &weidu_context),
-        Commands::Invalidate(ref params) => invalidate(params, &cache),
+        Commands::Invalidate(ref params) => invalidate(params, &config, &cache),
         Commands::Reverse(ref params) => extract_manifest(params, &current_dir),
+        Commands::Export(ref params) => export_manifest(params, &current_dir),
         Commands::AppendMod(ref params) => append_mod(params, &weidu_context),
         Commands::Reset(ref reset_args) => reset(reset_args, &weidu_context),
         Commands::Discover(ref params) => discover(params, &weidu_context),
         Commands::Introspect(ref params) => introspect(params, &settings, &current_dir,
                                                                     &global_conf_dir(),
                                                                     &log_settings),
+        Commands::Audit(ref params) => audit(params, &config, &current_dir, &cache),
+        Commands::Check(ref params) => check(params, &config, &current_dir, &cache),
+        Commands::Version(ref params) => version(params, env!("CARGO_PKG_VERSION"), &weidu_context),
         Commands::GlobalConfig(sub) => match sub {
             ConfigArgs::Show(_) => open_global_config_dir(),
             ConfigArgs::Edit(_) => edit_global_config_dir(&config),
         }
+        Commands::Cache(sub) => match sub {
+            CacheArgs::Prune(ref params) => prune_cache(params, &cache),
+        }
     }
 }
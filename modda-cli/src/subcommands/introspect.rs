@@ -6,8 +6,13 @@ use handlebars::Handlebars;
 use serde_json::json;
 
 use modda_lib::args::Introspect;
+use modda_lib::cache::CacheLayout;
 use modda_lib::canon_path::CanonPath;
 use modda_lib::config::{ConfigSource, Settings, ARCHIVE_CACHE_ENV_VAR, EXTRACT_LOCATION_ENV_VAR, IGNORE_CURRENT_DIR_WEIDU_ENV_VAR, WEIDU_PATH_ENV_VAR};
+use modda_lib::download::{DEFAULT_MAX_REDIRECTS, DEFAULT_RETRY_DELAY_MS};
+use modda_lib::modda_context::WeiduContext;
+use modda_lib::module::manifest::Manifest;
+use modda_lib::run_weidu::weidu_executable;
 
 use crate::log_settings::LogSettings;
 
@@ -20,11 +25,15 @@ Found global config file: {{global_config_file}}
 Game dir: {{game_dir}}
 Found local config file: {{local_config_file}}
 
+Supported manifest versions: {{manifest_versions}}
+
 Config options from environment variables:
 {{environment}}
 
 Concrete config:
 {{concrete_config}}
+Effective config (and where each value comes from):
+{{effective_config}}
 
 Log settings:
 max level: {{max_level}}
@@ -34,7 +43,9 @@ max level: {{max_level}}
 
 pub fn introspect(params: &Introspect, settings: &Settings, game_dir: &CanonPath,
                     global_conf_dir: &Option<PathBuf>, log_settings: &LogSettings) -> Result<()> {
-    let registry = Handlebars::new();
+    let mut registry = Handlebars::new();
+    // plain text output, nothing to escape
+    registry.register_escape_fn(handlebars::no_escape);
 
     let context = &json!({
         "game_dir": game_dir.to_path_buf().as_os_str().to_string_lossy(),
@@ -50,6 +61,7 @@ pub fn introspect(params: &Introspect, settings: &Settings, game_dir: &CanonPath
             None => "no".to_string(),
             Some(file_name) => file_name.id.clone(),
         },
+        "manifest_versions": Manifest::supported_versions().join(", "),
         "environment": display_environment(&settings.env_config)
             .iter()
             .map(|(key, value)| format!(r#"{key} = "{value}""#))
@@ -59,6 +71,7 @@ pub fn introspect(params: &Introspect, settings: &Settings, game_dir: &CanonPath
             Ok(value) => value,
             Err(error) => format!("[error, could not serialize configuration:\n{error}]")
         },
+        "effective_config": display_effective_config(settings, game_dir),
         "max_level": log_settings.max_level.to_string(),
         "log_var_name": log_settings.log_var_name,
         "log_var_value": log_settings.log_var_value,
@@ -69,6 +82,48 @@ pub fn introspect(params: &Introspect, settings: &Settings, game_dir: &CanonPath
     Ok(())
 }
 
+fn display_effective_config(settings: &Settings, game_dir: &CanonPath) -> String {
+    let values = match settings.effective_config() {
+        Ok(values) => values,
+        Err(error) => return format!("[error, could not resolve configuration:\n{error}]"),
+    };
+    values.iter()
+        .map(|effective| {
+            let value = match &effective.value {
+                None => match resolved_default(&effective.name, settings, game_dir) {
+                    Some(default) => default,
+                    None => "(not set)".to_string(),
+                },
+                Some(value) => match serde_json::to_string(value) {
+                    Ok(value) => value,
+                    Err(error) => format!("[error, could not serialize value: {error}]"),
+                },
+            };
+            format!("{} = {}  <- {}", effective.name, value, effective.origin)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// What is used when the property is not set, for the properties whose default is computed.
+fn resolved_default(name: &str, settings: &Settings, game_dir: &CanonPath) -> Option<String> {
+    match name {
+        "archive_cache" => Some("(temporary directory, discarded after the run)".to_string()),
+        "extract_location" => Some(json!(std::env::temp_dir().to_string_lossy()).to_string()),
+        "weidu_path" => {
+            let weidu_context = WeiduContext { current_dir: game_dir, config: &settings.combined, debug_log: None };
+            Some(match weidu_executable(&weidu_context) {
+                Ok(weidu) => json!(weidu).to_string(),
+                Err(error) => format!("[error, could not resolve weidu: {error}]"),
+            })
+        }
+        "cache_layout" => Some(json!(CacheLayout::default()).to_string()),
+        "max_redirects" => Some(DEFAULT_MAX_REDIRECTS.to_string()),
+        "download_retry_delay_ms" => Some(DEFAULT_RETRY_DELAY_MS.to_string()),
+        _ => None,
+    }
+}
+
 const TRUE: &'static str = "true";
 const FALSE: &'static str = "false";
 
@@ -1,14 +1,17 @@
 
 use anyhow::{Result, bail};
 use itertools::Itertools;
-use log::info;
+use log::{info, warn};
 
-use modda_lib::args::Reset;
+use modda_lib::args::{manifest_root, Reset};
+use modda_lib::canon_path::CanonPath;
+use modda_lib::file_ledger::FileLedger;
 use modda_lib::modda_context::WeiduContext;
 use modda_lib::module::components::Components;
 use modda_lib::module::manifest::Manifest;
 use modda_lib::module::module::Module;
-use modda_lib::run_weidu::run_weidu_uninstall;
+use modda_lib::module::weidu_mod::BareMod;
+use modda_lib::run_weidu::{component_indexes, run_weidu_uninstall, uninstall_keeping_comments};
 use modda_lib::sub::extract_manifest::extract_bare_mods;
 use modda_lib::tp2::find_tp2_str;
 
@@ -28,12 +31,14 @@ pub fn reset(args: &Reset, weidu_context: &WeiduContext) -> Result<()> {
         Module::Mod { weidu_mod } => weidu_mod.clone(),
         Module::Generated { gen } => gen.as_weidu(),
     };
-    let components = match weidu_mod.components {
+    let manifest_root = manifest_root(&args.manifest_path, weidu_context.current_dir);
+    let components = match weidu_mod.components.resolve_file(&manifest_root)? {
         Components::None => bail!("Can't reset to a module fragment which doesn't install components (`components: none`)"),
         Components::Ask => bail!("Can't reset to a module fragment which doesn't specify components explicitly (`components: ask`)"),
         Components::All => bail!("Can't reset to a module fragment which doesn't specify components explicitly (`components: all`)"),
         Components::List(list) if list.is_empty() => bail!("Can't reset to a module fragment which doesn't install components (`components list is empty`)"),
-        Components::List(ref list) => list,
+        Components::List(list) => list,
+        Components::FromFile(_) => unreachable!("components file was read"),
     };
     let name_matches = installed.iter().enumerate()
         .filter(|(_, module)| module.name == weidu_mod.name)
@@ -42,8 +47,13 @@ pub fn reset(args: &Reset, weidu_context: &WeiduContext) -> Result<()> {
         info!("Nothing to remove, next mod at position {} ({}) was not installed", reset_index + 1, target_module.get_name());
         return Ok(());
     }
+    // groups are resolved the same way as when installing (the mod is in the game directory since it was installed)
+    let indexes = match component_indexes(&weidu_mod, &components, &manifest.global, weidu_context) {
+        Ok(indexes) => indexes,
+        Err(error) => bail!("Can't reset to mod {} whose component groups could not be resolved\n -> {:?}", weidu_mod.name, error),
+    };
     let component_matches = name_matches.iter().filter(|(_, module)|
-        components.iter().all(|comp| module.components.iter().any(|item| item.index == comp.index()))
+        indexes.iter().all(|index| module.components.iter().any(|item| item.index == *index))
     ).collect::<Vec<_>>();
 
     let (index, _) = match component_matches.as_slice() {
@@ -51,16 +61,51 @@ pub fn reset(args: &Reset, weidu_context: &WeiduContext) -> Result<()> {
         &[single_match] => single_match,
         _=> bail!("Found multiple occurrences of mod/component in weidu.log - aborting reset"),
     };
-    let removed = &installed[*index..];
-    let prompt = format!("Will uninstall these (in reverse order)\n  {}\nProceed? ", removed.iter().map(|item| item.short()).join("\n  "));
+    let (remaining, removed) = installed.split_at(*index);
+    let plan = removed.iter().map(|item| item.short()).join("\n  ");
+    if args.dry_run {
+        // nothing is actually uninstalled, no need to confirm
+        println!("Dry run, would uninstall these (in reverse order)\n  {}", plan);
+        for fragment in removed.iter().rev() {
+            let tp2 = find_tp2_str(weidu_context.current_dir, &fragment.name)?;
+            run_weidu_uninstall(&tp2, fragment, args.dry_run, weidu_context)?;
+        }
+        return remove_generated_files(weidu_context.current_dir, removed, remaining, true);
+    }
+    let prompt = format!("Will uninstall these (in reverse order)\n  {}\nProceed? ", plan);
     if dialoguer::Confirm::new().with_prompt(prompt).interact()? {
         for fragment in removed.iter().rev() {
             let tp2 = find_tp2_str(weidu_context.current_dir, &fragment.name)?;
-            run_weidu_uninstall(&tp2, fragment, args, weidu_context)?;
+            uninstall_keeping_comments(&tp2, fragment, args.dry_run, weidu_context)?;
         }
-        Ok(())
+        remove_generated_files(weidu_context.current_dir, removed, remaining, false)
     } else {
         info!("Aborted");
         Ok(())
     }
 }
+
+/// Removes the files modda wrote for the generated mods that are no longer installed (weidu doesn't know them).
+fn remove_generated_files(game_dir: &CanonPath, removed: &[BareMod], remaining: &[BareMod], dry_run: bool) -> Result<()> {
+    let mut ledger = FileLedger::read(game_dir)?;
+    for module in ledger.modules() {
+        let uninstalled = removed.iter().any(|fragment| fragment.name == module)
+                                && !remaining.iter().any(|fragment| fragment.name == module);
+        if !uninstalled {
+            continue;
+        }
+        let removal = ledger.remove_module_files(game_dir, &module, dry_run)?;
+        if dry_run {
+            println!("Dry run, would remove files created for {}\n  {}", module, removal.removed.join("\n  "));
+        } else {
+            info!("Removed {} file(s) created for {}", removal.removed.len(), module);
+        }
+        if !removal.kept.is_empty() {
+            warn!("Files created for {} were modified since, they are kept\n  {}", module, removal.kept.join("\n  "));
+        }
+    }
+    if !dry_run {
+        ledger.write(game_dir)?;
+    }
+    Ok(())
+}
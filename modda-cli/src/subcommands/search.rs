@@ -1,23 +1,30 @@
-
-
 use anyhow::Result;
 
-use modda_lib::args::Search;
+use modda_lib::args::{manifest_root, Search};
+use modda_lib::canon_path::CanonPath;
 use modda_lib::module::manifest::Manifest;
+use modda_lib::sub::audit::describe_source;
+use modda_lib::sub::search::{fuzzy_search, SearchHitKind};
 
 
-pub fn search(opts: &Search) -> Result<()> {
-    let manifest = Manifest::read_path(&opts.manifest_path)?;
-    let mut found = false;
-    for (idx, module) in manifest.modules.iter().enumerate() {
-        if module.get_name() == &opts.name.to_lowercase() {
-            found = true;
-            println!("idx: '{} - {}\n\t{:?}", idx + 1, module.describe(), module);
-        }
-    }
-
-    if !found {
+pub fn search(opts: &Search, game_dir: &CanonPath) -> Result<()> {
+    let manifest = Manifest::assemble_from_path(&opts.manifest_path, &manifest_root(&opts.manifest_path, game_dir))?;
+    let hits = fuzzy_search(&manifest, &opts.name);
+    if hits.is_empty() {
         println!("module {} not found", opts.name);
     }
+    for hit in hits {
+        match hit.kind {
+            SearchHitKind::Module { index } => println!("idx: {} - {}", index, hit.name),
+            SearchHitKind::LocationKey => println!("location: {}", hit.name),
+        }
+        if let Some(description) = hit.description {
+            println!("\t{}", description);
+        }
+        match hit.location {
+            Some(location) => println!("\tsource: {}", describe_source(&location.source)),
+            None => println!("\tsource: -"),
+        }
+    }
     Ok(())
 }
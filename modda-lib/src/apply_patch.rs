@@ -5,18 +5,94 @@ use std::path::{Path, PathBuf};
 use anyhow::{bail, Result};
 use log::{debug, info, warn};
 use patch::{Patch, Line};
+use sha2::{Digest, Sha256};
 
 use crate::args::Install;
+use crate::cache::Cache;
 use crate::canon_path::CanonPath;
+use crate::download::{DownloadOpts, Downloader};
 use crate::global::Global;
 use crate::lowercase::LwcString;
+use crate::module::refresh::RefreshCondition;
 use crate::patch_source::{PatchDesc, PatchEncoding, PatchSource};
+use crate::transform_preview::TransformPreview;
+
+/// Sub-directory of the archive cache where patches from HTTP resources are saved
+const PATCH_CACHE_DIR: &str = "patches";
+/// Number of hex digits of the SHA-256 digest of the URL used as name for cached patches
+const PATCH_NAME_LENGTH: usize = 24;
 
 pub async fn patch_module(game_dir: &CanonPath, module_name: &LwcString, patch: &PatchDesc,
-                            opts: &Install, global: &Global) -> Result<()> {
+                            opts: &Install, global: &Global, downloader: &Downloader, cache: &Cache) -> Result<()> {
     info!("mod {} needs patching", module_name);
     let patch_content = match &patch.patch_source {
-        PatchSource::Http { http: _http } => { bail!("not implemented yet - patch from source {:?}", patch); }
+        PatchSource::Http { http } => Cow::Owned(download_patch(http, module_name, patch.encoding, downloader, cache).await?),
+        _ => read_patch_content(game_dir, module_name, patch, opts, global)?,
+    };
+    patch_module_with_content(game_dir, module_name, &*patch_content, patch.encoding)
+}
+
+/// Patches from an HTTP resource are saved in the archive cache (named after the URL) and reused by later installs.
+async fn download_patch(url: &str, module_name: &LwcString, encoding: PatchEncoding,
+                        downloader: &Downloader, cache: &Cache) -> Result<String> {
+    let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Never };
+    let path = match downloader.download(url, &cache.join(PATCH_CACHE_DIR), patch_cache_name(url), &opts, &None).await {
+        Ok(path) => path,
+        Err(error) => bail!("Could not download patch {} for mod {}\n -> {:?}", url, module_name, error),
+    };
+    decode_file(&CanonPath::new(path)?, encoding)
+}
+
+fn patch_cache_name(url: &str) -> PathBuf {
+    let digest = format!("{:x}", Sha256::digest(url.as_bytes()));
+    PathBuf::from(format!("{}.diff", &digest[..PATCH_NAME_LENGTH]))
+}
+
+/// Same as `patch_module` but the result goes to `preview` (nothing is written).
+pub fn preview_patch_module(preview: &mut TransformPreview, game_dir: &CanonPath, module_name: &LwcString,
+                            patch: &PatchDesc, opts: &Install, global: &Global) -> Result<()> {
+    let patch_content = read_patch_content(game_dir, module_name, patch, opts, global)?;
+    let diff = match Patch::from_multiple(&patch_content) {
+        Ok(diff) => diff,
+        Err(error) => bail!("Couldn't parse patch for module {}\n -> {:?}", module_name, error),
+    };
+    for file_patch in diff {
+        let (old, new) = patched_paths(preview.root(), module_name, &file_patch)?;
+        let old_content = match preview.content(&old, |path| get_old_content(path, patch.encoding))? {
+            Some(content) => content,
+            None => bail!("File {:?} was removed by a previous patch of mod {}", old, module_name),
+        };
+        let old_lines: Vec<String> = old_content.split("\n").map(From::from).collect();
+        let new_lines = match apply_patch(&old_lines, &file_patch) {
+            Err(error) => bail!("Error patching file {:?} for mod {}\n -> {:?}", old, module_name, error),
+            Ok(new_lines) => new_lines,
+        };
+        if old != new {
+            preview.set(&old, None)?;
+        }
+        preview.set(&new, Some(new_lines.join("\n")))?;
+    }
+    Ok(())
+}
+
+/// Checks that the patch can be read (a relative patch file exists) and parsed, without applying it.<br>
+/// Patches from an HTTP resource are not checked (nothing is downloaded).
+pub fn check_patch(game_dir: &CanonPath, module_name: &LwcString, patch: &PatchDesc,
+                    opts: &Install, global: &Global) -> Result<()> {
+    if let PatchSource::Http { .. } = patch.patch_source {
+        return Ok(());
+    }
+    let patch_content = read_patch_content(game_dir, module_name, patch, opts, global)?;
+    if let Err(error) = Patch::from_multiple(&patch_content) {
+        bail!("Couldn't parse patch for module {}\n -> {:?}", module_name, error);
+    }
+    Ok(())
+}
+
+fn read_patch_content<'a>(game_dir: &CanonPath, module_name: &LwcString, patch: &'a PatchDesc,
+                            opts: &Install, global: &Global) -> Result<Cow<'a, str>> {
+    let patch_content = match &patch.patch_source {
+        PatchSource::Http { http } => bail!("Patch {} for mod {} is only downloaded when the mod is installed", http, module_name),
         PatchSource::Relative { relative } => {
             let diff = match read_patch_relative(relative, game_dir, opts, global, patch.encoding) {
                 Ok(diff) => diff,
@@ -25,9 +101,9 @@ pub async fn patch_module(game_dir: &CanonPath, module_name: &LwcString, patch:
             };
             Cow::Owned(diff)
         }
-        PatchSource::Inline { inline } => Cow::Borrowed(inline),
+        PatchSource::Inline { inline } => Cow::Borrowed(inline.as_str()),
     };
-    patch_module_with_content(game_dir, module_name, &*patch_content, patch.encoding)
+    Ok(patch_content)
 }
 
 fn patch_module_with_content(game_dir: &CanonPath, module_name: &LwcString, patch: &str, encoding: PatchEncoding) -> Result<()> {
@@ -36,21 +112,7 @@ fn patch_module_with_content(game_dir: &CanonPath, module_name: &LwcString, patc
         Err(error) => bail!("Couldn't parse patch for module {}\n -> {:?}", module_name, error),
     };
     for patch in diff {
-        let old_path = game_dir.join(&*patch.old.path);
-        let old = match old_path {
-            Ok(path) => path,
-            Err(ref error) => bail!("Failed to canonicalize old file path {:?} while patching mod {}\n -> {:?}",
-                                            old_path, module_name, error),
-        };
-        check_path(game_dir, &old)?;
-
-        let new_path = game_dir.join(&*patch.new.path);
-        let new = match new_path {
-            Ok(path) => path,
-            Err(ref error) => bail!("Failed to canonicalize new file path {:?} while patching mod {}\n -> {:?}",
-                                            new_path, module_name, error),
-        };
-        check_path(game_dir, &new)?;
+        let (old, new) = patched_paths(game_dir, module_name, &patch)?;
         if let Err(error) = patch_files(&old, &new, &patch, encoding) {
             bail!("Failed to patch file {:?} for mod {}\n -> {:?}", old, module_name, error);
         }
@@ -58,11 +120,18 @@ fn patch_module_with_content(game_dir: &CanonPath, module_name: &LwcString, patc
     Ok(())
 }
 
-fn check_path(base: &CanonPath, path: &CanonPath) -> Result<()> {
-    if !path.starts_with(base.path()) {
-        bail!("Attempt to patch file not in game directory\n  {:?} not in {:?}", path, base);
-    }
-    Ok(())
+fn patched_paths(game_dir: &CanonPath, module_name: &LwcString, patch: &Patch) -> Result<(CanonPath, CanonPath)> {
+    let old = match game_dir.safe_join(&*patch.old.path) {
+        Ok(path) => path,
+        Err(error) => bail!("Invalid old file path {:?} while patching mod {} (must be in the game directory)\n -> {:?}",
+                                        patch.old.path, module_name, error),
+    };
+    let new = match game_dir.safe_join(&*patch.new.path) {
+        Ok(path) => path,
+        Err(error) => bail!("Invalid new file path {:?} while patching mod {} (must be in the game directory)\n -> {:?}",
+                                        patch.new.path, module_name, error),
+    };
+    Ok((old, new))
 }
 
 fn decode_file(path:&CanonPath, encoding: PatchEncoding) -> Result<String> {
@@ -189,15 +258,9 @@ fn read_patch_relative(relative: &str, game_dir: &CanonPath, opts: &Install,
 }
 
 fn read_patch_from(relative: &Path, base: &CanonPath, encoding: PatchEncoding) -> Result<String> {
-    let complete = base.join(relative);
-    if let Ok(path) = complete {
-        if path.starts_with(base) {
-            decode_file(&path, encoding)
-        } else {
-            bail!("Relative patch not in expected location")
-        }
-    } else {
-        bail!("Could not canonalize path {:?}", complete);
+    match base.safe_join(relative) {
+        Ok(path) => decode_file(&path, encoding),
+        Err(error) => bail!("Relative patch not in expected location\n -> {:?}", error),
     }
 }
 
@@ -448,3 +511,105 @@ mod apply_patch_tests {
         assert_eq!(result.unwrap(), expected);
     }
 }
+
+#[cfg(test)]
+mod test_http_patch {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use crate::args::Install;
+    use crate::cache::Cache;
+    use crate::config::Config;
+    use crate::download::Downloader;
+    use crate::global::Global;
+    use crate::install_events::EventSender;
+    use crate::lowercase::lwc;
+    use crate::patch_source::{PatchDesc, PatchEncoding, PatchSource};
+
+    use super::patch_module;
+
+    const PATCH: &str = "--- modulename.tp2\n+++ modulename.tp2\n@@ -1,3 +1,3 @@\n BACKUP ~weidu_external/backup/modulename~\n SUPPORT ~http://somewhere.iflucky.org~\n-VERSION ~1.0~\n+VERSION ~2.0~\n";
+
+    /// Serves the patch at `/fix.diff` (counting the requests), 404 otherwise.
+    fn start_server(requests: Arc<AtomicUsize>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Err(_) => continue,
+                    Ok(stream) => stream,
+                };
+                let mut request = vec![];
+                let mut buffer = [0u8; 1024];
+                while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                    match stream.read(&mut buffer) {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => request.extend_from_slice(&buffer[..read]),
+                    }
+                }
+                let request = String::from_utf8_lossy(&request);
+                let response = match request.split_whitespace().nth(1) {
+                    Some("/fix.diff") => {
+                        requests.fetch_add(1, Ordering::SeqCst);
+                        format!("HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                                PATCH.len(), PATCH)
+                    }
+                    _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+                };
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    fn http_patch(url: String) -> PatchDesc {
+        PatchDesc { patch_source: PatchSource::Http { http: url }, encoding: PatchEncoding::UTF8 }
+    }
+
+    fn game_dir() -> (tempfile::TempDir, crate::canon_path::CanonPath) {
+        let tempdir = tempfile::tempdir().unwrap();
+        let origin = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/patch/modulename.tp2");
+        std::fs::copy(origin, tempdir.path().join("modulename.tp2")).unwrap();
+        let game_dir = crate::canon_path::CanonPath::new(tempdir.path()).unwrap();
+        (tempdir, game_dir)
+    }
+
+    #[tokio::test]
+    async fn patch_is_downloaded_then_reused() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let base = start_server(requests.clone());
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache = Cache::Path(cache_dir.path().to_path_buf());
+        let downloader = Downloader::from_config(&Config::default(), EventSender::default());
+        let patch = http_patch(format!("{}/fix.diff", base));
+
+        for _ in 0..2 {
+            let (_tempdir, game_dir) = game_dir();
+            patch_module(&game_dir, &lwc!("modulename"), &patch, &Install::default(), &Global::default(),
+                            &downloader, &cache).await.unwrap();
+            let patched = std::fs::read_to_string(game_dir.join_path("modulename.tp2")).unwrap();
+            assert!(patched.contains("VERSION ~2.0~"), "{}", patched);
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn missing_patch_fails() {
+        let base = start_server(Arc::new(AtomicUsize::new(0)));
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache = Cache::Path(cache_dir.path().to_path_buf());
+        let downloader = Downloader::from_config(&Config::default(), EventSender::default());
+        let (_tempdir, game_dir) = game_dir();
+
+        let error = patch_module(&game_dir, &lwc!("modulename"), &http_patch(format!("{}/missing.diff", base)),
+                                    &Install::default(), &Global::default(), &downloader, &cache).await.unwrap_err();
+        let message = format!("{:?}", error);
+        assert!(message.contains("Could not download patch") && message.contains("404"), "{}", message);
+        let content = std::fs::read_to_string(game_dir.join_path("modulename.tp2")).unwrap();
+        assert!(content.contains("VERSION ~1.0~"));
+    }
+}
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Component, PathBuf};
 use std::process::{Stdio, Command};
 use std::{path::Path, collections::HashSet};
 
@@ -6,7 +6,7 @@ use std::fs::File;
 use std::io::{BufReader, self};
 
 use globwalk::GlobWalkerBuilder;
-use log::{debug, info};
+use log::{debug, info, warn};
 use anyhow::{bail, Result, anyhow};
 use tempfile::TempDir;
 use zip::ZipArchive;
@@ -15,42 +15,74 @@ use zip::result::{ZipResult, ZipError};
 use crate::canon_path::CanonPath;
 use crate::lowercase::{LwcString, lwc};
 use crate::module::location::location::ConcreteLocation;
+use crate::module::location::source::Source;
 use crate::module::pre_copy_command::PrecopyCommand;
 use crate::config::{Config, ExtractorCommand};
 
 
+/// Extension of the file (next to a kept extraction directory) that records which archive was extracted there
+const KEPT_MARKER_EXTENSION: &str = "extracted";
+
+/// Maximum number of entries of the extracted content shown when the layout doesn't match anything
+const MAX_LISTED_ENTRIES: usize = 50;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TempDirOptions {
+    /// Keeps the extracted content after it was copied to the game directory (instead of moving it).
+    pub keep: bool,
+    /// Reuses extracted content kept by a previous run if it comes from the same archive.
+    pub reuse: bool,
+}
+
 #[cfg_attr(test, faux::create)]
 pub struct Extractor<'a> {
     game_dir: &'a CanonPath,
     config: &'a Config,
+    temp_options: TempDirOptions,
 }
 
 #[cfg_attr(test, faux::methods)]
 impl <'a> Extractor<'a> {
 
-    pub fn new(game_dir: &'a CanonPath, config: &'a Config) -> Self {
+    pub fn new(game_dir: &'a CanonPath, config: &'a Config, temp_options: TempDirOptions) -> Self {
         Self {
             game_dir,
             config,
+            temp_options,
         }
     }
 
-    pub fn extract_files(&self, archive: &Path, module_name: &LwcString, location: &ConcreteLocation,) -> Result<()> {
+    /// Returns the output of the `precopy` command, if it printed anything.
+    pub fn extract_files(&self, archive: &Path, module_name: &LwcString, location: &ConcreteLocation,) -> Result<Option<String>> {
         debug!("extract_files from archive {:?} for {}", archive, module_name);
+        if self.temp_options.reuse {
+            if let Some(kept_dir) = self.find_kept_extraction(archive, module_name)? {
+                info!("Reusing content of {} already extracted in {:?}", module_name, kept_dir);
+                return self.move_content_to_game_dir(&ExtractLocation::Kept(kept_dir), module_name, location).map(|_| None);
+            }
+        }
         let result = self.extract_files_to_temp(archive, module_name, location);
         debug!("done extracting files, ended in {}", result.as_ref().map(|_| "success".to_owned()).unwrap_or_else(|_| "failure".to_owned()));
 
         let temp_dir = result?;
-        if let Some(command) = &location.precopy {
-            if let Err(error) = self.run_precopy_command(&temp_dir.as_path_buf(), command) {
-                bail!("Couldn't run precopy command for mod {}, command={} with args {:?}\n{:?}",
-                        module_name, command.command, command.args, error);
+        let precopy_output = match self.run_precopy(&temp_dir, module_name, location) {
+            Ok(output) => output,
+            Err(error) => {
+                if self.temp_options.keep {
+                    self.keep_failed_extraction(temp_dir, module_name);
+                }
+                return Err(error);
             }
-        }
+        };
 
+        let temp_dir = if self.temp_options.keep {
+            self.keep_extraction(temp_dir, archive, module_name)?
+        } else {
+            temp_dir
+        };
         self.move_content_to_game_dir(&temp_dir, module_name, location)?;
 
-        Ok(())
+        Ok(precopy_output)
     }
 
     fn move_content_to_game_dir(&self, temp_dir: &ExtractLocation,  module_name: &LwcString, location: &ConcreteLocation) -> Result<()> {
@@ -58,7 +90,8 @@ impl <'a> Extractor<'a> {
             ExtractLocation::Temp(temp_dir) => {
                 debug!("Moving mod content to game location ...");
                 if let Err(error) = self.move_from_temp_dir(&temp_dir.as_ref(), module_name, location) {
-                    bail!("Failed to move files for mod {} from temp dir to game dir\n -> {:?}", module_name, error);
+                    bail!("Failed to move files for mod {} from temp dir to game dir (use --keep-temp to inspect the extracted content)\n -> {:?}",
+                            module_name, error);
                 }
                 debug!("files done moving to final destination");
 
@@ -70,15 +103,117 @@ impl <'a> Extractor<'a> {
                 }
                 debug!("files done copying to final destination");
             }
+            ExtractLocation::Kept(kept_dir) => {
+                debug!("Copying mod content from kept extraction dir to game location ...");
+                if let Err(error) = self.copy_from_kept_dir(kept_dir, module_name, location) {
+                    bail!("Failed to copy files for mod {} from kept dir to game dir\n -> {:?}", module_name, error);
+                }
+                debug!("files done copying to final destination");
+            }
         }
         Ok(())
     }
 
+    /// Turns the temporary extraction directory into one that will survive this run, so that it can be
+    /// inspected or reused with the `reuse` option.
+    fn keep_extraction(&self, extracted: ExtractLocation, archive: &Path, module_name: &LwcString) -> Result<ExtractLocation> {
+        let temp_dir = match extracted {
+            ExtractLocation::Temp(temp_dir) => temp_dir,
+            // not extracted, the source directory is used directly
+            other => return Ok(other),
+        };
+        let kept_dir = self.retain_temp_dir(&temp_dir, module_name)?;
+        let marker = kept_marker(&kept_dir);
+        // written last, so that an interrupted extraction is never reused
+        if let Err(error) = std::fs::write(&marker, archive_fingerprint(archive)?) {
+            bail!("Could not write extraction marker {:?}\n -> {:?}", marker, error);
+        }
+        info!("Extracted content of {} is kept in {:?}", module_name, kept_dir);
+        Ok(ExtractLocation::Kept(kept_dir))
+    }
+
+    /// Keeps the content of an extraction that failed before it was moved to the game directory (without marker,
+    /// it will not be reused), so that it can be inspected.
+    fn keep_failed_extraction(&self, extracted: ExtractLocation, module_name: &LwcString) {
+        let temp_dir = match extracted {
+            ExtractLocation::Temp(temp_dir) => temp_dir,
+            ExtractLocation::Regular(_) | ExtractLocation::Kept(_) => return,
+        };
+        match self.retain_temp_dir(&temp_dir, module_name) {
+            Ok(kept_dir) => info!("Extracted content of {} is kept in {:?}", module_name, kept_dir),
+            Err(error) => warn!("Could not keep extracted content of {}\n -> {:?}", module_name, error),
+        }
+    }
+
+    /// Moves the content of the temporary directory to the kept extraction directory of the mod (replacing a
+    /// previous one).
+    fn retain_temp_dir(&self, temp_dir: &TempDir, module_name: &LwcString) -> Result<PathBuf> {
+        let kept_dir = self.kept_dir(module_name)?;
+        let marker = kept_marker(&kept_dir);
+        if marker.exists() {
+            std::fs::remove_file(&marker)?;
+        }
+        if kept_dir.exists() {
+            if let Err(error) = std::fs::remove_dir_all(&kept_dir) {
+                bail!("Could not remove previously kept extraction dir {:?}\n -> {:?}", kept_dir, error);
+            }
+        }
+        // once moved, dropping the `TempDir` has nothing left to delete
+        if let Err(error) = std::fs::rename(temp_dir.path(), &kept_dir) {
+            bail!("Could not keep extracted content of {} from {:?} to {:?}\n -> {:?}", module_name, temp_dir.path(), kept_dir, error);
+        }
+        Ok(kept_dir)
+    }
+
+    /// Returns the directory kept by a previous run for this mod, if it was extracted from the same archive.
+    fn find_kept_extraction(&self, archive: &Path, module_name: &LwcString) -> Result<Option<PathBuf>> {
+        let kept_dir = self.kept_dir(module_name)?;
+        let marker = kept_marker(&kept_dir);
+        if !kept_dir.is_dir() || !marker.is_file() {
+            debug!("No kept extraction for {} in {:?}", module_name, kept_dir);
+            return Ok(None);
+        }
+        let recorded = match std::fs::read_to_string(&marker) {
+            Err(error) => bail!("Could not read extraction marker {:?}\n -> {:?}", marker, error),
+            Ok(content) => content,
+        };
+        if recorded != archive_fingerprint(archive)? {
+            info!("Kept extraction for {} in {:?} comes from another archive, extracting again", module_name, kept_dir);
+            return Ok(None);
+        }
+        Ok(Some(kept_dir))
+    }
+
+    fn kept_dir(&self, module_name: &LwcString) -> Result<PathBuf> {
+        let base = match &self.config.extract_location {
+            None => std::env::temp_dir(),
+            Some(location) => match shellexpand::full(location) {
+                Err(error) => bail!("Temporary dir expansion failed\n  {error}"),
+                Ok(expanded) => PathBuf::from(expanded.to_string()),
+            }
+        };
+        Ok(base.join(format!("modda-kept-{}", module_name)))
+    }
+
+    fn copy_from_kept_dir(&self, kept_dir: &Path, module_name: &LwcString, location: &ConcreteLocation) -> Result<()> {
+        let items = match self.files_to_move(kept_dir, module_name, location) {
+            Ok(items) => items,
+            Err(error) => bail!("Failed to prepare list of files to copy\n -> {:?}", error),
+        };
+        let included = self.included_files(kept_dir, module_name, location)?;
+        let copy_options = fs_extra::dir::CopyOptions {
+            copy_inside: true,
+            ..Default::default()
+        };
+        fs_extra::copy_items(&items.iter().collect::<Vec<_>>(), self.game_dir.path(), &copy_options)?;
+        copy_included_files(&included)
+    }
+
     /// Extracts (if needed) the archive to a temporary location.
     /// Returns the path to the extracted content.
     fn extract_files_to_temp(&self, archive: &Path, module_name: &LwcString, location: &ConcreteLocation) -> Result<ExtractLocation> {
-        if archive.is_dir() {
-            if location.precopy.is_some() {
+        let tmp_dir = match self.extraction_method(archive, location)? {
+            ExtractionMethod::Directory { temp_copy: true } => {
                 // precopy could modify the content so make a temp copy to preserve original
                 let temp_dir_attempt = self.create_temp_dir();
                 let temp_dir = match temp_dir_attempt {
@@ -87,42 +222,46 @@ impl <'a> Extractor<'a> {
                 };
                 self.copy_to_temp_dir(archive, temp_dir.as_ref())?;
                 debug!("Directory content was copied to {:?} for precopy command", temp_dir);
-                Ok(ExtractLocation::Temp(temp_dir))
-            } else {
-                // will not change the source directory, no need to create a temporary copy
-                Ok(ExtractLocation::Regular(archive.to_owned()))
+                return Ok(ExtractLocation::Temp(temp_dir));
             }
-        } else {
-            let tmp_dir = match archive.extension() {
-                Some(ext) =>  match ext.to_str() {
-                    None => bail!("Couldn't determine archive type for file {:?}", archive),
-                    Some("zip") | Some("iemod") => self.extract_zip(archive, module_name),
-                    Some("tgz") => self.extract_tgz(archive, module_name),
-                    Some("gz") => self.extract_gz(archive, module_name),
-                    Some(ext) => self.extract_external(archive, module_name, ext),
-                }
-                None => bail!("archive file has no extension {:?}", archive),
-            };
-            tmp_dir.map(|dir| ExtractLocation::Temp(dir))
-        }
+            // will not change the source directory, no need to create a temporary copy
+            ExtractionMethod::Directory { temp_copy: false } => return Ok(ExtractLocation::Regular(archive.to_owned())),
+            ExtractionMethod::Zip => self.extract_zip(archive, module_name),
+            ExtractionMethod::Tar(codec) => self.extract_tar(archive, module_name, codec),
+            ExtractionMethod::SevenZip => self.extract_7z(archive, module_name),
+            ExtractionMethod::Rar => self.extract_rar(archive, module_name),
+            ExtractionMethod::External(command) => self.extract_external(archive, module_name, &command),
+        };
+        tmp_dir.map(|dir| ExtractLocation::Temp(dir))
     }
 
-    fn extract_gz(&self, archive: &Path, module_name: &LwcString) -> Result<TempDir> {
-        let stem = archive.file_stem();
-        match stem {
-            Some(stem) => {
-                let stem_path = PathBuf::from(stem);
-                let sub_ext = stem_path.extension();
-                match sub_ext {
-                    None => bail!("unsupported .gz file for archive {:?}", archive),
-                    Some(sub_ext) => match sub_ext.to_str() {
-                        Some("tar") => self.extract_tgz(archive, module_name),
-                        _ =>  bail!("unsupported .gz file for archive {:?}", archive),
-                    }
+    /// Decides how the content of the mod "archive" is obtained (also used to show the installation plan).
+    pub fn extraction_method(&self, archive: &Path, location: &ConcreteLocation) -> Result<ExtractionMethod> {
+        if archive.is_dir() {
+            return Ok(ExtractionMethod::Directory { temp_copy: location.precopy.is_some() });
+        }
+        // local archives are not copied, a `rename` only changes how they are extracted
+        let name = match &location.source {
+            Source::Absolute { rename: Some(rename), .. } | Source::Local { rename: Some(rename), .. } => Path::new(rename),
+            _ => archive,
+        };
+        Ok(match name.extension() {
+            Some(ext) =>  match ext.to_str() {
+                None => bail!("Couldn't determine archive type for file {:?}", archive),
+                Some("zip") | Some("iemod") => ExtractionMethod::Zip,
+                Some(ext @ ("tgz" | "tzst" | "tbz2" | "tbz")) => match TarCodec::from_short_extension(ext) {
+                    Some(codec) => ExtractionMethod::Tar(codec),
+                    None => bail!("unsupported .{} file for archive {:?}", ext, archive),
                 }
+                Some(ext @ ("gz" | "zst" | "bz2")) => ExtractionMethod::Tar(compressed_tar_codec(archive, name, ext)?),
+                // a configured extractor still wins over the native one
+                Some("7z") if !self.has_extractor("7z", location) => ExtractionMethod::SevenZip,
+                // without the `rar` feature, an extractor must be configured
+                Some("rar") if cfg!(feature = "rar") && !self.has_extractor("rar", location) => ExtractionMethod::Rar,
+                Some(ext) => ExtractionMethod::External(self.extractor_command(ext, location)?.to_owned()),
             }
-            None => bail!("unsupported .gz file for archive {:?}", archive)
-        }
+            None => bail!("archive file has no extension {:?}", archive),
+        })
     }
 
     fn extract_zip(&self, archive: &Path,  module_name: &LwcString) -> Result<TempDir> {
@@ -149,31 +288,66 @@ impl <'a> Extractor<'a> {
         Ok(temp_dir)
     }
 
-    fn extract_tgz(&self, archive: &Path, module_name: &LwcString) -> Result<TempDir> {
-        let tar_gz = File::open(archive)?;
-        let tar = flate2::read::GzDecoder::new(tar_gz);
+    fn extract_tar(&self, archive: &Path, module_name: &LwcString, codec: TarCodec) -> Result<TempDir> {
+        let compressed = File::open(archive)?;
+        let tar: Box<dyn io::Read> = match codec {
+            TarCodec::Gzip => Box::new(flate2::read::GzDecoder::new(compressed)),
+            TarCodec::Zstd => match zstd::stream::read::Decoder::new(compressed) {
+                Ok(decoder) => Box::new(decoder),
+                Err(error) => bail!("Could not read zstd archive {:?}\n -> {:?}", archive, error),
+            },
+            TarCodec::Bzip2 => Box::new(bzip2::read::BzDecoder::new(compressed)),
+        };
         let mut tar_archive = tar::Archive::new(tar);
 
         let temp_dir_attempt = self.create_temp_dir();
         let temp_dir = match temp_dir_attempt {
             Ok(dir) => dir,
-            Err(error) => bail!("Extraction of tgz mod {} failed\n -> {:?}", module_name, error),
+            Err(error) => bail!("Extraction of {} mod {} failed\n -> {:?}", codec.name(), module_name, error),
         };
         if let Err(error) = tar_archive.unpack(&temp_dir) {
-            bail!("Tgz extraction failed for {:?} - {:?}", archive, error);
+            bail!("{} extraction failed for {:?} - {:?}", codec.name(), archive, error);
+        }
+
+        Ok(temp_dir)
+    }
+
+    fn extract_7z(&self, archive: &Path, module_name: &LwcString) -> Result<TempDir> {
+        let temp_dir = match self.create_temp_dir() {
+            Ok(dir) => dir,
+            Err(error) => bail!("Extraction of 7z mod {} failed\n -> {:?}", module_name, error),
+        };
+        debug!("7z extraction starting");
+        if let Err(error) = sevenz_rust::decompress_file(archive, &temp_dir) {
+            bail!("7z extraction failed for {:?}\n -> {:?}", archive, error);
+        }
+        debug!("7z extraction done");
+
+        Ok(temp_dir)
+    }
+
+    fn extract_rar(&self, archive: &Path, module_name: &LwcString) -> Result<TempDir> {
+        let temp_dir = match self.create_temp_dir() {
+            Ok(dir) => dir,
+            Err(error) => bail!("Extraction of rar mod {} failed\n -> {:?}", module_name, error),
+        };
+        debug!("rar extraction starting");
+        if let Err(error) = extract_rar_archive(archive, temp_dir.path()) {
+            bail!("rar extraction failed for {:?}\n -> {:?}", archive, error);
         }
+        debug!("rar extraction done");
 
         Ok(temp_dir)
     }
 
-    fn extract_external(&self, archive: &Path, module_name: &LwcString, extension: &str) -> Result<TempDir> {
+    fn extract_external(&self, archive: &Path, module_name: &LwcString, extractor_command: &ExtractorCommand) -> Result<TempDir> {
         let temp_dir_attempt = self.create_temp_dir();
         let temp_dir = match temp_dir_attempt {
             Ok(dir) => dir,
-            Err(error) => bail!("Extraction of '{}' mod {} failed\n -> {:?}", extension, module_name, error),
+            Err(error) => bail!("Extraction of mod {} with {} failed\n -> {:?}", module_name, extractor_command.command, error),
         };
 
-        if let Err(error) = self.external_extractor_tool(archive, extension, &temp_dir) {
+        if let Err(error) = self.external_extractor_tool(archive, extractor_command, &temp_dir) {
             bail!("Extraction with external tool failed for {:?} - {:?}", archive, error);
         }
 
@@ -232,13 +406,37 @@ impl <'a> Extractor<'a> {
             Ok(items) => items,
             Err(error) => bail!("Failed to prepare list of files to move\n -> {:?}", error),
         };
+        let included = self.included_files(temp_dir, module_name, location)?;
         let copy_options = fs_extra::dir::CopyOptions {
             copy_inside: true,
             ..Default::default()
         };
         let _result = fs_extra::move_items(&items.iter().collect::<Vec<_>>(), &self.game_dir.path(), &copy_options)?;
         // this is ne number of moved items ; I don't care
-        Ok(())
+        copy_included_files(&included)
+    }
+
+    /// Finds the `include_files` of the layout in the extracted content, with their destination in the game directory.
+    fn included_files(&self, base: &Path, module_name: &LwcString, location: &ConcreteLocation) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let base = location.layout.content_root(base)?;
+        let mut result = vec![];
+        for (pattern, relative) in location.layout.include_patterns(&location.source)? {
+            let glob = match GlobWalkerBuilder::from_patterns(&base, &[&pattern]).case_insensitive(true).build() {
+                Err(error) => bail!("Could not evaluate pattern {} for `include_files`\n -> {:?}", pattern, error),
+                Ok(glob) => glob,
+            };
+            let found = glob.into_iter().filter_map(Result::ok)
+                .filter(|entry| entry.file_type().is_file())
+                .map(|entry| entry.into_path())
+                .collect::<Vec<_>>();
+            match found.as_slice() {
+                [] => bail!("File {} (from `include_files`) was not found in the content of mod {}", relative, module_name),
+                [single] => result.push((single.to_owned(), self.game_dir.join_path(&relative))),
+                _ => bail!("File {} (from `include_files`) was found several times in the content of mod {}\n  {:?}",
+                            relative, module_name, found),
+            }
+        }
+        Ok(result)
     }
 
     fn files_to_move(&self, base: &Path, module_name: &LwcString, location:&ConcreteLocation) -> Result<HashSet<PathBuf>> {
@@ -250,7 +448,8 @@ impl <'a> Extractor<'a> {
             bail!("No file patterns to copy from archive for module {}", module_name);
         }
         debug!("Copy files from patterns: {:?}", glob_descs);
-        let glob_builder = GlobWalkerBuilder::from_patterns(base, &glob_descs.patterns)
+        let base = location.layout.content_root(base)?;
+        let glob_builder = GlobWalkerBuilder::from_patterns(&base, &glob_descs.patterns)
                 .case_insensitive(true)
                 .min_depth(glob_descs.strip)
                 .max_depth(glob_descs.strip + 1);
@@ -261,39 +460,63 @@ impl <'a> Extractor<'a> {
         for item in glob.into_iter().filter_map(Result::ok) {
             items.insert(item.into_path());
         }
+        if items.is_empty() {
+            bail!("No file of module {} matched the layout patterns {:?} (at depth {})\nExtracted content in {:?}:\n{}",
+                    module_name, glob_descs.patterns, glob_descs.strip, base, list_entries(&base, MAX_LISTED_ENTRIES)?);
+        }
         Ok(items)
     }
 
-    fn run_precopy_command(&self, from: &Path, precopy: &PrecopyCommand) -> Result<()> {
+    /// Runs the `precopy` command (if any) on the extracted content, before it is moved to the game directory.<br>
+    /// This is the same for all sources: archives (zip, tgz, external extractors...) are extracted to a
+    /// temporary directory, directory sources are copied to one first so that the command never modifies
+    /// the original files.
+    fn run_precopy(&self, extracted: &ExtractLocation, module_name: &LwcString, location: &ConcreteLocation) -> Result<Option<String>> {
+        let command = match &location.precopy {
+            None => return Ok(None),
+            Some(command) => command,
+        };
+        let from = match extracted {
+            ExtractLocation::Temp(temp_dir) => temp_dir.path(),
+            ExtractLocation::Regular(_) | ExtractLocation::Kept(_) =>
+                bail!("precopy command for mod {} would run on the original files", module_name),
+        };
+        match self.run_precopy_command(from, command) {
+            Err(error) => bail!("Couldn't run precopy command for mod {}, command={} with args {:?}\n{:?}",
+                                module_name, command.command, command.args, error),
+            Ok(captured) if captured.is_empty() => Ok(None),
+            Ok(captured) => Ok(Some(captured)),
+        }
+    }
+
+    fn run_precopy_command(&self, from: &Path, precopy: &PrecopyCommand) -> Result<String> {
         info!("Running precopy command `{}` with args {:?} from path `{:?}` in subdir {:?}",
                 precopy.command, precopy.args, from, precopy.subdir);
         let mut command = Command::new(&precopy.command);
-        let work_dir = match &precopy.subdir {
-            None => from.to_path_buf(),
-            Some(subdir) => from.join(subdir),
-        };
-        command.current_dir(work_dir)
-            .stdin(Stdio::inherit())
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit());
+        command.current_dir(precopy_work_dir(from, &precopy.subdir)?)
+            // the output is captured, a command waiting for an answer would hang without showing anything
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
         if let Some(args) = &precopy.args {
             command.args(args);
         }
         debug!("command: {:?}", command);
-        return match command.status() {
-            Ok(status) => {
-                if status.success() {
-                    Ok(())
-                } else {
-                    bail!("precopy command failed with status\n{:?}", status.code())
-                }
-            }
+        let output = match command.output() {
+            Ok(output) => output,
             Err(error) => bail!("failure running precopy command\n{:?}", error),
+        };
+        let captured = format_command_output(&output.stdout, &output.stderr);
+        if !output.status.success() {
+            bail!("precopy command failed with status\n{:?}{}", output.status.code(), captured);
+        }
+        if !captured.is_empty() {
+            info!("precopy command output:{}", captured);
         }
+        Ok(captured)
     }
 
-    fn external_extractor_tool(&self, archive: &Path, extension: &str,  tmp_dir: &TempDir) -> Result<()> {
-        let extractor_command = self.extractor_command(extension)?;
+    fn external_extractor_tool(&self, archive: &Path, extractor_command: &ExtractorCommand, tmp_dir: &TempDir) -> Result<()> {
         let mut command = Command::new(&extractor_command.command);
         let args = extractor_command.args.iter().map(|arg| {
             match arg.as_str() {
@@ -329,7 +552,16 @@ impl <'a> Extractor<'a> {
         Ok(())
     }
 
-    fn extractor_command(&self, extension: &str) -> Result<&ExtractorCommand> {
+    /// Whether an external extractor would be used for the extension (see `extractor_command`).
+    fn has_extractor(&self, extension: &str, location: &ConcreteLocation) -> bool {
+        location.extractor.is_some() || self.config.extractors.contains_key(&lwc!(extension))
+    }
+
+    /// The extractor of the location (if any) wins over the one configured for the extension.
+    fn extractor_command<'b>(&'b self, extension: &str, location: &'b ConcreteLocation) -> Result<&'b ExtractorCommand> {
+        if let Some(extractor) = &location.extractor {
+            return Ok(extractor);
+        }
         match self.config.extractors.get(&lwc!(extension)) {
             Some(extractor) => Ok(extractor),
             None => bail!("No extractor configured for {extension}"),
@@ -337,6 +569,20 @@ impl <'a> Extractor<'a> {
     }
 }
 
+#[cfg(feature = "rar")]
+fn extract_rar_archive(archive: &Path, directory: &Path) -> Result<()> {
+    let mut rar_archive = unrar::Archive::new(archive).open_for_processing()?;
+    while let Some(header) = rar_archive.read_header()? {
+        rar_archive = header.extract_with_base(directory)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "rar"))]
+fn extract_rar_archive(archive: &Path, _directory: &Path) -> Result<()> {
+    bail!("native rar extraction is not available (modda was built without the `rar` feature) for {:?}", archive)
+}
+
 // duplicated from zip-rs source
 fn extract_zip_archive<P: AsRef<Path>>(zip_archive: &mut ZipArchive<BufReader<File>>, directory: P) -> ZipResult<()> {
     use std::fs;
@@ -367,13 +613,675 @@ fn extract_zip_archive<P: AsRef<Path>>(zip_archive: &mut ZipArchive<BufReader<Fi
 enum ExtractLocation {
     Temp(TempDir),
     Regular(PathBuf),
+    /// Extraction directory that is not deleted at the end (see `TempDirOptions`)
+    Kept(PathBuf),
+}
+
+/// Copies the `include_files` (that were not already moved along with a directory selected by the layout).
+fn copy_included_files(included: &[(PathBuf, PathBuf)]) -> Result<()> {
+    for (source, dest) in included {
+        if !source.exists() {
+            continue;
+        }
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        if let Err(error) = std::fs::copy(source, dest) {
+            bail!("Could not copy {:?} to {:?}\n -> {:?}", source, dest, error);
+        }
+    }
+    Ok(())
+}
+
+/// How the content of a mod is obtained from its "archive".
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExtractionMethod {
+    /// The source is a directory, copied to a temporary directory first when a `precopy` command would modify it
+    Directory { temp_copy: bool },
+    Zip,
+    Tar(TarCodec),
+    SevenZip,
+    Rar,
+    /// With the extractor of the location, or the one configured for the extension
+    External(ExtractorCommand),
 }
 
-impl ExtractLocation {
-    pub fn as_path_buf(&self) -> PathBuf {
+/// Compression of a tarball, all are extracted natively.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TarCodec {
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl TarCodec {
+    /// From the last extension of a `tar.<codec>` archive.
+    fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "gz" => Some(TarCodec::Gzip),
+            "zst" => Some(TarCodec::Zstd),
+            "bz2" => Some(TarCodec::Bzip2),
+            _ => None,
+        }
+    }
+
+    /// From the single extension forms (`tgz`...).
+    fn from_short_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "tgz" => Some(TarCodec::Gzip),
+            "tzst" => Some(TarCodec::Zstd),
+            "tbz2" | "tbz" => Some(TarCodec::Bzip2),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
         match self {
-            ExtractLocation::Temp(temp_dir) => temp_dir.path().to_owned(),
-            ExtractLocation::Regular(path_buf) => path_buf.to_owned()
+            TarCodec::Gzip => "tar.gz",
+            TarCodec::Zstd => "tar.zst",
+            TarCodec::Bzip2 => "tar.bz2",
         }
     }
 }
+
+/// Compressed files (`.gz`, `.zst`, `.bz2`) are only supported when they contain a tarball (`foo.tar.gz`...).
+fn compressed_tar_codec(archive: &Path, name: &Path, extension: &str) -> Result<TarCodec> {
+    let stem = match name.file_stem() {
+        Some(stem) => PathBuf::from(stem),
+        None => bail!("unsupported .{} file for archive {:?}", extension, archive),
+    };
+    match (stem.extension().and_then(|sub_ext| sub_ext.to_str()), TarCodec::from_extension(extension)) {
+        (Some("tar"), Some(codec)) => Ok(codec),
+        _ =>  bail!("unsupported .{} file for archive {:?}", extension, archive),
+    }
+}
+
+/// Directory where the `precopy` command runs, `subdir` can't lead out of the extracted content.
+fn precopy_work_dir(from: &Path, subdir: &Option<String>) -> Result<PathBuf> {
+    let subdir = match subdir {
+        None => return Ok(from.to_path_buf()),
+        Some(subdir) => subdir,
+    };
+    let inside = Path::new(subdir).components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if !inside {
+        bail!("precopy `subdir` {:?} must be a relative path inside the mod content (without `..`)", subdir);
+    }
+    Ok(from.join(subdir))
+}
+
+/// Captured stdout and stderr of a command (the empty ones are left out), to be appended to a message.
+fn format_command_output(stdout: &[u8], stderr: &[u8]) -> String {
+    [("stdout", stdout), ("stderr", stderr)].iter()
+        .map(|(name, content)| (name, String::from_utf8_lossy(content)))
+        .filter(|(_, content)| !content.trim().is_empty())
+        .map(|(name, content)| format!("\n{}:\n{}", name, content.trim_end()))
+        .collect()
+}
+
+/// Lists (sorted) the top-level entries of a directory, at most `max`, directories end with a `/`.
+fn list_entries(dir: &Path, max: usize) -> Result<String> {
+    let read_dir = match std::fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(error) => bail!("Could not list directory {:?}\n -> {:?}", dir, error),
+    };
+    let mut entries = vec![];
+    for entry in read_dir {
+        let entry = entry?;
+        let suffix = if entry.file_type()?.is_dir() { "/" } else { "" };
+        entries.push(format!("{}{}", entry.file_name().to_string_lossy(), suffix));
+    }
+    entries.sort();
+    let mut listed = entries.iter().take(max).map(|entry| format!("  {}", entry)).collect::<Vec<_>>();
+    if entries.len() > max {
+        listed.push(format!("  ... and {} more", entries.len() - max));
+    }
+    if listed.is_empty() {
+        listed.push("  (empty)".to_string());
+    }
+    Ok(listed.join("\n"))
+}
+
+fn kept_marker(kept_dir: &Path) -> PathBuf {
+    // not `with_extension`, mod names can contain dots
+    let mut name = kept_dir.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(KEPT_MARKER_EXTENSION);
+    kept_dir.with_file_name(name)
+}
+
+/// Identifies an archive by path, size and modification time.
+fn archive_fingerprint(archive: &Path) -> Result<String> {
+    let metadata = match std::fs::metadata(archive) {
+        Err(error) => bail!("Could not read archive metadata {:?}\n -> {:?}", archive, error),
+        Ok(metadata) => metadata,
+    };
+    let mtime = filetime::FileTime::from_last_modification_time(&metadata);
+    Ok(format!("{}\n{}\n{}.{}\n", archive.display(), metadata.len(), mtime.unix_seconds(), mtime.nanoseconds()))
+}
+
+#[cfg(test)]
+mod test_kept_extraction {
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+
+    use zip::write::FileOptions;
+
+    use crate::canon_path::CanonPath;
+    use crate::config::Config;
+    use crate::lowercase::lwc;
+    use crate::module::location::location::ConcreteLocation;
+    use crate::module::pre_copy_command::PrecopyCommand;
+
+    use super::{kept_marker, Extractor, TempDirOptions};
+
+    fn write_zip(path: &Path, tp2_content: &str) {
+        let file = std::fs::File::create(path).unwrap();
+        let mut zip = zip::ZipWriter::new(file);
+        zip.start_file("my_mod/my_mod.tp2", FileOptions::default()).unwrap();
+        zip.write_all(tp2_content.as_bytes()).unwrap();
+        zip.finish().unwrap();
+    }
+
+    fn extract(config: &Config, archive: &Path, temp_options: TempDirOptions) -> (tempfile::TempDir, PathBuf) {
+        let game_dir = tempfile::tempdir().unwrap();
+        let canon_game_dir = CanonPath::new(game_dir.path()).unwrap();
+        let extractor = Extractor::new(&canon_game_dir, config, temp_options);
+        extractor.extract_files(archive, &lwc!("my_mod"), &ConcreteLocation::default()).unwrap();
+        let tp2 = game_dir.path().join("my_mod").join("my_mod.tp2");
+        (game_dir, tp2)
+    }
+
+    #[test]
+    fn kept_extraction_is_reused() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let archive = work_dir.path().join("my_mod.zip");
+        write_zip(&archive, "BACKUP ~my_mod/backup~");
+        let config = Config {
+            extract_location: Some(work_dir.path().join("extract").to_string_lossy().to_string()),
+            ..Config::default()
+        };
+        std::fs::create_dir_all(work_dir.path().join("extract")).unwrap();
+
+        let (_game_dir, tp2) = extract(&config, &archive, TempDirOptions { keep: true, reuse: false });
+        assert_eq!(std::fs::read_to_string(tp2).unwrap(), "BACKUP ~my_mod/backup~");
+
+        let kept_dir = work_dir.path().join("extract").join("modda-kept-my_mod");
+        assert!(kept_dir.join("my_mod").join("my_mod.tp2").is_file());
+        assert!(kept_marker(&kept_dir).is_file());
+
+        // altered kept content shows the archive was not extracted again
+        std::fs::write(kept_dir.join("my_mod").join("my_mod.tp2"), "from kept dir").unwrap();
+        let (_game_dir, tp2) = extract(&config, &archive, TempDirOptions { keep: false, reuse: true });
+        assert_eq!(std::fs::read_to_string(tp2).unwrap(), "from kept dir");
+        assert!(kept_dir.join("my_mod").join("my_mod.tp2").is_file());
+    }
+
+    #[test]
+    fn kept_extraction_from_other_archive_is_not_reused() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let archive = work_dir.path().join("my_mod.zip");
+        write_zip(&archive, "first version");
+        let config = Config {
+            extract_location: Some(work_dir.path().join("extract").to_string_lossy().to_string()),
+            ..Config::default()
+        };
+        std::fs::create_dir_all(work_dir.path().join("extract")).unwrap();
+
+        extract(&config, &archive, TempDirOptions { keep: true, reuse: false });
+        write_zip(&archive, "second version, with a different size");
+
+        let (_game_dir, tp2) = extract(&config, &archive, TempDirOptions { keep: true, reuse: true });
+        assert_eq!(std::fs::read_to_string(tp2).unwrap(), "second version, with a different size");
+    }
+
+    #[test]
+    fn extraction_is_not_reused_without_marker() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let archive = work_dir.path().join("my_mod.zip");
+        write_zip(&archive, "from archive");
+        let config = Config {
+            extract_location: Some(work_dir.path().join("extract").to_string_lossy().to_string()),
+            ..Config::default()
+        };
+        // looks like an interrupted extraction
+        let kept_dir = work_dir.path().join("extract").join("modda-kept-my_mod");
+        std::fs::create_dir_all(kept_dir.join("my_mod")).unwrap();
+        std::fs::write(kept_dir.join("my_mod").join("my_mod.tp2"), "incomplete").unwrap();
+
+        let (_game_dir, tp2) = extract(&config, &archive, TempDirOptions { keep: false, reuse: true });
+        assert_eq!(std::fs::read_to_string(tp2).unwrap(), "from archive");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn content_is_kept_when_precopy_fails() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let archive = work_dir.path().join("my_mod.zip");
+        write_zip(&archive, "from archive");
+        let config = Config {
+            extract_location: Some(work_dir.path().join("extract").to_string_lossy().to_string()),
+            ..Config::default()
+        };
+        std::fs::create_dir_all(work_dir.path().join("extract")).unwrap();
+        let location = ConcreteLocation {
+            precopy: Some(PrecopyCommand { command: "false".to_string(), args: None, subdir: None }),
+            ..ConcreteLocation::default()
+        };
+        let game_dir = tempfile::tempdir().unwrap();
+        let canon_game_dir = CanonPath::new(game_dir.path()).unwrap();
+        let extractor = Extractor::new(&canon_game_dir, &config, TempDirOptions { keep: true, reuse: false });
+
+        assert!(extractor.extract_files(&archive, &lwc!("my_mod"), &location).is_err());
+
+        let kept_dir = work_dir.path().join("extract").join("modda-kept-my_mod");
+        assert_eq!(std::fs::read_to_string(kept_dir.join("my_mod").join("my_mod.tp2")).unwrap(), "from archive");
+        // the content may be incomplete, it must not be reused
+        assert!(!kept_marker(&kept_dir).exists());
+    }
+
+    #[test]
+    fn marker_name_keeps_dots_in_mod_name() {
+        assert_eq!(kept_marker(Path::new("/tmp/modda-kept-my.mod")), PathBuf::from("/tmp/modda-kept-my.mod.extracted"));
+    }
+}
+
+#[cfg(test)]
+mod test_include_files {
+    use std::path::PathBuf;
+
+    use crate::archive_layout::Layout;
+    use crate::canon_path::CanonPath;
+    use crate::config::Config;
+    use crate::lowercase::lwc;
+    use crate::module::location::location::ConcreteLocation;
+
+    use super::{list_entries, Extractor, TempDirOptions};
+
+    fn fixture() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/archive_layout/include_files.zip")
+    }
+
+    fn location(include_files: &[&str]) -> ConcreteLocation {
+        ConcreteLocation {
+            layout: Layout {
+                include_files: include_files.iter().map(|file| file.to_string()).collect(),
+                ..Layout::single_dir(1)
+            },
+            ..ConcreteLocation::default()
+        }
+    }
+
+    #[test]
+    fn unmatched_layout_lists_extracted_content() {
+        let game_dir = tempfile::tempdir().unwrap();
+        let canon_game_dir = CanonPath::new(game_dir.path()).unwrap();
+        let config = Config::default();
+        let extractor = Extractor::new(&canon_game_dir, &config, TempDirOptions::default());
+
+        // the mod directory is under `my_mod-v1`, the default strip level doesn't find it
+        let location = ConcreteLocation { layout: Layout::single_dir(0), ..ConcreteLocation::default() };
+        let error = extractor.extract_files(&fixture(), &lwc!("my_mod"), &location).unwrap_err();
+
+        let message = format!("{:?}", error);
+        assert!(message.contains(r#"No file of module my_mod matched the layout patterns ["my_mod"] (at depth 0)"#),
+                "{}", message);
+        assert!(message.contains("\n  my_mod-v1/"), "{}", message);
+    }
+
+    #[test]
+    fn list_entries_is_limited() {
+        let dir = tempfile::tempdir().unwrap();
+        for index in 0..4 {
+            std::fs::write(dir.path().join(format!("file{}.txt", index)), "").unwrap();
+        }
+        std::fs::create_dir(dir.path().join("a_dir")).unwrap();
+        assert_eq!(list_entries(dir.path(), 3).unwrap(), "  a_dir/\n  file0.txt\n  file1.txt\n  ... and 2 more");
+        assert_eq!(list_entries(&dir.path().join("a_dir"), 3).unwrap(), "  (empty)");
+    }
+
+    #[test]
+    fn explicit_files_are_copied() {
+        let game_dir = tempfile::tempdir().unwrap();
+        let canon_game_dir = CanonPath::new(game_dir.path()).unwrap();
+        let config = Config::default();
+        let extractor = Extractor::new(&canon_game_dir, &config, TempDirOptions::default());
+
+        let location = location(&["extras/readme.txt", "tools/config.ini", "my_mod/lib/a.tpa"]);
+        extractor.extract_files(&fixture(), &lwc!("my_mod"), &location).unwrap();
+
+        assert!(game_dir.path().join("my_mod/setup-my_mod.tp2").is_file());
+        assert!(game_dir.path().join("my_mod/lib/a.tpa").is_file());
+        assert_eq!(std::fs::read_to_string(game_dir.path().join("extras/readme.txt")).unwrap(), "read me\n");
+        assert!(game_dir.path().join("tools/config.ini").is_file());
+        assert!(!game_dir.path().join("extras/unused.txt").exists());
+    }
+
+    #[test]
+    fn missing_explicit_file_fails() {
+        let game_dir = tempfile::tempdir().unwrap();
+        let canon_game_dir = CanonPath::new(game_dir.path()).unwrap();
+        let config = Config::default();
+        let extractor = Extractor::new(&canon_game_dir, &config, TempDirOptions::default());
+
+        let location = location(&["extras/readme.txt", "extras/missing.txt"]);
+        let error = extractor.extract_files(&fixture(), &lwc!("my_mod"), &location).unwrap_err();
+
+        assert!(format!("{:?}", error).contains("extras/missing.txt"));
+        assert!(!game_dir.path().join("extras").exists());
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test_precopy {
+    use std::io::Write;
+    use std::path::Path;
+
+    use zip::write::FileOptions;
+
+    use crate::canon_path::CanonPath;
+    use crate::config::Config;
+    use crate::lowercase::lwc;
+    use crate::module::location::location::ConcreteLocation;
+    use crate::module::pre_copy_command::PrecopyCommand;
+
+    use super::{precopy_work_dir, Extractor, TempDirOptions};
+
+    fn shell(script: &str, subdir: Option<&str>) -> ConcreteLocation {
+        ConcreteLocation {
+            precopy: Some(PrecopyCommand {
+                command: "sh".to_string(),
+                args: Some(vec!["-c".to_string(), script.to_string()]),
+                subdir: subdir.map(|subdir| subdir.to_string()),
+            }),
+            ..ConcreteLocation::default()
+        }
+    }
+
+    fn install(source: &Path, location: &ConcreteLocation) -> tempfile::TempDir {
+        let game_dir = tempfile::tempdir().unwrap();
+        let canon_game_dir = CanonPath::new(game_dir.path()).unwrap();
+        let config = Config::default();
+        let extractor = Extractor::new(&canon_game_dir, &config, TempDirOptions::default());
+        extractor.extract_files(source, &lwc!("my_mod"), location).unwrap();
+        game_dir
+    }
+
+    #[test]
+    fn precopy_modifies_extracted_zip_content() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let archive = work_dir.path().join("my_mod.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&archive).unwrap());
+        zip.start_file("my_mod/my_mod.tp2", FileOptions::default()).unwrap();
+        zip.write_all(b"original").unwrap();
+        zip.finish().unwrap();
+
+        let game_dir = install(&archive, &shell("printf patched > my_mod/my_mod.tp2", None));
+
+        assert_eq!(std::fs::read_to_string(game_dir.path().join("my_mod/my_mod.tp2")).unwrap(), "patched");
+    }
+
+    #[test]
+    fn precopy_runs_in_subdir() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let archive = work_dir.path().join("my_mod.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&archive).unwrap());
+        zip.start_file("my_mod/my_mod.tp2", FileOptions::default()).unwrap();
+        zip.write_all(b"original").unwrap();
+        zip.finish().unwrap();
+
+        let game_dir = install(&archive, &shell("printf patched > my_mod.tp2", Some("my_mod")));
+
+        assert_eq!(std::fs::read_to_string(game_dir.path().join("my_mod/my_mod.tp2")).unwrap(), "patched");
+    }
+
+    #[test]
+    fn precopy_doesnt_modify_directory_source() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::create_dir(source.path().join("my_mod")).unwrap();
+        std::fs::write(source.path().join("my_mod/my_mod.tp2"), "original").unwrap();
+
+        let game_dir = install(source.path(), &shell("printf patched > my_mod/my_mod.tp2", None));
+
+        assert_eq!(std::fs::read_to_string(game_dir.path().join("my_mod/my_mod.tp2")).unwrap(), "patched");
+        assert_eq!(std::fs::read_to_string(source.path().join("my_mod/my_mod.tp2")).unwrap(), "original");
+    }
+
+    #[test]
+    fn precopy_subdir_stays_inside_content() {
+        let from = Path::new("/tmp/extracted");
+        assert_eq!(precopy_work_dir(from, &None).unwrap(), from);
+        assert_eq!(precopy_work_dir(from, &Some("my_mod/./lib".to_string())).unwrap(), from.join("my_mod/./lib"));
+        assert!(precopy_work_dir(from, &Some("my_mod/../..".to_string())).is_err());
+        assert!(precopy_work_dir(from, &Some("/usr/bin".to_string())).is_err());
+    }
+
+    #[test]
+    fn failing_precopy_output_is_reported() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::create_dir(source.path().join("my_mod")).unwrap();
+        std::fs::write(source.path().join("my_mod/my_mod.tp2"), "original").unwrap();
+        let game_dir = tempfile::tempdir().unwrap();
+        let canon_game_dir = CanonPath::new(game_dir.path()).unwrap();
+        let config = Config::default();
+        let extractor = Extractor::new(&canon_game_dir, &config, TempDirOptions::default());
+
+        let location = shell("echo converting; echo missing tool >&2; exit 2", None);
+        let error = extractor.extract_files(source.path(), &lwc!("my_mod"), &location).unwrap_err();
+
+        let message = format!("{:?}", error);
+        assert!(message.contains("Some(2)\nstdout:\nconverting\nstderr:\nmissing tool"), "{}", message);
+    }
+
+    #[test]
+    fn failing_precopy_stops_installation() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::create_dir(source.path().join("my_mod")).unwrap();
+        std::fs::write(source.path().join("my_mod/my_mod.tp2"), "original").unwrap();
+        let game_dir = tempfile::tempdir().unwrap();
+        let canon_game_dir = CanonPath::new(game_dir.path()).unwrap();
+        let config = Config::default();
+        let extractor = Extractor::new(&canon_game_dir, &config, TempDirOptions::default());
+
+        let result = extractor.extract_files(source.path(), &lwc!("my_mod"), &shell("exit 1", None));
+
+        assert!(result.is_err());
+        assert!(!game_dir.path().join("my_mod").exists());
+    }
+
+    #[test]
+    fn precopy_output_is_returned_and_stdin_is_closed() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::create_dir(source.path().join("my_mod")).unwrap();
+        std::fs::write(source.path().join("my_mod/my_mod.tp2"), "original").unwrap();
+        let game_dir = tempfile::tempdir().unwrap();
+        let canon_game_dir = CanonPath::new(game_dir.path()).unwrap();
+        let config = Config::default();
+        let extractor = Extractor::new(&canon_game_dir, &config, TempDirOptions::default());
+
+        // `read` fails at once on a closed stdin, instead of waiting for an answer
+        let location = shell("echo converted; read answer || echo no answer", None);
+        let output = extractor.extract_files(source.path(), &lwc!("my_mod"), &location).unwrap();
+
+        assert_eq!(output.as_deref(), Some("\nstdout:\nconverted\nno answer"));
+    }
+}
+
+#[cfg(test)]
+mod test_extractor_command {
+    use std::collections::HashMap;
+
+    use crate::canon_path::CanonPath;
+    use crate::config::{Config, ExtractorCommand};
+    use crate::lowercase::lwc;
+    use crate::module::location::location::ConcreteLocation;
+    use crate::module::location::source::Source;
+
+    use super::{Extractor, TempDirOptions};
+
+    fn command(name: &str) -> ExtractorCommand {
+        ExtractorCommand { command: name.to_string(), args: vec!["${input}".to_string(), "${target}".to_string()] }
+    }
+
+    fn config() -> Config {
+        Config {
+            extractors: HashMap::from([(lwc!("rar"), command("global-unrar"))]),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn location_extractor_wins_over_global() {
+        let game_dir = tempfile::tempdir().unwrap();
+        let canon_game_dir = CanonPath::new(game_dir.path()).unwrap();
+        let config = config();
+        let extractor = Extractor::new(&canon_game_dir, &config, TempDirOptions::default());
+        let location = ConcreteLocation { extractor: Some(command("mod-unrar")), ..ConcreteLocation::default() };
+
+        assert_eq!(extractor.extractor_command("rar", &location).unwrap(), &command("mod-unrar"));
+        // also used for an extension which has no configured extractor
+        assert_eq!(extractor.extractor_command("7z", &location).unwrap(), &command("mod-unrar"));
+    }
+
+    #[test]
+    fn global_extractor_without_location_extractor() {
+        let game_dir = tempfile::tempdir().unwrap();
+        let canon_game_dir = CanonPath::new(game_dir.path()).unwrap();
+        let config = config();
+        let extractor = Extractor::new(&canon_game_dir, &config, TempDirOptions::default());
+        let location = ConcreteLocation::default();
+
+        assert_eq!(extractor.extractor_command("rar", &location).unwrap(), &command("global-unrar"));
+        assert!(extractor.extractor_command("7z", &location).is_err());
+    }
+
+    #[test]
+    fn native_7z_extraction() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(source.path().join("my_mod/tra")).unwrap();
+        std::fs::write(source.path().join("my_mod/my_mod.tp2"), "BACKUP ~weidu_external/backup/my_mod~").unwrap();
+        std::fs::write(source.path().join("my_mod/tra/setup.tra"), "@1 = ~hello~").unwrap();
+        let archive_dir = tempfile::tempdir().unwrap();
+        let archive = archive_dir.path().join("my_mod.7z");
+        sevenz_rust::compress_to_path(source.path(), &archive).unwrap();
+
+        let game_dir = tempfile::tempdir().unwrap();
+        let canon_game_dir = CanonPath::new(game_dir.path()).unwrap();
+        let config = config();
+        let extractor = Extractor::new(&canon_game_dir, &config, TempDirOptions::default());
+        let extracted = extractor.extract_7z(&archive, &lwc!("my_mod")).unwrap();
+
+        assert_eq!(std::fs::read_to_string(extracted.path().join("my_mod/tra/setup.tra")).unwrap(), "@1 = ~hello~");
+        assert!(extracted.path().join("my_mod/my_mod.tp2").is_file());
+    }
+
+    fn write_tar(writer: impl std::io::Write) {
+        let mut tar = tar::Builder::new(writer);
+        let content = b"BACKUP ~weidu_external/backup/my_mod~";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, "my_mod/my_mod.tp2", &content[..]).unwrap();
+        tar.into_inner().unwrap();
+    }
+
+    #[test]
+    fn compressed_tarballs_extraction() {
+        let archive_dir = tempfile::tempdir().unwrap();
+        let tar_zst = archive_dir.path().join("my_mod-1.0.tar.zst");
+        write_tar(zstd::stream::write::Encoder::new(std::fs::File::create(&tar_zst).unwrap(), 0).unwrap().auto_finish());
+        let tzst = archive_dir.path().join("my_mod.tzst");
+        std::fs::copy(&tar_zst, &tzst).unwrap();
+        let tar_gz = archive_dir.path().join("my_mod-1.0.tar.gz");
+        write_tar(flate2::write::GzEncoder::new(std::fs::File::create(&tar_gz).unwrap(), flate2::Compression::default()));
+        let tar_bz2 = archive_dir.path().join("my_mod-1.0.tar.bz2");
+        write_tar(bzip2::write::BzEncoder::new(std::fs::File::create(&tar_bz2).unwrap(), bzip2::Compression::default()));
+        let tbz2 = archive_dir.path().join("my_mod.tbz2");
+        std::fs::copy(&tar_bz2, &tbz2).unwrap();
+
+        for archive in [&tar_zst, &tzst, &tar_gz, &tar_bz2, &tbz2] {
+            let game_dir = tempfile::tempdir().unwrap();
+            let canon_game_dir = CanonPath::new(game_dir.path()).unwrap();
+            let config = config();
+            let extractor = Extractor::new(&canon_game_dir, &config, TempDirOptions::default());
+            extractor.extract_files(archive, &lwc!("my_mod"), &ConcreteLocation::default()).unwrap();
+
+            assert_eq!(std::fs::read_to_string(game_dir.path().join("my_mod/my_mod.tp2")).unwrap(),
+                        "BACKUP ~weidu_external/backup/my_mod~", "{:?}", archive);
+        }
+
+        let zst = archive_dir.path().join("my_mod.zst");
+        std::fs::copy(&tar_zst, &zst).unwrap();
+        let game_dir = tempfile::tempdir().unwrap();
+        let canon_game_dir = CanonPath::new(game_dir.path()).unwrap();
+        let config = config();
+        let extractor = Extractor::new(&canon_game_dir, &config, TempDirOptions::default());
+        let error = extractor.extract_files(&zst, &lwc!("my_mod"), &ConcreteLocation::default()).unwrap_err();
+        assert!(error.to_string().starts_with("unsupported .zst file"), "{}", error);
+    }
+
+    #[test]
+    fn renamed_local_archive_extraction() {
+        let archive_dir = tempfile::tempdir().unwrap();
+        // named after the tag, the `.3` extension doesn't tell the archive type
+        let archive = archive_dir.path().join("v1.3");
+        write_tar(flate2::write::GzEncoder::new(std::fs::File::create(&archive).unwrap(), flate2::Compression::default()));
+        let location = ConcreteLocation {
+            source: Source::Local { local: "v1.3".to_string(), rename: Some("my_mod-v1.3.tar.gz".to_string()) },
+            ..ConcreteLocation::default()
+        };
+
+        let game_dir = tempfile::tempdir().unwrap();
+        let canon_game_dir = CanonPath::new(game_dir.path()).unwrap();
+        let config = config();
+        let extractor = Extractor::new(&canon_game_dir, &config, TempDirOptions::default());
+        extractor.extract_files(&archive, &lwc!("my_mod"), &location).unwrap();
+
+        assert_eq!(std::fs::read_to_string(game_dir.path().join("my_mod/my_mod.tp2")).unwrap(),
+                    "BACKUP ~weidu_external/backup/my_mod~");
+    }
+
+    #[test]
+    fn configured_7z_extractor_overrides_native() {
+        let game_dir = tempfile::tempdir().unwrap();
+        let canon_game_dir = CanonPath::new(game_dir.path()).unwrap();
+        let config = Config {
+            extractors: HashMap::from([(lwc!("7z"), command("7za"))]),
+            ..Config::default()
+        };
+        let extractor = Extractor::new(&canon_game_dir, &config, TempDirOptions::default());
+        assert!(extractor.has_extractor("7z", &ConcreteLocation::default()));
+
+        let config = self::config();
+        let extractor = Extractor::new(&canon_game_dir, &config, TempDirOptions::default());
+        assert!(!extractor.has_extractor("7z", &ConcreteLocation::default()));
+        let location = ConcreteLocation { extractor: Some(command("mod-7z")), ..ConcreteLocation::default() };
+        assert!(extractor.has_extractor("7z", &location));
+    }
+}
+
+#[cfg(all(test, feature = "rar"))]
+mod test_rar_extraction {
+    use std::path::PathBuf;
+
+    use crate::canon_path::CanonPath;
+    use crate::config::Config;
+    use crate::lowercase::lwc;
+    use crate::module::location::location::ConcreteLocation;
+
+    use super::{Extractor, TempDirOptions};
+
+    #[test]
+    fn rar_archive_is_extracted_natively() {
+        let archive = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/rar/my_mod.rar");
+        let game_dir = tempfile::tempdir().unwrap();
+        let canon_game_dir = CanonPath::new(game_dir.path()).unwrap();
+        let config = Config::default();
+        let extractor = Extractor::new(&canon_game_dir, &config, TempDirOptions::default());
+
+        extractor.extract_files(&archive, &lwc!("my_mod"), &ConcreteLocation::default()).unwrap();
+
+        let tp2 = std::fs::read_to_string(game_dir.path().join("my_mod").join("my_mod.tp2")).unwrap();
+        assert_eq!(tp2, "BACKUP ~my_mod/backup~\n");
+    }
+}
@@ -1,4 +1,8 @@
 
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use globwalk::{FileType, GlobWalkerBuilder};
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 
@@ -10,8 +14,17 @@ use crate::module::location::source::Source;
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
 pub struct Layout {
     pub strip_leading: Option<usize>,
+    /// Strips everything up to and including the first directory (the least deep one) whose name matches this
+    /// glob pattern (case-insensitive), for archives whose top directory name is unpredictable
+    /// (`strip_until: "A7-DlcMerger-*"`).<br>
+    /// Can't be used with `strip_leading`.
+    pub strip_until: Option<String>,
     #[serde(default, flatten)]
     pub layout: LayoutContent,
+    /// Files (relative to the archive root, after `strip_leading`) that are copied to the game directory with
+    /// the same relative path, in addition to what `layout_type` selects.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include_files: Vec<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
@@ -75,12 +88,52 @@ impl Layout {
         }
     }
 
+    /// Anchored glob pattern matching each of the `include_files` (below the stripped levels)
+    /// and the path it is copied to, relative to the game directory.
+    pub fn include_patterns(&self, location_source: &Source) -> Result<Vec<(String, String)>> {
+        let strip_level = self.strip_level(location_source);
+        self.include_files.iter().map(|file| {
+            let file = file.replace('\\', "/");
+            let relative = file.trim_start_matches("./");
+            if relative.is_empty() || relative.starts_with('/') || relative.split('/').any(|part| part == "..") {
+                bail!("Invalid `include_files` entry {} (must be a path relative to the archive root)", file);
+            }
+            Ok((format!("/{}{}", "*/".repeat(strip_level), relative), relative.to_owned()))
+        }).collect()
+    }
+
     fn strip_level(&self, source: &Source) -> usize {
         match self {
+            // the leading directories are stripped by `content_root`
+            Layout { strip_until: Some(_), .. } => 0,
             Layout { strip_leading: None, .. } => source.default_strip_leading(),
             Layout { strip_leading: Some(v), .. } => *v,
         }
     }
+
+    /// Directory of the extracted content (`base`) the glob patterns apply to: `base` itself, or with `strip_until`
+    /// the first matching directory (least deep, then in name order).
+    pub fn content_root(&self, base: &Path) -> Result<PathBuf> {
+        let pattern = match &self.strip_until {
+            None => return Ok(base.to_path_buf()),
+            Some(_) if self.strip_leading.is_some() => bail!("`strip_leading` and `strip_until` can't be used together"),
+            Some(pattern) => pattern,
+        };
+        let walker = match GlobWalkerBuilder::from_patterns(base, &[pattern])
+                .case_insensitive(true)
+                .file_type(FileType::DIR)
+                .build() {
+            Err(error) => bail!("Could not evaluate `strip_until` pattern {}\n -> {:?}", pattern, error),
+            Ok(walker) => walker,
+        };
+        let found = walker.into_iter()
+            .filter_map(Result::ok)
+            .min_by(|left, right| left.depth().cmp(&right.depth()).then_with(|| left.path().cmp(right.path())));
+        match found {
+            None => bail!("No directory matching `strip_until` pattern {} in the archive content", pattern),
+            Some(entry) => Ok(entry.into_path()),
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -102,6 +155,7 @@ impl Layout {
         Layout {
             layout: LayoutContent::SingleDir,
             strip_leading: Some(strip_lvl),
+            ..Self::default()
         }
     }
     pub fn with_tp2(tp2: String) -> Self {
@@ -120,12 +174,14 @@ impl Layout {
         Layout {
             layout: LayoutContent::with_tp2(tp2),
             strip_leading: Some(strip_lvl),
+            ..Self::default()
         }
     }
     pub fn with_tp2_default_and_strip(strip_lvl: usize) -> Self {
         Layout {
             layout: LayoutContent::with_tp2_default(),
             strip_leading: Some(strip_lvl),
+            ..Self::default()
         }
     }
     pub fn multi_dir(dirs: Vec<String>) -> Self {
@@ -138,6 +194,7 @@ impl Layout {
         Layout {
             layout: LayoutContent::multi_dir(dirs),
             strip_leading: Some(strip_lvl),
+            ..Self::default()
         }
     }
 }
@@ -214,6 +271,29 @@ fn test_to_glob() {
     );
 }
 
+#[test]
+fn strip_until_directory_name() {
+    use crate::lowercase::lwc;
+
+    let base = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(base.path().join("A7-DlcMerger-1.3-abc123/DlcMerger")).unwrap();
+    std::fs::create_dir_all(base.path().join("A7-DlcMerger-1.3-abc123/nested/a7-dlcmerger-old")).unwrap();
+    std::fs::write(base.path().join("a7-dlcmerger-notes.txt"), "").unwrap();
+    let layout: Layout = serde_yaml::from_str(r#"
+    strip_until: "A7-DlcMerger-*"
+    layout_type: single_dir
+    "#).unwrap();
+
+    assert_eq!(layout.content_root(base.path()).unwrap(), base.path().join("A7-DlcMerger-1.3-abc123"));
+    assert_eq!(layout.to_glob(&lwc!("DlcMerger"), &Source::gh_branch_source()), GlobDesc::single("dlcmerger", 0));
+    assert_eq!(Layout::default().content_root(base.path()).unwrap(), base.path());
+
+    let missing = Layout { strip_until: Some("other-*".to_string()), ..Layout::default() };
+    assert!(missing.content_root(base.path()).is_err());
+    let both = Layout { strip_until: Some("A7-*".to_string()), ..Layout::single_dir(1) };
+    assert!(both.content_root(base.path()).is_err());
+}
+
 #[test]
 fn deserialize_layout_single_dir_missing_strip() {
     let yaml = r#"
@@ -280,3 +360,44 @@ fn deserialize_layout_multi_dir() {
         Layout::multi_dir(vec!["a".to_string(), "b".to_string()])
     );
 }
+
+#[test]
+fn include_patterns_below_stripped_levels() {
+    let layout = Layout {
+        strip_leading: Some(1),
+        include_files: vec!["extras/readme.txt".to_string(), "./my_mod\\lib\\a.tpa".to_string()],
+        ..Layout::default()
+    };
+    assert_eq!(
+        layout.include_patterns(&Source::http_source()).unwrap(),
+        vec![
+            ("/*/extras/readme.txt".to_string(), "extras/readme.txt".to_string()),
+            ("/*/my_mod/lib/a.tpa".to_string(), "my_mod/lib/a.tpa".to_string()),
+        ]
+    );
+    let layout = Layout { strip_leading: Some(0), ..layout };
+    assert_eq!(layout.include_patterns(&Source::http_source()).unwrap()[0].0, "/extras/readme.txt");
+}
+
+#[test]
+fn include_patterns_stay_in_archive() {
+    for file in ["../outside.txt", "/etc/passwd", "my_mod/../../outside.txt", ""] {
+        let layout = Layout { include_files: vec![file.to_string()], ..Layout::default() };
+        assert!(layout.include_patterns(&Source::http_source()).is_err(), "{} should be rejected", file);
+    }
+}
+
+#[test]
+fn deserialize_layout_with_include_files() {
+    let yaml = r#"
+    strip_leading: 1
+    layout_type: single_dir
+    include_files:
+        - extras/readme.txt
+    "#;
+    let layout: Layout = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(
+        layout,
+        Layout { include_files: vec!["extras/readme.txt".to_string()], ..Layout::single_dir(1) }
+    );
+}
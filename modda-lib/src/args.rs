@@ -1,9 +1,10 @@
 
 use std::path::PathBuf;
 
-use clap_derive::{Parser, Subcommand, Args};
+use clap_derive::{Parser, Subcommand, Args, ValueEnum};
 
 use crate::canon_path::CanonPath;
+use crate::list_components::{ComponentGrouping, ComponentListFormat, ComponentSort};
 use crate::lowercase::LwcString;
 use crate::obtain::get_options::StrictReplaceAction;
 use crate::progname::PROGNAME;
@@ -15,6 +16,15 @@ use crate::progname::PROGNAME;
 #[command(about = "Weidu install automation", long_about = None)]
 pub struct Cli {
 
+    /// Don't enter the `game` sub-directory when chitin.key is there instead of in the current directory.
+    #[arg(long, global = true)]
+    pub no_auto_game_subdir: bool,
+
+    /// Location of the archive cache for this run (overrides `archive_cache` in the configuration).<br>
+    /// Expanded like the configuration value (environment variables and `~`).
+    #[arg(long, global = true)]
+    pub cache_dir: Option<String>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -22,8 +32,9 @@ pub struct Cli {
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     /// install mods.
-    Install(Install),
-    /// Search all module declarations in the manifest with the given name.
+    Install(Box<Install>),
+    /// Search the modules of the manifest and the location keys (including external registries) matching a name
+    /// (fuzzy matching), and shows where they would be obtained from.
     Search(Search),
     /// List the available components of a weidu mod (by index).
     ListComponents(ListComponents),
@@ -34,16 +45,29 @@ pub enum Commands {
     /// Append all components of a mod to a manifest. This can result in an uninstallable mod (incompatible components, GROUPs etc.)
     /// so it should probably manually edited.
     AppendMod(AppendMod),
+    /// Writes the manifest in another format (for example a `weidu.log`, for tools that read one).
+    Export(Export),
     /// Tries to uninstall modules that are after a given index in the manifest (EXPERIMENTAL).
     Reset(Reset),
     /// Discovers mods in the game directory and builds a manifest skeleton.
     Discover(Discover),
     /// Show configuration/settings information.
     Introspect(Introspect),
+    /// Checks that the sources of the mods in the manifest can still be obtained (nothing is installed).
+    Audit(Audit),
+    /// Checks the manifest (and everything it references locally) without installing or downloading anything.
+    #[command(visible_alias = "verify")]
+    Check(Check),
+    /// Shows the program version (with `--full`, also weidu, game and manifest format information).
+    Version(Version),
 
     /// Works with the global configuration
     #[clap(subcommand)]
-    GlobalConfig(ConfigArgs)
+    GlobalConfig(ConfigArgs),
+
+    /// Works with the archive cache
+    #[clap(subcommand)]
+    Cache(CacheArgs),
 }
 
 impl Commands {
@@ -55,28 +79,46 @@ impl Commands {
             Commands::Invalidate(..) => false,
             Commands::Reverse(..) => true,
             Commands::AppendMod(..) => true,
+            Commands::Export(..) => false,
             Commands::Reset(..) => true,
             Commands::Discover(..) => true,
             Commands::Introspect(..) => true,
+            Commands::Audit(..) => false,
+            Commands::Check(..) => false,
+            Commands::Version(..) => false,
             Commands::GlobalConfig(variant) => match variant {
                 ConfigArgs::Edit(..) => false,
                 ConfigArgs::Show(..) => false,
             }
+            Commands::Cache(variant) => match variant {
+                CacheArgs::Prune(..) => false,
+            }
         }
     }
 }
 
-#[derive(Args, Debug, Default)]
+#[derive(Args, Debug, Default, Clone)]
 pub struct Install {
 
     /// Path of the YAML manifest file.
-    #[arg(long, short)]
+    #[arg(long, short, default_value = "", required_unless_present = "from_manifest_url")]
     pub manifest_path: String,
 
+    /// URL of the manifest, instead of a local file (`--manifest-path`).<br>
+    /// The manifest is downloaded to the archive cache, it can't refer to local files
+    /// (`local` sources, `relative` patches, local location registries).
+    #[arg(long, conflicts_with = "manifest_path")]
+    pub from_manifest_url: Option<String>,
+
     /// If set to true, will not stop when weidu returns a warning.
     #[arg(long)]
     pub no_stop_on_warn: bool,
 
+    /// If set, a module that fails (download, extraction or weidu error) doesn't stop the installation: the following
+    /// modules are installed and the failed ones are listed at the end.
+    #[arg(long)]
+    pub continue_on_error: bool,
+
     /// Index in the module list where we start (counting from *one*).
     #[arg(long, short = 'f')]
     pub from_index: Option<usize>,
@@ -97,7 +139,8 @@ pub struct Install {
     #[arg(long, short = 'o')]
     pub output: Option<String>,
 
-    /// If set to true, the mods will be downloaded and copied in the game directory, but not actually installed.
+    /// Prints the installation plan (how each mod would be obtained and modified, what weidu would be given),
+    /// without downloading, extracting or installing anything.
     #[arg(long)]
     pub dry_run: bool,
 
@@ -105,6 +148,27 @@ pub struct Install {
     #[arg(long)]
     pub timeline: bool,
 
+    /// If set, writes the duration information to this file, in the Chrome tracing format
+    /// (`chrome://tracing`, Perfetto).
+    #[arg(long)]
+    pub trace_timeline: Option<String>,
+
+    /// If set, writes what happened to each module to this JSON file. Each module is appended (as one line)
+    /// as soon as it's done; once the installation is over, the file is rewritten as a single JSON document.
+    #[arg(long)]
+    pub summary: Option<String>,
+
+    /// If set, writes a report of the installation to this JSON file once it's over (or interrupted): for each
+    /// processed module, its name, index, status (success, warning_ignored, failed, skipped_disabled,
+    /// skipped_requested) and timeline.
+    #[arg(long)]
+    pub report: Option<String>,
+
+    /// If set, measures the size added to the game directory by each mod (listed at the end of the installation).
+    /// The whole game directory is scanned after each mod.
+    #[arg(long)]
+    pub disk_usage: bool,
+
     /// If set, records the weidu component selection for mod fragments with `components:ask`.
     #[arg(long, short)]
     pub record: Option<String>,
@@ -113,6 +177,11 @@ pub struct Install {
     #[arg(long, requires = "record")]
     pub record_no_confirm: bool,
 
+    /// If set, records the weidu component selection for mod fragments with `components:ask` in the manifest
+    /// file itself (replacing `ask`), so that the next installations are reproducible.
+    #[arg(long, conflicts_with_all = ["record", "from_manifest_url"])]
+    pub save_choices: bool,
+
     /// If set along with `--record`, will not ask for confirmation before recording.
     #[arg(long, requires = "record")]
     pub record_with_comment_as_field: bool,
@@ -120,18 +189,140 @@ pub struct Install {
     /// Decides what to do if a replace action has a `strict` property that is not obeyed.<br>
     #[arg(long, default_value = "ask")]
     pub check_replace: StrictReplaceAction,
+
+    /// If set, only lists the modules disabled by their `disabled_if` condition (with the reason), then exits.
+    #[arg(long)]
+    pub list_disabled: bool,
+
+    /// If set, prints the modules that would be installed (after applying the range, `--phase` and `--reinstall`),
+    /// in order, with their index in the manifest and their components, then exits.
+    #[arg(long)]
+    pub print_order: bool,
+
+    /// If set, shows (as a diff) what the `patch`, `patches` and `replace` properties of this mod would change
+    /// in the mod files, then exits.<br>
+    /// The mod is retrieved (from the cache if possible) and extracted in a temporary directory, nothing is
+    /// written to the game directory.
+    #[arg(long)]
+    pub preview_transforms: Option<LwcString>,
+
+    /// If set, the extracted content of each mod is kept (in the extraction location) after it was copied to the game directory,
+    /// or when the `precopy` command or the copy (for example with a wrong `layout`) failed, so that it can be inspected.
+    #[arg(long)]
+    pub keep_temp: bool,
+
+    /// If set, the extracted content kept by a previous run with `--keep-temp` is reused instead of extracting the archive again.
+    #[arg(long)]
+    pub resume_extract: bool,
+
+    /// If set, the tra files of mods with an `encoding` property are checked before installation,
+    /// with a warning when they don't seem to use this encoding.
+    #[arg(long)]
+    pub check_encoding: bool,
+
+    /// If set, a mod that weidu installs "successfully" without adding any component to weidu.log
+    /// (for example, all components were skipped) stops the installation (otherwise, it's only a warning).<br>
+    /// Not checked with `--reinstall` (the components are already in weidu.log).
+    #[arg(long)]
+    pub strict_effect: bool,
+
+    /// If set, proceeds even when weidu.log contains components that are not in the manifest
+    /// (for example installed by another tool before modda).
+    #[arg(long)]
+    pub ignore_foreign: bool,
+
+    /// If set, proceeds even when the game detected in the game directory is not the one the manifest
+    /// is meant for (`global.game`).
+    #[arg(long)]
+    pub ignore_game_check: bool,
+
+    /// If set, the weidu debug log of each mod (`setup-<mod>.debug` or `weidu_debug_log`) is moved from the game directory
+    /// to this directory after the mod is installed.
+    #[arg(long)]
+    pub output_dir: Option<String>,
+
+    /// For mods with `components: ask`, the components are picked in a list (instead of answering weidu's
+    /// questions), then installed without further questions.
+    #[arg(long)]
+    pub interactive_select: bool,
+
+    /// Asks for confirmation before installing more than this number of components of a single mod
+    /// (guards against an unintended `components: all`). Overrides the `components_max` configuration.
+    #[arg(long)]
+    pub components_max: Option<usize>,
+
+    /// Answers yes to confirmations that guard against mistakes (like `--components-max`).
+    #[arg(long)]
+    pub assume_yes: bool,
+
+    /// Never downloads anything: all archives must already be in the cache (or available locally for `local`
+    /// and `path` locations), the installation fails with the list of missing archives otherwise.
+    #[arg(long)]
+    pub no_download: bool,
+
+    /// Downloads the archives of all selected mods (at most N at the same time) before installing anything.<br>
+    /// A failed download is only reported, it will be tried again when the mod is installed.
+    #[arg(long, value_name = "N", conflicts_with = "no_download")]
+    pub download_concurrency: Option<usize>,
+
+    /// Doesn't check downloaded archives against the `sha256` and `size` of their location.
+    #[arg(long)]
+    pub no_verify: bool,
+
+    /// Doesn't warn about mods obtained from a github branch (whose content changes over time).
+    #[arg(long)]
+    pub allow_branches: bool,
+
+    /// Installs only the mods with this `phase` (in the manifest order), inside the selected range if any.
+    #[arg(long, conflicts_with = "reinstall")]
+    pub phase: Option<String>,
+
+    /// Installs only the given mod(s) (all their fragments, in the manifest order), inside the selected range if any.<br>
+    /// Disabled fragments are still skipped.
+    #[arg(long, value_delimiter = ',', conflicts_with = "reinstall")]
+    pub only: Vec<LwcString>,
+
+    /// Skips the given mod(s) (all their fragments), in addition to those in the `skip` list of the manifest `global`.<br>
+    /// They keep their index in the manifest, so that `--from-index` and `--resume` are not affected.
+    #[arg(long, value_delimiter = ',')]
+    pub skip: Vec<LwcString>,
+
+    /// Reinstalls the given mod(s) in place: installed components that are not in the manifest are uninstalled,
+    /// the others are reinstalled by weidu (which also reinstalls the components installed after them).<br>
+    /// Only the fragments of these mods are processed.
+    #[arg(long, value_delimiter = ',', conflicts_with_all = ["from_index", "to_index", "just_one", "count"])]
+    pub reinstall: Vec<LwcString>,
+
+    /// Resumes an installation: weidu.log is compared with the manifest and the installation starts at the first
+    /// module that is not installed.<br>
+    /// If weidu.log diverges from the manifest, the components installed after the divergence are uninstalled first.
+    #[arg(long, conflicts_with_all = ["from_index", "reinstall", "phase"])]
+    pub resume: bool,
 }
 
 impl Install {
+    /// Root of the relative paths in the manifest (the directory containing the manifest file).
     pub fn get_manifest_root(&self, game_dir: &CanonPath) -> CanonPath {
-        let manifest = PathBuf::from(&self.manifest_path);
-        match manifest.parent() {
-            None => game_dir.to_owned(),
-            Some(path) => CanonPath::new(path).unwrap_or_else(|_| game_dir.to_owned()),
-        }
+        manifest_root(&self.manifest_path, game_dir)
+    }
+}
+
+/// Directory containing the manifest file (the game directory if it can't be determined).
+pub fn manifest_root(manifest_path: &str, game_dir: &CanonPath) -> CanonPath {
+    let manifest = PathBuf::from(manifest_path);
+    match manifest.parent() {
+        None => game_dir.to_owned(),
+        Some(path) => CanonPath::new(path).unwrap_or_else(|_| game_dir.to_owned()),
     }
 }
 
+#[derive(Args, Debug)]
+pub struct Version {
+    /// Also reports the weidu executable and version, the detected game and the supported manifest format.
+    #[arg(long)]
+    pub full: bool,
+}
+
 #[derive(Args, Debug)]
 pub struct Search {
 
@@ -139,7 +330,7 @@ pub struct Search {
     #[arg(long, short)]
     pub manifest_path: String,
 
-    /// Name of the module we want to find.
+    /// Name (or part of the name) of the module or location key we want to find.
     #[arg(long, short)]
     pub name: String,
 }
@@ -152,6 +343,26 @@ pub struct ListComponents {
     /// Language we want the component names to appear in.
     #[arg(long, short)]
     pub lang: u32,
+
+    /// Sorts the components (by default, they are listed in the weidu order).
+    #[arg(long)]
+    pub sort: Option<ComponentSort>,
+
+    /// Groups the components by weidu `GROUP` or by `SUBCOMPONENT` (text format only).
+    #[arg(long)]
+    pub group_by: Option<ComponentGrouping>,
+
+    /// Output format, `json` and `csv` include the index, number, name, subgroup and group of the components.
+    #[arg(long, value_enum, default_value_t = ComponentListFormat::Text)]
+    pub format: ComponentListFormat,
+
+    /// Prints a YAML `components` property (with component names as comments) to paste in a manifest.
+    #[arg(long, conflicts_with_all = ["sort", "group_by", "format"])]
+    pub components_dump: bool,
+
+    /// With `--components-dump`, only keeps the components whose name matches this (case-insensitive) regex.
+    #[arg(long, requires = "components_dump")]
+    pub filter: Option<String>,
 }
 
 #[derive(Args, Debug)]
@@ -172,10 +383,14 @@ pub struct Reverse {
     #[arg(long, short)]
     pub output: String,
 
-    /// If set, the `language` field in mod definitions will be generated (default: `false`).
+    /// If set, the `language` field in mod definitions will be generated (default: `true`).
     #[arg(long, short = 'l')]
     pub export_language: Option<bool>,
 
+    /// Don't generate the `language` field in mod definitions (same as `--export-language false`).
+    #[arg(long, conflicts_with = "export_language")]
+    pub no_language: bool,
+
     /// If set, the component names will be generated (default: `true`).
     #[arg(long, short = 'c')]
     pub export_component_name: Option<bool>,
@@ -198,6 +413,10 @@ pub struct AppendMod {
     /// If set, the component names will be generated (default: `true`).
     #[arg(long, short = 'c')]
     pub export_component_name: Option<bool>,
+
+    /// If set, the resulting manifest is printed on the standard output instead of being written to the output file.
+    #[arg(long, short)]
+    pub dry_run: bool,
 }
 
 
@@ -213,7 +432,8 @@ pub struct Reset {
     #[arg(long, short)]
     pub to_index: usize,
 
-    /// If set, doesn't actually run the weidu command, only prints what would be executed
+    /// If set, doesn't actually run the weidu command, only prints the mods that would be uninstalled
+    /// and what would be executed
     #[arg(long, short)]
     pub dry_run: bool,
 }
@@ -232,6 +452,48 @@ pub struct Introspect {
     pub show_config: bool,
 }
 
+#[derive(Args, Debug)]
+pub struct Audit {
+
+    /// Path of the YAML manifest file.
+    #[arg(long, short)]
+    pub manifest_path: String,
+
+    /// Doesn't warn about mods obtained from a github branch (whose content changes over time).
+    #[arg(long)]
+    pub allow_branches: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct Export {
+
+    /// Path of the YAML manifest file.
+    #[arg(long, short, visible_short_alias = 'f')]
+    pub manifest_path: String,
+
+    /// Format of the exported file.
+    #[arg(long, value_enum, default_value_t = ExportFormat::WeiduLog)]
+    pub format: ExportFormat,
+
+    /// Name of the file that will be generated (on the standard output if absent).
+    #[arg(long, short)]
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, ValueEnum)]
+pub enum ExportFormat {
+    /// `weidu.log` (`~TP2~ #language #component // name`), with explicit component lists
+    WeiduLog,
+}
+
+#[derive(Args, Debug)]
+pub struct Check {
+
+    /// Path of the YAML manifest file.
+    #[arg(long, short, visible_short_alias = 'f')]
+    pub manifest_path: String,
+}
+
 #[derive(Debug, Subcommand)]
 pub enum ConfigArgs {
     /// Show the global configuration (opens the directory that contains the global configuration file)
@@ -246,3 +508,25 @@ pub struct ConfigShow {}
 
 #[derive(Args, Debug)]
 pub struct ConfigEdit {}
+
+#[derive(Debug, Subcommand)]
+pub enum CacheArgs {
+    /// Removes archives from the cache, least recently modified first, until it is under a size limit
+    /// and/or removes those older than a given age.
+    Prune(CachePrune),
+}
+
+#[derive(Args, Debug)]
+pub struct CachePrune {
+    /// Size the cache must not exceed (`500MB`, `10GB`...).
+    #[arg(long)]
+    pub max_size: Option<String>,
+
+    /// Archives not modified for this long are removed (`90d`, `2weeks`...).
+    #[arg(long)]
+    pub older_than: Option<String>,
+
+    /// Only prints what would be removed.
+    #[arg(long)]
+    pub dry_run: bool,
+}
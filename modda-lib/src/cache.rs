@@ -1,10 +1,43 @@
 
+use std::fs::File;
 use std::path::{PathBuf, Path};
+use std::time::Duration;
 
 use anyhow::{bail, Result};
+use fs2::FileExt;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use crate::config::Config;
 
+/// Extension of the file that records the original name of an archive saved under a hashed name
+pub const NAME_SIDECAR_EXTENSION: &str = "name";
+/// Extension of the file that records the archive downloaded for a release asset pattern
+pub const ASSET_SIDECAR_EXTENSION: &str = "asset";
+/// Extension of the file locked while an archive is downloaded to the cache
+pub const LOCK_EXTENSION: &str = "lock";
+/// Delay between two attempts to take the lock of an archive held by another process
+const LOCK_POLL_DELAY: Duration = Duration::from_millis(500);
+/// Room kept for the suffix of partial downloads (`.<ext>.partial`)
+const PARTIAL_SUFFIX_MARGIN: usize = 16;
+/// Number of hex digits of the SHA-256 digest kept in hashed names
+const HASHED_NAME_LENGTH: usize = 24;
+/// Number of hex digits of the SHA-256 digest appended to names in a flat cache
+const FLAT_SUFFIX_LENGTH: usize = 8;
+
+/// How archives are organized in the cache directory.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheLayout {
+    /// One sub-directory per source type and host/repository (`http/<host>`, `github/<user>/<repository>`)
+    #[default]
+    Partitioned,
+    /// All archives directly in the cache directory, names get a suffix computed from the host/repository
+    /// so that archives with the same name from different places don't collide
+    Flat,
+}
+
 
 
 pub enum Cache {
@@ -38,3 +71,278 @@ impl Cache {
         }
     }
 }
+
+/// Name under which an archive is saved in `dest_dir`.<br>
+/// When the path (plus the partial download suffix) would be longer than `max_length`, the name is replaced
+/// by a digest of the original name (the archive extension is kept, so it can still be extracted).
+pub fn cache_file_name(dest_dir: &Path, save_name: &Path, max_length: Option<usize>) -> PathBuf {
+    match max_length {
+        Some(max_length) if path_too_long(dest_dir, save_name, max_length) => {
+            let hashed = hashed_name(save_name);
+            debug!("cache path for {:?} is too long, using {:?}", save_name, hashed);
+            hashed
+        }
+        _ => save_name.to_path_buf(),
+    }
+}
+
+fn path_too_long(dest_dir: &Path, save_name: &Path, max_length: usize) -> bool {
+    dest_dir.join(save_name).as_os_str().len() + PARTIAL_SUFFIX_MARGIN > max_length
+}
+
+fn hashed_name(save_name: &Path) -> PathBuf {
+    let name = save_name.to_string_lossy();
+    let digest = format!("{:x}", Sha256::digest(name.as_bytes()));
+    let digest = &digest[..HASHED_NAME_LENGTH];
+    match archive_extension(&name) {
+        None => PathBuf::from(digest),
+        Some(extension) => PathBuf::from(format!("{digest}.{extension}")),
+    }
+}
+
+/// Name of an archive in a flat cache: the partition (sub-directory it would be saved in with the
+/// partitioned layout) is replaced by a short digest appended to the name (before the extension).
+pub fn flat_cache_name(partition: &Path, save_name: &Path) -> PathBuf {
+    let name = save_name.to_string_lossy();
+    // components are joined explicitly, so that the digest is the same on all platforms
+    let partitioned = partition.iter().chain(save_name.iter())
+        .map(|component| component.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    let digest = format!("{:x}", Sha256::digest(partitioned.as_bytes()));
+    let digest = &digest[..FLAT_SUFFIX_LENGTH];
+    match archive_extension(&name) {
+        None => PathBuf::from(format!("{name}-{digest}")),
+        Some(extension) => {
+            let stem = &name[..name.len() - extension.len() - 1];
+            PathBuf::from(format!("{stem}-{digest}.{extension}"))
+        }
+    }
+}
+
+/// Last extension, or the last two for compressed tarballs (`tar.gz`...)
+pub(crate) fn archive_extension(name: &str) -> Option<String> {
+    let mut parts = name.rsplit('.');
+    let last = parts.next()?;
+    if last.len() == name.len() {
+        return None;
+    }
+    match parts.next() {
+        Some(previous) if previous.eq_ignore_ascii_case("tar") && !name.starts_with(previous) =>
+            Some(format!("{previous}.{last}")),
+        _ => Some(last.to_owned()),
+    }
+}
+
+fn name_sidecar(archive: &Path) -> PathBuf {
+    with_added_extension(archive, NAME_SIDECAR_EXTENSION)
+}
+
+/// Records the original name next to an archive saved under a hashed name (does nothing otherwise).
+pub fn write_name_sidecar(dest_dir: &Path, file_name: &Path, save_name: &Path) -> Result<()> {
+    if file_name == save_name {
+        return Ok(());
+    }
+    if let Err(error) = std::fs::create_dir_all(dest_dir) {
+        bail!("Could not create cache directory {:?}\n -> {:?}", dest_dir, error);
+    }
+    let sidecar = name_sidecar(&dest_dir.join(file_name));
+    if let Err(error) = std::fs::write(&sidecar, save_name.to_string_lossy().as_bytes()) {
+        bail!("Could not write original archive name to {:?}\n -> {:?}", sidecar, error);
+    }
+    Ok(())
+}
+
+/// Original name of an archive saved under a hashed name, `None` if the name was not hashed.
+pub fn read_name_sidecar(archive: &Path) -> Result<Option<String>> {
+    let sidecar = name_sidecar(archive);
+    if !sidecar.exists() {
+        return Ok(None);
+    }
+    match std::fs::read_to_string(&sidecar) {
+        Err(error) => bail!("Could not read original archive name from {:?}\n -> {:?}", sidecar, error),
+        Ok(name) => Ok(Some(name)),
+    }
+}
+
+/// Removes the original name record of an archive, if there is one.
+pub fn remove_name_sidecar(archive: &Path) -> Result<()> {
+    let sidecar = name_sidecar(archive);
+    if sidecar.exists() {
+        if let Err(error) = std::fs::remove_file(&sidecar) {
+            bail!("Could not remove archive name file {:?}\n -> {:?}", sidecar, error);
+        }
+    }
+    Ok(())
+}
+
+/// Records the archive downloaded for the last asset that matched a release asset pattern, next to the
+/// placeholder name of the pattern, so that it is found without asking github again (`--no-download`).
+pub fn write_asset_sidecar(placeholder: &Path, archive: &Path) -> Result<()> {
+    let sidecar = with_added_extension(placeholder, ASSET_SIDECAR_EXTENSION);
+    let name = archive.file_name().unwrap_or_default().to_string_lossy();
+    if let Err(error) = std::fs::write(&sidecar, name.as_bytes()) {
+        bail!("Could not write downloaded asset name to {:?}\n -> {:?}", sidecar, error);
+    }
+    Ok(())
+}
+
+/// Archive downloaded for a release asset pattern (see `write_asset_sidecar`), if there is one.
+pub fn read_asset_sidecar(placeholder: &Path) -> Option<PathBuf> {
+    let name = std::fs::read_to_string(with_added_extension(placeholder, ASSET_SIDECAR_EXTENSION)).ok()?;
+    placeholder.parent().map(|dir| dir.join(name.trim()))
+}
+
+/// Exclusive (advisory) lock on an archive of the cache, so that several processes sharing the cache don't download
+/// the same archive at the same time. Released when dropped.<br>
+/// The lock file is left in place: removing it could let another process lock a new file while one waits on the old one.
+pub struct CacheLock {
+    file: File,
+}
+
+impl CacheLock {
+    /// Takes the lock of the archive, waiting while another process holds it.
+    pub async fn acquire(archive: &Path) -> Result<Self> {
+        let lock_path = with_added_extension(archive, LOCK_EXTENSION);
+        if let Some(parent) = lock_path.parent() {
+            if let Err(error) = std::fs::create_dir_all(parent) {
+                bail!("Could not create cache directory {:?}\n -> {:?}", parent, error);
+            }
+        }
+        let file = match std::fs::OpenOptions::new().create(true).truncate(false).write(true).open(&lock_path) {
+            Ok(file) => file,
+            Err(error) => bail!("Could not open cache lock file {:?}\n -> {:?}", lock_path, error),
+        };
+        let mut waiting = false;
+        loop {
+            match file.try_lock_exclusive() {
+                Ok(()) => return Ok(Self { file }),
+                Err(error) if error.kind() == fs2::lock_contended_error().kind() => {
+                    if !waiting {
+                        info!("{:?} is being downloaded by another process, waiting", archive);
+                        waiting = true;
+                    }
+                    tokio::time::sleep(LOCK_POLL_DELAY).await;
+                }
+                Err(error) => bail!("Could not lock {:?}\n -> {:?}", lock_path, error),
+            }
+        }
+    }
+}
+
+impl Drop for CacheLock {
+    fn drop(&mut self) {
+        if let Err(error) = self.file.unlock() {
+            debug!("Could not unlock cache lock file\n -> {:?}", error);
+        }
+    }
+}
+
+fn with_added_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(extension);
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod test_cache_lock {
+    use std::time::Duration;
+
+    use super::CacheLock;
+
+    #[tokio::test]
+    async fn second_lock_waits_for_the_first() {
+        let cache = tempfile::tempdir().unwrap();
+        let archive = cache.path().join("github/user/repo/my_mod.zip");
+
+        let first = CacheLock::acquire(&archive).await.unwrap();
+        assert!(cache.path().join("github/user/repo/my_mod.zip.lock").is_file());
+        let waiting = tokio::time::timeout(Duration::from_millis(800), CacheLock::acquire(&archive)).await;
+        assert!(waiting.is_err());
+
+        drop(first);
+        tokio::time::timeout(Duration::from_secs(5), CacheLock::acquire(&archive)).await.unwrap().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test_cache_file_name {
+    use std::path::{Path, PathBuf};
+
+    use super::{archive_extension, cache_file_name, read_asset_sidecar, read_name_sidecar, write_asset_sidecar,
+                write_name_sidecar};
+
+    const LONG_ASSET: &str = "my-very-long-mod-name-with-a-lot-of-details-v12.3.4-for-enhanced-editions-and-classic-games.zip";
+
+    #[test]
+    fn short_paths_are_kept() {
+        let dest = Path::new("/cache/github/some_user/some_repo");
+        assert_eq!(cache_file_name(dest, Path::new(LONG_ASSET), None), PathBuf::from(LONG_ASSET));
+        assert_eq!(cache_file_name(dest, Path::new(LONG_ASSET), Some(400)), PathBuf::from(LONG_ASSET));
+        assert_eq!(cache_file_name(dest, Path::new("mod.zip"), Some(80)), PathBuf::from("mod.zip"));
+    }
+
+    #[test]
+    fn long_paths_are_hashed() {
+        let dest = Path::new("/cache/github/some_user/some_repo");
+        let hashed = cache_file_name(dest, Path::new(LONG_ASSET), Some(120));
+
+        assert_ne!(hashed, PathBuf::from(LONG_ASSET));
+        assert_eq!(hashed.extension().unwrap(), "zip");
+        assert_eq!(hashed.as_os_str().len(), 24 + ".zip".len());
+        assert!(dest.join(&hashed).as_os_str().len() + 16 <= 120);
+        // stable, so that the archive is found in the cache on the next run
+        assert_eq!(cache_file_name(dest, Path::new(LONG_ASSET), Some(120)), hashed);
+        assert_ne!(cache_file_name(dest, Path::new("other-very-long-name-to-be-hashed-as-well-because-the-limit-is-low.zip"), Some(120)), hashed);
+    }
+
+    #[test]
+    fn threshold_includes_partial_suffix() {
+        let dest = Path::new("/cache");
+        // "/cache/abcdef.zip" is 17 chars
+        assert_eq!(cache_file_name(dest, Path::new("abcdef.zip"), Some(33)), PathBuf::from("abcdef.zip"));
+        assert_ne!(cache_file_name(dest, Path::new("abcdef.zip"), Some(32)), PathBuf::from("abcdef.zip"));
+    }
+
+    #[test]
+    fn compressed_tarball_extension_is_kept() {
+        assert_eq!(archive_extension("my_mod-1.0.tar.gz"), Some("tar.gz".to_string()));
+        assert_eq!(archive_extension("my_mod-1.0.zip"), Some("zip".to_string()));
+        assert_eq!(archive_extension("tar.gz"), Some("gz".to_string()));
+        assert_eq!(archive_extension("no_extension"), None);
+    }
+
+    #[test]
+    fn name_mapping_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("github").join("user").join("repo");
+        let save_name = Path::new(LONG_ASSET);
+        let file_name = cache_file_name(&dest, save_name, Some(60));
+
+        write_name_sidecar(&dest, &file_name, save_name).unwrap();
+        std::fs::write(dest.join(&file_name), "archive").unwrap();
+
+        assert_eq!(read_name_sidecar(&dest.join(&file_name)).unwrap(), Some(LONG_ASSET.to_string()));
+    }
+
+    #[test]
+    fn no_mapping_for_unhashed_names() {
+        let dir = tempfile::tempdir().unwrap();
+        write_name_sidecar(dir.path(), Path::new("mod.zip"), Path::new("mod.zip")).unwrap();
+
+        assert_eq!(read_name_sidecar(&dir.path().join("mod.zip")).unwrap(), None);
+        assert!(!dir.path().join("mod.zip.name").exists());
+    }
+
+    #[test]
+    fn asset_pattern_points_to_last_downloaded_asset() {
+        let dir = tempfile::tempdir().unwrap();
+        let placeholder = dir.path().join("my_mod-v_._.zip");
+        assert_eq!(read_asset_sidecar(&placeholder), None);
+
+        write_asset_sidecar(&placeholder, &dir.path().join("my_mod-v1.2.zip")).unwrap();
+        write_asset_sidecar(&placeholder, &dir.path().join("my_mod-v1.3.zip")).unwrap();
+        assert_eq!(read_asset_sidecar(&placeholder), Some(dir.path().join("my_mod-v1.3.zip")));
+    }
+}
@@ -1,7 +1,7 @@
 
 use std::{fmt::Debug, path::{Path, PathBuf}, ffi::OsStr};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use path_absolutize::*;
 
 #[derive(PartialEq, Clone)]
@@ -10,11 +10,38 @@ impl CanonPath {
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> { Ok(Self((path.as_ref().absolutize()?).into_owned())) }
     pub fn path(&self) -> &Path { &self.0 }
     pub fn join<P: AsRef<Path>>(&self, p: P) -> Result<CanonPath> { Self::new(self.0.join(p)) }
+    /// Joins a relative path, and fails if the result is not inside this path
+    /// (absolute path, or too many `..`).<br>
+    /// See `starts_with` for symbolic links.
+    pub fn safe_join<P: AsRef<Path>>(&self, relative: P) -> Result<CanonPath> {
+        let joined = self.join(&relative)?;
+        if !joined.starts_with(&self.0) {
+            bail!("{:?} is not inside {:?}", relative.as_ref(), self.0);
+        }
+        Ok(joined)
+    }
     pub fn join_path<P: AsRef<Path>>(&self, p: P) -> PathBuf { self.0.join(p) }
-    pub fn starts_with<P: AsRef<Path>>(&self, base: P) -> bool { self.0.starts_with(base) }
+    /// True if this path is inside `base`, either as written or once symbolic links are resolved in both paths
+    /// (when the game directory is a symlink, a path can be seen through the link or through its target).
+    pub fn starts_with<P: AsRef<Path>>(&self, base: P) -> bool {
+        self.0.starts_with(&base) || resolve_links(&self.0).starts_with(resolve_links(base.as_ref()))
+    }
     pub fn to_path_buf(&self) -> PathBuf { self.0.to_path_buf() }
 }
 
+/// Resolves the symbolic links of the deepest existing ancestor, the rest of the path (which doesn't exist yet) is kept as is.
+fn resolve_links(path: &Path) -> PathBuf {
+    for ancestor in path.ancestors() {
+        if let Ok(resolved) = ancestor.canonicalize() {
+            return match path.strip_prefix(ancestor) {
+                Ok(rest) => resolved.join(rest),
+                Err(_) => resolved,
+            };
+        }
+    }
+    path.to_path_buf()
+}
+
 impl AsRef<Path> for CanonPath {
     fn as_ref(&self) -> &Path {
         &self.0
@@ -32,3 +59,66 @@ impl Debug for CanonPath {
         self.0.fmt(f)
     }
 }
+
+#[cfg(test)]
+mod test_safe_join {
+    use std::path::PathBuf;
+
+    use super::CanonPath;
+
+    fn base() -> CanonPath {
+        CanonPath::new("/games/bg2").unwrap()
+    }
+
+    #[test]
+    fn join_inside_base() {
+        assert_eq!(base().safe_join("my_mod/setup.tra").unwrap().to_path_buf(), PathBuf::from("/games/bg2/my_mod/setup.tra"));
+        assert_eq!(base().safe_join("my_mod/../other_mod").unwrap().to_path_buf(), PathBuf::from("/games/bg2/other_mod"));
+        assert_eq!(base().safe_join("").unwrap().to_path_buf(), PathBuf::from("/games/bg2"));
+    }
+
+    #[test]
+    fn join_escaping_base() {
+        assert!(base().safe_join("../bg1/chitin.key").is_err());
+        assert!(base().safe_join("my_mod/../../bg1").is_err());
+        assert!(base().safe_join("/etc/passwd").is_err());
+        // same prefix, but not the same directory
+        assert!(base().safe_join("../bg2ee").is_err());
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test_symlinked_base {
+    use super::CanonPath;
+
+    fn setup() -> (tempfile::TempDir, CanonPath) {
+        let work_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(work_dir.path().join("real/my_mod")).unwrap();
+        std::fs::create_dir_all(work_dir.path().join("other")).unwrap();
+        std::os::unix::fs::symlink(work_dir.path().join("real"), work_dir.path().join("game")).unwrap();
+        let game_dir = CanonPath::new(work_dir.path().join("game")).unwrap();
+        (work_dir, game_dir)
+    }
+
+    #[test]
+    fn resolved_path_inside_symlinked_base() {
+        let (work_dir, game_dir) = setup();
+        let resolved = CanonPath::new(work_dir.path().join("real/my_mod").canonicalize().unwrap()).unwrap();
+        assert!(resolved.starts_with(&game_dir));
+        // and the other way round
+        assert!(game_dir.join("my_mod").unwrap().starts_with(work_dir.path().join("real").canonicalize().unwrap()));
+        assert!(!CanonPath::new(work_dir.path().join("other")).unwrap().starts_with(&game_dir));
+    }
+
+    #[test]
+    fn patch_path_in_symlinked_game_dir() {
+        let (_work_dir, game_dir) = setup();
+        // the patched file doesn't exist yet
+        let patched = game_dir.safe_join("my_mod/new_file.tra").unwrap();
+        assert_eq!(patched.to_path_buf(), game_dir.join_path("my_mod/new_file.tra"));
+        // same directory, reached through the link target
+        assert!(game_dir.safe_join("../real/my_mod/my_mod.tp2").is_ok());
+        assert!(game_dir.safe_join("../other/file.txt").is_err());
+        assert!(game_dir.safe_join("../game2/file.txt").is_err());
+    }
+}
@@ -6,16 +6,16 @@ use anyhow::{bail, Result};
 use globwalk::GlobWalkerBuilder;
 use log::{debug, info};
 
-pub fn ensure_chitin_key() -> Result<()> {
-    if !PathBuf::from("chitin.key").exists() {
-        if PathBuf::from("game/chitin.key").exists() {
-            if let Err(err) = set_current_dir("game") {
-                bail!("Could not enter game directory 'game' {:?}", err)
-            } else {
-                info!("./game/chitin.key found, entered game subdir");
-            }
+use crate::game_detect::{detect_game, GameFlavor};
+
+/// Checks that `chitin.key` is in the current directory, or enters the `game` sub-directory if it is there
+/// (unless `auto_game_subdir` is false).
+pub fn ensure_chitin_key(auto_game_subdir: bool) -> Result<()> {
+    if let Some(game_dir) = game_subdir(Path::new("."), auto_game_subdir)? {
+        if let Err(err) = set_current_dir(&game_dir) {
+            bail!("Could not enter game directory 'game' {:?}", err)
         } else {
-            bail!("no chitin.key of game/chitin.key file");
+            info!("./game/chitin.key found, entered game subdir");
         }
     } else {
         info!("./chitin.key found");
@@ -23,6 +23,40 @@ pub fn ensure_chitin_key() -> Result<()> {
     Ok(())
 }
 
+/// Whether a directory can be used as game directory, for front-ends that let the user choose one.
+#[derive(Debug, PartialEq)]
+pub enum GameDirStatus {
+    /// `game_dir` is the chosen directory, or its `game` sub-directory
+    Valid { game_dir: PathBuf, game: Option<GameFlavor> },
+    Invalid { reason: String },
+}
+
+/// Same check as `ensure_chitin_key` (without entering the directory), with the detected game.
+pub fn check_game_dir(dir: &Path, auto_game_subdir: bool) -> GameDirStatus {
+    let game_dir = match game_subdir(dir, auto_game_subdir) {
+        Ok(None) => dir.to_path_buf(),
+        Ok(Some(subdir)) => subdir,
+        Err(error) => return GameDirStatus::Invalid { reason: error.to_string() },
+    };
+    match detect_game(&game_dir) {
+        Ok(game) => GameDirStatus::Valid { game_dir, game },
+        Err(error) => GameDirStatus::Invalid { reason: format!("{:?}", error) },
+    }
+}
+
+/// The sub-directory of `dir` that must be entered to be in the game directory, if any.
+fn game_subdir(dir: &Path, auto_game_subdir: bool) -> Result<Option<PathBuf>> {
+    if dir.join("chitin.key").exists() {
+        Ok(None)
+    } else if !auto_game_subdir {
+        bail!("no chitin.key file (automatic descent in the game sub-directory is disabled)");
+    } else if dir.join("game/chitin.key").exists() {
+        Ok(Some(dir.join("game")))
+    } else {
+        bail!("no chitin.key of game/chitin.key file");
+    }
+}
+
 pub fn has_chitin_key(path: &Path) -> Result<bool> {
     let glob_builder = GlobWalkerBuilder::from_patterns(path, &["chitin.key"])
         .case_insensitive(true)
@@ -37,3 +71,56 @@ pub fn has_chitin_key(path: &Path) -> Result<bool> {
     }
     Ok(false)
 }
+
+#[cfg(test)]
+mod test_game_subdir {
+    use crate::game_detect::GameFlavor;
+
+    use super::{check_game_dir, game_subdir, GameDirStatus};
+
+    fn game_dir(files: &[&str]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for file in files {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn chitin_key_in_current_dir() {
+        let dir = game_dir(&["chitin.key", "game/chitin.key"]);
+        assert_eq!(game_subdir(dir.path(), true).unwrap(), None);
+        assert_eq!(game_subdir(dir.path(), false).unwrap(), None);
+    }
+
+    #[test]
+    fn descends_in_game_subdir() {
+        let dir = game_dir(&["game/chitin.key"]);
+        assert_eq!(game_subdir(dir.path(), true).unwrap(), Some(dir.path().join("game")));
+    }
+
+    #[test]
+    fn no_descent_when_disabled() {
+        let dir = game_dir(&["game/chitin.key"]);
+        assert!(game_subdir(dir.path(), false).is_err());
+    }
+
+    #[test]
+    fn no_chitin_key() {
+        let dir = game_dir(&["other/chitin.key"]);
+        assert!(game_subdir(dir.path(), true).is_err());
+    }
+
+    #[test]
+    fn game_dir_status() {
+        let dir = game_dir(&["game/chitin.key", "game/engine.lua", "game/movies/pocketzz.wbm"]);
+        assert_eq!(check_game_dir(dir.path(), true),
+                    GameDirStatus::Valid { game_dir: dir.path().join("game"), game: Some(GameFlavor::Bg2ee) });
+        assert!(matches!(check_game_dir(dir.path(), false), GameDirStatus::Invalid { .. }));
+
+        let dir = game_dir(&["chitin.key"]);
+        assert_eq!(check_game_dir(dir.path(), true), GameDirStatus::Valid { game_dir: dir.path().to_path_buf(), game: None });
+    }
+}
@@ -1,6 +1,7 @@
 
 
 use std::collections::HashMap;
+use std::fmt::Display;
 use std::hash::Hash;
 use std::io::{BufReader, Result as IoResult};
 use std::fs::File;
@@ -10,15 +11,17 @@ use anyhow::{bail, Ok, Result};
 use log::debug;
 use serde::{Deserialize, Serialize};
 
+use crate::cache::CacheLayout;
 use crate::canon_path::CanonPath;
 use crate::lowercase::LwcString;
 use crate::progname::PROGNAME;
 
-pub const ARCHIVE_CACHE_ENV_VAR: &'static str = "MODDA_ARCHIVE_CACHE";
-pub const EXTRACT_LOCATION_ENV_VAR: &'static str = "MODDA_EXTRACT_LOCATION";
-pub const WEIDU_PATH_ENV_VAR: &'static str = "MODDA_WEIDU_PATH";
-pub const IGNORE_CURRENT_DIR_WEIDU_ENV_VAR: &'static str = "MODDA_IGNORE_CURRENT_DIR_WEIDU";
-pub const CODE_EDITOR_ENV_VAR: &'static str = "MODDA_CODE_EDITOR";
+pub const ARCHIVE_CACHE_ENV_VAR: &str = "MODDA_ARCHIVE_CACHE";
+pub const EXTRACT_LOCATION_ENV_VAR: &str = "MODDA_EXTRACT_LOCATION";
+pub const WEIDU_PATH_ENV_VAR: &str = "MODDA_WEIDU_PATH";
+pub const IGNORE_CURRENT_DIR_WEIDU_ENV_VAR: &str = "MODDA_IGNORE_CURRENT_DIR_WEIDU";
+pub const CODE_EDITOR_ENV_VAR: &str = "MODDA_CODE_EDITOR";
+pub const MAX_REDIRECTS_ENV_VAR: &str = "MODDA_MAX_REDIRECTS";
 
 #[derive(Deserialize, Serialize, Debug, Default, Clone)]
 pub struct Config {
@@ -74,9 +77,58 @@ pub struct Config {
     /// Path to the code editor program.<br>
     /// Used with the `config edit` subcommands.
     pub code_editor: Option<String>,
+
+    /// Maximum number of HTTP redirects followed when downloading an archive.
+    /// - If not set, up to 10 redirects are followed.
+    /// - `0` means redirects are not followed at all (and the download fails).
+    ///
+    /// Some mirrors chain a lot of redirects, this can be raised for them.
+    pub max_redirects: Option<usize>,
+
+    /// Number of times a download is tried again after a network error or a server error (HTTP 5xx).
+    /// Other failures (404, checksum mismatch...) are not retried. Not retried if not set.
+    pub download_retries: Option<u32>,
+
+    /// Delay (in milliseconds) before the first retry of a download, doubled for each following retry
+    /// (up to one minute)
+    /// (default is 1000).
+    pub download_retry_delay_ms: Option<u64>,
+
+    /// Maximum length of the path of archives in the cache.<br>
+    /// Archives whose path would be longer are saved under a (fixed length) hashed name, the original
+    /// name is kept in a `<hashed name>.name` file next to it.
+    ///
+    /// Useful on windows where paths are limited to 260 characters (by default), for example `200`.
+    /// If not set, archives are always saved under their original name.
+    pub cache_path_max_length: Option<usize>,
+
+    /// How archives are organized in the cache.
+    /// - `partitioned` (the default) uses a sub-directory per source type and host (or github repository)
+    /// - `flat` puts all archives in the cache directory, with a suffix in their name to avoid collisions
+    pub cache_layout: Option<CacheLayout>,
+
+    /// Asks for confirmation before installing more than this number of components of a single mod.<br>
+    /// Guards against an unintended `components: all` on a mod with hundreds of components. Not limited if not set.
+    pub components_max: Option<usize>,
+
+    /// Maximum duration (in seconds) of a non-interactive weidu installation run, weidu (and the programs it started)
+    /// is killed after that and the installation stops. Not limited if not set.
+    pub weidu_timeout_seconds: Option<u64>,
+
+    /// When `chitin.key` is not in the current directory but in a `game` sub-directory, modda enters it
+    /// automatically.<br>
+    /// If set to true, modda only runs where `chitin.key` is directly present.
+    pub no_auto_game_subdir: Option<bool>,
+
+    /// Name of the debug log written by weidu for each mod (its `--log` argument), relative to the game directory
+    /// (it can't leave it).<br>
+    /// `${mod}` is replaced by the mod name, `${occurrence}` by the number of times the mod was installed in
+    /// this run (starting at 1) and `${timestamp}` by the time the installation of the mod started.
+    /// - If not set, it is `setup-${mod}.debug` (all installations of a mod append to the same file).
+    pub weidu_debug_log: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
 pub struct ExtractorCommand {
     pub command: String,
     pub args: Vec<String>,
@@ -96,6 +148,8 @@ pub struct Settings {
     pub local: Option<ConfigSource>,
     pub env_config: ConfigSource,
     pub combined: Config,
+    /// `archive_cache` was set with `--cache-dir`
+    pub cache_dir_override: bool,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -104,6 +158,42 @@ pub struct ConfigSource {
     pub config: Option<Config>,
 }
 
+/// Where the effective value of a configuration property comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigOrigin {
+    /// `--cache-dir` (only for `archive_cache`)
+    CommandLine,
+    Environment,
+    /// Config file in the game directory (with its path)
+    Local(String),
+    /// Config file in the global config directory (with its path)
+    Global(String),
+    /// Not set anywhere, the program default applies
+    Default,
+}
+
+impl Display for ConfigOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigOrigin::CommandLine => write!(f, "command line"),
+            ConfigOrigin::Environment => write!(f, "environment"),
+            ConfigOrigin::Local(id) => write!(f, "local config file {id}"),
+            ConfigOrigin::Global(id) => write!(f, "global config file {id}"),
+            ConfigOrigin::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// A property of the combined configuration, with the source it was taken from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EffectiveValue {
+    /// Property name, `extractors.<extension>` for the extractors
+    pub name: String,
+    /// `None` if the property is not set
+    pub value: Option<serde_yaml::Value>,
+    pub origin: ConfigOrigin,
+}
+
 impl Settings {
     pub fn read_settings(game_dir: &CanonPath) -> Result<Settings> {
         let global = match global_conf_dir() {
@@ -125,6 +215,7 @@ impl Settings {
             global: global.clone(),
             local: local.clone(),
             env_config: env_config.clone(),
+            cache_dir_override: false,
             combined: combine(
                 match global {
                     None => None,
@@ -144,6 +235,70 @@ impl Settings {
         })
     }
 
+    /// Applies the `--cache-dir` command line option, which has precedence over all configuration sources.
+    pub fn override_archive_cache(&mut self, cache_dir: Option<&str>) {
+        if let Some(cache_dir) = cache_dir {
+            debug!("archive cache set to {cache_dir} from the command line");
+            self.combined.archive_cache = Some(cache_dir.to_string());
+            self.cache_dir_override = true;
+        }
+    }
+
+    /// All properties of the combined configuration (in declaration order), each one with the source
+    /// (by order of precedence) where it was found.
+    pub fn effective_config(&self) -> Result<Vec<EffectiveValue>> {
+        let mut sources = vec![];
+        for (origin, source) in [
+            (ConfigOrigin::Environment, Some(&self.env_config)),
+            (ConfigOrigin::Local(self.local.as_ref().map(|source| source.id.clone()).unwrap_or_default()), self.local.as_ref()),
+            (ConfigOrigin::Global(self.global.as_ref().map(|source| source.id.clone()).unwrap_or_default()), self.global.as_ref()),
+        ] {
+            if let Some(config) = source.and_then(|source| source.config.as_ref()) {
+                sources.push((origin, config_mapping(config)?));
+            }
+        }
+        let origin_of = |key: &serde_yaml::Value, sub_key: Option<&serde_yaml::Value>| sources.iter()
+            .find(|(_, mapping)| {
+                let value = mapping.get(key);
+                let value = match sub_key {
+                    None => value,
+                    Some(sub_key) => value.and_then(|value| value.get(sub_key)),
+                };
+                value.is_some_and(|value| !value.is_null())
+            })
+            .map(|(origin, _)| origin.clone())
+            .unwrap_or(ConfigOrigin::Default);
+
+        let mut values = vec![];
+        for (key, value) in config_mapping(&self.combined)? {
+            let name = key.as_str().unwrap_or_default().to_string();
+            match value {
+                serde_yaml::Value::Mapping(extractors) if !extractors.is_empty() => {
+                    let mut extractors = extractors.into_iter().collect::<Vec<_>>();
+                    extractors.sort_by_key(|(extension, _)| extension.as_str().unwrap_or_default().to_string());
+                    for (extension, extractor) in extractors {
+                        values.push(EffectiveValue {
+                            name: format!("{}.{}", name, extension.as_str().unwrap_or_default()),
+                            origin: origin_of(&key, Some(&extension)),
+                            value: Some(extractor),
+                        });
+                    }
+                }
+                serde_yaml::Value::Mapping(_) | serde_yaml::Value::Null =>
+                    values.push(EffectiveValue { name, value: None, origin: ConfigOrigin::Default }),
+                value => {
+                    let origin = if name == "archive_cache" && self.cache_dir_override {
+                        ConfigOrigin::CommandLine
+                    } else {
+                        origin_of(&key, None)
+                    };
+                    values.push(EffectiveValue { name, value: Some(value), origin });
+                }
+            }
+        }
+        Ok(values)
+    }
+
     pub fn find_config_in_dir(dir: &Path) -> Result<Option<PathBuf>> {
         let yml_name = format!("{prog_name}.yml", prog_name = PROGNAME);
         let yaml_name = format!("{prog_name}.yaml", prog_name = PROGNAME);
@@ -193,6 +348,13 @@ impl Settings {
             Result::Ok(s) if s == "false" => Some(false),
             _ => bail!("Incorrect value for {IGNORE_CURRENT_DIR_WEIDU_ENV_VAR} env var")
         };
+        let max_redirects = match std::env::var(MAX_REDIRECTS_ENV_VAR) {
+            Err(_) => None,
+            Result::Ok(s) => match s.parse::<usize>() {
+                Result::Ok(value) => Some(value),
+                Err(_) => bail!("Incorrect value for {MAX_REDIRECTS_ENV_VAR} env var (expected a number)"),
+            }
+        };
         Ok(ConfigSource {
             id: "environment".to_string(),
             config: Some(Config {
@@ -203,12 +365,28 @@ impl Settings {
                 // Setting extractor not supported for now
                 extractors: HashMap::new(),
                 code_editor: std::env::var(CODE_EDITOR_ENV_VAR).ok(),
+                max_redirects,
+                download_retries: None,
+                download_retry_delay_ms: None,
+                cache_path_max_length: None,
+                cache_layout: None,
+                components_max: None,
+                weidu_timeout_seconds: None,
+                no_auto_game_subdir: None,
+                weidu_debug_log: None,
             })
         })
     }
 
 }
 
+fn config_mapping(config: &Config) -> Result<serde_yaml::Mapping> {
+    match serde_yaml::to_value(config)? {
+        serde_yaml::Value::Mapping(mapping) => Ok(mapping),
+        other => bail!("Unexpected configuration representation {:?}", other),
+    }
+}
+
 fn combine(global: Option<Config>, local: Option<Config>, env_config: Option<Config>) -> Config {
     let global = global.unwrap_or_else(|| Config::default());
     let local = local.unwrap_or_else(|| Config::default());
@@ -220,6 +398,15 @@ fn combine(global: Option<Config>, local: Option<Config>, env_config: Option<Con
         ignore_current_dir_weidu: env_config.ignore_current_dir_weidu.or(local.ignore_current_dir_weidu).or(global.ignore_current_dir_weidu),
         extractors: merge_maps(&global.extractors, &local.extractors, &env_config.extractors),
         code_editor: env_config.code_editor.or(local.code_editor).or(global.code_editor),
+        max_redirects: env_config.max_redirects.or(local.max_redirects).or(global.max_redirects),
+        download_retries: env_config.download_retries.or(local.download_retries).or(global.download_retries),
+        download_retry_delay_ms: env_config.download_retry_delay_ms.or(local.download_retry_delay_ms).or(global.download_retry_delay_ms),
+        cache_path_max_length: env_config.cache_path_max_length.or(local.cache_path_max_length).or(global.cache_path_max_length),
+        cache_layout: env_config.cache_layout.or(local.cache_layout).or(global.cache_layout),
+        components_max: env_config.components_max.or(local.components_max).or(global.components_max),
+        weidu_timeout_seconds: env_config.weidu_timeout_seconds.or(local.weidu_timeout_seconds).or(global.weidu_timeout_seconds),
+        no_auto_game_subdir: env_config.no_auto_game_subdir.or(local.no_auto_game_subdir).or(global.no_auto_game_subdir),
+        weidu_debug_log: env_config.weidu_debug_log.or(local.weidu_debug_log).or(global.weidu_debug_log),
     }
 }
 
@@ -268,4 +455,70 @@ mod tests {
             expected
         )
     }
+
+    #[test]
+    fn effective_config_origins() {
+        use super::{ConfigOrigin, ConfigSource, Config, ExtractorCommand, Settings};
+        use crate::lowercase::lwc;
+
+        let extractor = |command: &str| ExtractorCommand { command: command.to_string(), args: vec![] };
+        let global = Config {
+            weidu_path: Some("/global/weidu".to_string()),
+            code_editor: Some("vi".to_string()),
+            extractors: HashMap::from([(lwc!("rar"), extractor("unrar")), (lwc!("7z"), extractor("7z"))]),
+            ..Config::default()
+        };
+        let local = Config {
+            weidu_path: Some("/local/weidu".to_string()),
+            extractors: HashMap::from([(lwc!("rar"), extractor("unrar-free"))]),
+            ..Config::default()
+        };
+        let env_config = Config { code_editor: Some("emacs".to_string()), ..Config::default() };
+        let mut settings = Settings {
+            global: Some(ConfigSource { id: "/home/modda.yml".to_string(), config: Some(global.clone()) }),
+            local: Some(ConfigSource { id: "/game/modda.yml".to_string(), config: Some(local.clone()) }),
+            env_config: ConfigSource { id: "environment".to_string(), config: Some(env_config.clone()) },
+            combined: super::combine(Some(global), Some(local), Some(env_config)),
+            cache_dir_override: false,
+        };
+        settings.override_archive_cache(Some("/tmp/cache"));
+
+        let origins = settings.effective_config().unwrap().into_iter()
+            .map(|value| (value.name, value.value.map(|value| serde_yaml::to_string(&value).unwrap()), value.origin))
+            .collect::<Vec<_>>();
+        let find = |name: &str| origins.iter().find(|(found, _, _)| found == name).unwrap().clone();
+        assert_eq!(find("archive_cache"), ("archive_cache".to_string(), Some("/tmp/cache\n".to_string()), ConfigOrigin::CommandLine));
+        assert_eq!(find("weidu_path").2, ConfigOrigin::Local("/game/modda.yml".to_string()));
+        assert_eq!(find("code_editor"), ("code_editor".to_string(), Some("emacs\n".to_string()), ConfigOrigin::Environment));
+        assert_eq!(find("extractors.rar").2, ConfigOrigin::Local("/game/modda.yml".to_string()));
+        assert_eq!(find("extractors.7z").2, ConfigOrigin::Global("/home/modda.yml".to_string()));
+        assert_eq!(find("extract_location"), ("extract_location".to_string(), None, ConfigOrigin::Default));
+        assert_eq!(origins[0].0, "archive_cache");
+    }
+
+    #[test]
+    fn cache_dir_overrides_config() {
+        use crate::cache::Cache;
+        use super::{Config, Settings};
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut settings = Settings {
+            combined: Config { archive_cache: Some(dir.path().join("from_config").to_string_lossy().to_string()), ..Config::default() },
+            ..Settings::default()
+        };
+
+        settings.override_archive_cache(None);
+        match Cache::ensure_from_config(&settings.combined).unwrap() {
+            Cache::Path(path) => assert_eq!(path, dir.path().join("from_config")),
+            Cache::Tmp(_) => panic!("expected the configured cache"),
+        }
+
+        let cli_dir = dir.path().join("from_cli");
+        settings.override_archive_cache(Some(&cli_dir.to_string_lossy()));
+        match Cache::ensure_from_config(&settings.combined).unwrap() {
+            Cache::Path(path) => assert_eq!(path, cli_dir),
+            Cache::Tmp(_) => panic!("expected the command line cache"),
+        }
+        assert!(cli_dir.is_dir());
+    }
 }
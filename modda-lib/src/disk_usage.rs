@@ -0,0 +1,112 @@
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+/// Total size of the files in `dir` (recursively, symbolic links are not followed).
+pub fn dir_size(dir: &Path) -> Result<u64> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) => bail!("Could not list {:?} to compute its size\n -> {:?}", dir, error),
+    };
+    let mut total = 0;
+    for entry in entries {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            total += dir_size(&entry.path())?;
+        } else if file_type.is_file() {
+            total += entry.metadata()?.len();
+        }
+    }
+    Ok(total)
+}
+
+/// Size difference in a human-readable form (`+1.5 MiB`, `-200 B`).
+pub fn format_size_delta(bytes: i64) -> String {
+    let sign = if bytes < 0 { "-" } else { "+" };
+    format!("{}{}", sign, format_size(bytes.unsigned_abs()))
+}
+
+/// Size in a human-readable form (`1.5 MiB`, `200 B`).
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Reads a size given as a number of bytes with an optional unit (`500MB`, `10 GB`, `1.5GiB`).<br>
+/// Units are binary whatever their spelling (`KB` and `KiB` are both 1024 bytes).
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let split = size.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number = match number.parse::<f64>() {
+        Ok(number) => number,
+        Err(error) => bail!("Invalid size {:?}\n -> {:?}", size, error),
+    };
+    let factor: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1024,
+        "m" | "mb" | "mib" => 1024 * 1024,
+        "g" | "gb" | "gib" => 1024 * 1024 * 1024,
+        "t" | "tb" | "tib" => 1024 * 1024 * 1024 * 1024,
+        other => bail!("Invalid size {:?}, unknown unit {:?} (expected B, KB, MB, GB or TB)", size, other),
+    };
+    Ok((number * factor as f64) as u64)
+}
+
+#[cfg(test)]
+mod test_disk_usage {
+    use super::{dir_size, format_size, format_size_delta, parse_size};
+
+    #[test]
+    fn size_delta_after_writes() {
+        let game_dir = tempfile::tempdir().unwrap();
+        std::fs::write(game_dir.path().join("chitin.key"), vec![0u8; 100]).unwrap();
+        let before = dir_size(game_dir.path()).unwrap();
+        assert_eq!(before, 100);
+
+        // a mod adds a directory and overrides a file
+        std::fs::create_dir_all(game_dir.path().join("my_mod/lib")).unwrap();
+        std::fs::write(game_dir.path().join("my_mod/setup-my_mod.tp2"), vec![0u8; 2000]).unwrap();
+        std::fs::write(game_dir.path().join("my_mod/lib/a.tpa"), vec![0u8; 48]).unwrap();
+        std::fs::create_dir_all(game_dir.path().join("override")).unwrap();
+        std::fs::write(game_dir.path().join("override/sw1h01.itm"), vec![0u8; 400]).unwrap();
+        let after = dir_size(game_dir.path()).unwrap();
+        assert_eq!(after as i64 - before as i64, 2448);
+
+        // another one removes a file
+        std::fs::remove_file(game_dir.path().join("override/sw1h01.itm")).unwrap();
+        let last = dir_size(game_dir.path()).unwrap();
+        assert_eq!(last as i64 - after as i64, -400);
+    }
+
+    #[test]
+    fn format_delta() {
+        assert_eq!(format_size_delta(0), "+0 B");
+        assert_eq!(format_size_delta(-400), "-400 B");
+        assert_eq!(format_size_delta(2448), "+2.4 KiB");
+        assert_eq!(format_size_delta(3 * 1024 * 1024 + 512 * 1024), "+3.5 MiB");
+        assert_eq!(format_size_delta(-5 * 1024 * 1024 * 1024), "-5.0 GiB");
+        assert_eq!(format_size(2448), "2.4 KiB");
+    }
+
+    #[test]
+    fn parse_sizes() {
+        assert_eq!(parse_size("300").unwrap(), 300);
+        assert_eq!(parse_size("2KiB").unwrap(), 2048);
+        assert_eq!(parse_size("10GB").unwrap(), 10 * 1024 * 1024 * 1024);
+        assert_eq!(parse_size("1.5 mb").unwrap(), 1536 * 1024);
+        assert!(parse_size("10 parsecs").is_err());
+        assert!(parse_size("GB").is_err());
+    }
+}
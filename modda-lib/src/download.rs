@@ -1,22 +1,59 @@
 
 use std::cmp::min;
 use std::fs::File;
-use std::io::{Write, ErrorKind};
-use std::path::PathBuf;
+use std::io::{BufReader, Write, ErrorKind};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use anyhow::{bail, Result};
 use filetime::FileTime;
 use futures_util::stream::StreamExt;
 use indicatif::{ProgressBar, ProgressStyle, ProgressState};
-use log::{debug, info};
-use reqwest::header::{HeaderMap, USER_AGENT};
+use log::{debug, info, warn};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_DISPOSITION, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_RANGE,
+                      LAST_MODIFIED, RANGE, USER_AGENT};
+use reqwest::redirect::Policy;
+use reqwest::{RequestBuilder, Response, StatusCode};
 
+use crate::cache::{archive_extension, CacheLock, ASSET_SIDECAR_EXTENSION, LOCK_EXTENSION, NAME_SIDECAR_EXTENSION};
+use crate::config::Config;
+use crate::install_events::{EventSender, InstallEvent};
 use crate::module::refresh::RefreshCondition;
 use crate::progname::PROGNAME;
+use crate::rate_limit::send_with_rate_limit;
 
+/// Minimal number of bytes between two `DownloadProgress` events
+const PROGRESS_EVENT_STEP: u64 = 256 * 1024;
+
+/// Used when `max_redirects` is not set in the config
+pub const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+/// Used when `download_retry_delay_ms` is not set in the config
+pub const DEFAULT_RETRY_DELAY_MS: u64 = 1000;
+
+/// Upper bound of the (exponentially growing) delay between two download attempts
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
 
 #[cfg_attr(test, faux::create)]
-pub struct Downloader {}
+pub struct Downloader {
+    events: EventSender,
+    max_redirects: usize,
+    retries: u32,
+    retry_delay: Duration,
+}
+
+/// Download failure that may not happen again (network error, server error): the download can be retried.
+#[derive(Debug)]
+struct TransientError(String);
+
+impl Display for TransientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for TransientError {}
 
 #[derive(Debug, Clone)]
 pub struct DownloadOpts {
@@ -27,28 +64,55 @@ pub struct DownloadOpts {
 #[cfg_attr(test, faux::methods)]
 impl Downloader {
     pub fn new() -> Self {
-        Downloader {}
+        Downloader {
+            events: EventSender::default(),
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            retries: 0,
+            retry_delay: Duration::from_millis(DEFAULT_RETRY_DELAY_MS),
+        }
     }
 
-    pub async fn download(&self, url: &str, dest_dir: &PathBuf, file_name: PathBuf, opts: &DownloadOpts,
+    /// Uses the HTTP settings from the config, download progress will be sent to `events`.
+    pub fn from_config(config: &Config, events: EventSender) -> Self {
+        Downloader {
+            events,
+            max_redirects: config.max_redirects.unwrap_or(DEFAULT_MAX_REDIRECTS),
+            retries: config.download_retries.unwrap_or(0),
+            retry_delay: Duration::from_millis(config.download_retry_delay_ms.unwrap_or(DEFAULT_RETRY_DELAY_MS)),
+        }
+    }
+
+    pub async fn download(&self, url: &str, dest_dir: &Path, file_name: PathBuf, opts: &DownloadOpts,
                             headers: &Option<HeaderMap>) -> Result<PathBuf> {
         info!("obtaining {:?}, url is {} (cache={:?})", file_name, url, dest_dir);
 
         // check if archive exists in the cache
         let file_name = dest_dir.join(file_name);
+        // held until the archive is in the cache, another process downloading it is waited for (and its result reused)
+        let _lock = CacheLock::acquire(&file_name).await?;
+        // without extension, the archive was saved with the extension given by the server
+        let file_name = match file_name.extension() {
+            None => find_with_extension(&file_name).unwrap_or(file_name),
+            Some(_) => file_name,
+        };
 
         if use_from_cache(opts, &file_name)? {
-            info!("File already downloaded before, reusing");
-            return Ok(file_name.to_owned());
+            if cached_archive_is_readable(&file_name) {
+                info!("File already downloaded before, reusing");
+                return Ok(file_name.to_owned());
+            }
+            warn!("Archive {:?} in the cache is damaged, it will be downloaded again", file_name);
         }
 
         debug!("will be located under: '{:?}'", file_name);
 
         let partial_name = get_partial_filename(&file_name)?;
 
-        if let Err(error) = self.download_partial(url, &partial_name, &dest_dir, headers).await {
-            bail!("download_partial failed for {} to {:?}\n  {}", url, partial_name, error);
+        let disposition_name = match self.download_partial_with_retry(url, &partial_name, dest_dir, headers).await {
+            Ok(disposition_name) => disposition_name,
+            Err(error) => bail!("download_partial failed for {} to {:?}\n  {}", url, partial_name, error),
         };
+        let file_name = with_disposition_extension(&file_name, disposition_name.as_deref());
 
         if opts.no_cache {
             Ok(partial_name)
@@ -60,29 +124,58 @@ impl Downloader {
         }
     }
 
-    pub async fn download_partial(&self, url: &str, partial_name: &PathBuf, dest_dir: &PathBuf,
-                                    headers: &Option<HeaderMap>)  -> Result<()> {
+    /// Same as `download_partial`, tried again (up to `download_retries` times, with an exponential backoff)
+    /// while it fails with a network or server error.
+    async fn download_partial_with_retry(&self, url: &str, partial_name: &Path, dest_dir: &Path,
+                                            headers: &Option<HeaderMap>) -> Result<Option<String>> {
+        let mut attempt = 0;
+        loop {
+            match self.download_partial(url, partial_name, dest_dir, headers).await {
+                Err(error) if attempt < self.retries && error.downcast_ref::<TransientError>().is_some() => {
+                    let delay = retry_delay(self.retry_delay, attempt);
+                    attempt += 1;
+                    warn!("download of {} failed ({}), retry {}/{} in {}", url, error, attempt, self.retries,
+                            humantime::format_duration(delay));
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Returns the file name given by the server in the `Content-Disposition` header, if any.
+    pub async fn download_partial(&self, url: &str, partial_name: &Path, dest_dir: &Path,
+                                    headers: &Option<HeaderMap>)  -> Result<Option<String>> {
         info!("download {} to {:?}", url, dest_dir);
         std::fs::create_dir_all(dest_dir)?;
 
-        let client = reqwest::Client::new();
+        let client = self.http_client()?;
 
-        let mut partial_file = match File::create(&partial_name) {
-            Err(error) => bail!("failed to create file {:?}\n -> {:?}", partial_name, error),
-            Ok(file) => file,
+        // what an interrupted download left is resumed, if the server still has the same content
+        let validator_file = validator_sidecar(partial_name);
+        // without a recorded version, nothing tells that the partial content is still the same: downloaded again
+        let validator = std::fs::read_to_string(&validator_file).ok();
+        let existing = match validator {
+            Some(_) => std::fs::metadata(partial_name).map(|metadata| metadata.len()).unwrap_or(0),
+            None => 0,
         };
-        let mut request = client.get(url)
-            .header(USER_AGENT, PROGNAME);
-
-        if let Some(headers) = headers {
-            request = request.headers(headers.to_owned());
+        let mut response = send_download_request(
+            download_request(&client, url, headers, existing, validator.as_deref()), url, self.max_redirects).await?;
+        let status = response.status();
+        let resumed = existing > 0 && status == StatusCode::PARTIAL_CONTENT
+                        && content_range_start(response.headers()) == Some(existing);
+        if existing > 0 && !resumed {
+            if status == StatusCode::RANGE_NOT_SATISFIABLE || status == StatusCode::PARTIAL_CONTENT {
+                info!("partial download of {} can't be resumed, downloading again", url);
+                response = send_download_request(download_request(&client, url, headers, 0, None), url, self.max_redirects).await?;
+            } else {
+                debug!("partial download of {} not resumed (status {}), downloading again", url, status);
+            }
+        } else if resumed {
+            info!("resuming download of {} after {} bytes", url, existing);
         }
-
-        let response = match request.send().await {
-            Ok(response) => response,
-            Err(error) => bail!("HTTP download failed\n -> {:?}", error),
-        };
-        let total_size = response.content_length();
+        let offset = if resumed { existing } else { 0 };
+        let total_size = response.content_length().map(|length| length + offset);
 
 
         // Indicatif setup
@@ -118,18 +211,50 @@ impl Downloader {
         pb.set_message(format!("Downloading {}", url));
 
         let response = match response.error_for_status() {
+            Err(ref error) if error.status().is_some_and(|status| status.is_server_error()) =>
+                return Err(TransientError(format!("Could not download mod archive at {}\n -> {}", url, error)).into()),
             Err(ref error) => bail!("Could not download mod archive at {}\n -> {}", url, error),
             Ok(response) => response,
         };
+        let final_url = response.url().to_string();
+        let content_type = response.headers().get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_owned());
+        check_not_html(&final_url, content_type.as_deref(), &[])?;
+        let disposition_name = response.headers().get(CONTENT_DISPOSITION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(content_disposition_filename);
+
+        let partial_file = if resumed {
+            std::fs::OpenOptions::new().append(true).open(partial_name)
+        } else {
+            File::create(partial_name)
+        };
+        let mut partial_file = match partial_file {
+            Err(error) => bail!("failed to create file {:?}\n -> {:?}", partial_name, error),
+            Ok(file) => file,
+        };
+        match response_validator(response.headers()) {
+            Some(validator) => if let Err(error) = std::fs::write(&validator_file, validator) {
+                warn!("Could not record the version of {} in {:?}, an interrupted download will restart from the beginning\n -> {:?}",
+                        url, validator_file, error);
+            }
+            None => { let _ = std::fs::remove_file(&validator_file); }
+        }
 
         let mut stream = response.bytes_stream();
-        let mut downloaded: u64 = 0;
+        let mut downloaded: u64 = offset;
+        let mut last_event: u64 = offset;
+        pb.set_position(offset);
 
         while let Some(item) = stream.next().await {
             let chunk = match item {
-                Err(error) => bail!("Error while downloading file\n -> {:?}", error),
+                Err(error) => return Err(TransientError(format!("Error while downloading file\n -> {:?}", error)).into()),
                 Ok(chunk) => chunk,
             };
+            if downloaded == 0 {
+                check_not_html(&final_url, None, &chunk)?;
+            }
             if let Err(error) = partial_file.write(&chunk) {
                 bail!("Error while writing to file\n ->{:?}", error);
             }
@@ -142,13 +267,19 @@ impl Downloader {
                 downloaded = new;
                 pb.set_position(new);
             }
+            if downloaded - last_event >= PROGRESS_EVENT_STEP {
+                last_event = downloaded;
+                self.events.emit(InstallEvent::DownloadProgress { url: url.to_owned(), downloaded, total: total_size });
+            }
         }
+        self.events.emit(InstallEvent::DownloadProgress { url: url.to_owned(), downloaded, total: total_size });
         pb.finish_with_message(format!("Download from {} finished", url));
-        Ok(())
+        let _ = std::fs::remove_file(&validator_file);
+        Ok(disposition_name)
     }
 
-    pub fn rename_partial(&self, partial_file_name: &PathBuf, final_file_name: &PathBuf) -> Result<()> {
-        if let Err(error) = std::fs::rename(partial_file_name, final_file_name.clone()) {
+    pub fn rename_partial(&self, partial_file_name: &Path, final_file_name: &Path) -> Result<()> {
+        if let Err(error) = std::fs::rename(partial_file_name, final_file_name) {
             bail!("Failed to rename partial file {:?} to {:?}\n -> {:?}", partial_file_name, final_file_name, error);
         } else {
             debug!("renamed partial download file to {:?}", final_file_name);
@@ -156,25 +287,219 @@ impl Downloader {
         Ok(())
     }
 
-    pub fn target_exists(&self, file_name: &PathBuf) -> bool {
+    /// Checks that the resource at `url` can be obtained, without downloading it.
+    pub async fn check_url(&self, url: &str, headers: &Option<HeaderMap>) -> Result<bool> {
+        debug!("check availability of {}", url);
+        let client = self.http_client()?;
+
+        let mut request = client.head(url)
+            .header(USER_AGENT, PROGNAME);
+        if let Some(headers) = headers {
+            request = request.headers(headers.to_owned());
+        }
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(error) => bail!("HTTP availability check failed for {}\n -> {:?}", url, error),
+        };
+        let status = match response.status() {
+            // some hosts refuse HEAD requests, ask for the first byte instead
+            StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED => {
+                let mut request = client.get(url)
+                    .header(USER_AGENT, PROGNAME)
+                    .header(RANGE, "bytes=0-0");
+                if let Some(headers) = headers {
+                    request = request.headers(headers.to_owned());
+                }
+                match request.send().await {
+                    Ok(response) => response.status(),
+                    Err(error) => bail!("HTTP availability check failed for {}\n -> {:?}", url, error),
+                }
+            }
+            status => status,
+        };
+        debug!("availability check for {} returned {}", url, status);
+        Ok(status.is_success())
+    }
+
+    pub fn target_exists(&self, file_name: &Path) -> bool {
         file_name.exists()
     }
+
+    fn http_client(&self) -> Result<reqwest::Client> {
+        // with a limit of 0, any redirect is an error
+        match reqwest::Client::builder().redirect(Policy::limited(self.max_redirects)).build() {
+            Err(error) => bail!("Could not create HTTP client\n -> {:?}", error),
+            Ok(client) => Ok(client),
+        }
+    }
+}
+
+/// GET request for `url`, for the bytes after `resume_from` when it's not 0 (only if the content still matches
+/// `validator`, when there is one).
+fn download_request(client: &reqwest::Client, url: &str, headers: &Option<HeaderMap>, resume_from: u64,
+                    validator: Option<&str>) -> RequestBuilder {
+    let mut request = client.get(url)
+        .header(USER_AGENT, PROGNAME);
+    if let Some(headers) = headers {
+        request = request.headers(headers.to_owned());
+    }
+    if resume_from > 0 {
+        request = request.header(RANGE, format!("bytes={}-", resume_from));
+        if let Some(validator) = validator {
+            request = request.header(IF_RANGE, validator);
+        }
+    }
+    request
+}
+
+async fn send_download_request(request: RequestBuilder, url: &str, max_redirects: usize) -> Result<Response> {
+    match send_with_rate_limit(request).await {
+        Ok(response) => Ok(response),
+        Err(error) if error.is_redirect() => bail!(
+            "HTTP download of {} failed, too many redirects (the limit is {})\n\
+             The `max_redirects` config property can be raised if this mirror is known to need more.\n -> {:?}",
+            url, max_redirects, error
+        ),
+        Err(error) => Err(TransientError(format!("HTTP download failed\n -> {:?}", error)).into()),
+    }
+}
+
+/// First byte of a `206 Partial Content` response (from `Content-Range: bytes <start>-<end>/<length>`).
+fn content_range_start(headers: &HeaderMap) -> Option<u64> {
+    let value = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+    let range = value.trim().strip_prefix("bytes")?.trim_start();
+    range.split('-').next()?.trim().parse().ok()
+}
+
+/// Identifies the version of the downloaded content (`ETag`, or `Last-Modified`), so that a partial
+/// download is only resumed if it didn't change. Weak ETags can't be used for that.
+fn response_validator(headers: &HeaderMap) -> Option<String> {
+    let header = |name| headers.get(name).and_then(|value: &HeaderValue| value.to_str().ok());
+    match header(ETAG) {
+        Some(etag) if !etag.starts_with("W/") => Some(etag.to_owned()),
+        _ => header(LAST_MODIFIED).map(|value| value.to_owned()),
+    }
+}
+
+/// File next to a partial download that records the version (`ETag`...) of the content being downloaded.
+fn validator_sidecar(partial_name: &Path) -> PathBuf {
+    let mut name = partial_name.as_os_str().to_os_string();
+    name.push(".validator");
+    PathBuf::from(name)
 }
 
-fn get_partial_filename(file_name: &PathBuf) -> Result<PathBuf> {
-    let extension = match file_name.extension() {
-        None => bail!("file to download {:?} has no extension", file_name),
-        Some(ext) => ext,
+/// Fails if the server answered with an HTML page where an archive was expected.
+/// This usually happens when a link points to a login or "click here to download" page.
+///
+/// `content_type` is the value of the `Content-Type` header, `first_bytes` the beginning of the body
+/// (either can be empty).
+fn check_not_html(url: &str, content_type: Option<&str>, first_bytes: &[u8]) -> Result<()> {
+    let html_content_type = match content_type {
+        None => false,
+        Some(content_type) => {
+            let mime = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+            mime == "text/html" || mime == "application/xhtml+xml"
+        }
     };
-    let mut partial_name: std::ffi::OsString = file_name.clone().into();
-    partial_name.push(".");
-    partial_name.push(extension);
+    let start = String::from_utf8_lossy(&first_bytes[..min(first_bytes.len(), 512)]);
+    let start = start.trim_start_matches('\u{feff}').trim_start().to_lowercase();
+    let html_content = start.starts_with("<!doctype html") || start.starts_with("<html");
+
+    if html_content_type || html_content {
+        bail!(
+            "Expected an archive but the server returned an HTML page (at {})\n\
+             The link may require a login, or point to a download page instead of the file itself.",
+            url
+        );
+    }
+    Ok(())
+}
+
+fn get_partial_filename(file_name: &Path) -> Result<PathBuf> {
+    let mut partial_name = file_name.as_os_str().to_owned();
+    // without extension, it may come from the `Content-Disposition` header once downloaded
+    if let Some(extension) = file_name.extension() {
+        partial_name.push(".");
+        partial_name.push(extension);
+    }
     partial_name.push(".partial");
 
     Ok(PathBuf::from(partial_name))
 }
 
-fn use_from_cache(opts: &DownloadOpts, file_name: &PathBuf) -> Result<bool> {
+/// File name from a `Content-Disposition` header value (`filename*=` has priority over `filename=`).<br>
+/// Only the last component is kept, the server doesn't choose where the file goes.
+pub fn content_disposition_filename(value: &str) -> Option<String> {
+    let params = value.split(';').skip(1)
+        .filter_map(|param| param.split_once('='))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim()))
+        .collect::<Vec<_>>();
+    let extended = params.iter()
+        .find(|(name, _)| name == "filename*")
+        .and_then(|(_, value)| {
+            // charset'language'percent-encoded-name
+            let (_, encoded) = value.split_once('\'')?;
+            let (_, encoded) = encoded.split_once('\'')?;
+            Some(percent_encoding::percent_decode_str(encoded).decode_utf8_lossy().into_owned())
+        });
+    let name = extended.or_else(|| params.iter()
+        .find(|(name, _)| name == "filename")
+        .map(|(_, value)| value.trim_matches('"').to_string()))?;
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default().trim();
+    if name.is_empty() || name == "." || name == ".." {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+/// Name under which a download is saved: when `file_name` (from the URL) has no extension, the archive
+/// extension of the name given by the server is appended (so that the archive can be extracted).
+fn with_disposition_extension(file_name: &Path, disposition_name: Option<&str>) -> PathBuf {
+    if file_name.extension().is_some() {
+        return file_name.to_path_buf();
+    }
+    match disposition_name.and_then(archive_extension) {
+        None => file_name.to_path_buf(),
+        Some(extension) => {
+            let mut name = file_name.as_os_str().to_os_string();
+            name.push(".");
+            name.push(extension);
+            PathBuf::from(name)
+        }
+    }
+}
+
+/// Archive saved by a previous download as `<file_name>.<extension from Content-Disposition>`.
+fn find_with_extension(file_name: &Path) -> Option<PathBuf> {
+    let dir = file_name.parent()?;
+    let stem = file_name.file_name()?.to_string_lossy().into_owned();
+    std::fs::read_dir(dir).ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .find(|name| {
+            let extension = archive_extension(name);
+            extension.is_some_and(|extension| extension != "partial" && extension != NAME_SIDECAR_EXTENSION
+                                    && extension != LOCK_EXTENSION && extension != ASSET_SIDECAR_EXTENSION
+                                    && *name == format!("{stem}.{extension}"))
+        })
+        .map(|name| dir.join(name))
+}
+
+/// Partial downloads are only renamed when complete, but the file could have been damaged since.
+/// Only zip archives can be checked cheaply (the central directory is at the end of the file).
+fn cached_archive_is_readable(file_name: &Path) -> bool {
+    let extension = file_name.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_lowercase());
+    match extension.as_deref() {
+        Some("zip") | Some("iemod") => match File::open(file_name) {
+            Err(_) => false,
+            Ok(file) => zip::ZipArchive::new(BufReader::new(file)).is_ok(),
+        }
+        _ => std::fs::metadata(file_name).map(|metadata| metadata.len() > 0).unwrap_or(false),
+    }
+}
+
+fn use_from_cache(opts: &DownloadOpts, file_name: &Path) -> Result<bool> {
     match opts.refresh {
         RefreshCondition::Always => Ok(false),
         RefreshCondition::Never => Ok(file_name.exists()),
@@ -204,6 +529,15 @@ fn use_from_cache(opts: &DownloadOpts, file_name: &PathBuf) -> Result<bool> {
     }
 }
 
+/// Delay before the retry following attempt number `attempt` (0-based): doubled after each attempt,
+/// capped at `MAX_RETRY_DELAY`.
+fn retry_delay(base: Duration, attempt: u32) -> Duration {
+    match base.checked_mul(2u32.saturating_pow(attempt)) {
+        Some(delay) => delay.min(MAX_RETRY_DELAY),
+        None => MAX_RETRY_DELAY,
+    }
+}
+
 #[cfg(test)]
 mod test_cache_duration {
     use std::fs::OpenOptions;
@@ -316,3 +650,399 @@ mod test_cache_duration {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test_retry_delay {
+    use std::time::Duration;
+
+    use super::{retry_delay, MAX_RETRY_DELAY};
+
+    #[test]
+    fn delay_doubles_up_to_the_max() {
+        let base = Duration::from_millis(1000);
+        assert_eq!(retry_delay(base, 0), Duration::from_secs(1));
+        assert_eq!(retry_delay(base, 3), Duration::from_secs(8));
+        assert_eq!(retry_delay(base, 6), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn huge_delay_does_not_overflow() {
+        assert_eq!(retry_delay(Duration::from_millis(u64::MAX), 1), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(Duration::from_millis(1000), u32::MAX), MAX_RETRY_DELAY);
+    }
+}
+
+#[cfg(test)]
+mod test_html_detection {
+    use super::check_not_html;
+
+    #[test]
+    fn archive_content_is_accepted() {
+        assert!(check_not_html("http://example.com/a.zip", Some("application/zip"), b"PK\x03\x04").is_ok());
+        assert!(check_not_html("http://example.com/a.zip", Some("application/octet-stream"), &[]).is_ok());
+        assert!(check_not_html("http://example.com/a.zip", None, b"PK\x03\x04").is_ok());
+    }
+
+    #[test]
+    fn html_content_type_is_rejected() {
+        let result = check_not_html("http://example.com/login", Some("text/html; charset=UTF-8"), &[]);
+        let message = result.unwrap_err().to_string();
+        assert!(message.contains("HTML page"));
+        assert!(message.contains("http://example.com/login"));
+        assert!(check_not_html("http://example.com/a.zip", Some("Application/XHTML+XML"), &[]).is_err());
+    }
+
+    #[test]
+    fn html_body_is_rejected_even_with_wrong_content_type() {
+        let body = "\u{feff}\n  <!DOCTYPE html>\n<html><body>Please log in</body></html>";
+        assert!(check_not_html("http://example.com/a.zip", Some("application/octet-stream"), body.as_bytes()).is_err());
+        assert!(check_not_html("http://example.com/a.zip", None, b"<HTML><head></head></HTML>").is_err());
+    }
+
+    #[test]
+    fn text_that_is_not_html_is_accepted() {
+        assert!(check_not_html("http://example.com/a.patch", Some("text/plain"), b"--- a/file\n+++ b/file").is_ok());
+    }
+}
+
+#[cfg(test)]
+mod test_redirects {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    use crate::config::Config;
+    use crate::install_events::EventSender;
+    use crate::module::location::http::Http;
+    use crate::module::refresh::RefreshCondition;
+
+    use super::{DownloadOpts, Downloader, DEFAULT_MAX_REDIRECTS};
+
+    const RANGED_CONTENT: &str = "PK\x03\x04 and the rest of the archive";
+    const RANGED_ETAG: &str = "\"v1\"";
+
+    /// Serves `/r/<n>` as a chain of `n` redirects ending on an archive, `/login` as an HTML page,
+    /// `/flaky` as an archive after two server errors and `/ranged` as an archive that supports range requests.
+    fn start_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut flaky_failures = 0;
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Err(_) => continue,
+                    Ok(stream) => stream,
+                };
+                let mut request = vec![];
+                let mut buffer = [0u8; 1024];
+                while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                    match stream.read(&mut buffer) {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => request.extend_from_slice(&buffer[..read]),
+                    }
+                }
+                let request = String::from_utf8_lossy(&request);
+                let path = request.split_whitespace().nth(1).unwrap_or("/").to_owned();
+                let header = |name: &str| request.lines()
+                    .find_map(|line| line.split_once(':').filter(|(header, _)| header.eq_ignore_ascii_case(name)))
+                    .map(|(_, value)| value.trim().to_owned());
+                let response = match path.strip_prefix("/r/").map(|n| n.parse::<u32>()) {
+                    Some(Ok(0)) => "HTTP/1.1 200 OK\r\nContent-Type: application/zip\r\nContent-Length: 4\r\nConnection: close\r\n\r\nPK\x03\x04".to_string(),
+                    Some(Ok(n)) => format!("HTTP/1.1 302 Found\r\nLocation: /r/{}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", n - 1),
+                    _ if path == "/download" => "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Disposition: attachment; filename=\"my_mod-v2.zip\"\r\nContent-Length: 4\r\nConnection: close\r\n\r\nPK\x03\x04".to_string(),
+                    _ if path == "/flaky" && flaky_failures < 2 => {
+                        flaky_failures += 1;
+                        "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+                    }
+                    _ if path == "/flaky" => "HTTP/1.1 200 OK\r\nContent-Type: application/zip\r\nContent-Length: 4\r\nConnection: close\r\n\r\nPK\x03\x04".to_string(),
+                    _ if path == "/ranged" => {
+                        let start = header("range")
+                            .filter(|_| header("if-range").is_none_or(|validator| validator == RANGED_ETAG))
+                            .and_then(|range| range.strip_prefix("bytes=")?.trim_end_matches('-').parse::<usize>().ok());
+                        match start {
+                            None => format!("HTTP/1.1 200 OK\r\nETag: {RANGED_ETAG}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{RANGED_CONTENT}",
+                                            RANGED_CONTENT.len()),
+                            Some(start) => format!("HTTP/1.1 206 Partial Content\r\nETag: {RANGED_ETAG}\r\nContent-Range: bytes {start}-{}/{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                                                    RANGED_CONTENT.len() - 1, RANGED_CONTENT.len(), RANGED_CONTENT.len() - start, &RANGED_CONTENT[start..]),
+                        }
+                    }
+                    _ if path == "/login" => "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 15\r\nConnection: close\r\n\r\n<html>login</h>".to_string(),
+                    _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+                };
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    fn downloader(max_redirects: Option<usize>) -> Downloader {
+        let config = Config { max_redirects, ..Config::default() };
+        Downloader::from_config(&config, EventSender::default())
+    }
+
+    fn opts() -> DownloadOpts {
+        DownloadOpts { no_cache: false, refresh: RefreshCondition::Always }
+    }
+
+    fn retrying_downloader(download_retries: u32) -> Downloader {
+        let config = Config { download_retries: Some(download_retries), download_retry_delay_ms: Some(10), ..Config::default() };
+        Downloader::from_config(&config, EventSender::default())
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried() {
+        let base = start_server();
+        let dest = tempfile::tempdir().unwrap();
+        let dest_dir = dest.path().to_path_buf();
+
+        let result = retrying_downloader(1).download(&format!("{base}/flaky"), &dest_dir, "archive.zip".into(), &opts(), &None).await;
+        assert!(format!("{:?}", result.unwrap_err()).contains("503"));
+
+        // third attempt on the same server
+        let path = retrying_downloader(1).download(&format!("{base}/flaky"), &dest_dir, "archive.zip".into(), &opts(), &None).await.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), b"PK\x03\x04");
+    }
+
+    #[tokio::test]
+    async fn partial_download_is_resumed() {
+        let base = start_server();
+        let dest = tempfile::tempdir().unwrap();
+        let dest_dir = dest.path().to_path_buf();
+        let partial = dest_dir.join("archive.zip.zip.partial");
+
+        // same version on the server: only the missing bytes are downloaded (the existing ones are kept as is)
+        std::fs::write(&partial, "PK\x03\x04 #####").unwrap();
+        std::fs::write(dest_dir.join("archive.zip.zip.partial.validator"), RANGED_ETAG).unwrap();
+        let path = downloader(None).download(&format!("{base}/ranged"), &dest_dir, "archive.zip".into(), &opts(), &None).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), format!("PK\x03\x04 #####{}", &RANGED_CONTENT[10..]));
+        assert!(!dest_dir.join("archive.zip.zip.partial.validator").exists());
+
+        // the content changed since: downloaded from the start
+        std::fs::write(&partial, "something else").unwrap();
+        std::fs::write(dest_dir.join("archive.zip.zip.partial.validator"), "\"v0\"").unwrap();
+        let path = downloader(None).download(&format!("{base}/ranged"), &dest_dir, "archive.zip".into(), &opts(), &None).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), RANGED_CONTENT);
+
+        // no version was recorded: downloaded from the start
+        std::fs::write(&partial, "PK\x03\x04 #####").unwrap();
+        let path = downloader(None).download(&format!("{base}/ranged"), &dest_dir, "archive.zip".into(), &opts(), &None).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), RANGED_CONTENT);
+
+        // the server doesn't support ranges
+        std::fs::write(&partial, "PK").unwrap();
+        let path = downloader(None).download(&format!("{base}/r/0"), &dest_dir, "archive.zip".into(), &opts(), &None).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"PK\x03\x04");
+    }
+
+    #[tokio::test]
+    async fn mirrors_are_tried_in_order() {
+        let base = start_server();
+        let dest = tempfile::tempdir().unwrap();
+        let dest_dir = dest.path().to_path_buf();
+        let http = Http {
+            http: format!("{base}/missing"),
+            mirrors: vec![format!("{base}/login"), format!("{base}/r/0")],
+            refresh: RefreshCondition::Always,
+            ..Http::default()
+        };
+
+        // cached under the name of the primary URL
+        let path = http.download(&downloader(None), &dest_dir, "missing.zip".into()).await.unwrap();
+        assert_eq!(path, dest_dir.join("missing.zip"));
+        assert_eq!(std::fs::read(path).unwrap(), b"PK\x03\x04");
+
+        let http = Http { mirrors: vec![format!("{base}/login")], ..http };
+        let message = format!("{:?}", http.download(&downloader(None), &dest_dir, "missing.zip".into()).await.unwrap_err());
+        assert!(message.contains(&format!("Download failed from {base}/missing and all its mirrors")), "{}", message);
+        assert!(message.contains("404") && message.contains(&format!("{base}/login")), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn not_found_is_not_retried() {
+        let base = start_server();
+        let dest = tempfile::tempdir().unwrap();
+        let dest_dir = dest.path().to_path_buf();
+        let config = Config { download_retries: Some(3), download_retry_delay_ms: Some(60_000), ..Config::default() };
+        let downloader = Downloader::from_config(&config, EventSender::default());
+
+        let result = downloader.download(&format!("{base}/missing"), &dest_dir, "archive.zip".into(), &opts(), &None).await;
+        assert!(format!("{:?}", result.unwrap_err()).contains("404"));
+    }
+
+    #[tokio::test]
+    async fn redirects_within_limit_are_followed() {
+        let base = start_server();
+        let dest = tempfile::tempdir().unwrap();
+        let dest_dir = dest.path().to_path_buf();
+
+        let result = downloader(Some(3)).download(&format!("{base}/r/3"), &dest_dir, "archive.zip".into(), &opts(), &None).await;
+
+        let path = result.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), b"PK\x03\x04");
+    }
+
+    #[tokio::test]
+    async fn redirects_over_limit_fail() {
+        let base = start_server();
+        let dest = tempfile::tempdir().unwrap();
+        let dest_dir = dest.path().to_path_buf();
+
+        let result = downloader(Some(2)).download(&format!("{base}/r/3"), &dest_dir, "archive.zip".into(), &opts(), &None).await;
+
+        let message = format!("{:?}", result.unwrap_err());
+        assert!(message.contains("too many redirects (the limit is 2)"));
+    }
+
+    #[tokio::test]
+    async fn default_redirect_limit() {
+        let base = start_server();
+        let dest = tempfile::tempdir().unwrap();
+        let dest_dir = dest.path().to_path_buf();
+        let url = format!("{base}/r/{}", DEFAULT_MAX_REDIRECTS);
+
+        assert!(downloader(None).download(&url, &dest_dir, "archive.zip".into(), &opts(), &None).await.is_ok());
+
+        let url = format!("{base}/r/{}", DEFAULT_MAX_REDIRECTS + 1);
+        assert!(downloader(None).download(&url, &dest_dir, "other.zip".into(), &opts(), &None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn extension_from_content_disposition() {
+        let base = start_server();
+        let dest = tempfile::tempdir().unwrap();
+        let dest_dir = dest.path().to_path_buf();
+
+        let path = downloader(None).download(&format!("{base}/download"), &dest_dir, "download".into(), &opts(), &None).await.unwrap();
+        assert_eq!(path, dest_dir.join("download.zip"));
+        assert_eq!(std::fs::read(&path).unwrap(), b"PK\x03\x04");
+
+        // the URL gives the extension: the header is not used
+        let path = downloader(None).download(&format!("{base}/download"), &dest_dir, "archive.rar".into(), &opts(), &None).await.unwrap();
+        assert_eq!(path, dest_dir.join("archive.rar"));
+    }
+
+    #[tokio::test]
+    async fn html_page_instead_of_archive_fails() {
+        let base = start_server();
+        let dest = tempfile::tempdir().unwrap();
+        let dest_dir = dest.path().to_path_buf();
+
+        let result = downloader(None).download(&format!("{base}/login"), &dest_dir, "archive.zip".into(), &opts(), &None).await;
+
+        let message = format!("{:?}", result.unwrap_err());
+        assert!(message.contains("HTML page"));
+        assert!(message.contains("/login"));
+    }
+}
+
+#[cfg(test)]
+mod test_cached_archive {
+    use std::io::Write;
+
+    use zip::write::FileOptions;
+
+    use crate::module::refresh::RefreshCondition;
+
+    use super::{cached_archive_is_readable, DownloadOpts, Downloader};
+
+    // nothing listens there, any download attempt fails
+    const UNREACHABLE_URL: &str = "http://127.0.0.1:1/my_mod.zip";
+
+    fn write_zip(path: &std::path::Path) {
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+        zip.start_file("my_mod/my_mod.tp2", FileOptions::default()).unwrap();
+        zip.write_all(b"BACKUP ~my_mod/backup~").unwrap();
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn damaged_zip_is_detected() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("my_mod.zip");
+        write_zip(&archive);
+        assert!(cached_archive_is_readable(&archive));
+
+        let content = std::fs::read(&archive).unwrap();
+        std::fs::write(&archive, &content[..content.len() / 2]).unwrap();
+        assert!(!cached_archive_is_readable(&archive));
+    }
+
+    #[test]
+    fn empty_archive_is_not_readable() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("my_mod.rar");
+        std::fs::write(&archive, b"").unwrap();
+        assert!(!cached_archive_is_readable(&archive));
+        std::fs::write(&archive, b"Rar!").unwrap();
+        assert!(cached_archive_is_readable(&archive));
+    }
+
+    #[tokio::test]
+    async fn cached_archive_skips_download() {
+        let dir = tempfile::tempdir().unwrap();
+        write_zip(&dir.path().join("my_mod.zip"));
+        let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Never };
+
+        let result = Downloader::new().download(UNREACHABLE_URL, &dir.path().to_path_buf(), "my_mod.zip".into(), &opts, &None).await;
+
+        assert_eq!(result.unwrap(), dir.path().join("my_mod.zip"));
+    }
+
+    #[tokio::test]
+    async fn cached_archive_with_extension_from_server() {
+        let dir = tempfile::tempdir().unwrap();
+        write_zip(&dir.path().join("download.zip"));
+        std::fs::write(dir.path().join("download.zip.partial"), b"").unwrap();
+        let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Never };
+
+        let result = Downloader::new().download(UNREACHABLE_URL, &dir.path().to_path_buf(), "download".into(), &opts, &None).await;
+
+        assert_eq!(result.unwrap(), dir.path().join("download.zip"));
+    }
+
+    #[tokio::test]
+    async fn damaged_cached_archive_is_downloaded_again() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("my_mod.zip"), b"PK\x03\x04 truncated").unwrap();
+        let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Never };
+
+        let result = Downloader::new().download(UNREACHABLE_URL, &dir.path().to_path_buf(), "my_mod.zip".into(), &opts, &None).await;
+
+        assert!(result.is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_content_disposition {
+    use std::path::{Path, PathBuf};
+
+    use super::{content_disposition_filename, with_disposition_extension};
+
+    #[test]
+    fn filename_from_header() {
+        assert_eq!(content_disposition_filename(r#"attachment; filename="my_mod-v2.zip""#), Some("my_mod-v2.zip".to_string()));
+        assert_eq!(content_disposition_filename("attachment; filename=my_mod.7z"), Some("my_mod.7z".to_string()));
+        assert_eq!(
+            content_disposition_filename(r#"attachment; filename="fallback.zip"; filename*=UTF-8''mod%20fran%C3%A7ais.tar.gz"#),
+            Some("mod français.tar.gz".to_string())
+        );
+        // no path from the server
+        assert_eq!(content_disposition_filename(r#"attachment; filename="../../evil.zip""#), Some("evil.zip".to_string()));
+        assert_eq!(content_disposition_filename("attachment"), None);
+        assert_eq!(content_disposition_filename(r#"attachment; filename="""#), None);
+    }
+
+    #[test]
+    fn extension_from_header_when_url_has_none() {
+        assert_eq!(with_disposition_extension(Path::new("/cache/download"), Some("my_mod-v2.zip")), PathBuf::from("/cache/download.zip"));
+        assert_eq!(with_disposition_extension(Path::new("/cache/download"), Some("my_mod-v2.tar.gz")), PathBuf::from("/cache/download.tar.gz"));
+    }
+
+    #[test]
+    fn url_name_is_kept() {
+        // URL has an extension
+        assert_eq!(with_disposition_extension(Path::new("/cache/my_mod.zip"), Some("other.rar")), PathBuf::from("/cache/my_mod.zip"));
+        // no header, or no extension in the header
+        assert_eq!(with_disposition_extension(Path::new("/cache/download"), None), PathBuf::from("/cache/download"));
+        assert_eq!(with_disposition_extension(Path::new("/cache/download"), Some("README")), PathBuf::from("/cache/download"));
+    }
+}
@@ -1,10 +1,10 @@
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Result, bail};
 use globwalk::GlobWalkerBuilder;
 use itertools::Itertools;
-use path_clean::PathClean;
+use log::info;
 use serde::{Serialize, Deserialize};
 
 use crate::args::Install;
@@ -23,9 +23,22 @@ impl <'a> FileInstaller<'a> {
         FileInstaller { global, opts, game_dir }
     }
 
-    pub fn copy_from_origins(&self, origins: &[&FileModuleOrigin], target: &PathBuf, allow_overwrite: bool) -> Result<()> {
+    pub fn copy_from_origins(&self, origins: &[&FileModuleOrigin], target: &PathBuf, overwrite: &OverwriteOptions) -> Result<()> {
         let globs = self.get_file_globs(origins)?;
-        self.copy_from_globs(&globs, target, allow_overwrite)
+        self.copy_from_globs(&globs, target, overwrite)
+    }
+
+    /// `backup` is relative to the game directory.
+    pub fn overwrite_options(&self, policy: Option<OverwritePolicy>, allow_overwrite: bool,
+                                backup: Option<&str>) -> Result<OverwriteOptions> {
+        let backup_dir = match backup {
+            None => None,
+            Some(backup) => match self.game_dir.safe_join(backup) {
+                Ok(backup_dir) => Some(backup_dir.to_path_buf()),
+                Err(error) => bail!("Invalid backup_on_overwrite value\n -> {:?}", error),
+            },
+        };
+        Ok(OverwriteOptions { policy: OverwritePolicy::with_allow_overwrite(policy, allow_overwrite), backup_dir })
     }
 
     fn get_file_globs(&self, origins: &[&FileModuleOrigin]) -> Result<Vec<CopyGlob>> {
@@ -55,38 +68,34 @@ impl <'a> FileInstaller<'a> {
 
     fn get_local_base_path(&self, file_path: &String) -> Result<PathBuf, anyhow::Error> {
         let manifest_path = self.opts.get_manifest_root(self.game_dir);
-        let local_files = match &self.global.local_files {
-            None => PathBuf::new(),
-            Some(path) => PathBuf::from(path).clean(),
+        let local_files = match manifest_path.safe_join(self.global.local_files.as_deref().unwrap_or_default()) {
+            Ok(local_files) => local_files,
+            Err(error) => bail!("Invalid local_files value\n -> {:?}", error),
         };
-        if local_files.is_absolute() || local_files.starts_with("..") {
-            bail!("Invalid local_files value");
-        }
-        let file_path = PathBuf::from(file_path).clean();
-        if file_path.is_absolute() || local_files.starts_with("..") {
-            bail!("Invalid local value");
+        match local_files.safe_join(file_path) {
+            Ok(path) => Ok(path.to_path_buf()),
+            Err(error) => bail!("Invalid local value\n -> {:?}", error),
         }
-        let relative_path = local_files.join(file_path);
-        Ok(manifest_path.join(relative_path)?.to_path_buf())
     }
 
-    fn copy_from_globs(&self, globs: &[CopyGlob], target: &PathBuf, allow_overwrite: bool) -> Result<()> {
+    fn copy_from_globs(&self, globs: &[CopyGlob], target: &PathBuf, overwrite: &OverwriteOptions) -> Result<()> {
         // ensure the destination path exists
         ensure_path(target)?;
 
         for glob in globs {
-            self.copy_from_glob(glob, target, allow_overwrite)?;
+            self.copy_from_glob(glob, target, overwrite)?;
         }
         Ok(())
     }
 
-    fn copy_from_glob(&self, copy_glob: &CopyGlob, target: &PathBuf, allow_overwrite: bool) -> Result<()> {
+    fn copy_from_glob(&self, copy_glob: &CopyGlob, target: &Path, overwrite: &OverwriteOptions) -> Result<()> {
         match &copy_glob.glob {
             None => {
                 if copy_glob.base.is_dir() {
-                    copy_single_dir(&copy_glob.base, target, allow_overwrite)
+                    // content only
+                    copy_entry(&copy_glob.base, target, Path::new(""), overwrite)
                 } else {
-                    copy_single_file(&copy_glob.base, target, allow_overwrite)
+                    copy_entry(&copy_glob.base, target, Path::new(copy_glob.base.file_name().unwrap_or_default()), overwrite)
                 }
             },
             Some(glob) =>  {
@@ -97,11 +106,7 @@ impl <'a> FileInstaller<'a> {
                     Ok(glob) => glob,
                 };
                 for item in glob.into_iter().filter_map(Result::ok) {
-                    let copy_options = fs_extra::dir::CopyOptions {
-                        overwrite: allow_overwrite,
-                        ..Default::default()
-                    };
-                    let _bytes = fs_extra::copy_items(&vec![&item.into_path()], target, &copy_options)?;
+                    copy_entry(item.path(), target, Path::new(item.file_name()), overwrite)?;
                 }
                 Ok(())
             }
@@ -110,27 +115,42 @@ impl <'a> FileInstaller<'a> {
 
 }
 
-fn copy_single_file(path: &PathBuf, target: &PathBuf, allow_overwrite: bool) -> Result<()> {
-    let copy_options = fs_extra::dir::CopyOptions {
-        overwrite: allow_overwrite,
-        copy_inside: true,
-        ..Default::default()
-    };
-    if let Err(error) = fs_extra::copy_items(&[path], target, &copy_options) {
-        bail!("Could not copy single file {:?} to {:?}\n  {}", path, target, error);
+/// Copies a file or (recursively) the content of a directory to `target/relative`, applying the overwrite
+/// policy to each file.
+fn copy_entry(source: &Path, target: &Path, relative: &Path, overwrite: &OverwriteOptions) -> Result<()> {
+    let destination = target.join(relative);
+    if source.is_dir() {
+        ensure_path(&destination)?;
+        let entries = match std::fs::read_dir(source) {
+            Ok(entries) => entries,
+            Err(error) => bail!("Could not read directory {:?}\n  {}", source, error),
+        };
+        for entry in entries {
+            let entry = entry?;
+            copy_entry(&entry.path(), target, &relative.join(entry.file_name()), overwrite)?;
+        }
+        return Ok(());
     }
-    Ok(())
-}
-
-fn copy_single_dir(path: &PathBuf, target: &PathBuf, allow_overwrite: bool) -> Result<()> {
-    let copy_options = fs_extra::dir::CopyOptions {
-        overwrite: allow_overwrite,
-        copy_inside: true,
-        content_only: true,
-        ..Default::default()
-    };
-    if let Err(error) = fs_extra::dir::copy(path, target, &copy_options) {
-        bail!("Could not copy single dir {:?} to {:?}\n  {}", path, target, error);
+    if destination.exists() {
+        match overwrite.policy {
+            OverwritePolicy::Fail => bail!("Could not copy file {:?}, {:?} already exists", source, destination),
+            OverwritePolicy::Skip => {
+                info!("{:?} already exists, {:?} is not copied", destination, source);
+                return Ok(());
+            }
+            OverwritePolicy::Overwrite => if let Some(backup_dir) = &overwrite.backup_dir {
+                let backup = backup_dir.join(relative);
+                if let Some(parent) = backup.parent() {
+                    ensure_path(&parent.to_path_buf())?;
+                }
+                if let Err(error) = std::fs::copy(&destination, &backup) {
+                    bail!("Could not save overwritten file {:?} to {:?}\n  {}", destination, backup, error);
+                }
+            }
+        }
+    }
+    if let Err(error) = std::fs::copy(source, &destination) {
+        bail!("Could not copy file {:?} to {:?}\n  {}", source, destination, error);
     }
     Ok(())
 }
@@ -157,6 +177,37 @@ fn check_absolute(path: &str) -> Result<PathBuf> {
     }
 }
 
+/// What happens when a copied file already exists at its destination.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OverwritePolicy {
+    /// The existing file is kept
+    Skip,
+    /// The existing file is replaced
+    Overwrite,
+    /// The copy fails
+    #[default]
+    Fail,
+}
+
+impl OverwritePolicy {
+    /// Without an explicit policy, the (older) `allow_overwrite` flag decides between `overwrite` and `fail`.
+    pub fn with_allow_overwrite(policy: Option<OverwritePolicy>, allow_overwrite: bool) -> Self {
+        match policy {
+            Some(policy) => policy,
+            None if allow_overwrite => OverwritePolicy::Overwrite,
+            None => OverwritePolicy::Fail,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct OverwriteOptions {
+    pub policy: OverwritePolicy,
+    /// Where overwritten files are saved (with their path relative to the copy target)
+    pub backup_dir: Option<PathBuf>,
+}
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Default)]
 #[serde(untagged)]
 pub enum AllowOverwrite {
@@ -182,3 +233,92 @@ struct CopyGlob {
     pub base: PathBuf,
     pub glob: Option<String>,
 }
+
+#[cfg(test)]
+mod test_overwrite_policy {
+    use std::path::{Path, PathBuf};
+
+    use crate::args::Install;
+    use crate::canon_path::CanonPath;
+    use crate::global::Global;
+    use crate::module::file_module_origin::FileModuleOrigin;
+
+    use super::{FileInstaller, OverwriteOptions, OverwritePolicy};
+
+    /// Source directory with `a.itm` and `sub/b.itm`, target directory already containing an `a.itm`.
+    fn setup() -> (tempfile::TempDir, tempfile::TempDir, PathBuf) {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(source.path().join("sub")).unwrap();
+        std::fs::write(source.path().join("a.itm"), "new a").unwrap();
+        std::fs::write(source.path().join("sub/b.itm"), "new b").unwrap();
+        let game_dir = tempfile::tempdir().unwrap();
+        let target = game_dir.path().join("data");
+        std::fs::create_dir_all(&target).unwrap();
+        std::fs::write(target.join("a.itm"), "old a").unwrap();
+        (source, game_dir, target)
+    }
+
+    fn copy(source: &Path, game_dir: &Path, target: &Path, overwrite: &OverwriteOptions) -> anyhow::Result<()> {
+        let global = Global::default();
+        let opts = Install::default();
+        let game_dir = CanonPath::new(game_dir).unwrap();
+        let installer = FileInstaller::new(&global, &opts, &game_dir);
+        let origin = FileModuleOrigin::Absolute { absolute: source.to_str().unwrap().to_string(), glob: None };
+        installer.copy_from_origins(&[&origin], &target.to_path_buf(), overwrite)
+    }
+
+    fn read(path: PathBuf) -> String {
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn policy_fail() {
+        let (source, game_dir, target) = setup();
+        let overwrite = OverwriteOptions { policy: OverwritePolicy::Fail, backup_dir: None };
+        let error = copy(source.path(), game_dir.path(), &target, &overwrite).unwrap_err();
+        assert!(error.to_string().contains("already exists"), "{}", error);
+        assert_eq!(read(target.join("a.itm")), "old a");
+    }
+
+    #[test]
+    fn policy_skip() {
+        let (source, game_dir, target) = setup();
+        let overwrite = OverwriteOptions { policy: OverwritePolicy::Skip, backup_dir: None };
+        copy(source.path(), game_dir.path(), &target, &overwrite).unwrap();
+        assert_eq!(read(target.join("a.itm")), "old a");
+        assert_eq!(read(target.join("sub/b.itm")), "new b");
+    }
+
+    #[test]
+    fn policy_overwrite() {
+        let (source, game_dir, target) = setup();
+        let overwrite = OverwriteOptions { policy: OverwritePolicy::Overwrite, backup_dir: None };
+        copy(source.path(), game_dir.path(), &target, &overwrite).unwrap();
+        assert_eq!(read(target.join("a.itm")), "new a");
+        assert_eq!(read(target.join("sub/b.itm")), "new b");
+    }
+
+    #[test]
+    fn policy_overwrite_with_backup() {
+        let (source, game_dir, target) = setup();
+        let global = Global::default();
+        let opts = Install::default();
+        let canon_game_dir = CanonPath::new(game_dir.path()).unwrap();
+        let overwrite = FileInstaller::new(&global, &opts, &canon_game_dir)
+            .overwrite_options(None, true, Some("overwritten")).unwrap();
+        assert_eq!(overwrite.policy, OverwritePolicy::Overwrite);
+
+        copy(source.path(), game_dir.path(), &target, &overwrite).unwrap();
+        assert_eq!(read(target.join("a.itm")), "new a");
+        assert_eq!(read(canon_game_dir.path().join("overwritten/a.itm")), "old a");
+        // nothing was replaced
+        assert!(!canon_game_dir.path().join("overwritten/sub/b.itm").exists());
+    }
+
+    #[test]
+    fn default_policy() {
+        assert_eq!(OverwritePolicy::with_allow_overwrite(None, false), OverwritePolicy::Fail);
+        assert_eq!(OverwritePolicy::with_allow_overwrite(None, true), OverwritePolicy::Overwrite);
+        assert_eq!(OverwritePolicy::with_allow_overwrite(Some(OverwritePolicy::Skip), true), OverwritePolicy::Skip);
+    }
+}
@@ -0,0 +1,232 @@
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use globwalk::GlobWalkerBuilder;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::canon_path::CanonPath;
+use crate::lowercase::LwcString;
+
+/// Name of the ledger file, in the game directory
+pub const FILE_LEDGER_NAME: &str = "modda-files.yml";
+
+/// Files written by modda in the game directory outside of weidu tracking (generated mods), so that `reset`
+/// can remove them.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct FileLedger {
+    #[serde(default)]
+    pub entries: Vec<LedgerEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct LedgerEntry {
+    pub module: LwcString,
+    /// Relative to the game directory, with `/` separators
+    pub path: String,
+    /// SHA-256 of the content written by modda, a file with another content is not removed
+    pub sha256: String,
+}
+
+/// What happened to the files of a module when they were removed.
+#[derive(Debug, Default, PartialEq)]
+pub struct LedgerRemoval {
+    pub removed: Vec<String>,
+    /// Files that were modified since modda wrote them
+    pub kept: Vec<String>,
+}
+
+impl FileLedger {
+    pub fn read(game_dir: &CanonPath) -> Result<Self> {
+        let path = game_dir.join_path(FILE_LEDGER_NAME);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(error) => bail!("Could not read file ledger {:?}\n -> {:?}", path, error),
+        };
+        match serde_yaml::from_str(&content) {
+            Ok(ledger) => Ok(ledger),
+            Err(error) => bail!("Invalid file ledger {:?}\n -> {:?}", path, error),
+        }
+    }
+
+    pub fn write(&self, game_dir: &CanonPath) -> Result<()> {
+        let path = game_dir.join_path(FILE_LEDGER_NAME);
+        if self.entries.is_empty() {
+            if path.exists() {
+                std::fs::remove_file(&path)?;
+            }
+            return Ok(());
+        }
+        let content = serde_yaml::to_string(self)?;
+        if let Err(error) = std::fs::write(&path, content) {
+            bail!("Could not write file ledger {:?}\n -> {:?}", path, error);
+        }
+        Ok(())
+    }
+
+    /// Records all files currently in `dir` (inside the game directory) as written by modda for `module`.
+    pub fn record_dir(&mut self, game_dir: &CanonPath, module: &LwcString, dir: &Path) -> Result<()> {
+        let walker = match GlobWalkerBuilder::from_patterns(dir, &["**"]).build() {
+            Ok(walker) => walker,
+            Err(error) => bail!("Could not list files in {:?}\n -> {:?}", dir, error),
+        };
+        for entry in walker.filter_map(Result::ok).filter(|entry| entry.file_type().is_file()) {
+            let path = match relative_path(game_dir, entry.path()) {
+                None => bail!("File {:?} is not in the game directory", entry.path()),
+                Some(path) => path,
+            };
+            let sha256 = file_sha256(entry.path())?;
+            self.entries.retain(|existing| existing.path != path);
+            self.entries.push(LedgerEntry { module: module.to_owned(), path, sha256 });
+        }
+        Ok(())
+    }
+
+    pub fn modules(&self) -> Vec<LwcString> {
+        let mut modules: Vec<LwcString> = vec![];
+        for entry in &self.entries {
+            if !modules.contains(&entry.module) {
+                modules.push(entry.module.to_owned());
+            }
+        }
+        modules
+    }
+
+    /// Removes the files recorded for `module` that still have the content written by modda, then the
+    /// directories left empty.<br>
+    /// With `dry_run`, nothing is removed (the result tells what would be).
+    pub fn remove_module_files(&mut self, game_dir: &CanonPath, module: &LwcString, dry_run: bool) -> Result<LedgerRemoval> {
+        let mut removal = LedgerRemoval::default();
+        let mut dirs = vec![];
+        for entry in self.entries.iter().filter(|entry| entry.module == *module) {
+            let path = match game_dir.safe_join(&entry.path) {
+                Ok(path) => path,
+                Err(error) => bail!("Invalid path in file ledger {}\n -> {:?}", entry.path, error),
+            };
+            if !path.path().is_file() {
+                continue;
+            }
+            if file_sha256(path.path())? != entry.sha256 {
+                warn!("File {} was modified after it was written by modda, it is not removed", entry.path);
+                removal.kept.push(entry.path.to_owned());
+                continue;
+            }
+            if !dry_run {
+                if let Err(error) = std::fs::remove_file(path.path()) {
+                    bail!("Could not remove {:?}\n -> {:?}", path, error);
+                }
+                info!("removed {}", entry.path);
+            }
+            removal.removed.push(entry.path.to_owned());
+            dirs.extend(path.path().ancestors().skip(1)
+                .take_while(|dir| *dir != game_dir.path())
+                .map(|dir| dir.to_path_buf()));
+        }
+        if !dry_run {
+            remove_empty_dirs(dirs);
+            self.entries.retain(|entry| entry.module != *module);
+        }
+        Ok(removal)
+    }
+}
+
+fn remove_empty_dirs(mut dirs: Vec<PathBuf>) {
+    // deepest first, so that a parent is empty once its children were removed
+    dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+    dirs.dedup();
+    for dir in dirs {
+        // fails (and is kept) when not empty
+        let _ = std::fs::remove_dir(dir);
+    }
+}
+
+fn relative_path(game_dir: &CanonPath, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(game_dir.path()).ok()?;
+    let parts = relative.components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    Some(parts.join("/"))
+}
+
+fn file_sha256(path: &Path) -> Result<String> {
+    match std::fs::read(path) {
+        Ok(content) => Ok(format!("{:x}", Sha256::digest(content))),
+        Err(error) => bail!("Could not read {:?}\n -> {:?}", path, error),
+    }
+}
+
+#[cfg(test)]
+mod test_file_ledger {
+    use crate::canon_path::CanonPath;
+    use crate::lowercase::lwc;
+
+    use super::FileLedger;
+
+    fn setup() -> (tempfile::TempDir, CanonPath) {
+        let game_dir = tempfile::tempdir().unwrap();
+        let canon = CanonPath::new(game_dir.path()).unwrap();
+        for (path, content) in [
+            ("gen_mod/gen_mod.tp2", "BACKUP ~gen_mod/backup~"),
+            ("gen_mod/data/portrait.bmp", "BM..."),
+            ("gen_mod/data/sounds/hello.wav", "RIFF..."),
+            ("other_mod/other_mod.tp2", "BACKUP ~other_mod/backup~"),
+        ] {
+            let path = game_dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        (game_dir, canon)
+    }
+
+    #[test]
+    fn write_and_read_ledger() {
+        let (game_dir, canon) = setup();
+        let mut ledger = FileLedger::default();
+        ledger.record_dir(&canon, &lwc!("gen_mod"), &game_dir.path().join("gen_mod")).unwrap();
+        ledger.write(&canon).unwrap();
+
+        let read = FileLedger::read(&canon).unwrap();
+        let mut paths = read.entries.iter().map(|entry| entry.path.as_str()).collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(paths, vec!["gen_mod/data/portrait.bmp", "gen_mod/data/sounds/hello.wav", "gen_mod/gen_mod.tp2"]);
+        assert!(read.entries.iter().all(|entry| entry.module == lwc!("gen_mod") && entry.sha256.len() == 64));
+        assert_eq!(read.modules(), vec![lwc!("gen_mod")]);
+        assert_eq!(read, ledger);
+    }
+
+    #[test]
+    fn remove_only_unmodified_recorded_files() {
+        let (game_dir, canon) = setup();
+        let mut ledger = FileLedger::default();
+        ledger.record_dir(&canon, &lwc!("gen_mod"), &game_dir.path().join("gen_mod")).unwrap();
+        // modified by the user, and created by weidu: none of them must be removed
+        std::fs::write(game_dir.path().join("gen_mod/data/portrait.bmp"), "edited").unwrap();
+        std::fs::create_dir_all(game_dir.path().join("gen_mod/backup/0")).unwrap();
+        std::fs::write(game_dir.path().join("gen_mod/backup/0/UNINSTALL.0"), "").unwrap();
+
+        let dry_run = ledger.remove_module_files(&canon, &lwc!("gen_mod"), true).unwrap();
+        assert!(game_dir.path().join("gen_mod/gen_mod.tp2").exists());
+
+        let removal = ledger.remove_module_files(&canon, &lwc!("gen_mod"), false).unwrap();
+        assert_eq!(removal, dry_run);
+        let mut removed = removal.removed;
+        removed.sort();
+        assert_eq!(
+            (removed, removal.kept),
+            (
+                vec!["gen_mod/data/sounds/hello.wav".to_string(), "gen_mod/gen_mod.tp2".to_string()],
+                vec!["gen_mod/data/portrait.bmp".to_string()],
+            )
+        );
+        assert!(!game_dir.path().join("gen_mod/data/sounds").exists());
+        assert!(game_dir.path().join("gen_mod/data/portrait.bmp").exists());
+        assert!(game_dir.path().join("gen_mod/backup/0/UNINSTALL.0").exists());
+        assert!(game_dir.path().join("other_mod/other_mod.tp2").exists());
+        assert!(ledger.entries.is_empty());
+    }
+}
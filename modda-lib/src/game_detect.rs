@@ -0,0 +1,171 @@
+
+use std::fmt::Display;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use globwalk::GlobWalkerBuilder;
+use serde::{Deserialize, Serialize};
+
+/// Infinity engine games, as detected from the files in the game directory.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GameFlavor {
+    Bg1,
+    Bg2,
+    Iwd,
+    Iwd2,
+    Pst,
+    Bgee,
+    Bg2ee,
+    Eet,
+    Iwdee,
+    Pstee,
+}
+
+impl Display for GameFlavor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            GameFlavor::Bg1 => "bg1",
+            GameFlavor::Bg2 => "bg2",
+            GameFlavor::Iwd => "iwd",
+            GameFlavor::Iwd2 => "iwd2",
+            GameFlavor::Pst => "pst",
+            GameFlavor::Bgee => "bgee",
+            GameFlavor::Bg2ee => "bg2ee",
+            GameFlavor::Eet => "eet",
+            GameFlavor::Iwdee => "iwdee",
+            GameFlavor::Pstee => "pstee",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Best-effort detection, based on files known to be present in each game (case-insensitive).
+///
+/// Enhanced editions have an `engine.lua` file, and are told apart by their executable or some
+/// distinctive files (EET leaves an `EET.flag` in `override`).
+/// Returns `None` when the directory doesn't look like a known game.
+pub fn detect_game(game_dir: &Path) -> Result<Option<GameFlavor>> {
+    let has = |patterns: &[&str]| has_file(game_dir, patterns);
+    let flavor = if has(&["engine.lua"])? {
+        if has(&["override/eet.flag"])? {
+            Some(GameFlavor::Eet)
+        } else if has(&["movies/pocketzz.wbm"])? {
+            Some(GameFlavor::Bg2ee)
+        } else if has(&["icewind", "icewind.exe"])? {
+            Some(GameFlavor::Iwdee)
+        } else if has(&["torment", "torment64", "torment.exe"])? {
+            Some(GameFlavor::Pstee)
+        } else {
+            Some(GameFlavor::Bgee)
+        }
+    } else if has(&["bgmain.exe"])? {
+        Some(GameFlavor::Bg2)
+    } else if has(&["idmain.exe"])? {
+        Some(GameFlavor::Iwd)
+    } else if has(&["iwd2.exe"])? {
+        Some(GameFlavor::Iwd2)
+    } else if has(&["torment.exe"])? {
+        Some(GameFlavor::Pst)
+    } else if has(&["baldur.exe"])? {
+        Some(GameFlavor::Bg1)
+    } else {
+        None
+    };
+    Ok(flavor)
+}
+
+/// Fails when the game detected in `game_dir` is not the `expected` one (or no game is detected).
+pub fn check_expected_game(game_dir: &Path, expected: GameFlavor) -> Result<()> {
+    match detect_game(game_dir)? {
+        Some(detected) if detected == expected => Ok(()),
+        Some(detected) => bail!("The manifest is for {} but the game directory {:?} looks like {}\nUse --ignore-game-check to install anyway",
+                                expected, game_dir, detected),
+        None => bail!("The manifest is for {} but no known game was detected in {:?}\nUse --ignore-game-check to install anyway",
+                        expected, game_dir),
+    }
+}
+
+fn has_file(game_dir: &Path, patterns: &[&str]) -> Result<bool> {
+    let depth = patterns.iter().map(|pattern| pattern.split('/').count()).max().unwrap_or(1);
+    let glob = match GlobWalkerBuilder::from_patterns(game_dir, patterns)
+            .case_insensitive(true)
+            .max_depth(depth)
+            .build() {
+        Err(error) => bail!("Could not look up {:?} in game directory\n -> {:?}", patterns, error),
+        Ok(glob) => glob,
+    };
+    Ok(glob.into_iter().filter_map(Result::ok).next().is_some())
+}
+
+#[cfg(test)]
+mod test_game_detect {
+    use std::path::Path;
+
+    use super::{check_expected_game, detect_game, GameFlavor};
+
+    fn game_dir(files: &[&str]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for file in files {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        dir
+    }
+
+    fn detect(files: &[&str]) -> Option<GameFlavor> {
+        let dir = game_dir(files);
+        detect_game(dir.path()).unwrap()
+    }
+
+    #[test]
+    fn detect_enhanced_editions() {
+        assert_eq!(detect(&["chitin.key", "engine.lua", "Baldur.exe"]), Some(GameFlavor::Bgee));
+        assert_eq!(detect(&["chitin.key", "engine.lua", "Baldur.exe", "movies/POCKETZZ.WBM"]), Some(GameFlavor::Bg2ee));
+        assert_eq!(
+            detect(&["chitin.key", "engine.lua", "Baldur.exe", "movies/pocketzz.wbm", "override/EET.flag"]),
+            Some(GameFlavor::Eet)
+        );
+        assert_eq!(detect(&["chitin.key", "engine.lua", "Icewind.exe"]), Some(GameFlavor::Iwdee));
+        assert_eq!(detect(&["chitin.key", "engine.lua", "Torment64"]), Some(GameFlavor::Pstee));
+    }
+
+    #[test]
+    fn detect_original_games() {
+        assert_eq!(detect(&["chitin.key", "BGMain.exe"]), Some(GameFlavor::Bg2));
+        assert_eq!(detect(&["chitin.key", "Baldur.exe"]), Some(GameFlavor::Bg1));
+        assert_eq!(detect(&["chitin.key", "IDMain.exe"]), Some(GameFlavor::Iwd));
+        assert_eq!(detect(&["chitin.key", "iwd2.exe"]), Some(GameFlavor::Iwd2));
+        assert_eq!(detect(&["chitin.key", "Torment.exe"]), Some(GameFlavor::Pst));
+    }
+
+    #[test]
+    fn unknown_game() {
+        assert_eq!(detect(&["chitin.key"]), None);
+        assert_eq!(detect_game(Path::new("/does/not/exist")).unwrap(), None);
+    }
+
+    #[test]
+    fn expected_game_matches() {
+        let dir = game_dir(&["chitin.key", "engine.lua", "Baldur.exe", "movies/pocketzz.wbm"]);
+        check_expected_game(dir.path(), GameFlavor::Bg2ee).unwrap();
+    }
+
+    #[test]
+    fn expected_game_mismatch() {
+        let dir = game_dir(&["chitin.key", "engine.lua", "Baldur.exe"]);
+        let error = check_expected_game(dir.path(), GameFlavor::Bg2ee).unwrap_err();
+        assert!(error.to_string().contains("The manifest is for bg2ee but the game directory"));
+        assert!(error.to_string().contains("looks like bgee"));
+
+        let unknown = game_dir(&["chitin.key"]);
+        assert!(check_expected_game(unknown.path(), GameFlavor::Bg2ee).is_err());
+    }
+
+    #[test]
+    fn flavor_names() {
+        assert_eq!(GameFlavor::Bg2ee.to_string(), "bg2ee");
+        assert_eq!(serde_yaml::from_str::<GameFlavor>("eet").unwrap(), GameFlavor::Eet);
+    }
+}
@@ -1,6 +1,10 @@
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 
+use crate::game_detect::GameFlavor;
+use crate::lowercase::LwcString;
+use crate::module::location::replace::ReplaceSpec;
+
 
 
 #[skip_serializing_none]
@@ -22,4 +26,20 @@ pub struct Global {
     /// Path from manifest root (yml file location directory) where "local" mods can be found.
     pub local_mods: Option<String>,
     pub local_files: Option<String>,
+    /// Text replacements applied once all mods of the manifest are installed.<br>
+    /// Same syntax as the `replace` property of mod locations, but `file_globs` are relative to the
+    /// game directory (and can't leave it).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_install_replace: Vec<ReplaceSpec>,
+    /// Game this manifest is meant for (`bg2ee`, `eet`...), checked against the game detected in the game
+    /// directory before installing (`--ignore-game-check` skips this).
+    pub game: Option<GameFlavor>,
+    /// TLK file (relative to the game directory) used and updated by weidu instead of `dialog.tlk`
+    /// (`--tlkin`/`--tlkout`), for games with several TLK files.
+    pub tlk: Option<String>,
+    /// Same as `tlk` for the female TLK file (`--ftlkin`/`--ftlkout`).
+    pub ftlk: Option<String>,
+    /// Mods that are not installed (all their fragments), like with `install --skip`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skip: Vec<LwcString>,
 }
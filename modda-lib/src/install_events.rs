@@ -0,0 +1,66 @@
+
+use std::path::PathBuf;
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::lowercase::LwcString;
+
+/// Progress of an installation, for applications that embed the installer (the GUI).
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstallEvent {
+    DownloadStarted { mod_name: LwcString },
+    /// `total` is unknown when the server doesn't provide a content length
+    DownloadProgress { url: String, downloaded: u64, total: Option<u64> },
+    DownloadFinished { mod_name: LwcString, path: PathBuf },
+    ExtractStarted { mod_name: LwcString },
+    ExtractFinished { mod_name: LwcString },
+    WeiduStarted { mod_name: LwcString },
+    /// `status` is the weidu exit code (0 on success, 3 for warnings), none if weidu was killed or couldn't be run
+    WeiduFinished { mod_name: LwcString, status: Option<i32> },
+    ModDisabled { mod_name: LwcString, reason: String },
+}
+
+/// Sending side of the event channel. The default value discards all events.
+#[derive(Debug, Clone, Default)]
+pub struct EventSender(Option<UnboundedSender<InstallEvent>>);
+
+impl EventSender {
+    pub fn emit(&self, event: InstallEvent) {
+        if let Some(sender) = &self.0 {
+            // the receiver may have been dropped, this must not interrupt the installation
+            let _ = sender.send(event);
+        }
+    }
+}
+
+pub fn event_channel() -> (EventSender, UnboundedReceiver<InstallEvent>) {
+    let (sender, receiver) = unbounded_channel();
+    (EventSender(Some(sender)), receiver)
+}
+
+#[cfg(test)]
+mod test_events {
+    use crate::lowercase::lwc;
+
+    use super::{event_channel, EventSender, InstallEvent};
+
+    #[test]
+    fn events_are_received_in_order() {
+        let (sender, mut receiver) = event_channel();
+        sender.emit(InstallEvent::WeiduStarted { mod_name: lwc!("my_mod") });
+        sender.emit(InstallEvent::WeiduFinished { mod_name: lwc!("my_mod"), status: Some(0) });
+
+        assert_eq!(receiver.try_recv().unwrap(), InstallEvent::WeiduStarted { mod_name: lwc!("my_mod") });
+        assert_eq!(receiver.try_recv().unwrap(), InstallEvent::WeiduFinished { mod_name: lwc!("my_mod"), status: Some(0) });
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn emit_without_receiver_is_ignored() {
+        let (sender, receiver) = event_channel();
+        drop(receiver);
+        sender.emit(InstallEvent::ExtractStarted { mod_name: lwc!("my_mod") });
+
+        EventSender::default().emit(InstallEvent::ExtractStarted { mod_name: lwc!("my_mod") });
+    }
+}
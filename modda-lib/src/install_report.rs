@@ -0,0 +1,155 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::lowercase::LwcString;
+use crate::post_install::InstallStatus;
+use crate::process_weidu_mod::SkipReason;
+use crate::timeline::InstallTimeline;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportStatus {
+    Success,
+    /// Installed, weidu reported warnings that were ignored (or allowed)
+    WarningIgnored,
+    /// The installation stopped on this module
+    Failed,
+    SkippedDisabled,
+    /// Skipped with `--skip` or the `skip` list of the manifest
+    SkippedRequested,
+}
+
+impl ReportStatus {
+    pub fn new(skipped: Option<&SkipReason>, stopped: bool, status: InstallStatus) -> Self {
+        match (skipped, stopped, status) {
+            (Some(SkipReason::Disabled(_)), _, _) => ReportStatus::SkippedDisabled,
+            (Some(_), _, _) => ReportStatus::SkippedRequested,
+            (None, true, _) | (None, _, InstallStatus::Failed) => ReportStatus::Failed,
+            (None, false, InstallStatus::Warning) => ReportStatus::WarningIgnored,
+            (None, false, InstallStatus::Success) => ReportStatus::Success,
+        }
+    }
+}
+
+/// Timestamps (RFC 3339) of the installation steps of a module, absent for the steps that didn't happen.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub struct ReportTimeline {
+    pub start: String,
+    pub start_download: Option<String>,
+    pub downloaded: Option<String>,
+    pub copied: Option<String>,
+    pub patched: Option<String>,
+    pub replaced: Option<String>,
+    pub configured: Option<String>,
+    pub start_install: Option<String>,
+    pub installed: Option<String>,
+    /// Size difference of the game directory (only measured with `--disk-usage`)
+    pub added_bytes: Option<i64>,
+    /// Install time reported by weidu in its debug log, in seconds
+    pub weidu_reported: Option<f64>,
+}
+
+impl From<&InstallTimeline> for ReportTimeline {
+    fn from(timeline: &InstallTimeline) -> Self {
+        let date = |date: Option<DateTime<Local>>| date.map(|date| date.to_rfc3339());
+        ReportTimeline {
+            start: timeline.start.to_rfc3339(),
+            start_download: date(timeline.start_download),
+            downloaded: date(timeline.downloaded),
+            copied: date(timeline.copied),
+            patched: date(timeline.patched),
+            replaced: date(timeline.replaced),
+            configured: date(timeline.configured),
+            start_install: date(timeline.start_install),
+            installed: date(timeline.installed),
+            added_bytes: timeline.added_bytes,
+            weidu_reported: timeline.weidu_reported.map(|duration| duration.num_milliseconds() as f64 / 1000.0),
+        }
+    }
+}
+
+/// What happened to one module processed by `install --report`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ReportEntry {
+    pub name: LwcString,
+    /// Index in the manifest, counting from one
+    pub index: usize,
+    pub status: ReportStatus,
+    pub disabled: bool,
+    pub timeline: ReportTimeline,
+}
+
+impl ReportEntry {
+    pub fn new(index: usize, name: &LwcString, status: ReportStatus, timeline: &InstallTimeline) -> Self {
+        ReportEntry {
+            name: name.to_owned(),
+            index,
+            status,
+            disabled: status == ReportStatus::SkippedDisabled,
+            timeline: ReportTimeline::from(timeline),
+        }
+    }
+}
+
+/// Writes the report (a JSON array, one entry per processed module, in order).
+pub fn write_report(path: &Path, entries: &[ReportEntry]) -> Result<()> {
+    if let Err(error) = std::fs::write(path, serde_json::to_string_pretty(entries)?) {
+        bail!("Could not write installation report to {:?}\n -> {:?}", path, error);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_report {
+    use chrono::{Duration, Local};
+
+    use crate::lowercase::lwc;
+    use crate::post_install::InstallStatus;
+    use crate::process_weidu_mod::SkipReason;
+    use crate::timeline::InstallTimeline;
+
+    use super::{write_report, ReportEntry, ReportStatus};
+
+    #[test]
+    fn report_status() {
+        let disabled = SkipReason::Disabled("not ready".to_string());
+        assert_eq!(ReportStatus::new(Some(&disabled), false, InstallStatus::Success), ReportStatus::SkippedDisabled);
+        assert_eq!(ReportStatus::new(Some(&SkipReason::Requested), false, InstallStatus::Success),
+                    ReportStatus::SkippedRequested);
+        assert_eq!(ReportStatus::new(None, true, InstallStatus::Warning), ReportStatus::Failed);
+        assert_eq!(ReportStatus::new(None, true, InstallStatus::Failed), ReportStatus::Failed);
+        assert_eq!(ReportStatus::new(None, false, InstallStatus::Warning), ReportStatus::WarningIgnored);
+        assert_eq!(ReportStatus::new(None, false, InstallStatus::Success), ReportStatus::Success);
+    }
+
+    #[test]
+    fn write_report_entries() {
+        let start = Local::now();
+        let mut timeline = InstallTimeline::new(lwc!("my_mod"), start);
+        timeline.start_install = Some(start);
+        timeline.installed = Some(start + Duration::seconds(5));
+        timeline.weidu_reported = Some(Duration::milliseconds(4_500));
+        let entries = vec![
+            ReportEntry::new(1, &lwc!("my_mod"), ReportStatus::WarningIgnored, &timeline),
+            ReportEntry::new(2, &lwc!("other_mod"), ReportStatus::SkippedDisabled, &InstallTimeline::new(lwc!("other_mod"), start)),
+        ];
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("report.json");
+        write_report(&path, &entries).unwrap();
+
+        let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(report[0]["name"], "my_mod");
+        assert_eq!(report[0]["status"], "warning_ignored");
+        assert_eq!(report[0]["disabled"], false);
+        assert_eq!(report[0]["timeline"]["weidu_reported"], 4.5);
+        assert_eq!(report[0]["timeline"]["start_install"], start.to_rfc3339());
+        assert_eq!(report[1]["index"], 2);
+        assert_eq!(report[1]["status"], "skipped_disabled");
+        assert_eq!(report[1]["disabled"], true);
+        assert!(report[1]["timeline"]["installed"].is_null());
+        assert_eq!(serde_json::from_value::<Vec<ReportEntry>>(report).unwrap(), entries);
+    }
+}
@@ -0,0 +1,182 @@
+
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::lowercase::LwcString;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryOutcome {
+    Installed,
+    /// Installed, weidu reported (accepted) warnings
+    Warning,
+    Skipped,
+    /// The installation stopped on this module
+    Failed,
+}
+
+/// What happened to one module of the manifest.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct SummaryRecord {
+    /// Index in the manifest, counting from one
+    pub index: usize,
+    pub name: LwcString,
+    pub outcome: SummaryOutcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// RFC 3339 timestamps
+    pub start: String,
+    pub end: String,
+}
+
+impl SummaryRecord {
+    pub fn new(index: usize, name: &LwcString, outcome: SummaryOutcome, reason: Option<String>,
+                start: DateTime<Local>) -> Self {
+        SummaryRecord {
+            index, name: name.to_owned(), outcome, reason,
+            start: start.to_rfc3339(), end: Local::now().to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Summary {
+    pub modules: Vec<SummaryRecord>,
+}
+
+/// `install --summary`: records are appended (one JSON document per line) as soon as each module is done, so that
+/// a crash leaves the records of the modules already processed. Once the installation is over, `compact` rewrites
+/// the file as a single JSON document.
+pub struct SummaryWriter {
+    path: PathBuf,
+}
+
+impl SummaryWriter {
+    /// Starts a new summary, an existing file is emptied.
+    pub fn create(path: &Path) -> Result<Self> {
+        if let Err(error) = OpenOptions::new().create(true).write(true).truncate(true).open(path) {
+            bail!("Could not create summary file {:?}\n -> {:?}", path, error);
+        }
+        Ok(SummaryWriter { path: path.to_path_buf() })
+    }
+
+    /// Appends the record as one line, with a single write so that concurrent writers don't mix their lines.
+    pub fn append(&self, record: &SummaryRecord) -> Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        let mut file = match OpenOptions::new().append(true).open(&self.path) {
+            Ok(file) => file,
+            Err(error) => bail!("Could not open summary file {:?}\n -> {:?}", self.path, error),
+        };
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// Replaces the line-by-line records with a single JSON document.
+    pub fn compact(&self) -> Result<()> {
+        let summary = Summary { modules: read_summary_records(&self.path)? };
+        let temp_path = self.path.with_extension("compact");
+        std::fs::write(&temp_path, serde_json::to_string_pretty(&summary)?)?;
+        if let Err(error) = std::fs::rename(&temp_path, &self.path) {
+            bail!("Failed to rename temp summary file {:?} to {:?}\n -> {:?}", temp_path, self.path, error);
+        }
+        debug!("compacted summary file {:?}", self.path);
+        Ok(())
+    }
+}
+
+/// Reads the records of a summary that was not compacted. A line that can't be read (the last one, if modda was
+/// killed while writing it) is ignored.
+pub fn read_summary_records(path: &Path) -> Result<Vec<SummaryRecord>> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(error) => bail!("Could not open summary file {:?}\n -> {:?}", path, error),
+    };
+    let mut records = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(record) => records.push(record),
+            Err(error) => warn!("Ignored invalid summary record {:?}\n -> {:?}", line, error),
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod test_summary {
+    use chrono::Local;
+
+    use crate::lowercase::lwc;
+
+    use super::{read_summary_records, Summary, SummaryOutcome, SummaryRecord, SummaryWriter};
+
+    fn records() -> Vec<SummaryRecord> {
+        vec![
+            SummaryRecord::new(1, &lwc!("first_mod"), SummaryOutcome::Installed, None, Local::now()),
+            SummaryRecord::new(2, &lwc!("second_mod"), SummaryOutcome::Skipped, Some("disabled".to_string()), Local::now()),
+        ]
+    }
+
+    #[test]
+    fn interrupted_after_two_mods() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("summary.json");
+        std::fs::write(&path, "previous content\n").unwrap();
+        let writer = SummaryWriter::create(&path).unwrap();
+        for record in records() {
+            writer.append(&record).unwrap();
+        }
+        // no compact: the installation was interrupted
+        drop(writer);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        for (line, expected) in lines.iter().zip(records()) {
+            let record: SummaryRecord = serde_json::from_str(line).unwrap();
+            assert_eq!((record.index, record.name, record.outcome, record.reason),
+                        (expected.index, expected.name, expected.outcome, expected.reason));
+        }
+    }
+
+    #[test]
+    fn truncated_last_record_is_ignored() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("summary.json");
+        let writer = SummaryWriter::create(&path).unwrap();
+        let record = records().remove(0);
+        writer.append(&record).unwrap();
+        let mut content = std::fs::read_to_string(&path).unwrap();
+        content.push_str("{\"index\":2,\"na");
+        std::fs::write(&path, content).unwrap();
+
+        assert_eq!(read_summary_records(&path).unwrap(), vec![record]);
+    }
+
+    #[test]
+    fn compact_to_single_document() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("summary.json");
+        let writer = SummaryWriter::create(&path).unwrap();
+        let records = records();
+        for record in &records {
+            writer.append(record).unwrap();
+        }
+        writer.compact().unwrap();
+
+        let summary: Summary = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(summary, Summary { modules: records });
+        assert!(!temp_dir.path().join("summary.compact").exists());
+    }
+}
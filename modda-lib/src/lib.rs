@@ -7,9 +7,15 @@ pub mod cache;
 pub mod canon_path;
 pub mod chitin;
 pub mod credentials;
+pub mod disk_usage;
 pub mod download;
 pub mod file_installer;
+pub mod file_ledger;
+pub mod game_detect;
 pub mod global;
+pub mod install_events;
+pub mod install_report;
+pub mod install_summary;
 pub mod list_components;
 pub mod log_parser;
 pub mod lowercase;
@@ -21,6 +27,7 @@ pub mod patch_source;
 pub mod post_install;
 pub mod process_weidu_mod;
 pub mod progname;
+pub mod rate_limit;
 pub mod run_result;
 pub mod run_weidu;
 pub mod config;
@@ -28,7 +35,10 @@ pub mod sub;
 pub mod timeline;
 pub mod tp2;
 pub mod tp2_template;
+pub mod tra_encoding;
+pub mod transform_preview;
 pub mod unique_component;
 pub mod utils;
 pub mod weidu_conf;
+pub mod weidu_warnings;
 pub mod modda_context;
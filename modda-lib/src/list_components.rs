@@ -1,8 +1,14 @@
 
 use anyhow::{bail, Result};
+use clap_derive::ValueEnum;
+use itertools::Itertools;
+use regex::RegexBuilder;
+use serde::Serialize;
 
 use crate::lowercase::LwcString;
 use crate::modda_context::WeiduContext;
+use crate::module::components::{Component, Components, FullComponent};
+use crate::module::manifest::reformat_to_yaml_comments;
 use crate::tp2::find_tp2_str;
 use crate::run_weidu::{run_weidu_list_components, WeiduComponent};
 
@@ -17,3 +23,312 @@ pub fn list_components(module_name: &LwcString, lang_index: u32, weidu_context:
         }
     }
 }
+
+#[derive(Debug, Copy, Clone, PartialEq, ValueEnum)]
+pub enum ComponentSort {
+    /// Component number (`DESIGNATED`)
+    Index,
+    /// Component name (case-insensitive)
+    Name,
+    /// First `GROUP` of the component (components without group come first)
+    Group,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, ValueEnum)]
+pub enum ComponentGrouping {
+    /// weidu `GROUP` (a component appears under each of its groups)
+    Group,
+    /// `SUBCOMPONENT` (options of the same choice)
+    Subgroup,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, ValueEnum)]
+pub enum ComponentListFormat {
+    /// One `number - name` line per component
+    Text,
+    /// The weidu component list (index, number, forced, name, subgroup, group)
+    Json,
+    /// One line per component, with a header (`group` values are separated with `;`)
+    Csv,
+}
+
+/// Formats the component list in the requested format, `group_by` only applies to the text format.
+pub fn format_components_as(list: &[WeiduComponent], format: ComponentListFormat, sort: Option<ComponentSort>,
+                            group_by: Option<ComponentGrouping>) -> Result<String> {
+    if format != ComponentListFormat::Text && group_by.is_some() {
+        bail!("Components can only be grouped with the text format");
+    }
+    match format {
+        ComponentListFormat::Text => Ok(format_components(list, sort, group_by)),
+        ComponentListFormat::Json => Ok(serde_json::to_string_pretty(&sort_components(list, sort))?),
+        ComponentListFormat::Csv => Ok(components_csv(&sort_components(list, sort))),
+    }
+}
+
+fn sort_components(list: &[WeiduComponent], sort: Option<ComponentSort>) -> Vec<&WeiduComponent> {
+    let mut sorted = list.iter().collect::<Vec<_>>();
+    match sort {
+        None => {}
+        Some(ComponentSort::Index) => sorted.sort_by_key(|comp| comp.number),
+        Some(ComponentSort::Name) => sorted.sort_by_key(|comp| comp.name.to_lowercase()),
+        Some(ComponentSort::Group) => sorted.sort_by_key(|comp| comp.group.first().map(|group| group.to_lowercase())),
+    }
+    sorted
+}
+
+fn components_csv(list: &[&WeiduComponent]) -> String {
+    let lines = list.iter().map(|comp| [
+        comp.index.to_string(),
+        comp.number.to_string(),
+        csv_field(&comp.name),
+        csv_field(comp.subgroup.as_deref().unwrap_or_default()),
+        csv_field(&comp.group.join(";")),
+    ].join(","));
+    std::iter::once("index,number,name,subgroup,group".to_string()).chain(lines).join("\n")
+}
+
+/// Quotes the value when it contains a separator, a quote or a line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Formats the component list, one component per line.<br>
+/// The sort is stable (components with the same key stay in weidu order), groups appear in the order
+/// of their first component.
+pub fn format_components(list: &[WeiduComponent], sort: Option<ComponentSort>,
+                            group_by: Option<ComponentGrouping>) -> String {
+    let sorted = sort_components(list, sort);
+    let line = |comp: &WeiduComponent| format!("{} - {}", comp.number, comp.name);
+    let grouped = match group_by {
+        None => return sorted.into_iter().map(line).join("\n"),
+        Some(ComponentGrouping::Group) => group_by_first_occurrence(sorted.into_iter()
+            .flat_map(|comp| match comp.group.as_slice() {
+                [] => vec![(None, comp)],
+                groups => groups.iter().map(|group| (Some(group.as_str()), comp)).collect(),
+            })),
+        Some(ComponentGrouping::Subgroup) => group_by_first_occurrence(sorted.into_iter()
+            .map(|comp| (comp.subgroup.as_deref(), comp))),
+    };
+    grouped.into_iter()
+        .map(|(title, comps)| format!("[{}]\n{}",
+            title.unwrap_or("-"),
+            comps.into_iter().map(|comp| format!("  {}", line(comp))).join("\n")
+        ))
+        .join("\n")
+}
+
+type ComponentGroup<'a> = (Option<&'a str>, Vec<&'a WeiduComponent>);
+
+fn group_by_first_occurrence<'a>(items: impl Iterator<Item = (Option<&'a str>, &'a WeiduComponent)>) -> Vec<ComponentGroup<'a>> {
+    let mut groups: Vec<ComponentGroup<'a>> = vec![];
+    for (key, comp) in items {
+        match groups.iter_mut().find(|(existing, _)| *existing == key) {
+            Some((_, comps)) => comps.push(comp),
+            None => groups.push((key, vec![comp])),
+        }
+    }
+    groups
+}
+
+#[derive(Serialize)]
+struct ComponentsSnippet {
+    components: Components,
+}
+
+/// YAML `components` property (component names as comments) that can be pasted in a mod definition.<br>
+/// `filter` is a (case-insensitive) regex, only components with a matching name are kept.
+pub fn components_snippet(list: &[WeiduComponent], filter: Option<&str>) -> Result<String> {
+    let filter = match filter.map(|filter| RegexBuilder::new(filter).case_insensitive(true).build()) {
+        None => None,
+        Some(Ok(regex)) => Some(regex),
+        Some(Err(error)) => bail!("Invalid component filter\n -> {:?}", error),
+    };
+    let components = list.iter()
+        .filter(|comp| filter.as_ref().is_none_or(|regex| regex.is_match(&comp.name)))
+        .map(|comp| Component::Full(FullComponent { index: comp.number, component_name: comp.name.to_owned(), language: None }))
+        .collect::<Vec<_>>();
+    if components.is_empty() {
+        bail!("No component to dump");
+    }
+    let snippet = serde_yaml::to_string(&ComponentsSnippet { components: Components::List(components) })?;
+    reformat_to_yaml_comments(&snippet)
+}
+
+#[cfg(test)]
+mod test_format_components {
+    use indoc::indoc;
+
+    use crate::run_weidu::WeiduComponent;
+
+    use super::{format_components, format_components_as, ComponentGrouping, ComponentListFormat, ComponentSort};
+
+    fn component(number: u32, name: &str, group: &[&str], subgroup: Option<&str>) -> WeiduComponent {
+        WeiduComponent {
+            index: number, number, forced: false, name: name.to_string(),
+            subgroup: subgroup.map(|subgroup| subgroup.to_string()),
+            group: group.iter().map(|group| group.to_string()).collect(),
+        }
+    }
+
+    fn sample() -> Vec<WeiduComponent> {
+        vec![
+            component(20, "Romance speed: fast", &["NPC tweaks"], Some("Romance speed")),
+            component(3, "Bigger stacks", &["Items"], None),
+            component(21, "Romance speed: slow", &["NPC tweaks"], Some("Romance speed")),
+            component(1, "all spells", &[], None),
+            component(10, "Better arrows", &["Items", "Rules"], None),
+        ]
+    }
+
+    #[test]
+    fn weidu_order() {
+        assert_eq!(
+            format_components(&sample(), None, None),
+            indoc!("
+                20 - Romance speed: fast
+                3 - Bigger stacks
+                21 - Romance speed: slow
+                1 - all spells
+                10 - Better arrows"
+            ).trim_start()
+        );
+    }
+
+    #[test]
+    fn sorted_by_index_and_name() {
+        assert_eq!(
+            format_components(&sample(), Some(ComponentSort::Index), None),
+            "1 - all spells\n3 - Bigger stacks\n10 - Better arrows\n20 - Romance speed: fast\n21 - Romance speed: slow"
+        );
+        assert_eq!(
+            format_components(&sample(), Some(ComponentSort::Name), None),
+            "1 - all spells\n10 - Better arrows\n3 - Bigger stacks\n20 - Romance speed: fast\n21 - Romance speed: slow"
+        );
+    }
+
+    #[test]
+    fn sorted_by_group_is_stable() {
+        assert_eq!(
+            format_components(&sample(), Some(ComponentSort::Group), None),
+            "1 - all spells\n3 - Bigger stacks\n10 - Better arrows\n20 - Romance speed: fast\n21 - Romance speed: slow"
+        );
+    }
+
+    #[test]
+    fn grouped_by_group() {
+        assert_eq!(
+            format_components(&sample(), Some(ComponentSort::Index), Some(ComponentGrouping::Group)),
+            indoc!("
+                [-]
+                  1 - all spells
+                [Items]
+                  3 - Bigger stacks
+                  10 - Better arrows
+                [Rules]
+                  10 - Better arrows
+                [NPC tweaks]
+                  20 - Romance speed: fast
+                  21 - Romance speed: slow"
+            ).trim_start()
+        );
+    }
+
+    #[test]
+    fn grouped_by_subgroup() {
+        assert_eq!(
+            format_components(&sample(), None, Some(ComponentGrouping::Subgroup)),
+            indoc!("
+                [Romance speed]
+                  20 - Romance speed: fast
+                  21 - Romance speed: slow
+                [-]
+                  3 - Bigger stacks
+                  1 - all spells
+                  10 - Better arrows"
+            ).trim_start()
+        );
+    }
+
+    #[test]
+    fn csv_format() {
+        let mut list = sample();
+        list[1].name = "Bigger stacks, \"really\"".to_string();
+        assert_eq!(
+            format_components_as(&list, ComponentListFormat::Csv, Some(ComponentSort::Index), None).unwrap(),
+            indoc!(r#"
+                index,number,name,subgroup,group
+                1,1,all spells,,
+                3,3,"Bigger stacks, ""really""",,Items
+                10,10,Better arrows,,Items;Rules
+                20,20,Romance speed: fast,Romance speed,NPC tweaks
+                21,21,Romance speed: slow,Romance speed,NPC tweaks"#
+            ).trim_start()
+        );
+    }
+
+    #[test]
+    fn json_format() {
+        let json = format_components_as(&sample()[..2], ComponentListFormat::Json, None, None).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value, serde_json::json!([
+            { "index": 20, "number": 20, "forced": false, "name": "Romance speed: fast",
+                "subgroup": "Romance speed", "group": ["NPC tweaks"] },
+            { "index": 3, "number": 3, "forced": false, "name": "Bigger stacks", "subgroup": null, "group": ["Items"] },
+        ]));
+        assert!(format_components_as(&sample(), ComponentListFormat::Json, None, Some(ComponentGrouping::Group)).is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_components_snippet {
+    use indoc::indoc;
+
+    use crate::run_weidu::WeiduComponent;
+
+    use super::components_snippet;
+
+    fn component(index: u32, number: u32, name: &str) -> WeiduComponent {
+        WeiduComponent { index, number, forced: false, name: name.to_string(), subgroup: None, group: vec![] }
+    }
+
+    fn sample() -> Vec<WeiduComponent> {
+        vec![
+            component(0, 0, "Ice Island Level Two Restoration"),
+            component(1, 11, "Scar and the Sashenstar's Daughter"),
+            component(2, 16, "Creature Corrections"),
+            component(3, 17, "Creature Restorations"),
+        ]
+    }
+
+    #[test]
+    fn snippet() {
+        assert_eq!(
+            components_snippet(&sample(), None).unwrap(),
+            indoc!("
+                components:
+                - 0 # Ice Island Level Two Restoration
+                - 11 # Scar and the Sashenstar's Daughter
+                - 16 # Creature Corrections
+                - 17 # Creature Restorations
+            ").trim_start()
+        );
+    }
+
+    #[test]
+    fn filtered_snippet() {
+        assert_eq!(
+            components_snippet(&sample(), Some("^creature")).unwrap(),
+            indoc!("
+                components:
+                - 16 # Creature Corrections
+                - 17 # Creature Restorations
+            ").trim_start()
+        );
+        assert!(components_snippet(&sample(), Some("nothing like this")).is_err());
+        assert!(components_snippet(&sample(), Some("(")).is_err());
+    }
+}
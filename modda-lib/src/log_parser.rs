@@ -4,17 +4,22 @@
 use std::collections::HashSet;
 use std::fs::File;
 use std::io::BufReader;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, bail, Result};
+use fs2::FileExt;
 use lazy_static::lazy_static;
 use log::{warn, info};
 use regex::{Regex, RegexBuilder};
 
+use crate::canon_path::CanonPath;
+use crate::global::Global;
 use crate::lowercase::LwcString;
+use crate::modda_context::WeiduContext;
 use crate::module::components::Components;
 use crate::module::module::Module;
 use crate::module::weidu_mod::WeiduMod;
+use crate::run_weidu::component_indexes;
 use crate::utils::bufread_raw::BufReadRaw;
 
 // doesn't support --quick-log generated logs ATM
@@ -33,10 +38,14 @@ lazy_static! {
 }
 
 pub fn parse_weidu_log(mod_filter: Option<&LwcString>) -> Result<Vec<LogRow>> {
-    if !PathBuf::from("weidu.log").exists() {
+    parse_weidu_log_file(&PathBuf::from("weidu.log"), mod_filter)
+}
+
+pub fn parse_weidu_log_file(log_path: &Path, mod_filter: Option<&LwcString>) -> Result<Vec<LogRow>> {
+    if !log_path.exists() {
         return Ok(vec![]);
     }
-    let weidu_log = match std::fs::File::open("weidu.log") { // TODO: handle case variations
+    let weidu_log = match std::fs::File::open(log_path) { // TODO: handle case variations
         Err(error) => return Err(
             anyhow!(format!("Could not open weidu.log - {:?}", error)
         )),
@@ -88,15 +97,45 @@ pub fn parse_weidu_log(mod_filter: Option<&LwcString>) -> Result<Vec<LogRow>> {
     result
 }
 
-pub fn check_install_complete(module: &Module) -> Result<()> {
+const WEIDU_LOG_LOCKED_HELP: &str = "weidu.log is locked by another process.
+Close any program that holds it open (text editor, file viewer...) or exclude the game directory from antivirus scanning, then retry.";
+
+/// Fails with an actionable message if `weidu.log` can't be written to because another process holds it.
+pub fn check_weidu_log_not_locked(game_dir: &CanonPath) -> Result<()> {
+    let log_path = game_dir.join_path("weidu.log");
+    if !log_path.exists() {
+        return Ok(());
+    }
+    let file = match std::fs::OpenOptions::new().read(true).append(true).open(&log_path) {
+        Ok(file) => file,
+        Err(error) if is_lock_error(&error) => bail!("{WEIDU_LOG_LOCKED_HELP}\n -> {:?}", error),
+        Err(error) => bail!("Could not open weidu.log for writing\n -> {:?}", error),
+    };
+    match file.try_lock_exclusive() {
+        Ok(_) => {
+            file.unlock()?;
+            Ok(())
+        }
+        Err(error) if is_lock_error(&error) => bail!("{WEIDU_LOG_LOCKED_HELP}\n -> {:?}", error),
+        Err(error) => bail!("Could not check whether weidu.log is locked\n -> {:?}", error),
+    }
+}
+
+fn is_lock_error(error: &std::io::Error) -> bool {
+    // 32 = ERROR_SHARING_VIOLATION, 33 = ERROR_LOCK_VIOLATION on windows
+    error.kind() == fs2::lock_contended_error().kind()
+        || (cfg!(windows) && matches!(error.raw_os_error(), Some(32) | Some(33)))
+}
+
+pub fn check_install_complete(module: &Module, global: &Global, weidu_context: &WeiduContext) -> Result<()> {
     match module {
-        Module::Mod { weidu_mod } => check_install_weidu_mod(weidu_mod),
-        Module::Generated { gen } => check_install_weidu_mod(&gen.as_weidu()),
+        Module::Mod { weidu_mod } => check_install_weidu_mod(weidu_mod, global, weidu_context),
+        Module::Generated { gen } => check_install_weidu_mod(&gen.as_weidu(), global, weidu_context),
     }
 }
 
-fn check_install_weidu_mod(weidu_mod: &WeiduMod) -> Result<()> {
-    match check_installed_components(weidu_mod) {
+fn check_install_weidu_mod(weidu_mod: &WeiduMod, global: &Global, weidu_context: &WeiduContext) -> Result<()> {
+    match check_installed_components(weidu_mod, global, weidu_context) {
         Err(err) => return Err(err),
         Ok(missing) => if !missing.is_empty() {
             bail!("All requested components for mod {} could not be installed.\nMissing: {:?}", weidu_mod.name , missing);
@@ -104,25 +143,346 @@ fn check_install_weidu_mod(weidu_mod: &WeiduMod) -> Result<()> {
     }
 }
 
-fn check_installed_components(module: &WeiduMod) -> Result<Vec<u32>> {
+fn check_installed_components(module: &WeiduMod, global: &Global, weidu_context: &WeiduContext) -> Result<Vec<u32>> {
+    if let Err(error) = module.components.check_file_resolved() {
+        bail!("Could not check installed components of mod {}\n -> {:?}", module.name, error);
+    }
     match &module.components {
         Components::None => Ok(vec![]),
         Components::Ask => Ok(vec![]),
         Components::All => Ok(vec![]),
+        Components::FromFile(_) => unreachable!("components file checked above"),
         Components::List(components) => {
-            let log_rows = match parse_weidu_log(Some(&module.name)) {
+            let log_path = weidu_context.current_dir.join_path("weidu.log");
+            let log_rows = match parse_weidu_log_file(&log_path, Some(&module.name)) {
                 Ok(log_rows) => log_rows,
                 Err(err) => bail!("Could not check installed components\n -> {:?}", err),
             };
             let installed = log_rows.iter().map(|row| row.component_index).collect::<HashSet<_>>();
             info!("installed={:?}", installed);
 
-            let missing = components.iter().filter(|component|
-                !installed.contains(&component.index())
-            )
-            .map(|component| component.index())
-            .collect::<Vec<_>>();
+            let missing = component_indexes(module, components, global, weidu_context)?.into_iter()
+                .filter(|index| !installed.contains(index))
+                .collect::<Vec<_>>();
             Ok(missing)
         }
     }
 }
+
+/// Comment lines written by weidu itself at the top of `weidu.log`
+const WEIDU_LOG_HEADER: &[&str] = &[
+    "// Log of Currently Installed WeiDU Mods",
+    "// The top of the file is the 'oldest' mod",
+    "// ~TP2_File~ #language_strings.tra_index #component_number // [Subcomponent Name -> ] Component Name [ : Version]",
+];
+
+/// (lowercase mod name, component index)
+pub type ComponentKey = (String, u32);
+
+/// Consecutive comment lines of `weidu.log` that weidu didn't write itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommentBlock {
+    /// Component line above the comments
+    /// (`None` if the comments are before the first component).
+    pub after: Option<ComponentKey>,
+    pub lines: Vec<String>,
+}
+
+fn component_key(line: &str) -> Option<ComponentKey> {
+    let cap = TP2_REGEX.captures(line)?;
+    let module = cap.get(1)?.as_str().to_lowercase();
+    let component = cap.get(3)?.as_str().parse::<u32>().ok()?;
+    Some((module, component))
+}
+
+fn is_user_comment(line: &str) -> bool {
+    line.starts_with("//") && !WEIDU_LOG_HEADER.contains(&line.trim_end())
+}
+
+/// Collects the comment blocks of a `weidu.log` content, with the component they follow.
+pub fn extract_comment_blocks(content: &str) -> Vec<CommentBlock> {
+    let mut blocks: Vec<CommentBlock> = vec![];
+    let mut after = None;
+    let mut in_block = false;
+    for line in content.lines() {
+        if let Some(key) = component_key(line) {
+            after = Some(key);
+            in_block = false;
+        } else if is_user_comment(line) {
+            match blocks.last_mut() {
+                Some(block) if in_block => block.lines.push(line.to_owned()),
+                _ => blocks.push(CommentBlock { after: after.clone(), lines: vec![line.to_owned()] }),
+            }
+            in_block = true;
+        } else {
+            in_block = false;
+        }
+    }
+    blocks
+}
+
+/// Re-inserts comment blocks that are missing from `content` (a `weidu.log` rewritten by weidu).<br>
+/// Each block goes after the component it followed, or after the nearest preceding component still present
+/// when it was uninstalled.
+pub fn restore_comment_blocks(content: &str, blocks: &[CommentBlock], previous_order: &[ComponentKey]) -> String {
+    let lines = content.lines().collect::<Vec<_>>();
+    let existing = lines.iter().map(|line| line.trim_end()).collect::<HashSet<_>>();
+    let present = lines.iter().filter_map(|line| component_key(line)).collect::<HashSet<_>>();
+
+    let mut inserted: Vec<(Option<ComponentKey>, &CommentBlock)> = vec![];
+    for block in blocks {
+        if block.lines.iter().all(|line| existing.contains(line.trim_end())) {
+            continue; // kept by weidu
+        }
+        let anchor = match &block.after {
+            None => None,
+            Some(key) if present.contains(key) => Some(key.clone()),
+            Some(key) => {
+                // nearest component before the removed one that is still installed
+                let position = previous_order.iter().position(|item| item == key).unwrap_or(previous_order.len());
+                previous_order[..position].iter().rev().find(|item| present.contains(*item)).cloned()
+            }
+        };
+        inserted.push((anchor, block));
+    }
+    if inserted.is_empty() {
+        return content.to_owned();
+    }
+
+    let mut result = vec![];
+    let header_end = lines.iter().position(|line| component_key(line).is_some()).unwrap_or(lines.len());
+    result.extend(lines[..header_end].iter().map(|line| line.to_string()));
+    for (_, block) in inserted.iter().filter(|(anchor, _)| anchor.is_none()) {
+        result.extend(block.lines.iter().cloned());
+    }
+    for line in &lines[header_end..] {
+        result.push(line.to_string());
+        if let Some(key) = component_key(line) {
+            for (_, block) in inserted.iter().filter(|(anchor, _)| anchor.as_ref() == Some(&key)) {
+                result.extend(block.lines.iter().cloned());
+            }
+        }
+    }
+    let mut result = result.join("\n");
+    result.push('\n');
+    result
+}
+
+/// Comment blocks of the `weidu.log` in the game directory, and the order of the components.
+pub fn read_weidu_log_comments(game_dir: &CanonPath) -> Result<(Vec<CommentBlock>, Vec<ComponentKey>)> {
+    let log_path = game_dir.join_path("weidu.log");
+    if !log_path.exists() {
+        return Ok((vec![], vec![]));
+    }
+    let content = match std::fs::read(&log_path) {
+        Err(error) => bail!("Could not read weidu.log\n -> {:?}", error),
+        Ok(content) => String::from_utf8_lossy(&content).to_string(),
+    };
+    let order = content.lines().filter_map(component_key).collect();
+    Ok((extract_comment_blocks(&content), order))
+}
+
+/// Puts back in `weidu.log` the comment blocks read (with `read_weidu_log_comments`) before weidu rewrote it.
+pub fn restore_weidu_log_comments(game_dir: &CanonPath, blocks: &[CommentBlock], previous_order: &[ComponentKey]) -> Result<()> {
+    if blocks.is_empty() {
+        return Ok(());
+    }
+    let log_path = game_dir.join_path("weidu.log");
+    let content = match std::fs::read(&log_path) {
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(error) => bail!("Could not read weidu.log\n -> {:?}", error),
+        Ok(content) => String::from_utf8_lossy(&content).to_string(),
+    };
+    let restored = restore_comment_blocks(&content, blocks, previous_order);
+    if restored != content {
+        if let Err(error) = std::fs::write(&log_path, restored) {
+            bail!("Could not restore comments in weidu.log\n -> {:?}", error);
+        }
+        info!("Comments in weidu.log were restored");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_weidu_log_lock {
+    use std::fs::File;
+
+    use fs2::FileExt;
+
+    use crate::canon_path::CanonPath;
+    use crate::log_parser::check_weidu_log_not_locked;
+
+    #[test]
+    fn unlocked_weidu_log_is_accepted() {
+        let game_dir = tempfile::tempdir().unwrap();
+        std::fs::write(game_dir.path().join("weidu.log"), "// Log of Currently Installed WeiDU Mods\n").unwrap();
+
+        assert!(check_weidu_log_not_locked(&CanonPath::new(game_dir.path()).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn missing_weidu_log_is_accepted() {
+        let game_dir = tempfile::tempdir().unwrap();
+
+        assert!(check_weidu_log_not_locked(&CanonPath::new(game_dir.path()).unwrap()).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn locked_weidu_log_is_reported() {
+        let game_dir = tempfile::tempdir().unwrap();
+        let log_path = game_dir.path().join("weidu.log");
+        std::fs::write(&log_path, "// Log of Currently Installed WeiDU Mods\n").unwrap();
+        let holder = File::open(&log_path).unwrap();
+        holder.lock_exclusive().unwrap();
+
+        let result = check_weidu_log_not_locked(&CanonPath::new(game_dir.path()).unwrap());
+
+        holder.unlock().unwrap();
+        let message = result.unwrap_err().to_string();
+        assert!(message.starts_with("weidu.log is locked by another process."), "unexpected message: {message}");
+    }
+}
+
+#[cfg(test)]
+mod test_weidu_log_comments {
+    use indoc::indoc;
+
+    use super::{extract_comment_blocks, restore_comment_blocks, CommentBlock};
+
+    const BEFORE_RESET: &str = indoc!(r#"
+        // Log of Currently Installed WeiDU Mods
+        // The top of the file is the 'oldest' mod
+        // ~TP2_File~ #language_strings.tra_index #component_number // [Subcomponent Name -> ] Component Name [ : Version]
+        // Installation comments: base fixes
+        ~DLCMERGER/DLCMERGER.TP2~ #0 #1 // Merge DLC into game -> Merge "Siege of Dragonspear" DLC: 1.7
+        ~BG2FIXPACK/SETUP-BG2FIXPACK.TP2~ #0 #0 // BG2 Fixpack - Core Fixes: v13
+        // fixpack is required by everything below
+        // (do not remove)
+        ~BG2FIXPACK/SETUP-BG2FIXPACK.TP2~ #0 #3 // Party Gets XP for Killing Irenicus: v13
+        ~ASCENSION/ASCENSION.TP2~ #0 #0 // Ascension v2.0.x: v2.0.22
+        // tactics from here
+        ~SCS/SETUP-SCS.TP2~ #0 #100 // Initialise mod: v35
+    "#);
+
+    const AFTER_RESET: &str = indoc!(r#"
+        // Log of Currently Installed WeiDU Mods
+        // The top of the file is the 'oldest' mod
+        // ~TP2_File~ #language_strings.tra_index #component_number // [Subcomponent Name -> ] Component Name [ : Version]
+        ~DLCMERGER/DLCMERGER.TP2~ #0 #1 // Merge DLC into game -> Merge "Siege of Dragonspear" DLC: 1.7
+        ~BG2FIXPACK/SETUP-BG2FIXPACK.TP2~ #0 #0 // BG2 Fixpack - Core Fixes: v13
+        ~BG2FIXPACK/SETUP-BG2FIXPACK.TP2~ #0 #3 // Party Gets XP for Killing Irenicus: v13
+    "#);
+
+    fn order() -> Vec<(String, u32)> {
+        vec![
+            ("dlcmerger".to_string(), 1),
+            ("bg2fixpack".to_string(), 0),
+            ("bg2fixpack".to_string(), 3),
+            ("ascension".to_string(), 0),
+            ("scs".to_string(), 100),
+        ]
+    }
+
+    #[test]
+    fn extract_blocks_ignores_weidu_header() {
+        let blocks = extract_comment_blocks(BEFORE_RESET);
+        assert_eq!(
+            blocks,
+            vec![
+                CommentBlock { after: None, lines: vec!["// Installation comments: base fixes".to_string()] },
+                CommentBlock {
+                    after: Some(("bg2fixpack".to_string(), 0)),
+                    lines: vec!["// fixpack is required by everything below".to_string(), "// (do not remove)".to_string()],
+                },
+                CommentBlock { after: Some(("ascension".to_string(), 0)), lines: vec!["// tactics from here".to_string()] },
+            ]
+        );
+    }
+
+    #[test]
+    fn restore_blocks_after_reset() {
+        let blocks = extract_comment_blocks(BEFORE_RESET);
+        let restored = restore_comment_blocks(AFTER_RESET, &blocks, &order());
+        assert_eq!(
+            restored,
+            indoc!(r#"
+                // Log of Currently Installed WeiDU Mods
+                // The top of the file is the 'oldest' mod
+                // ~TP2_File~ #language_strings.tra_index #component_number // [Subcomponent Name -> ] Component Name [ : Version]
+                // Installation comments: base fixes
+                ~DLCMERGER/DLCMERGER.TP2~ #0 #1 // Merge DLC into game -> Merge "Siege of Dragonspear" DLC: 1.7
+                ~BG2FIXPACK/SETUP-BG2FIXPACK.TP2~ #0 #0 // BG2 Fixpack - Core Fixes: v13
+                // fixpack is required by everything below
+                // (do not remove)
+                ~BG2FIXPACK/SETUP-BG2FIXPACK.TP2~ #0 #3 // Party Gets XP for Killing Irenicus: v13
+                // tactics from here
+            "#)
+        );
+    }
+
+    #[test]
+    fn restore_is_idempotent() {
+        let blocks = extract_comment_blocks(BEFORE_RESET);
+        let restored = restore_comment_blocks(AFTER_RESET, &blocks, &order());
+        assert_eq!(restore_comment_blocks(&restored, &blocks, &order()), restored);
+        assert_eq!(restore_comment_blocks(BEFORE_RESET, &blocks, &order()), BEFORE_RESET);
+    }
+}
+
+#[cfg(test)]
+mod test_install_complete {
+    use crate::canon_path::CanonPath;
+    use crate::config::Config;
+    use crate::global::Global;
+    use crate::lowercase::lwc;
+    use crate::modda_context::WeiduContext;
+    use crate::module::components::{Component, Components, GroupComponent};
+    use crate::module::module::Module;
+    use crate::module::weidu_mod::WeiduMod;
+
+    use super::check_install_complete;
+
+    #[cfg(unix)]
+    #[test]
+    fn group_components_are_checked() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let game_dir = temp_dir.path().join("game");
+        std::fs::create_dir_all(game_dir.join("my_mod")).unwrap();
+        std::fs::write(game_dir.join("my_mod/setup-my_mod.tp2"), "").unwrap();
+        // weidu only lists the components of the mod here
+        let weidu = temp_dir.path().join("weidu-stub");
+        std::fs::write(&weidu, concat!(
+            "#!/bin/sh\n",
+            r#"echo '[{"index":0,"number":0,"forced":false,"name":"Core","subgroup":null,"group":["Fixes"]},"#,
+            r#"{"index":1,"number":20,"forced":false,"name":"Better kits","subgroup":null,"group":["Kits"]}]'"#,
+            "\n",
+        )).unwrap();
+        std::fs::set_permissions(&weidu, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let config = Config { weidu_path: Some(weidu.to_string_lossy().to_string()), ..Default::default() };
+        let game_dir = CanonPath::new(game_dir).unwrap();
+        let weidu_context = WeiduContext { config: &config, current_dir: &game_dir, debug_log: None };
+        let module = Module::Mod {
+            weidu_mod: WeiduMod {
+                name: lwc!("my_mod"),
+                language: Some(0),
+                components: Components::List(vec![
+                    Component::Simple(0),
+                    Component::Group(GroupComponent { group: "Kits".to_string(), subgroup: None }),
+                ]),
+                ..WeiduMod::default()
+            },
+        };
+        let global = Global::default();
+
+        std::fs::write(game_dir.join_path("weidu.log"), "~MY_MOD/SETUP-MY_MOD.TP2~ #0 #0 // Core\n").unwrap();
+        let error = check_install_complete(&module, &global, &weidu_context).unwrap_err().to_string();
+        assert!(error.contains("Missing: [20]"), "unexpected error: {error}");
+
+        std::fs::write(game_dir.join_path("weidu.log"),
+                        "~MY_MOD/SETUP-MY_MOD.TP2~ #0 #0 // Core\n~MY_MOD/SETUP-MY_MOD.TP2~ #0 #20 // Better kits\n").unwrap();
+        assert!(check_install_complete(&module, &global, &weidu_context).is_ok());
+    }
+}
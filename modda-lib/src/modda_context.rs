@@ -1,16 +1,21 @@
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufWriter;
 
 use std::io::Write;
 
 use anyhow::Result;
+use chrono::Local;
 
 use crate::args::Install;
 use crate::canon_path::CanonPath;
 use crate::file_installer::FileInstaller;
 use crate::config::Config;
+use crate::install_events::EventSender;
+use crate::lowercase::LwcString;
 use crate::obtain::get_module::ModuleDownload;
+use crate::run_weidu::{debug_log_name_from_pattern, weidu_debug_log_name};
 
 
 pub struct ModdaContext<'a> {
@@ -19,7 +24,10 @@ pub struct ModdaContext<'a> {
     pub opts: &'a Install,
     pub module_downloader: &'a ModuleDownload<'a>,
     pub file_installer: &'a FileInstaller<'a>,
-    pub log: RefCell<Option<BufWriter<File>>>
+    pub log: RefCell<Option<BufWriter<File>>>,
+    pub events: EventSender,
+    /// How many times each mod was installed in this run
+    pub occurrences: RefCell<HashMap<LwcString, usize>>,
 }
 
 impl <'a> ModdaContext<'a> {
@@ -46,11 +54,35 @@ impl <'a> ModdaContext<'a> {
         WeiduContext {
             current_dir: self.current_dir,
             config: self.config,
+            debug_log: None,
         }
     }
+
+    /// Name of the weidu debug log for the next installation of the mod (counted as a new occurrence).
+    pub fn next_debug_log_name(&self, mod_name: &LwcString) -> String {
+        let mut occurrences = self.occurrences.borrow_mut();
+        let occurrence = occurrences.entry(mod_name.to_owned()).or_insert(0);
+        *occurrence += 1;
+        debug_log_name_from_pattern(self.config.weidu_debug_log.as_deref(), mod_name, *occurrence, Local::now())
+    }
 }
 
 pub struct WeiduContext<'a> {
     pub current_dir: &'a CanonPath,
     pub config: &'a Config,
+    /// Debug log of the installation (`--log`), `setup-<mod>.debug` if not set
+    pub debug_log: Option<String>,
+}
+
+impl <'a> WeiduContext<'a> {
+    pub fn with_debug_log(self, debug_log: &str) -> Self {
+        Self { debug_log: Some(debug_log.to_owned()), ..self }
+    }
+
+    pub fn debug_log_name(&self, mod_name: &LwcString) -> String {
+        match &self.debug_log {
+            Some(debug_log) => debug_log.to_owned(),
+            None => weidu_debug_log_name(mod_name),
+        }
+    }
 }
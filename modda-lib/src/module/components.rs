@@ -3,8 +3,14 @@ use std::fmt::{self, Display};
 use std::str::FromStr;
 use std::marker::PhantomData;
 
+use anyhow::bail;
+use itertools::Itertools;
 use serde::{Deserialize, Deserializer, Serialize};
-use serde::de::{self, Visitor, SeqAccess};
+use serde::de::{self, Visitor, SeqAccess, MapAccess};
+use serde_with::skip_serializing_none;
+
+use crate::canon_path::CanonPath;
+use crate::run_weidu::WeiduComponent;
 
 #[derive(Deserialize, Debug, PartialEq, Clone)]
 #[serde(untagged)]
@@ -13,6 +19,8 @@ pub enum Components {
     None,
     All,
     List(Vec<Component>),
+    /// Components listed in a separate file (shareable presets), replaced by its content at install time.
+    FromFile(ComponentsFile),
 }
 
 impl Default for Components {
@@ -28,6 +36,90 @@ impl Components {
             _ => false,
         }
     }
+
+    /// Short form for display (`ask`, `all`, `none`, or the list of components).
+    pub fn describe(&self) -> String {
+        match self {
+            Components::Ask => "ask".to_string(),
+            Components::None => "none".to_string(),
+            Components::All => "all".to_string(),
+            Components::List(list) => format!("[{}]", list.iter().map(|component| match component {
+                Component::Group(GroupComponent { group, subgroup: None }) => format!("group {}", group),
+                Component::Group(GroupComponent { group, subgroup: Some(subgroup) }) => format!("group {}/{}", group, subgroup),
+                other => other.index().unwrap_or_default().to_string(),
+            }).join(", ")),
+            Components::FromFile(file) => format!("file {}", file.file),
+        }
+    }
+
+    /// Replaces a component list kept in a file by the content of this file (other values are unchanged).
+    pub fn resolve_file(&self, manifest_root: &CanonPath) -> anyhow::Result<Components> {
+        match self {
+            Components::FromFile(file) => Ok(Components::List(file.read(manifest_root)?)),
+            other => Ok(other.clone()),
+        }
+    }
+
+    /// Fails for a components file that was not replaced by its content (with `resolve_file`).
+    pub fn check_file_resolved(&self) -> anyhow::Result<()> {
+        match self {
+            Components::FromFile(file) => bail!("Components file {} was not read", file.file),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct ComponentsFile {
+    /// Path of the file, relative to the manifest (must stay inside its directory)
+    pub file: String,
+}
+
+impl ComponentsFile {
+    pub fn read(&self, manifest_root: &CanonPath) -> anyhow::Result<Vec<Component>> {
+        let path = match manifest_root.safe_join(&self.file) {
+            Ok(path) => path,
+            Err(error) => bail!("Invalid components file {}\n -> {:?}", self.file, error),
+        };
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(error) => bail!("Could not read components file {:?}\n -> {:?}", path, error),
+        };
+        let components = match parse_components_file(&content) {
+            Ok(components) => components,
+            Err(error) => bail!("Invalid components file {:?}\n -> {:?}", path, error),
+        };
+        // an empty list would mean `ask`
+        if components.is_empty() {
+            bail!("Components file {:?} contains no component", path);
+        }
+        Ok(components)
+    }
+}
+
+/// Either a JSON list (same syntax as `components` in the manifest), or one component number per line,
+/// optionally followed by `// <component name>` (like in `weidu.log`).<br>
+/// In the line format, empty lines and lines starting with `#` are ignored.
+fn parse_components_file(content: &str) -> anyhow::Result<Vec<Component>> {
+    if content.trim_start().starts_with('[') {
+        return Ok(serde_json::from_str(content)?);
+    }
+    let mut components = vec![];
+    for line in content.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let (index, name) = match line.split_once("//") {
+            None => (line, None),
+            Some((index, name)) => (index.trim(), Some(name.trim())),
+        };
+        let index = match index.parse::<u32>() {
+            Ok(index) => index,
+            Err(_) => bail!("Invalid component number in line `{}`", line),
+        };
+        components.push(match name {
+            None | Some("") => Component::Simple(index),
+            Some(name) => Component::Full(FullComponent { index, component_name: name.to_string(), language: None }),
+        });
+    }
+    Ok(components)
 }
 
 impl Serialize for Components {
@@ -38,6 +130,7 @@ impl Serialize for Components {
             Components::None => serializer.serialize_str("none"),
             Components::All => serializer.serialize_str("all"),
             Components::List(list) => serializer.collect_seq(list.iter()),
+            Components::FromFile(file) => file.serialize(serializer),
         }
     }
 }
@@ -80,13 +173,26 @@ impl FromStr for Components {
 pub enum Component {
     Simple(u32),
     Full(FullComponent),
+    /// All components in a (TP2) `GROUP`, optionally restricted to a `SUBCOMPONENT` group.<br>
+    /// Resolved to actual components (by asking weidu) just before installation.
+    Group(GroupComponent),
 }
 
 impl Component {
-    pub fn index(&self) -> u32 {
+    /// Component index (`None` for symbolic components that must be resolved first).
+    pub fn index(&self) -> Option<u32> {
+        match &self {
+            Component::Simple(index) => Some(*index),
+            Component::Full(full_component) => Some(full_component.index),
+            Component::Group(_) => None,
+        }
+    }
+
+    /// Language index this component is installed with, when it's not the one of the mod.
+    pub fn language(&self) -> Option<u32> {
         match &self {
-            Component::Simple(index) => *index,
-            Component::Full(full_component) => full_component.index,
+            Component::Full(full_component) => full_component.language,
+            _ => None,
         }
     }
 }
@@ -94,7 +200,166 @@ impl Component {
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub struct FullComponent {
     pub index: u32,
+    #[serde(default)]
     pub component_name: String,
+    /// Language index (weidu `--language`) for this component only, instead of the one selected for the mod
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<u32>,
+}
+
+#[skip_serializing_none]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct GroupComponent {
+    pub group: String,
+    pub subgroup: Option<String>,
+}
+
+impl GroupComponent {
+    /// Finds the components (weidu component numbers) in the group/subgroup.
+    /// It is an error if nothing matches, or if several options of the same subcomponent would be selected.
+    pub fn resolve(&self, available: &[WeiduComponent]) -> anyhow::Result<Vec<u32>> {
+        let in_group = available.iter()
+            .filter(|comp| comp.group.iter().any(|group| group.eq_ignore_ascii_case(&self.group)))
+            .collect_vec();
+        if in_group.is_empty() {
+            let groups = available.iter().flat_map(|comp| comp.group.iter()).unique().join("\n  - ");
+            bail!("No component group named '{}'\navailable groups are\n  - {}", self.group, groups);
+        }
+        let matches = match &self.subgroup {
+            None => in_group,
+            Some(subgroup) => {
+                let matches = in_group.iter()
+                    .filter(|comp| matches!(&comp.subgroup, Some(name) if name.eq_ignore_ascii_case(subgroup)))
+                    .cloned()
+                    .collect_vec();
+                if matches.is_empty() {
+                    let subgroups = in_group.iter().filter_map(|comp| comp.subgroup.as_ref()).unique().join("\n  - ");
+                    bail!("No subgroup named '{}' in component group '{}'\navailable subgroups are\n  - {}",
+                            subgroup, self.group, subgroups);
+                }
+                matches
+            }
+        };
+        let alternatives = matches.iter()
+            .filter_map(|comp| comp.subgroup.as_ref())
+            .duplicates()
+            .collect_vec();
+        if let Some(subgroup) = alternatives.first() {
+            let options = matches.iter()
+                .filter(|comp| comp.subgroup.as_ref() == Some(subgroup))
+                .map(|comp| format!("{} - {}", comp.number, comp.name))
+                .join("\n  - ");
+            bail!("Component group '{}' is ambiguous, subgroup '{}' has several mutually exclusive options (select one by index)\n  - {}",
+                    self.group, subgroup, options);
+        }
+        Ok(matches.iter().map(|comp| comp.number).collect())
+    }
+}
+
+/// Map form of `components`: either a components file or a range of component numbers,
+/// for example `{ range: 0-100, except: [5, 10-12] }`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ComponentsSpec {
+    file: Option<String>,
+    range: Option<IndexRange>,
+    except: Option<Vec<IndexRange>>,
+}
+
+/// Component numbers from `first` to `last` (both included), written `<first>-<last>` or as a single number.
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct IndexRange {
+    first: u32,
+    last: u32,
+}
+
+impl Display for IndexRange {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        if self.first == self.last {
+            write!(formatter, "{}", self.first)
+        } else {
+            write!(formatter, "{}-{}", self.first, self.last)
+        }
+    }
+}
+
+impl FromStr for IndexRange {
+    type Err = ParseComponentError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseComponentError(format!("invalid component range `{}` (expected `<first>-<last>`)", s));
+        let (first, last) = match s.split_once('-') {
+            None => (s, s),
+            Some((first, last)) if first.trim().is_empty() || last.trim().is_empty() =>
+                return Err(ParseComponentError(format!("invalid component range `{}` (missing start or end)", s))),
+            Some((first, last)) => (first, last),
+        };
+        let first = first.trim().parse::<u32>().map_err(|_| invalid())?;
+        let last = last.trim().parse::<u32>().map_err(|_| invalid())?;
+        if first > last {
+            return Err(ParseComponentError(format!("invalid component range `{}` (the end is before the start)", s)));
+        }
+        Ok(IndexRange { first, last })
+    }
+}
+
+impl<'de> Deserialize<'de> for IndexRange {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where D: Deserializer<'de> {
+        struct IndexRangeVisitor;
+
+        impl<'de> Visitor<'de> for IndexRangeVisitor {
+            type Value = IndexRange;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("component number or range (`<first>-<last>`)")
+            }
+
+            fn visit_u64<E>(self, value: u64) -> Result<IndexRange, E>
+            where E: de::Error {
+                match u32::try_from(value) {
+                    Ok(index) => Ok(IndexRange { first: index, last: index }),
+                    Err(_) => Err(E::custom(format!("invalid component number {}", value))),
+                }
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<IndexRange, E>
+            where E: de::Error {
+                IndexRange::from_str(value).map_err(|error| E::custom(error.to_string()))
+            }
+        }
+
+        deserializer.deserialize_any(IndexRangeVisitor)
+    }
+}
+
+/// Upper bound of the number of components of a range (`components_max` is only checked at installation,
+/// this keeps a typo from building a huge list when the manifest is read).
+const MAX_RANGE_LENGTH: u32 = 10_000;
+
+/// Component numbers of `range`, without those of `except` (which must be inside `range` and not overlap).
+fn resolve_range(range: &IndexRange, except: &[IndexRange]) -> Result<Vec<u32>, String> {
+    if range.last - range.first >= MAX_RANGE_LENGTH {
+        return Err(format!("component range {} is too large (more than {} components)", range, MAX_RANGE_LENGTH));
+    }
+    for excluded in except {
+        if excluded.first < range.first || excluded.last > range.last {
+            return Err(format!("excluded components {} are not in range {}", excluded, range));
+        }
+    }
+    for (left, right) in except.iter().tuple_combinations() {
+        if left.first <= right.last && right.first <= left.last {
+            return Err(format!("excluded components {} and {} overlap", left, right));
+        }
+    }
+    let list = (range.first..=range.last)
+        .filter(|index| !except.iter().any(|excluded| (excluded.first..=excluded.last).contains(index)))
+        .collect::<Vec<_>>();
+    // an empty list would mean `ask`
+    if list.is_empty() {
+        return Err(format!("all components of range {} are excluded", range));
+    }
+    Ok(list)
 }
 
 pub fn component_deser<'de, D>(deserializer: D) -> Result<Components, D::Error>
@@ -112,7 +377,7 @@ where
         type Value = Components;
 
         fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-            formatter.write_str("'ask', 'none' or list of components")
+            formatter.write_str("'ask', 'none', list of components, components file or range")
         }
 
         fn visit_str<E>(self, value: &str) -> Result<Components, E>
@@ -127,6 +392,26 @@ where
         where A: SeqAccess<'de>, {
             Deserialize::deserialize(de::value::SeqAccessDeserializer::new(seq))
         }
+
+        fn visit_map<A>(self, map: A) -> Result<Components, A::Error>
+        where A: MapAccess<'de>, {
+            let spec: ComponentsSpec = Deserialize::deserialize(de::value::MapAccessDeserializer::new(map))?;
+            match spec {
+                ComponentsSpec { file: Some(file), range: None, except: None } =>
+                    Ok(Components::FromFile(ComponentsFile { file })),
+                ComponentsSpec { file: None, range: Some(range), except } =>
+                    match resolve_range(&range, &except.unwrap_or_default()) {
+                        Ok(list) => Ok(Components::List(list.into_iter().map(Component::Simple).collect())),
+                        Err(error) => Err(de::Error::custom(error)),
+                    },
+                ComponentsSpec { file: Some(_), range: Some(_), .. } =>
+                    Err(de::Error::custom("components can't have both `file` and `range`")),
+                ComponentsSpec { file: Some(_), except: Some(_), .. } =>
+                    Err(de::Error::custom("`except` can only be used with `range`")),
+                ComponentsSpec { file: None, range: None, .. } =>
+                    Err(de::Error::custom("components must have a `file` or a `range` property")),
+            }
+        }
     }
 
     deserializer.deserialize_any(StringOrComponents(PhantomData))
@@ -138,7 +423,7 @@ mod test_deserialize {
     use crate::lowercase::lwc;
     use crate::module::weidu_mod::WeiduMod;
 
-    use super::{Component, Components};
+    use super::{Component, Components, ComponentsFile, FullComponent};
 
     #[test]
     fn deserialize_ask() {
@@ -209,4 +494,220 @@ mod test_deserialize {
             }
         );
     }
+
+    #[test]
+    fn deserialize_file() {
+        let yaml = r#"
+        name: mod_name
+        components:
+            file: presets/mod_name.txt
+        "#;
+
+        let module: WeiduMod = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            module.components,
+            Components::FromFile(ComponentsFile { file: "presets/mod_name.txt".to_string() })
+        );
+        assert_eq!(serde_yaml::to_string(&module.components).unwrap(), "file: presets/mod_name.txt\n");
+    }
+
+    fn components(yaml: &str) -> Result<Components, String> {
+        serde_yaml::from_str::<WeiduMod>(&format!("name: mod_name\ncomponents: {}", yaml))
+            .map(|module| module.components)
+            .map_err(|error| error.to_string())
+    }
+
+    fn list(indexes: impl IntoIterator<Item = u32>) -> Components {
+        Components::List(indexes.into_iter().map(Component::Simple).collect())
+    }
+
+    #[test]
+    fn deserialize_range() {
+        assert_eq!(components("{ range: 3-6 }").unwrap(), list([3, 4, 5, 6]));
+        assert_eq!(components("{ range: 7 }").unwrap(), list([7]));
+    }
+
+    #[test]
+    fn deserialize_range_with_exclusions() {
+        assert_eq!(components("{ range: 0-100, except: [5, 10-12] }").unwrap(),
+                    list((0..=100).filter(|index| ![5, 10, 11, 12].contains(index))));
+        assert_eq!(components("{ range: 0-5, except: [0, 5] }").unwrap(), list([1, 2, 3, 4]));
+        assert_eq!(components("{ range: 0-5, except: [\"1-2\", 4-4] }").unwrap(), list([0, 3, 5]));
+    }
+
+    #[test]
+    fn deserialize_component_language() {
+        assert_eq!(
+            components("[ 0, { index: 3, language: 1 } ]").unwrap(),
+            Components::List(vec![
+                Component::Simple(0),
+                Component::Full(FullComponent { index: 3, component_name: String::new(), language: Some(1) }),
+            ])
+        );
+    }
+
+    #[test]
+    fn reject_invalid_ranges() {
+        assert!(components("{ range: 10-2 }").unwrap_err().contains("the end is before the start"));
+        assert!(components("{ range: 5-2 }").unwrap_err().contains("invalid component range `5-2` (the end is before the start)"));
+        assert!(components("{ range: 1- }").unwrap_err().contains("invalid component range `1-` (missing start or end)"));
+        assert!(components("{ range: \"-3\" }").unwrap_err().contains("invalid component range `-3` (missing start or end)"));
+        assert!(components("{ range: 0-4000000000 }").unwrap_err().contains("component range 0-4000000000 is too large"));
+        assert!(components("{ range: a-b }").unwrap_err().contains("invalid component range `a-b`"));
+        assert!(components("{ range: 0-10, except: [12] }").unwrap_err().contains("excluded components 12 are not in range 0-10"));
+        assert!(components("{ range: 0-10, except: [2-5, 5-7] }").unwrap_err().contains("excluded components 2-5 and 5-7 overlap"));
+        assert!(components("{ range: 0-10, except: [0-10] }").unwrap_err().contains("all components of range 0-10 are excluded"));
+        assert!(components("{ range: 0-10, file: presets.txt }").unwrap_err().contains("both `file` and `range`"));
+        assert!(components("{ file: presets.txt, except: [1] }").unwrap_err().contains("`except` can only be used with `range`"));
+        assert!(components("{ except: [1] }").unwrap_err().contains("must have a `file` or a `range`"));
+    }
+}
+
+#[cfg(test)]
+mod test_components_file {
+    use std::path::Path;
+
+    use crate::canon_path::CanonPath;
+
+    use super::{Component, Components, ComponentsFile, FullComponent};
+
+    fn manifest_root() -> CanonPath {
+        CanonPath::new(Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/components_file")).unwrap()
+    }
+
+    fn resolve(file: &str) -> anyhow::Result<Components> {
+        Components::FromFile(ComponentsFile { file: file.to_string() }).resolve_file(&manifest_root())
+    }
+
+    #[test]
+    fn resolve_line_file() {
+        assert_eq!(
+            resolve("presets/lines.txt").unwrap(),
+            Components::List(vec![
+                Component::Simple(0),
+                Component::Full(FullComponent { index: 10, component_name: "Better kits".to_string(), language: None }),
+                Component::Simple(20),
+            ])
+        );
+    }
+
+    #[test]
+    fn unresolved_file_is_rejected() {
+        let unresolved = Components::FromFile(ComponentsFile { file: "presets/lines.txt".to_string() });
+        assert_eq!(unresolved.check_file_resolved().unwrap_err().to_string(), "Components file presets/lines.txt was not read");
+        assert!(resolve("presets/lines.txt").unwrap().check_file_resolved().is_ok());
+    }
+
+    #[test]
+    fn resolve_json_file() {
+        assert_eq!(
+            resolve("presets/list.json").unwrap(),
+            Components::List(vec![
+                Component::Simple(1),
+                Component::Full(FullComponent { index: 2, component_name: "Second".to_string(), language: None }),
+            ])
+        );
+    }
+
+    #[test]
+    fn other_components_are_unchanged() {
+        assert_eq!(Components::All.resolve_file(&manifest_root()).unwrap(), Components::All);
+    }
+
+    #[test]
+    fn reject_invalid_files() {
+        // would be `ask`
+        assert!(resolve("presets/empty.txt").is_err());
+        assert!(resolve("presets/missing.txt").is_err());
+        assert!(resolve("../components_file/../patch/modulename.tp2").is_err());
+        assert!(resolve("presets/invalid.txt").unwrap_err().to_string().contains("invalid.txt"));
+    }
+}
+
+#[cfg(test)]
+mod test_group_resolution {
+
+    use crate::run_weidu::WeiduComponent;
+
+    use super::GroupComponent;
+
+    fn component(number: u32, name: &str, subgroup: Option<&str>, group: &[&str]) -> WeiduComponent {
+        WeiduComponent {
+            index: number,
+            number,
+            forced: false,
+            name: name.to_string(),
+            subgroup: subgroup.map(|subgroup| subgroup.to_string()),
+            group: group.iter().map(|group| group.to_string()).collect(),
+        }
+    }
+
+    fn available() -> Vec<WeiduComponent> {
+        vec![
+            component(0, "Core fixes", None, &["Fixes"]),
+            component(10, "Aerie romance tweaks", Some("Romance"), &["NPC tweaks"]),
+            component(11, "Faster romances", Some("Romance speed"), &["NPC tweaks"]),
+            component(12, "Slower romances", Some("Romance speed"), &["NPC tweaks"]),
+            component(20, "Better kits", None, &["NPC tweaks", "Kits"]),
+        ]
+    }
+
+    #[test]
+    fn resolve_group_and_subgroup() {
+        let group = GroupComponent { group: "NPC tweaks".to_string(), subgroup: Some("Romance".to_string()) };
+        assert_eq!(group.resolve(&available()).unwrap(), vec![10]);
+    }
+
+    #[test]
+    fn resolve_group_is_case_insensitive() {
+        let group = GroupComponent { group: "kits".to_string(), subgroup: None };
+        assert_eq!(group.resolve(&available()).unwrap(), vec![20]);
+    }
+
+    #[test]
+    fn resolve_group_with_exclusive_options_is_ambiguous() {
+        let group = GroupComponent { group: "NPC tweaks".to_string(), subgroup: None };
+        let error = group.resolve(&available()).unwrap_err().to_string();
+        assert!(error.contains("ambiguous"));
+        assert!(error.contains("11 - Faster romances"));
+        assert!(error.contains("12 - Slower romances"));
+    }
+
+    #[test]
+    fn resolve_missing_group_lists_groups() {
+        let group = GroupComponent { group: "Cosmetic".to_string(), subgroup: None };
+        let error = group.resolve(&available()).unwrap_err().to_string();
+        assert!(error.contains("No component group named 'Cosmetic'"));
+        assert!(error.contains("  - Fixes\n  - NPC tweaks\n  - Kits"));
+    }
+
+    #[test]
+    fn resolve_missing_subgroup_lists_subgroups() {
+        let group = GroupComponent { group: "NPC tweaks".to_string(), subgroup: Some("Banter".to_string()) };
+        let error = group.resolve(&available()).unwrap_err().to_string();
+        assert!(error.contains("No subgroup named 'Banter'"));
+        assert!(error.contains("  - Romance\n  - Romance speed"));
+    }
+
+    #[test]
+    fn deserialize_group_component() {
+        use crate::module::components::{Component, Components};
+        use crate::module::weidu_mod::WeiduMod;
+
+        let yaml = r#"
+        name: mod_name
+        components:
+            - 1
+            - group: NPC tweaks
+              subgroup: Romance
+        "#;
+        let module: WeiduMod = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            module.components,
+            Components::List(vec![
+                Component::Simple(1),
+                Component::Group(GroupComponent { group: "NPC tweaks".to_string(), subgroup: Some("Romance".to_string()) }),
+            ])
+        );
+    }
 }
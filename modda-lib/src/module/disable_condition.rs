@@ -1,15 +1,20 @@
 
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::path::Path;
 use std::sync::OnceLock;
 
 use anyhow::{bail, Result};
+use globwalk::GlobWalkerBuilder;
 use itertools::{FoldWhile, Itertools};
 use log::warn;
 use regex::{Captures, Regex};
 use serde::{Deserialize, Serialize};
 
 use crate::canon_path::CanonPath;
+use crate::game_detect::{detect_game, GameFlavor};
+use crate::log_parser::parse_weidu_log_file;
+use crate::lowercase::LwcString;
 
 use super::manifest_conditions::ManifestConditions;
 
@@ -33,7 +38,15 @@ pub enum DisableCondition {
     ///
     /// - If the file doesn't exist, the condition  is evaluated as "don't disable".<br>
     /// - if the file doesn't contain the key, the condition  is evaluated as "don't disable".
-    File { in_file: String, key: String },
+    ///
+    /// `in_file` can also be a glob pattern (for example `conf/*.txt`), the key is then looked up
+    /// in all matching files (in path order), according to `mode`.
+    File {
+        in_file: String,
+        key: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        mode: Option<FileMatchMode>,
+    },
     /// Disables the module if any of the conditions inside evaluates to "disabled".<br>
     /// (If there is no sub-condition, it never disables)
     Any { any: Vec<DisableCondition> },
@@ -44,14 +57,34 @@ pub enum DisableCondition {
     Not { not: Box<DisableCondition> },
     /// Disables the module on a condition defined in the manifest
     ManifestCondition { manifest_condition: String },
+    /// Disables the module unless the mod (or this component of the mod) is in the `weidu.log` of the game directory.
+    ModInstalled {
+        mod_installed: LwcString,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        component: Option<u32>,
+    },
+    /// Disables the module unless the game detected in the game directory is one of those listed
+    /// (`bg1`, `bg2`, `iwd`, `iwd2`, `pst`, `bgee`, `bg2ee`, `eet`, `iwdee` or `pstee`).
+    GameIs { game_is: Vec<GameFlavor> },
+}
+
+/// How the key is looked up when `in_file` matches several files.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FileMatchMode {
+    /// The first file (in path order) that contains the key decides.
+    #[default]
+    First,
+    /// All files that contain the key must agree, it's an error otherwise.
+    All,
 }
 
 pub trait DisableCheck {
-    fn check(&self, manifest_root: &CanonPath, manifest_conditions: &ManifestConditions) -> Result<DisableOutCome>;
+    fn check(&self, manifest_root: &CanonPath, game_dir: &CanonPath, manifest_conditions: &ManifestConditions) -> Result<DisableOutCome>;
 }
 
 impl DisableCheck for DisableCondition {
-    fn check(&self, manifest_root: &CanonPath, manifest_conditions: &ManifestConditions) -> Result<DisableOutCome> {
+    fn check(&self, manifest_root: &CanonPath, game_dir: &CanonPath, manifest_conditions: &ManifestConditions) -> Result<DisableOutCome> {
         match self {
             Self::Because { ref because } => Ok(DisableOutCome::Yes(because.to_string())),
             Self::EnvVar { env_is_set: if_env_set } => {
@@ -65,12 +98,12 @@ impl DisableCheck for DisableCondition {
                     )),
                 }
             }
-            Self::File { in_file, key } => evaluate_file(in_file, key, manifest_root),
-            Self::Any { any } => evaluate_any(any, manifest_root, manifest_conditions),
-            Self::All { all } => evaluate_all(all, manifest_root, manifest_conditions),
+            Self::File { in_file, key, mode } => evaluate_file(in_file, key, mode.unwrap_or_default(), manifest_root),
+            Self::Any { any } => evaluate_any(any, manifest_root, game_dir, manifest_conditions),
+            Self::All { all } => evaluate_all(all, manifest_root, game_dir, manifest_conditions),
             Self::Not { not } => {
                 use DisableOutCome::{Yes, No};
-                match not.check(manifest_root, manifest_conditions) {
+                match not.check(manifest_root, game_dir, manifest_conditions) {
                     Err(error) => Err(error),
                     Ok(No(None)) => Ok(Yes("Negation of 'not disabled'".to_string())),
                     Ok(No(Some(reason))) =>
@@ -81,17 +114,19 @@ impl DisableCheck for DisableCondition {
             }
             Self::ManifestCondition { manifest_condition } => match manifest_conditions.get(&manifest_condition) {
                 None => Ok(DisableOutCome::No(Some(format!("manifest global condition {} is not present", manifest_condition)))),
-                Some(condition) => condition.check(manifest_root, manifest_conditions)
+                Some(condition) => condition.check(manifest_root, game_dir, manifest_conditions)
             }
+            Self::ModInstalled { mod_installed, component } => evaluate_mod_installed(mod_installed, *component, game_dir),
+            Self::GameIs { game_is } => evaluate_game_is(game_is, game_dir),
         }
     }
 }
 
 impl DisableCheck for Option<DisableCondition> {
-    fn check(&self, manifest_root: &CanonPath, manifest_conditions: &ManifestConditions) -> Result<DisableOutCome> {
+    fn check(&self, manifest_root: &CanonPath, game_dir: &CanonPath, manifest_conditions: &ManifestConditions) -> Result<DisableOutCome> {
         match self {
             None => Ok(DisableOutCome::No(None)),
-            Some(condition) => condition.check(manifest_root, manifest_conditions)
+            Some(condition) => condition.check(manifest_root, game_dir, manifest_conditions)
         }
     }
 }
@@ -104,14 +139,72 @@ fn disable_file_regex() -> &'static Regex {
 }
 
 
-fn evaluate_file(in_file: &str, key: &str, manifest_root: &CanonPath) -> Result<DisableOutCome> {
-    let file_path = manifest_root.join(in_file)?;
-    if !file_path.starts_with(manifest_root) {
-        bail!("File for disable condition '{in_file:?}' is not under the manifest location.");
+fn evaluate_file(in_file: &str, key: &str, mode: FileMatchMode, manifest_root: &CanonPath) -> Result<DisableOutCome> {
+    if is_glob(in_file) {
+        return evaluate_file_glob(in_file, key, mode, manifest_root);
     }
+    let file_path = match manifest_root.safe_join(in_file) {
+        Ok(file_path) => file_path,
+        Err(_) => bail!("File for disable condition '{in_file:?}' is not under the manifest location."),
+    };
     if !file_path.path().exists() {
         return Ok(DisableOutCome::No(Some(format!("File '{in_file}' does not exist"))));
     }
+    match lookup_key_in_file(file_path.path(), key, in_file)? {
+        Some(outcome) => Ok(outcome),
+        // end of file, we did not find any matching line
+        None => Ok(DisableOutCome::No(Some(format!("Key '{key}' not present in file '{in_file}'")))),
+    }
+}
+
+fn is_glob(in_file: &str) -> bool {
+    in_file.contains(['*', '?', '[', '{'])
+}
+
+fn evaluate_file_glob(pattern: &str, key: &str, mode: FileMatchMode, manifest_root: &CanonPath) -> Result<DisableOutCome> {
+    let walker = match GlobWalkerBuilder::from_patterns(manifest_root, &[pattern]).build() {
+        Ok(walker) => walker,
+        Err(error) => bail!("Invalid file pattern '{pattern}' in disable condition\n  {error}"),
+    };
+    let mut files = vec![];
+    for entry in walker.filter_map(Result::ok).filter(|entry| entry.file_type().is_file()) {
+        let file_path = CanonPath::new(entry.path())?;
+        if !file_path.starts_with(manifest_root) {
+            bail!("File for disable condition '{:?}' is not under the manifest location.", entry.path());
+        }
+        files.push(file_path);
+    }
+    if files.is_empty() {
+        return Ok(DisableOutCome::No(Some(format!("No file matches '{pattern}'"))));
+    }
+    files.sort_by(|left, right| left.path().cmp(right.path()));
+
+    let mut outcomes = vec![];
+    for file_path in &files {
+        let file_name = match file_path.path().strip_prefix(manifest_root) {
+            Ok(relative) => relative.to_string_lossy().to_string(),
+            Err(_) => file_path.path().to_string_lossy().to_string(),
+        };
+        if let Some(outcome) = lookup_key_in_file(file_path.path(), key, &file_name)? {
+            match mode {
+                FileMatchMode::First => return Ok(outcome),
+                FileMatchMode::All => outcomes.push((file_name, outcome)),
+            }
+        }
+    }
+    match outcomes.first() {
+        None => Ok(DisableOutCome::No(Some(format!("Key '{key}' not present in files matching '{pattern}'")))),
+        Some((first_name, first)) => {
+            if let Some((other_name, _)) = outcomes.iter().find(|(_, outcome)| outcome.is_yes() != first.is_yes()) {
+                bail!("Files matching '{pattern}' disagree on key '{key}' ('{first_name}' and '{other_name}')");
+            }
+            Ok(first.clone())
+        }
+    }
+}
+
+/// Returns `None` if the key is not present in the file.
+fn lookup_key_in_file(file_path: &Path, key: &str, in_file: &str) -> Result<Option<DisableOutCome>> {
     let file = File::open(file_path)?;
     let buf = BufReader::new(file);
     let entries = buf.lines().enumerate();
@@ -122,8 +215,8 @@ fn evaluate_file(in_file: &str, key: &str, manifest_root: &CanonPath) -> Result<
                     Some(s) if s.as_str() == key => {
                         let disabled = parts.name("value");
                         return match disabled {
-                            Some(s) if s.as_str() == "true" => Ok(file_outcome(parts, true, in_file)),
-                            Some(s) if s.as_str() == "false" => Ok(file_outcome(parts, false, in_file)),
+                            Some(s) if s.as_str() == "true" => Ok(Some(file_outcome(parts, true, in_file))),
+                            Some(s) if s.as_str() == "false" => Ok(Some(file_outcome(parts, false, in_file))),
                             Some(s) => bail!("Invalid value in {in_file} ; expected true or false but got {value} (key={key})", value = s.as_str()),
                             None => bail!("Invalid line in '{in_file}' ; value is missing (key={key})"),
                         }
@@ -136,8 +229,7 @@ fn evaluate_file(in_file: &str, key: &str, manifest_root: &CanonPath) -> Result<
             }
         } // else ignore and continue
     }
-    // end of file, we did not find any matching line
-    Ok(DisableOutCome::No(Some(format!("Key '{key}' not present in file '{in_file}'"))))
+    Ok(None)
 }
 
 fn file_outcome(captures: Captures, disabled: bool, file_name: &str) -> DisableOutCome {
@@ -150,11 +242,35 @@ fn file_outcome(captures: Captures, disabled: bool, file_name: &str) -> DisableO
     }
 }
 
-fn evaluate_all(conditions: &[DisableCondition], manifest_root: &CanonPath, manifest_conditions: &ManifestConditions) -> Result<DisableOutCome> {
+fn evaluate_mod_installed(mod_name: &LwcString, component: Option<u32>, game_dir: &CanonPath) -> Result<DisableOutCome> {
+    let rows = parse_weidu_log_file(&game_dir.join_path("weidu.log"), Some(mod_name))?;
+    let installed = rows.iter().any(|row| component.is_none_or(|index| row.component_index == index));
+    let what = match component {
+        None => format!("mod {mod_name}"),
+        Some(index) => format!("component {index} of mod {mod_name}"),
+    };
+    if installed {
+        Ok(DisableOutCome::No(Some(format!("{what} is installed"))))
+    } else {
+        Ok(DisableOutCome::Yes(format!("{what} is not installed")))
+    }
+}
+
+fn evaluate_game_is(games: &[GameFlavor], game_dir: &CanonPath) -> Result<DisableOutCome> {
+    let expected = games.iter().join(", ");
+    match detect_game(game_dir.path())? {
+        None => Ok(DisableOutCome::Yes(format!("game could not be detected (expected one of {expected})"))),
+        Some(game) if games.contains(&game) => Ok(DisableOutCome::No(Some(format!("game is {game}")))),
+        Some(game) => Ok(DisableOutCome::Yes(format!("game is {game}, not one of {expected}"))),
+    }
+}
+
+fn evaluate_all(conditions: &[DisableCondition], manifest_root: &CanonPath, game_dir: &CanonPath,
+                    manifest_conditions: &ManifestConditions) -> Result<DisableOutCome> {
     conditions.iter().fold_while(
         Ok(DisableOutCome::Yes("all conditions filled".to_string())),
         |acc, condition| {
-            match condition.check(manifest_root, manifest_conditions) {
+            match condition.check(manifest_root, game_dir, manifest_conditions) {
                 Err(error) => FoldWhile::Done(Err(error)),
                 Ok(DisableOutCome::Yes(_yes)) => FoldWhile::Continue(acc),
                 Ok(no) => FoldWhile::Done(Ok(no)),
@@ -163,11 +279,12 @@ fn evaluate_all(conditions: &[DisableCondition], manifest_root: &CanonPath, mani
     ).into_inner()
 }
 
-fn evaluate_any(conditions: &[DisableCondition], manifest_root: &CanonPath, manifest_conditions: &ManifestConditions) -> Result<DisableOutCome> {
+fn evaluate_any(conditions: &[DisableCondition], manifest_root: &CanonPath, game_dir: &CanonPath,
+                    manifest_conditions: &ManifestConditions) -> Result<DisableOutCome> {
     conditions.iter().fold_while(
         Ok(DisableOutCome::No(None)),
         |acc, condition| {
-            match condition.check(manifest_root, manifest_conditions) {
+            match condition.check(manifest_root, game_dir, manifest_conditions) {
                 Err(error) => FoldWhile::Done(Err(error)),
                 Ok(DisableOutCome::No(_)) => FoldWhile::Continue(acc),
                 Ok(yes) => FoldWhile::Done(Ok(yes)),
@@ -205,7 +322,7 @@ mod test {
     use std::path::Path;
 
     use crate::canon_path::CanonPath;
-    use crate::module::disable_condition::{DisableCheck, DisableCondition, DisableOutCome};
+    use crate::module::disable_condition::{DisableCheck, DisableCondition, DisableOutCome, FileMatchMode};
     use crate::module::manifest_conditions::ManifestConditions;
 
     #[test]
@@ -213,7 +330,7 @@ mod test {
         let because = "I'm testing things".to_string();
         assert_eq!(
             DisableCondition::Because { because: because.clone() }
-                .check(&CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
+                .check(&CanonPath::new("").unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
             DisableOutCome::Yes(because),
         )
     }
@@ -225,7 +342,7 @@ mod test {
         temp_env::with_var(env_var, Some(&value), || {
             assert_eq!(
                 DisableCondition::EnvVar { env_is_set: "MY_ENV_VAR".to_string() }
-                    .check(&CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
+                    .check(&CanonPath::new("").unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
                 DisableOutCome::Yes(format!("Environment variable '{}' is set to '{}'", env_var, value)),
             )
         })
@@ -237,7 +354,7 @@ mod test {
         temp_env::with_var(env_var, Some(""), || {
             assert_eq!(
                 DisableCondition::EnvVar { env_is_set: "MY_ENV_VAR".to_string() }
-                    .check(&CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
+                    .check(&CanonPath::new("").unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
                 DisableOutCome::No(Some(format!("Environment variable '{}' is empty", env_var))),
             )
         })
@@ -249,7 +366,7 @@ mod test {
         temp_env::with_var(env_var, None::<String>, || {
             assert_eq!(
                 DisableCondition::EnvVar { env_is_set: "MY_ENV_VAR".to_string() }
-                    .check(&CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
+                    .check(&CanonPath::new("").unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
                 DisableOutCome::No(Some(format!("Environment variable '{}' is not set", env_var))),
             )
         })
@@ -260,8 +377,8 @@ mod test {
         let base_path = Path::new(env!("CARGO_MANIFEST_DIR"))
             .join("resources/test/disable");
         assert_eq!(
-            DisableCondition::File { in_file: "missing".to_string(), key: "my_key".to_string() }
-                .check(&CanonPath::new(base_path).unwrap(), &ManifestConditions::default()).unwrap(),
+            DisableCondition::File { in_file: "missing".to_string(), key: "my_key".to_string(), mode: None }
+                .check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
             DisableOutCome::No(Some(format!("File '{}' does not exist", "missing"))),
         )
     }
@@ -273,8 +390,8 @@ mod test {
         let key = "my_key".to_string();
         let file_name = "example".to_string();
         assert_eq!(
-            DisableCondition::File { in_file: file_name.clone(), key: key.clone() }
-                .check(&CanonPath::new(base_path).unwrap(), &ManifestConditions::default()).unwrap(),
+            DisableCondition::File { in_file: file_name.clone(), key: key.clone(), mode: None }
+                .check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
             DisableOutCome::No(Some(format!("Key '{}' not present in file '{}'", key, file_name))),
         )
     }
@@ -286,8 +403,8 @@ mod test {
         let key = "my_key_1".to_string();
         let file_name = "example".to_string();
         assert_eq!(
-            DisableCondition::File { in_file: file_name.clone(), key: key.clone() }
-                .check(&CanonPath::new(base_path).unwrap(), &ManifestConditions::default()).unwrap(),
+            DisableCondition::File { in_file: file_name.clone(), key: key.clone(), mode: None }
+                .check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
             DisableOutCome::Yes(format!("disabled in file '{file_name}'")),
         )
     }
@@ -299,8 +416,8 @@ mod test {
         let key = "my_key_2".to_string();
         let file_name = "example".to_string();
         assert_eq!(
-            DisableCondition::File { in_file: file_name.clone(), key: key.clone() }
-                .check(&CanonPath::new(base_path).unwrap(), &ManifestConditions::default()).unwrap(),
+            DisableCondition::File { in_file: file_name.clone(), key: key.clone(), mode: None }
+                .check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
             DisableOutCome::No(Some(format!("not disabled in file '{file_name}'"))),
         )
     }
@@ -312,8 +429,8 @@ mod test {
         let key = "my_key_3".to_string();
         let file_name = "example".to_string();
         assert_eq!(
-            DisableCondition::File { in_file: file_name.clone(), key: key.clone() }
-                .check(&CanonPath::new(base_path).unwrap(), &ManifestConditions::default()).unwrap(),
+            DisableCondition::File { in_file: file_name.clone(), key: key.clone(), mode: None }
+                .check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
             DisableOutCome::Yes(format!("I don't want this")),
         )
     }
@@ -325,8 +442,8 @@ mod test {
         let key = "my_key_4".to_string();
         let file_name = "example".to_string();
         assert_eq!(
-            DisableCondition::File { in_file: file_name.clone(), key: key.clone() }
-                .check(&CanonPath::new(base_path).unwrap(), &ManifestConditions::default()).unwrap(),
+            DisableCondition::File { in_file: file_name.clone(), key: key.clone(), mode: None }
+                .check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
             DisableOutCome::No(Some(format!("but this one is ok"))),
         )
     }
@@ -338,8 +455,8 @@ mod test {
         let key = "my_key_5".to_string();
         let file_name = "example".to_string();
         assert_eq!(
-            DisableCondition::File { in_file: file_name.clone(), key: key.clone() }
-                .check(&CanonPath::new(base_path).unwrap(), &ManifestConditions::default()).unwrap(),
+            DisableCondition::File { in_file: file_name.clone(), key: key.clone(), mode: None }
+                .check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
             DisableOutCome::No(Some(format!("this one is kept"))),
         )
     }
@@ -351,8 +468,8 @@ mod test {
         let key = "my_key_6".to_string();
         let file_name = "example".to_string();
         assert!(
-            DisableCondition::File { in_file: file_name.clone(), key: key.clone() }
-                .check(&CanonPath::new(base_path).unwrap(), &ManifestConditions::default())
+            DisableCondition::File { in_file: file_name.clone(), key: key.clone(), mode: None }
+                .check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default())
                 .is_err()
         )
     }
@@ -364,8 +481,8 @@ mod test {
         let key = "my_key".to_string();
         let file_name = "subdir/example2".to_string();
         assert_eq!(
-            DisableCondition::File { in_file: file_name.clone(), key: key.clone() }
-                .check(&CanonPath::new(base_path).unwrap(), &ManifestConditions::default()).unwrap(),
+            DisableCondition::File { in_file: file_name.clone(), key: key.clone(), mode: None }
+                .check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
             DisableOutCome::Yes(format!("disabled in file '{file_name}'")),
         )
     }
@@ -377,8 +494,77 @@ mod test {
         let key = "my_key_6".to_string();
         let file_name = "../example".to_string();
         assert!(
-            DisableCondition::File { in_file: file_name.clone(), key: key.clone() }
-                .check(&CanonPath::new(base_path).unwrap(), &ManifestConditions::default()).is_err()
+            DisableCondition::File { in_file: file_name.clone(), key: key.clone(), mode: None }
+                .check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).is_err()
+        )
+    }
+
+    #[test]
+    fn evaluate_file_glob_condition_first_match() {
+        let base_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("resources/test/disable");
+        assert_eq!(
+            DisableCondition::File { in_file: "multi/*.conf".to_string(), key: "key_b".to_string(), mode: None }
+                .check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
+            DisableOutCome::Yes("disabled in file 'multi/b.conf'".to_string()),
+        )
+    }
+
+    #[test]
+    fn evaluate_file_glob_condition_first_match_uses_path_order() {
+        let base_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("resources/test/disable");
+        assert_eq!(
+            DisableCondition::File { in_file: "multi/*.conf".to_string(), key: "conflict".to_string(),
+                                     mode: Some(FileMatchMode::First) }
+                .check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
+            DisableOutCome::No(Some("not disabled in file 'multi/a.conf'".to_string())),
+        )
+    }
+
+    #[test]
+    fn evaluate_file_glob_condition_all_agree() {
+        let base_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("resources/test/disable");
+        assert_eq!(
+            DisableCondition::File { in_file: "multi/*.conf".to_string(), key: "shared".to_string(),
+                                     mode: Some(FileMatchMode::All) }
+                .check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
+            DisableOutCome::Yes("set in a".to_string()),
+        )
+    }
+
+    #[test]
+    fn evaluate_file_glob_condition_all_disagree() {
+        let base_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("resources/test/disable");
+        assert!(
+            DisableCondition::File { in_file: "multi/*.conf".to_string(), key: "conflict".to_string(),
+                                     mode: Some(FileMatchMode::All) }
+                .check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).is_err()
+        )
+    }
+
+    #[test]
+    fn evaluate_file_glob_condition_missing_key() {
+        let base_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("resources/test/disable");
+        assert_eq!(
+            DisableCondition::File { in_file: "multi/*.conf".to_string(), key: "absent".to_string(),
+                                     mode: Some(FileMatchMode::All) }
+                .check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
+            DisableOutCome::No(Some("Key 'absent' not present in files matching 'multi/*.conf'".to_string())),
+        )
+    }
+
+    #[test]
+    fn evaluate_file_glob_condition_no_matching_file() {
+        let base_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("resources/test/disable");
+        assert_eq!(
+            DisableCondition::File { in_file: "multi/*.none".to_string(), key: "key_b".to_string(), mode: None }
+                .check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
+            DisableOutCome::No(Some("No file matches 'multi/*.none'".to_string())),
         )
     }
 
@@ -386,7 +572,7 @@ mod test {
     fn evaluate_any_condition_zero_sub_conditions() {
         assert_eq!(
             DisableCondition::Any { any: vec![]}
-                .check(&CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
+                .check(&CanonPath::new("").unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
             DisableOutCome::No(None),
         )
     }
@@ -396,7 +582,7 @@ mod test {
         assert_eq!(
             DisableCondition::Any { any: vec![
                 DisableCondition::Because { because: "no reason".to_string() },
-            ]}.check(&CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
+            ]}.check(&CanonPath::new("").unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
             DisableOutCome::Yes("no reason".to_string()),
         )
     }
@@ -408,8 +594,8 @@ mod test {
 
         assert_eq!(
             DisableCondition::Any { any: vec![
-                DisableCondition::File { in_file: "missing".to_string(), key: "my_key".to_string() },
-            ]}.check(&CanonPath::new(base_path).unwrap(), &ManifestConditions::default()).unwrap(),
+                DisableCondition::File { in_file: "missing".to_string(), key: "my_key".to_string(), mode: None },
+            ]}.check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
             DisableOutCome::No(None),
         )
     }
@@ -422,8 +608,8 @@ mod test {
         assert_eq!(
             DisableCondition::Any { any: vec![
                 DisableCondition::Because { because: "no reason".to_string() },
-                DisableCondition::File { in_file: "missing".to_string(), key: "my_key".to_string() },
-            ]}.check(&CanonPath::new(base_path).unwrap(), &ManifestConditions::default()).unwrap(),
+                DisableCondition::File { in_file: "missing".to_string(), key: "my_key".to_string(), mode: None },
+            ]}.check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
             DisableOutCome::Yes("no reason".to_string()),
         )
     }
@@ -435,9 +621,9 @@ mod test {
 
         assert_eq!(
             DisableCondition::Any { any: vec![
-                DisableCondition::File { in_file: "missing".to_string(), key: "my_key".to_string() },
+                DisableCondition::File { in_file: "missing".to_string(), key: "my_key".to_string(), mode: None },
                 DisableCondition::Because { because: "no reason".to_string() },
-            ]}.check(&CanonPath::new(base_path).unwrap(), &ManifestConditions::default()).unwrap(),
+            ]}.check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
             DisableOutCome::Yes("no reason".to_string()),
         )
     }
@@ -446,7 +632,7 @@ mod test {
     fn evaluate_all_condition_zero_sub_conditions() {
             assert!(
                 DisableCondition::All { all: vec![] }
-                    .check(&CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap()
+                    .check(&CanonPath::new("").unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap()
                     .is_yes()
             )
     }
@@ -456,7 +642,7 @@ mod test {
         assert_eq!(
             DisableCondition::All { all: vec![
                 DisableCondition::Because { because: "no reason".to_string() },
-            ]}.check(&CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
+            ]}.check(&CanonPath::new("").unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
             DisableOutCome::Yes("all conditions filled".to_string()),
         )
     }
@@ -468,8 +654,8 @@ mod test {
 
         assert_eq!(
             DisableCondition::All { all: vec![
-                DisableCondition::File { in_file: "missing".to_string(), key: "my_key".to_string() },
-            ]}.check(&CanonPath::new(base_path).unwrap(), &ManifestConditions::default()).unwrap(),
+                DisableCondition::File { in_file: "missing".to_string(), key: "my_key".to_string(), mode: None },
+            ]}.check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
             DisableOutCome::No(Some("File 'missing' does not exist".to_string())),
         )
     }
@@ -481,9 +667,9 @@ mod test {
 
         assert_eq!(
             DisableCondition::All { all: vec![
-                DisableCondition::File { in_file: "missing".to_string(), key: "my_key".to_string() },
+                DisableCondition::File { in_file: "missing".to_string(), key: "my_key".to_string(), mode: None },
                 DisableCondition::Because { because: "no reason".to_string() },
-            ]}.check(&CanonPath::new(base_path).unwrap(), &ManifestConditions::default()).unwrap(),
+            ]}.check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
             DisableOutCome::No(Some("File 'missing' does not exist".to_string())),
         )
     }
@@ -496,8 +682,8 @@ mod test {
         assert_eq!(
             DisableCondition::All { all: vec![
                 DisableCondition::Because { because: "no reason".to_string() },
-                DisableCondition::File { in_file: "missing".to_string(), key: "my_key".to_string() },
-            ]}.check(&CanonPath::new(base_path).unwrap(), &ManifestConditions::default()).unwrap(),
+                DisableCondition::File { in_file: "missing".to_string(), key: "my_key".to_string(), mode: None },
+            ]}.check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap(),
             DisableOutCome::No(Some("File 'missing' does not exist".to_string())),
         )
     }
@@ -508,7 +694,7 @@ mod test {
             not: Box::new(DisableCondition::Because { because: "no reason".to_string() })
         };
         assert!(
-            not_condition.check(&CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap()
+            not_condition.check(&CanonPath::new("").unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap()
                         .is_no()
         )
     }
@@ -519,11 +705,11 @@ mod test {
             .join("resources/test/disable");
 
         let not_condition = DisableCondition::Not {
-            not: Box::new(DisableCondition::File { in_file: "missing".to_string(), key: "my_key".to_string() })
+            not: Box::new(DisableCondition::File { in_file: "missing".to_string(), key: "my_key".to_string(), mode: None })
         };
 
         assert!(
-            not_condition.check(&CanonPath::new(base_path).unwrap(), &ManifestConditions::default()).unwrap()
+            not_condition.check(&CanonPath::new(base_path).unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::default()).unwrap()
                 .is_yes()
         )
     }
@@ -535,7 +721,7 @@ mod test {
             ("my_global_var".to_string(), DisableCondition::Because { because: "it doesn't suit me".to_string() }),
         ]));
         assert_eq!(
-            condition.check(&CanonPath::new("").unwrap(), &manifest_conditions).unwrap(),
+            condition.check(&CanonPath::new("").unwrap(), &CanonPath::new("").unwrap(), &manifest_conditions).unwrap(),
             DisableOutCome::Yes("it doesn't suit me".to_string()),
         )
     }
@@ -550,7 +736,7 @@ mod test {
             ),
         ]));
         assert_eq!(
-            condition.check(&CanonPath::new("").unwrap(), &manifest_conditions).unwrap(),
+            condition.check(&CanonPath::new("").unwrap(), &CanonPath::new("").unwrap(), &manifest_conditions).unwrap(),
             DisableOutCome::No(Some("Negation of condition:\n  disabled because 'it doesn't suit me'".to_string())),
         )
     }
@@ -562,8 +748,64 @@ mod test {
             ("some_other_var".to_string(), DisableCondition::Because { because: "it doesn't suit me".to_string() }),
         ]));
         assert_eq!(
-            condition.check(&CanonPath::new("").unwrap(), &manifest_conditions).unwrap(),
+            condition.check(&CanonPath::new("").unwrap(), &CanonPath::new("").unwrap(), &manifest_conditions).unwrap(),
             DisableOutCome::No(Some("manifest global condition my_global_var is not present".to_string())),
         )
     }
+
+    fn game_dir_with_log() -> (tempfile::TempDir, CanonPath) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("weidu.log"), "\
+// Log of Currently Installed WeiDU Mods
+~BG1UB/SETUP-BG1UB.TP2~ #0 #0 // Ice Island Level Two Restoration
+~BG1UB/SETUP-BG1UB.TP2~ #0 #11 // Scar and the Sashenstar's Daughter
+").unwrap();
+        let game_dir = CanonPath::new(temp_dir.path()).unwrap();
+        (temp_dir, game_dir)
+    }
+
+    #[test]
+    fn evaluate_mod_installed() {
+        let (_temp_dir, game_dir) = game_dir_with_log();
+        let check = |yaml: &str| {
+            serde_yaml::from_str::<DisableCondition>(yaml).unwrap()
+                .check(&CanonPath::new("").unwrap(), &game_dir, &ManifestConditions::default()).unwrap()
+        };
+        assert_eq!(check("mod_installed: bg1ub"), DisableOutCome::No(Some("mod bg1ub is installed".to_string())));
+        assert_eq!(check("{ mod_installed: BG1UB, component: 11 }"),
+                    DisableOutCome::No(Some("component 11 of mod bg1ub is installed".to_string())));
+        assert_eq!(check("{ mod_installed: bg1ub, component: 12 }"),
+                    DisableOutCome::Yes("component 12 of mod bg1ub is not installed".to_string()));
+        assert_eq!(check("mod_installed: other_mod"), DisableOutCome::Yes("mod other_mod is not installed".to_string()));
+    }
+
+    #[test]
+    fn evaluate_mod_installed_without_weidu_log() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let condition = DisableCondition::ModInstalled { mod_installed: crate::lowercase::lwc!("bg1ub"), component: None };
+        assert!(condition.check(&CanonPath::new("").unwrap(), &CanonPath::new(temp_dir.path()).unwrap(),
+                                &ManifestConditions::default()).unwrap().is_yes());
+    }
+
+    #[test]
+    fn evaluate_game_is() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("BGMain.exe"), "").unwrap();
+        let game_dir = CanonPath::new(temp_dir.path()).unwrap();
+        let check = |yaml: &str| {
+            serde_yaml::from_str::<DisableCondition>(yaml).unwrap()
+                .check(&CanonPath::new("").unwrap(), &game_dir, &ManifestConditions::default()).unwrap()
+        };
+        assert_eq!(check("game_is: [bg1, bg2]"), DisableOutCome::No(Some("game is bg2".to_string())));
+        assert_eq!(check("game_is: [eet]"), DisableOutCome::Yes("game is bg2, not one of eet".to_string()));
+
+        let unknown = tempfile::tempdir().unwrap();
+        let condition = DisableCondition::GameIs { game_is: vec![crate::game_detect::GameFlavor::Bg2] };
+        assert_eq!(
+            condition.check(&CanonPath::new("").unwrap(), &CanonPath::new(unknown.path()).unwrap(),
+                            &ManifestConditions::default()).unwrap(),
+            DisableOutCome::Yes("game could not be detected (expected one of bg2)".to_string())
+        );
+        assert!(serde_yaml::from_str::<DisableCondition>("game_is: [bg3]").is_err());
+    }
 }
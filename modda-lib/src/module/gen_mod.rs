@@ -2,6 +2,7 @@
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 
+use crate::file_installer::OverwritePolicy;
 use crate::module::components::{Component, Components};
 use crate::lowercase::LwcString;
 use crate::post_install::PostInstall;
@@ -26,8 +27,17 @@ pub struct GeneratedMod {
     pub ignore_warnings: bool,
     #[serde(default)]
     pub allow_overwrite: bool,
+    /// What happens when a file is copied over another one: `skip`, `overwrite` or `fail`.<br>
+    /// If not set, `overwrite` when `allow_overwrite` is true, `fail` otherwise.
+    pub overwrite: Option<OverwritePolicy>,
+    /// Directory (relative to the game directory) where the files replaced by `overwrite` are saved.
+    pub backup_on_overwrite: Option<String>,
     /// Condition that disables the mod installation (if absent, not disabled)
     pub disabled_if: Option<DisableCondition>,
+    /// `BACKUP` directory of the generated tp2 (default is `weidu_external/backup/<gen_mod>`)
+    pub backup: Option<String>,
+    /// Installation phase, see the same property on weidu mods
+    pub phase: Option<String>,
 }
 
 impl GeneratedMod {
@@ -50,4 +60,8 @@ pub struct GenModComponent {
     #[serde(default)]
     pub index: u32,
     pub name: Option<String>,
+    /// Component flags added after `DESIGNATED` in the generated tp2, one per line<br>
+    /// ex. `[NO_LOG_RECORD, "LABEL ~my-label~", "REQUIRE_PREDICATE GAME_IS ~bg2ee eet~ ~Only for BG2~"]`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<String>,
 }
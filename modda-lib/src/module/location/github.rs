@@ -1,10 +1,11 @@
 
 use std::error::Error;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Result, bail, anyhow};
 use lazy_static::lazy_static;
 use log::info;
+use regex::Regex;
 use reqwest::header::{AUTHORIZATION, HeaderMap, ACCEPT, HeaderValue, HeaderName, USER_AGENT};
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
@@ -13,6 +14,7 @@ use crate::credentials::{Credentials, GithubCredentials};
 use crate::download::{Downloader, DownloadOpts};
 use crate::module::refresh::RefreshCondition;
 use crate::progname::PROGNAME;
+use crate::rate_limit::send_with_rate_limit;
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Default, Clone)]
 pub struct Github {
@@ -25,20 +27,41 @@ pub struct Github {
     pub no_cache: bool,
     /// None -> no auth needed, Some -> method + credentials for example `PAT <token_name>`
     pub auth: Option<String>,
+    /// Name of the archive in the cache (instead of the asset name, or a name made from the module and tag/branch/commit)
+    pub rename: Option<String>,
 }
 
 impl Github {
-    pub async fn get_github(&self, downloader: &Downloader, dest: &PathBuf, save_name: PathBuf) -> Result<PathBuf> {
-        let url = self.descriptor.get_url(&self.github_user, &self.repository, &self.auth).await?;
+    /// `save_name` gives the name of the archive in the cache, it receives the name of the matching asset
+    /// when the release `asset` is a pattern (and the archive is not renamed).
+    pub async fn get_github<F>(&self, downloader: &Downloader, dest: &Path, save_name: F) -> Result<PathBuf>
+            where F: FnOnce(Option<&str>) -> Result<PathBuf> {
+        let (url, asset_name) = self.descriptor.resolve(&self.github_user, &self.repository, &self.auth).await?;
+        let save_name = save_name(asset_name.as_deref().filter(|_| self.rename.is_none()))?;
         let opts = &DownloadOpts { no_cache: self.no_cache, refresh: self.refresh() };
+        let headers = self.request_headers()?;
+
+        downloader.download(&url, dest, save_name, opts, &Some(headers)).await
+    }
+
+    /// Checks that the artifact can be obtained, without downloading it.
+    /// For releases, resolving the asset in the release information is the check.
+    pub async fn check_available(&self, downloader: &Downloader) -> Result<bool> {
+        let url = self.descriptor.get_url(&self.github_user, &self.repository, &self.auth).await?;
+        match self.descriptor {
+            GithubDescriptor::Release { .. } => Ok(true),
+            _ => downloader.check_url(&url, &Some(self.request_headers()?)).await,
+        }
+    }
+
+    fn request_headers(&self) -> Result<HeaderMap> {
         let mut headers = HeaderMap::new();
         headers.insert(ACCEPT, self.descriptor.get_media_type());
         headers.insert(&*GITHUB_API_VERSION_NAME, (*GITHUB_API_VERSION_VALUE).clone());
         if let Some(auth_spec) = &self.auth {
             headers.extend(decode_auth(auth_spec)?);
         }
-
-        downloader.download(&url, dest, save_name, opts, &Some(headers)).await
+        Ok(headers)
     }
 
     pub fn refresh(&self) -> RefreshCondition {
@@ -84,7 +107,15 @@ fn decode_auth(spec: &str) -> Result<HeaderMap> {
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 #[serde(untagged)]
 pub enum GithubDescriptor {
-    Release { release: Option<String>, asset: String },
+    Release {
+        release: Option<String>,
+        /// Name of the asset, or a pattern (with `*` and `?` wildcards) that must match a single asset of the release
+        asset: String,
+        /// When `asset` is a pattern matching several assets, takes the first one instead of failing
+        #[serde(default)]
+        #[serde(skip_serializing_if = "is_false")]
+        first: bool,
+    },
     Commit { commit: String },
     Branch(GitBranch),
     Tag { tag: String },
@@ -114,10 +145,15 @@ impl GithubDescriptor {
     }
 
     pub async fn get_url(&self, user: &str, repository: &str, auth: &Option<String>,) -> Result<String> {
+        self.resolve(user, repository, auth).await.map(|(url, _)| url)
+    }
+
+    /// Download URL, with the name of the matching asset when the release `asset` is a pattern.
+    pub async fn resolve(&self, user: &str, repository: &str, auth: &Option<String>,) -> Result<(String, Option<String>)> {
         use GithubDescriptor::*;
 
         match self {
-            Release { release, asset } => {
+            Release { release, asset, first } => {
                 let release = match &release {
                     None => String::from("latest"),
                     Some(release) => release.to_owned(),
@@ -130,22 +166,50 @@ impl GithubDescriptor {
 
                 // Search a match in the listed assets
                 let lookup = asset.replace("{{release}}", &release);
-                release_info.assets.iter()
-                    .find(|asset| asset.name == lookup)
-                    .map(|asset| asset.url.to_owned())
-                    .ok_or(anyhow!("No asset named {asset} found for release {release} in github repository {user}/{repository}"))
+                match find_asset(&release_info.assets, &lookup, *first) {
+                    Ok(found) if is_asset_pattern(&lookup) => Ok((found.url.to_owned(), Some(found.name.to_owned()))),
+                    Ok(found) => Ok((found.url.to_owned(), None)),
+                    Err(error) => bail!("{error} for release {release} in github repository {user}/{repository}"),
+                }
             }
             Tag { tag } =>
-                Ok(format!("https://api.github.com/repos/{user}/{repository}/zipball/{tag}")),
+                Ok((format!("https://api.github.com/repos/{user}/{repository}/zipball/{tag}"), None)),
             Branch(GitBranch { branch, refresh: _}) =>
-                Ok(format!("https://api.github.com/repos/{user}/{repository}/zipball/{branch}")),
+                Ok((format!("https://api.github.com/repos/{user}/{repository}/zipball/{branch}"), None)),
             Commit { commit } =>
-                Ok(format!("https://api.github.com/repos/{user}/{repository}/zipball/{commit}")),
+                Ok((format!("https://api.github.com/repos/{user}/{repository}/zipball/{commit}"), None)),
         }
     }
 
 }
 
+/// Whether the `asset` of a release is a pattern rather than the exact name of the asset.
+pub fn is_asset_pattern(asset: &str) -> bool {
+    asset.contains(['*', '?'])
+}
+
+fn find_asset<'a>(assets: &'a [Asset], lookup: &str, first: bool) -> Result<&'a Asset> {
+    if !is_asset_pattern(lookup) {
+        return assets.iter()
+            .find(|asset| asset.name == lookup)
+            .ok_or(anyhow!("No asset named {lookup} found"));
+    }
+    let regex = format!("^{}$", regex::escape(lookup).replace(r"\*", ".*").replace(r"\?", "."));
+    let regex = match Regex::new(&regex) {
+        Ok(regex) => regex,
+        Err(error) => bail!("Invalid asset pattern {lookup}\n -> {:?}", error),
+    };
+    let found = assets.iter().filter(|asset| regex.is_match(&asset.name)).collect::<Vec<_>>();
+    match (found.as_slice(), first) {
+        ([], _) => bail!("No asset matching {lookup} found"),
+        ([single], _) | ([single, ..], true) => Ok(single),
+        (_, false) => bail!("Several assets match {lookup} ({}), set `first: true` to use the first one",
+                            found.iter().map(|asset| asset.name.as_str()).collect::<Vec<_>>().join(", ")),
+    }
+}
+
+fn is_false(value: &bool) -> bool { !value }
+
 pub struct GithubClient {
     client: reqwest::Client,
 }
@@ -171,7 +235,7 @@ impl GithubClient {
         let request = self.client.get(&url)
             .header(USER_AGENT, PROGNAME);
 
-        let response = request.send().await?;
+        let response = send_with_rate_limit(request).await?;
         info!("{:?}", response);
         if !response.status().is_success() {
             match response.status() {
@@ -220,3 +284,42 @@ struct Asset {
     pub size: usize,
 
 }
+
+#[cfg(test)]
+mod test_find_asset {
+    use super::{find_asset, Asset};
+
+    fn asset(name: &str) -> Asset {
+        Asset {
+            url: format!("https://api.github.com/assets/{name}"),
+            browser_download_url: format!("https://github.com/download/{name}"),
+            id: 1,
+            name: name.to_string(),
+            label: None,
+            content_type: "application/zip".to_string(),
+            size: 100,
+        }
+    }
+
+    #[test]
+    fn find_asset_by_name_or_pattern() {
+        let assets = vec![asset("A7-DlcMerger-v1.3.zip"), asset("lin-A7-DlcMerger-v1.3.zip"), asset("mac-A7-DlcMerger-v1.3.zip")];
+
+        assert_eq!(find_asset(&assets, "lin-A7-DlcMerger-v1.3.zip", false).unwrap().name, "lin-A7-DlcMerger-v1.3.zip");
+        assert_eq!(find_asset(&assets, "A7-DlcMerger-*.zip", false).unwrap().name, "A7-DlcMerger-v1.3.zip");
+        assert_eq!(find_asset(&assets, "lin-A7-DlcMerger-v?.?.zip", false).unwrap().name, "lin-A7-DlcMerger-v1.3.zip");
+        assert_eq!(find_asset(&assets, "A7-DlcMerger.zip", false).unwrap_err().to_string(),
+                    "No asset named A7-DlcMerger.zip found");
+        assert_eq!(find_asset(&assets, "*.tar.gz", false).unwrap_err().to_string(), "No asset matching *.tar.gz found");
+    }
+
+    #[test]
+    fn several_matching_assets() {
+        let assets = vec![asset("lin-A7-DlcMerger-v1.3.zip"), asset("mac-A7-DlcMerger-v1.3.zip")];
+
+        assert_eq!(find_asset(&assets, "*-A7-DlcMerger-*.zip", false).unwrap_err().to_string(),
+                    "Several assets match *-A7-DlcMerger-*.zip (lin-A7-DlcMerger-v1.3.zip, mac-A7-DlcMerger-v1.3.zip), \
+                    set `first: true` to use the first one");
+        assert_eq!(find_asset(&assets, "*-A7-DlcMerger-*.zip", true).unwrap().name, "lin-A7-DlcMerger-v1.3.zip");
+    }
+}
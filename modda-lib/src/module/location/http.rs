@@ -1,7 +1,8 @@
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 
 use crate::download::{Downloader, DownloadOpts};
@@ -17,13 +18,55 @@ pub struct Http {
     #[serde(default)]
     #[serde(with = "crate::module::refresh::RefreshConditionAsString")]
     pub refresh: RefreshCondition,
+    /// Alternate URLs, tried in order when the download from `http` fails.
+    /// The archive is cached under the name derived from `http` whichever URL it came from.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>,
 }
 
 impl Http {
     pub fn from(http: &str) -> Self { Self { http: http.to_owned(), ..Self::default() } }
 
-    pub async fn download(&self, downloader: &Downloader, dest: &PathBuf, save_name: PathBuf) -> Result<PathBuf> {
+    pub async fn download(&self, downloader: &Downloader, dest: &Path, save_name: PathBuf) -> Result<PathBuf> {
         let opts = &DownloadOpts { no_cache: self.no_cache, refresh: self.refresh.clone() };
-        downloader.download(&self.http, dest, save_name, opts, &None).await
+        if self.mirrors.is_empty() {
+            return downloader.download(&self.http, dest, save_name, opts, &None).await;
+        }
+        let mut failures = vec![];
+        for url in self.urls() {
+            match downloader.download(url, dest, save_name.clone(), opts, &None).await {
+                Ok(archive) => {
+                    info!("{:?} obtained from {}", save_name, url);
+                    return Ok(archive);
+                }
+                Err(error) => {
+                    warn!("Download from {} failed, {}", url,
+                            if failures.len() < self.mirrors.len() { "trying the next mirror" } else { "no mirror left" });
+                    failures.push(format!("{}\n -> {:?}", url, error));
+                }
+            }
+        }
+        bail!("Download failed from {} and all its mirrors\n{}", self.http, failures.join("\n"))
+    }
+
+    /// Available if the primary URL or one of the mirrors is.
+    pub async fn check_available(&self, downloader: &Downloader) -> Result<bool> {
+        let mut last_error = None;
+        for url in self.urls() {
+            match downloader.check_url(url, &None).await {
+                Ok(true) => return Ok(true),
+                Ok(false) => {}
+                Err(error) => last_error = Some(error),
+            }
+        }
+        match last_error {
+            Some(error) => Err(error),
+            None => Ok(false),
+        }
+    }
+
+    /// The primary URL, then the mirrors.
+    pub fn urls(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.http).chain(self.mirrors.iter())
     }
 }
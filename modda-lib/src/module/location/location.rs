@@ -9,6 +9,7 @@ use serde::de::{Visitor, MapAccess};
 use serde_with::skip_serializing_none;
 use void::Void;
 
+use crate::config::ExtractorCommand;
 use crate::lowercase::{LwcString, lwc};
 use crate::module::pre_copy_command::PrecopyCommand;
 use crate::{archive_layout::Layout, patch_source::PatchDesc};
@@ -63,7 +64,15 @@ pub struct ConcreteLocation {
     pub patches: Vec<PatchDesc>,
     /// regex-based search and replace, runs after patch.
     pub replace: Option<Vec<ReplaceSpec>>,
+    /// Command run on the extracted content (whatever the source type), before it is moved to the game directory.
     pub precopy: Option<PrecopyCommand>,
+    /// External extraction command used for this archive instead of the one configured (in `extractors`)
+    /// for its extension. Only archives that are not handled natively (zip, iemod, tgz, tar.gz, tzst, tar.zst, tbz2, tar.bz2) use it.
+    pub extractor: Option<ExtractorCommand>,
+    /// Expected SHA-256 digest (hex) of the downloaded archive, checked unless `--no-verify` is given.
+    pub sha256: Option<String>,
+    /// Expected size (in bytes) of the downloaded archive, checked unless `--no-verify` is given.
+    pub size: Option<u64>,
 }
 
 pub fn location_deser<'de, D>(deserializer: D) -> Result<Location, D::Error>
@@ -191,6 +200,7 @@ mod test_deserialize {
                 descriptor: GithubDescriptor::Release {
                     release: Some("1.0".to_string()),
                     asset: "my_repo-1.0.zip".to_string(),
+                    first: false,
                 },
                 ..Default::default()
             })
@@ -245,6 +255,7 @@ mod test_deserialize {
                         check: CheckReplace::BoolValue(false),
                         max_depth: None,
                         regex: false,
+                        binary: false,
                     }
                 ]),
                 ..Default::default()
@@ -281,6 +292,7 @@ mod test_deserialize {
                         with: "typo".to_string(),
                         max_depth: None,
                         regex: false,
+                        binary: false,
                         check: CheckReplace::BoolValue(false),
                     }
                 ]),
@@ -318,6 +330,7 @@ mod test_deserialize {
                         with: "typo".to_string(),
                         max_depth: None,
                         regex: false,
+                        binary: false,
                         check: CheckReplace::BoolValue(true),
                     }
                 ]),
@@ -355,6 +368,7 @@ mod test_deserialize {
                         with: "typo".to_string(),
                         max_depth: None,
                         regex: false,
+                        binary: false,
                         check: CheckReplace::Exact(NonZeroU32::new(123).unwrap()),
                     }
                 ]),
@@ -422,6 +436,7 @@ mod test_deserialize {
                         with: "typo".to_string(),
                         max_depth: None,
                         regex: false,
+                        binary: false,
                         check: CheckReplace::MoreThan(NonZeroU32::new(123).unwrap()),
                     }
                 ]),
@@ -459,6 +474,7 @@ mod test_deserialize {
                         with: "typo".to_string(),
                         max_depth: None,
                         regex: false,
+                        binary: false,
                         check: CheckReplace::MoreThan(NonZeroU32::new(123).unwrap()),
                     }
                 ]),
@@ -496,6 +512,7 @@ mod test_deserialize {
                         with: "typo".to_string(),
                         max_depth: None,
                         regex: false,
+                        binary: false,
                         check: CheckReplace::BoolValue(true),
                     }
                 ]),
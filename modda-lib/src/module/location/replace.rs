@@ -1,5 +1,5 @@
 
-use std::{borrow::Cow, fs::{copy, rename, File, OpenOptions}, io::{Read, Write}, path::PathBuf};
+use std::{borrow::Cow, fs::{copy, rename, File, OpenOptions}, io::{Read, Write}, path::{Component, Path, PathBuf}};
 
 use anyhow::{Result, bail};
 
@@ -9,7 +9,8 @@ use log::{debug, error, info, warn};
 use regex::{Regex, Replacer};
 use serde::{Deserialize, Serialize};
 
-use crate::{obtain::get_options::{GetOptions, StrictReplaceAction}, utils::pathext::append_extension};
+use crate::{canon_path::CanonPath, obtain::get_options::{GetOptions, StrictReplaceAction}, utils::pathext::append_extension};
+use crate::transform_preview::TransformPreview;
 
 use super::strict_replace::CheckReplace;
 
@@ -19,9 +20,10 @@ pub struct ReplaceSpec {
     /// https://git-scm.com/docs/gitignore#_pattern_format
     pub file_globs: Vec<String>,
     /// A regexp or plain string to search and replace.
-    /// Assumes UTF-8 content.
+    /// Assumes UTF-8 content (unless `binary` is set, then it's hexadecimal bytes).
     pub replace: String,
     /// the replacement string (may use capture group as positional/integer or named capture group)
+    /// or the replacement bytes (hexadecimal) with `binary`
     pub with: String,
     /// If set, put a limit of the depth (from mod root) where the file to modify are found.
     pub max_depth: Option<usize>,
@@ -30,6 +32,11 @@ pub struct ReplaceSpec {
     /// <https://regex101.com/> has a `rust` flavour.
     #[serde(default)]
     pub regex: bool,
+    /// If true, the files are not decoded, `replace` and `with` are byte sequences written in
+    /// hexadecimal (spaces are allowed between bytes, for example `DE AD 00 01`).<br>
+    /// Can't be used with `regex`.
+    #[serde(default)]
+    pub binary: bool,
     /// Tells to check something was actually replaced.<br>
     /// - If absent or `false` no check is done
     /// - if `true`` checks something was replaced (at least once)
@@ -40,7 +47,7 @@ pub struct ReplaceSpec {
 }
 
 impl ReplaceSpec {
-    fn find_matching_files(&self, root: &PathBuf) -> Result<GlobWalker> {
+    fn find_matching_files(&self, root: &Path) -> Result<GlobWalker> {
         let walker = GlobWalkerBuilder::from_patterns(root, &self.file_globs)
             .case_insensitive(true)
             .max_depth(self.max_depth.unwrap_or(0))
@@ -57,14 +64,14 @@ impl ReplaceSpec {
     /// Replaces the content of `<file_name>` with the new content
     /// `<file_name>` is renamed to `file_name>.replaced`
     /// a new `<file_name>` file is created with the new content inside.
-    fn swap_file_content(&self, file_path: &PathBuf, new_content: &str) -> Result<()> {
+    fn swap_file_content(&self, file_path: &PathBuf, new_content: &[u8]) -> Result<()> {
         let new_file_path = append_extension("new", file_path);
         debug!("swap_file_content will write new version in temporary file {:?}", new_file_path);
         let mut new_file = match OpenOptions::new().create(true).write(true).truncate(true).open(&new_file_path) {
             Ok(file) => file,
             Err(err) => bail!("apply_replace: could not create temp file - {}", err),
         };
-        if let Err(err) = write!(new_file, "{}", new_content) {
+        if let Err(err) = new_file.write_all(new_content) {
             bail!("apply_replace: could not write new data to temp file - {}", err);
         }
         if let Err(err) = new_file.flush() {
@@ -83,25 +90,100 @@ impl ReplaceSpec {
         Ok(())
     }
 
-    pub fn exec(&self, root: &PathBuf, get_options: &GetOptions) -> Result<()> {
+    /// Same as `exec` but refuses globs that could match files outside of `root`.
+    pub fn exec_contained(&self, root: &CanonPath, get_options: &GetOptions) -> Result<()> {
+        for glob in &self.file_globs {
+            let glob_path = Path::new(glob.trim_start_matches('!'));
+            if glob_path.has_root() || glob_path.components().any(|component| component == Component::ParentDir) {
+                bail!("replace glob {:?} could match files outside of {:?}", glob, root);
+            }
+        }
+        self.exec(&root.to_path_buf(), get_options)
+    }
+
+    pub fn exec(&self, root: &Path, get_options: &GetOptions) -> Result<()> {
         info!("ReplaceSpec.exec on {:?} - {} => {}", &self.file_globs, &self.replace, &self.with);
         let walker = self.find_matching_files(root)?;
+        let result = if self.binary {
+            let patterns = self.byte_patterns()?;
+            self.exec_binary_with_walker(&patterns, walker, get_options)
+        } else {
+            let regex = self.regex()?;
+            self.exec_with_walker(&regex, walker, get_options)
+        };
+        match result {
+            Err(ref err) => error!("ReplaceSpec.exec failure - {}", err),
+            Ok(_) => info!("ReplaceSpec.exec success"),
+        }
+        result
+    }
+
+    /// Same as `exec` but the result goes to `preview` (nothing is written, the `check` condition is not
+    /// evaluated), returns the number of replacements.
+    /// Binary replacements are only counted, they don't show up in the diff.
+    pub fn preview(&self, root: &Path, preview: &mut TransformPreview) -> Result<u32> {
+        let walker = self.find_matching_files(root)?;
+        if self.binary {
+            let patterns = self.byte_patterns()?;
+            let mut replace_count = 0;
+            for dir_entry in walker.into_iter().filter_map(Result::ok) {
+                if dir_entry.file_type().is_file() {
+                    replace_count += self.apply_binary_replace(dir_entry.path(), &patterns)?.count;
+                } else {
+                    warn!("ReplaceSpec.preview - ignore matching file {:?}", dir_entry.path())
+                }
+            }
+            return Ok(replace_count);
+        }
+        let regex = self.regex()?;
+        let mut replace_count = 0;
+        for dir_entry in walker.into_iter().filter_map(Result::ok) {
+            let file_path = CanonPath::new(dir_entry.path())?;
+            let content = match preview.content(&file_path, |path| read_utf8_content(path.path()))? {
+                Some(content) => content,
+                None => continue,
+            };
+            let ReplaceResult { count, replaced } = self.apply_replace_content(&regex, &content)?;
+            replace_count += count;
+            if count > 0 {
+                preview.set(&file_path, Some(replaced))?;
+            }
+        }
+        Ok(replace_count)
+    }
+
+    pub fn regex(&self) -> Result<Regex> {
         let pattern = if self.regex {
             Cow::Borrowed(&self.replace)
         } else {
             Cow::Owned(regex::escape(&self.replace))
         };
         debug!("actual regex is {:?}", pattern);
-        let regex = match Regex::new(&pattern) {
+        match Regex::new(&pattern) {
             Err(err) => bail!("Incorrect regex {} - {}", &self.replace, err),
-            Ok(regex) => regex,
-        };
-        let result = self.exec_with_walker(&regex, walker, get_options);
-        match result {
-            Err(ref err) => error!("ReplaceSpec.exec failure - {}", err),
-            Ok(_) => info!("ReplaceSpec.exec success"),
+            Ok(regex) => Ok(regex),
         }
-        result
+    }
+
+    /// Checks the `replace` pattern (and `with` for binary replacements) can be used.
+    pub fn check_patterns(&self) -> Result<()> {
+        if self.binary {
+            self.byte_patterns().map(|_| ())
+        } else {
+            self.regex().map(|_| ())
+        }
+    }
+
+    /// The `replace` and `with` byte sequences of a binary replacement.
+    pub fn byte_patterns(&self) -> Result<BytePatterns> {
+        if self.regex {
+            bail!("`regex` can't be used with a `binary` replace ({})", self.replace);
+        }
+        let replace = parse_hex_bytes(&self.replace)?;
+        if replace.is_empty() {
+            bail!("`replace` bytes can't be empty for a `binary` replace");
+        }
+        Ok(BytePatterns { replace, with: parse_hex_bytes(&self.with)? })
     }
 
     fn exec_with_walker(&self, regex: &Regex, walker: GlobWalker, get_options: &GetOptions) -> Result<()> {
@@ -112,6 +194,22 @@ impl ReplaceSpec {
                 debug!("process matching file {:?}", file_path);
                 let ReplaceResult { count, replaced } = self.apply_replace(&file_path, &regex)?;
                 replace_count += count;
+                self.swap_file_content(&file_path, replaced.as_bytes())?;
+            } else {
+                warn!("ReplaceSpec.exec - ignore matching file {:?}", dir_entry.path())
+            }
+        }
+        self.check_replace_condition(replace_count, get_options)
+    }
+
+    fn exec_binary_with_walker(&self, patterns: &BytePatterns, walker: GlobWalker, get_options: &GetOptions) -> Result<()> {
+        let mut replace_count = 0;
+        for dir_entry in walker.into_iter().filter_map(Result::ok) {
+            if dir_entry.file_type().is_file() {
+                let file_path = dir_entry.into_path();
+                debug!("process matching file {:?}", file_path);
+                let BinaryReplaceResult { count, replaced } = self.apply_binary_replace(&file_path, patterns)?;
+                replace_count += count;
                 self.swap_file_content(&file_path, &replaced)?;
             } else {
                 warn!("ReplaceSpec.exec - ignore matching file {:?}", dir_entry.path())
@@ -146,20 +244,8 @@ impl ReplaceSpec {
         }
     }
 
-    fn apply_replace(&self, file_path: &PathBuf, regex: &Regex) -> Result<ReplaceResult> {
-        let mut file = match File::open(file_path) {
-            Err(err) => bail!("apply_replace - fail to open old file {:?} - {}", file_path, err),
-            Ok(file) => file,
-        };
-        let mut buf = vec![];
-        if let Err(err) = file.read_to_end(&mut buf) {
-            bail!("apply_replace: could not read content of file {file_path:?}\n  {err}")
-        }
-
-        let content = match String::from_utf8(buf) {
-            Err(err)  => bail!("apply_replace: content of {file_path:?} does not appear to be UTF8\n  {err}"),
-            Ok(what) => what,
-        };
+    fn apply_replace(&self, file_path: &Path, regex: &Regex) -> Result<ReplaceResult> {
+        let content = read_utf8_content(file_path)?;
         self.apply_replace_content(regex, &content)
     }
 
@@ -168,6 +254,65 @@ impl ReplaceSpec {
         let replaced = regex.replace_all(content, counting_replacer.by_ref()).to_string();
         Ok(ReplaceResult { replaced, count: counting_replacer.count })
     }
+
+    fn apply_binary_replace(&self, file_path: &Path, patterns: &BytePatterns) -> Result<BinaryReplaceResult> {
+        let content = match std::fs::read(file_path) {
+            Ok(content) => content,
+            Err(err) => bail!("apply_replace: could not read content of file {file_path:?}\n  {err}"),
+        };
+        Ok(replace_bytes(&content, patterns))
+    }
+}
+
+/// Bytes written as hexadecimal (`DEAD01` or `DE AD 01`).
+fn parse_hex_bytes(hex: &str) -> Result<Vec<u8>> {
+    let digits = hex.chars().filter(|c| !c.is_whitespace()).collect::<Vec<_>>();
+    if digits.len() % 2 != 0 {
+        bail!("Incorrect hexadecimal bytes {} - odd number of digits", hex);
+    }
+    digits.chunks(2)
+        .map(|pair| {
+            let pair = pair.iter().collect::<String>();
+            match u8::from_str_radix(&pair, 16) {
+                Ok(byte) => Ok(byte),
+                Err(err) => bail!("Incorrect hexadecimal bytes {} - {:?} {}", hex, pair, err),
+            }
+        })
+        .collect()
+}
+
+/// Replaces all (non-overlapping, from the start) occurrences of `patterns.replace`.
+fn replace_bytes(content: &[u8], patterns: &BytePatterns) -> BinaryReplaceResult {
+    let mut replaced = Vec::with_capacity(content.len());
+    let mut count = 0;
+    let mut position = 0;
+    while position < content.len() {
+        if content[position..].starts_with(&patterns.replace) {
+            replaced.extend_from_slice(&patterns.with);
+            position += patterns.replace.len();
+            count += 1;
+        } else {
+            replaced.push(content[position]);
+            position += 1;
+        }
+    }
+    BinaryReplaceResult { count, replaced }
+}
+
+fn read_utf8_content(file_path: &Path) -> Result<String> {
+    let mut file = match File::open(file_path) {
+        Err(err) => bail!("apply_replace - fail to open old file {:?} - {}", file_path, err),
+        Ok(file) => file,
+    };
+    let mut buf = vec![];
+    if let Err(err) = file.read_to_end(&mut buf) {
+        bail!("apply_replace: could not read content of file {file_path:?}\n  {err}")
+    }
+
+    match String::from_utf8(buf) {
+        Err(err)  => bail!("apply_replace: content of {file_path:?} does not appear to be UTF8\n  {err}"),
+        Ok(what) => Ok(what),
+    }
 }
 
 pub struct ReplaceResult {
@@ -175,6 +320,17 @@ pub struct ReplaceResult {
     pub replaced: String,
 }
 
+#[derive(Debug, PartialEq)]
+pub struct BytePatterns {
+    pub replace: Vec<u8>,
+    pub with: Vec<u8>,
+}
+
+pub struct BinaryReplaceResult {
+    pub count: u32,
+    pub replaced: Vec<u8>,
+}
+
 struct CountingReplacer{
     with: String,
     count: u32,
@@ -197,7 +353,7 @@ mod replace_tests {
     use std::num::NonZeroU32;
     use std::path::{Path, PathBuf};
 
-    use crate::module::location::replace::{ReplaceSpec, CheckReplace};
+    use crate::module::location::replace::{BytePatterns, ReplaceSpec, CheckReplace};
     use crate::obtain::get_options::{GetOptions, StrictReplaceAction};
     use crate::utils::read_all::read_all;
 
@@ -220,6 +376,7 @@ mod replace_tests {
             with: "11".to_string(),
             max_depth: Some(1),
             regex: true,
+            binary: false,
             check: CheckReplace::BoolValue(false),
         };
         let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
@@ -255,6 +412,7 @@ mod replace_tests {
             with: "$1$1".to_string(),
             max_depth: Some(1),
             regex: true,
+            binary: false,
             check: CheckReplace::BoolValue(false),
         };
         let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
@@ -290,6 +448,7 @@ mod replace_tests {
             with: "[11]".to_string(),
             max_depth: Some(1),
             regex: false,
+            binary: false,
             check: CheckReplace::BoolValue(false),
         };
         let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
@@ -328,6 +487,7 @@ mod replace_tests {
             with: "[11]".to_string(),
             max_depth: Some(1),
             regex: false,
+            binary: false,
             check: CheckReplace::BoolValue(true),
         };
         let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
@@ -362,6 +522,7 @@ mod replace_tests {
             with: "[11]".to_string(),
             max_depth: Some(1),
             regex: false,
+            binary: false,
             check: CheckReplace::BoolValue(true),
         };
         let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
@@ -390,6 +551,7 @@ mod replace_tests {
             with: "[11]".to_string(),
             max_depth: Some(1),
             regex: false,
+            binary: false,
             check: CheckReplace::Exact(NonZeroU32::new(2u32).unwrap()),
         };
         let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
@@ -424,6 +586,7 @@ mod replace_tests {
             with: "[11]".to_string(),
             max_depth: Some(1),
             regex: false,
+            binary: false,
             check: CheckReplace::Exact(NonZeroU32::new(3u32).unwrap()),
         };
         let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
@@ -452,6 +615,7 @@ mod replace_tests {
             with: "[11]".to_string(),
             max_depth: Some(1),
             regex: false,
+            binary: false,
             check: CheckReplace::Exact(NonZeroU32::new(1u32).unwrap()),
         };
         let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
@@ -480,6 +644,7 @@ mod replace_tests {
             with: "[11]".to_string(),
             max_depth: Some(1),
             regex: false,
+            binary: false,
             check: CheckReplace::MoreThan(NonZeroU32::new(2u32).unwrap()),
         };
         let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
@@ -514,9 +679,73 @@ mod replace_tests {
             with: "[11]".to_string(),
             max_depth: Some(1),
             regex: false,
+            binary: false,
             check: CheckReplace::MoreThan(NonZeroU32::new(3u32).unwrap()),
         };
         let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
         replace_spec.exec(&test_dir, &get_options).unwrap_err();
     }
+
+    #[test]
+    fn replace_binary() {
+        let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let test_dir = project.join("target").join("replace").join("replace_binary");
+        std::fs::create_dir_all(&test_dir).unwrap();
+        let target_file_path = test_dir.join("input.bin");
+        std::fs::write(&target_file_path, [0x00, 0xDE, 0xAD, 0xFF, 0xDE, 0xAD, 0xDE, 0x01]).unwrap();
+
+        let replace_spec = ReplaceSpec {
+            file_globs: vec!["input.bin".to_string()],
+            replace: "de ad".to_string(),
+            with: "BEEF 00".to_string(),
+            max_depth: Some(1),
+            regex: false,
+            binary: true,
+            check: CheckReplace::Exact(NonZeroU32::new(2u32).unwrap()),
+        };
+        let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
+        replace_spec.exec(&test_dir, &get_options).unwrap();
+
+        assert_eq!(std::fs::read(&target_file_path).unwrap(),
+                    vec![0x00, 0xBE, 0xEF, 0x00, 0xFF, 0xBE, 0xEF, 0x00, 0xDE, 0x01]);
+        assert_eq!(std::fs::read(test_dir.join("input.bin.replaced")).unwrap(),
+                    vec![0x00, 0xDE, 0xAD, 0xFF, 0xDE, 0xAD, 0xDE, 0x01]);
+    }
+
+    #[test]
+    fn replace_binary_with_strict_exact_fails() {
+        let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let test_dir = project.join("target").join("replace_strict").join("binary_exact_fails");
+        std::fs::create_dir_all(&test_dir).unwrap();
+        std::fs::write(test_dir.join("input.bin"), [0x00, 0xDE, 0xAD, 0xFF]).unwrap();
+
+        let replace_spec = ReplaceSpec {
+            file_globs: vec!["input.bin".to_string()],
+            replace: "DEAD".to_string(),
+            with: "".to_string(),
+            max_depth: Some(1),
+            regex: false,
+            binary: true,
+            check: CheckReplace::Exact(NonZeroU32::new(2u32).unwrap()),
+        };
+        let get_options = GetOptions { strict_replace: StrictReplaceAction::Fail };
+        replace_spec.exec(&test_dir, &get_options).unwrap_err();
+    }
+
+    #[test]
+    fn binary_byte_patterns() {
+        let spec = |replace: &str, with: &str, regex: bool| ReplaceSpec {
+            replace: replace.to_string(),
+            with: with.to_string(),
+            regex,
+            binary: true,
+            ..ReplaceSpec::default()
+        };
+        assert_eq!(spec("0a Ff", "", false).byte_patterns().unwrap(),
+                    BytePatterns { replace: vec![0x0A, 0xFF], with: vec![] });
+        assert!(spec("0a F", "00", false).byte_patterns().is_err());
+        assert!(spec("0a", "zz", false).byte_patterns().is_err());
+        assert!(spec(" ", "00", false).byte_patterns().is_err());
+        assert!(spec("0a", "00", true).byte_patterns().is_err());
+    }
 }
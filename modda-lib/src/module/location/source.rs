@@ -7,6 +7,7 @@ use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 
+use crate::cache::{flat_cache_name, CacheLayout};
 use crate::lowercase::LwcString;
 use crate::module::location::github::{GithubDescriptor, GitBranch, Github};
 
@@ -22,21 +23,31 @@ pub enum Source {
     /// The artifact is hosted on github an obtained by http request
     Github(Github),
     /// The artifact is on the local computer filesystem.\
-    /// It can be either an archive (zip/tgz/...) or a directory
-    Absolute { path: String },
+    /// It can be either an archive (zip/tgz/...) or a directory.\
+    /// With `rename`, the archive is extracted according to this name (extension) instead of its own.
+    Absolute { path: String, rename: Option<String> },
     /// The artifact is on the local computer filesystem, the location is relative to the manifest file.\
-    /// It can be either an archive (zip/tgz/...) or a directory
-    Local { local: String },
+    /// It can be either an archive (zip/tgz/...) or a directory.\
+    /// With `rename`, the archive is extracted according to this name (extension) instead of its own.
+    Local { local: String, rename: Option<String> },
 }
 
 impl Default for Source {
     fn default() -> Self {
-        Source::Local { local: String::new() }
+        Source::Local { local: String::new(), rename: None }
     }
 }
 
 impl Source {
-    pub fn save_subdir(&self) -> Result<PathBuf> {
+    /// Sub-directory of the cache where the archive is saved.
+    pub fn save_subdir(&self, layout: CacheLayout) -> Result<PathBuf> {
+        match layout {
+            CacheLayout::Partitioned => self.partition(),
+            CacheLayout::Flat => Ok(PathBuf::new()),
+        }
+    }
+
+    fn partition(&self) -> Result<PathBuf> {
         use Source::*;
         use url::{Url, Host};
         match self {
@@ -59,7 +70,32 @@ impl Source {
         }
     }
 
-    pub fn save_name(&self, module_name: &LwcString) -> Result<PathBuf> {
+    /// Name of the archive in the cache (in the `save_subdir` sub-directory).<br>
+    /// For a release asset pattern, it's only a placeholder, see `asset_save_name`.
+    pub fn save_name(&self, module_name: &LwcString, layout: CacheLayout) -> Result<PathBuf> {
+        self.layout_name(self.archive_name(module_name)?, layout)
+    }
+
+    /// Name in the cache of the release asset that matched an asset pattern, each release gets its own archive.
+    pub fn asset_save_name(&self, asset_name: &str, layout: CacheLayout) -> Result<PathBuf> {
+        self.layout_name(PathBuf::from(asset_name), layout)
+    }
+
+    fn layout_name(&self, name: PathBuf, layout: CacheLayout) -> Result<PathBuf> {
+        match layout {
+            CacheLayout::Partitioned => Ok(name),
+            CacheLayout::Flat => {
+                let partition = self.partition()?;
+                if partition.as_os_str().is_empty() {
+                    Ok(name)
+                } else {
+                    Ok(flat_cache_name(&partition, &name))
+                }
+            }
+        }
+    }
+
+    fn archive_name(&self, module_name: &LwcString) -> Result<PathBuf> {
         use Source::*;
         match self {
             Http(super::http::Http { ref http, ref rename,.. }) => {
@@ -73,6 +109,8 @@ impl Source {
                         match url.path_segments() {
                             None => bail!("Couldn't decide archive name for url {} - provide one with 'rename' field", http),
                             Some(segments) => match segments.last() {
+                                // the actual name (and extension) may come from the `Content-Disposition` header
+                                Some("") => Ok(PathBuf::from(module_name.as_ref())),
                                 Some(seg) => Ok(PathBuf::from(
                                     percent_encoding::percent_decode_str(seg).decode_utf8_lossy().into_owned()
                                 )),
@@ -83,9 +121,11 @@ impl Source {
                 }
             }
             Absolute { .. } | Local { .. } => Ok(PathBuf::new()),
+            Github(self::Github { rename: Some(rename), .. }) => Ok(PathBuf::from(rename)),
             Github(self::Github { descriptor, .. }) => match descriptor {
+                // with a pattern, the name of the matching asset is only known when downloading (placeholder)
                 GithubDescriptor::Release { asset , ..} =>
-                                                    Ok(PathBuf::from(asset.to_owned())),
+                                                    Ok(PathBuf::from(asset.replace(['*', '?'], "_"))),
                 GithubDescriptor::Commit { commit } =>
                                                     Ok(PathBuf::from(format!("{}-{}.zip", module_name, commit))),
                 GithubDescriptor::Branch(GitBranch { branch, .. }) =>
@@ -119,6 +159,7 @@ impl Source {
                 descriptor: GithubDescriptor::Release {
                     release: Some("".to_string()),
                     asset: "".to_string(),
+                    first: false,
                 },
                 ..Default::default()
             }
@@ -137,3 +178,113 @@ impl Source {
     }
 }
 
+
+#[cfg(test)]
+mod test_cache_layout {
+    use std::path::PathBuf;
+
+    use crate::cache::CacheLayout;
+    use crate::lowercase::lwc;
+    use crate::module::location::github::{Github, GithubDescriptor};
+    use crate::module::location::http::Http;
+
+    use super::Source;
+
+    fn http(url: &str) -> Source {
+        Source::Http(Http { http: url.to_string(), rename: None, ..Default::default() })
+    }
+
+    fn github_tag(user: &str) -> Source {
+        Source::Github(Github {
+            github_user: user.to_string(),
+            repository: "my_repo".to_string(),
+            descriptor: GithubDescriptor::Tag { tag: "v1.0".to_string() },
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn partitioned_layout() {
+        let source = http("https://example.com/files/my_mod-1.0.tar.gz");
+        assert_eq!(source.save_subdir(CacheLayout::Partitioned).unwrap(), PathBuf::from("http/example.com"));
+        assert_eq!(source.save_name(&lwc!("my_mod"), CacheLayout::Partitioned).unwrap(), PathBuf::from("my_mod-1.0.tar.gz"));
+
+        let source = github_tag("someone");
+        assert_eq!(source.save_subdir(CacheLayout::Partitioned).unwrap(), PathBuf::from("github/someone/my_repo"));
+        assert_eq!(source.save_name(&lwc!("my_mod"), CacheLayout::Partitioned).unwrap(), PathBuf::from("my_mod-v1.0.zip"));
+    }
+
+    #[test]
+    fn flat_layout() {
+        let source = http("https://example.com/files/my_mod-1.0.tar.gz");
+        assert_eq!(source.save_subdir(CacheLayout::Flat).unwrap(), PathBuf::new());
+        let name = source.save_name(&lwc!("my_mod"), CacheLayout::Flat).unwrap();
+        let name = name.to_string_lossy();
+        assert!(name.starts_with("my_mod-1.0-"));
+        assert!(name.ends_with(".tar.gz"));
+        assert_eq!(name.len(), "my_mod-1.0-".len() + 8 + ".tar.gz".len());
+
+        let name = github_tag("someone").save_name(&lwc!("my_mod"), CacheLayout::Flat).unwrap();
+        assert!(name.to_string_lossy().starts_with("my_mod-v1.0-"));
+        assert_eq!(name.extension().unwrap(), "zip");
+    }
+
+    #[test]
+    fn flat_names_dont_collide() {
+        let module = lwc!("my_mod");
+        let from_example = http("https://example.com/my_mod.zip").save_name(&module, CacheLayout::Flat).unwrap();
+        let from_mirror = http("https://mirror.example.org/my_mod.zip").save_name(&module, CacheLayout::Flat).unwrap();
+        assert_ne!(from_example, from_mirror);
+
+        let from_someone = github_tag("someone").save_name(&module, CacheLayout::Flat).unwrap();
+        let from_fork = github_tag("someone_else").save_name(&module, CacheLayout::Flat).unwrap();
+        assert_ne!(from_someone, from_fork);
+
+        // stable, to find the archive again in the cache
+        assert_eq!(http("https://example.com/my_mod.zip").save_name(&module, CacheLayout::Flat).unwrap(), from_example);
+    }
+
+    #[test]
+    fn url_without_file_name() {
+        let source = http("https://example.com/download/");
+        assert_eq!(source.save_name(&lwc!("my_mod"), CacheLayout::Partitioned).unwrap(), PathBuf::from("my_mod"));
+        let source = http("https://example.com/download?id=42");
+        assert_eq!(source.save_name(&lwc!("my_mod"), CacheLayout::Partitioned).unwrap(), PathBuf::from("download"));
+    }
+
+    #[test]
+    fn renamed_github_archive() {
+        let source = Source::Github(Github {
+            github_user: "someone".to_string(),
+            repository: "my_repo".to_string(),
+            descriptor: GithubDescriptor::Release { release: Some("v1.3".to_string()), asset: "v1.3".to_string(), first: false },
+            rename: Some("my_mod-v1.3.tar.gz".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(source.save_name(&lwc!("my_mod"), CacheLayout::Partitioned).unwrap(), PathBuf::from("my_mod-v1.3.tar.gz"));
+        let name = source.save_name(&lwc!("my_mod"), CacheLayout::Flat).unwrap();
+        assert!(name.to_string_lossy().starts_with("my_mod-v1.3-"));
+        assert!(name.to_string_lossy().ends_with(".tar.gz"));
+    }
+
+    #[test]
+    fn github_asset_pattern_is_saved_under_the_matching_asset_name() {
+        let source = Source::Github(Github {
+            github_user: "someone".to_string(),
+            repository: "my_repo".to_string(),
+            descriptor: GithubDescriptor::Release { release: None, asset: "my_mod-v*.zip".to_string(), first: false },
+            ..Default::default()
+        });
+        assert_eq!(source.save_name(&lwc!("my_mod"), CacheLayout::Partitioned).unwrap(), PathBuf::from("my_mod-v_.zip"));
+        assert_eq!(source.asset_save_name("my_mod-v1.3.zip", CacheLayout::Partitioned).unwrap(), PathBuf::from("my_mod-v1.3.zip"));
+        assert_ne!(source.asset_save_name("my_mod-v1.3.zip", CacheLayout::Flat).unwrap(),
+                    source.asset_save_name("my_mod-v1.4.zip", CacheLayout::Flat).unwrap());
+    }
+
+    #[test]
+    fn local_sources_are_not_in_cache() {
+        let source = Source::Local { local: "my_mod".to_string(), rename: None };
+        assert_eq!(source.save_subdir(CacheLayout::Flat).unwrap(), PathBuf::new());
+        assert_eq!(source.save_name(&lwc!("my_mod"), CacheLayout::Flat).unwrap(), PathBuf::new());
+    }
+}
@@ -5,6 +5,7 @@ use std::fs::{File, OpenOptions};
 use std::io::{BufReader, Seek, SeekFrom, BufWriter, Write};
 use std::path::{PathBuf, Path};
 
+use itertools::Itertools;
 use lazy_static::lazy_static;
 use log::{info, debug};
 use regex::Regex;
@@ -23,10 +24,47 @@ use super::global_locations::{GlobalLocations, LocationRegistry};
 use super::location::location::ConcreteLocation;
 use super::manifest_conditions::ManifestConditions;
 
+/// A location key defined in an external registry, ignored because it has another definition.
+#[derive(Debug, PartialEq)]
+pub struct DivergentLocation {
+    pub key: LwcString,
+    pub registry: LocationRegistry,
+    /// Registry where the definition which is used comes from, `None` for `locations.entries`
+    pub kept_from: Option<LocationRegistry>,
+}
+
+impl DivergentLocation {
+    pub fn describe(&self) -> String {
+        let kept_from = match &self.kept_from {
+            None => "`entries`".to_string(),
+            Some(registry) => format!("registry {:?}", registry),
+        };
+        format!("Location {} from registry {:?} is ignored, it is defined differently in {}", self.key, self.registry, kept_from)
+    }
+}
+
+/// The manifest format version this program writes (and the most recent one it reads).
+pub const MANIFEST_VERSION: &str = "1";
+
+/// All the manifest format versions this program reads.
+pub const SUPPORTED_MANIFEST_VERSIONS: &[&str] = &["1"];
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct VersionDetect {
-    pub version: String,
+    pub version: Option<String>,
+}
+
+impl VersionDetect {
+    pub fn check(&self) -> Result<()> {
+        match &self.version {
+            None => bail!("Manifest has no `version` field, supported versions are {}",
+                            Manifest::supported_versions().join(", ")),
+            Some(version) if !Manifest::is_supported_version(version) =>
+                bail!("Manifest version {} is not supported, supported versions are {}",
+                        version, Manifest::supported_versions().join(", ")),
+            Some(_) => Ok(()),
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
@@ -50,6 +88,15 @@ pub struct Manifest {
 }
 
 impl Manifest {
+    /// Manifest format versions that can be read by this program.
+    pub fn supported_versions() -> &'static [&'static str] {
+        SUPPORTED_MANIFEST_VERSIONS
+    }
+
+    pub fn is_supported_version(version: &str) -> bool {
+        SUPPORTED_MANIFEST_VERSIONS.contains(&version.trim())
+    }
+
     pub fn assemble_from_path(path: &str, manifest_root: &CanonPath) -> Result<Self> {
         let mut base = Self::read_path(path)?;
         if !base.locations.external.is_empty() {
@@ -71,6 +118,19 @@ impl Manifest {
         Ok(base)
     }
 
+    /// Replaces the component lists kept in separate files by their content.
+    pub fn resolve_components_files(&mut self, manifest_root: &CanonPath) -> Result<()> {
+        for module in &mut self.modules {
+            if let Module::Mod { weidu_mod } = module {
+                weidu_mod.components = match weidu_mod.components.resolve_file(manifest_root) {
+                    Result::Ok(components) => components,
+                    Err(error) => bail!("Could not read components of mod {}\n -> {:?}", weidu_mod.name, error),
+                };
+            }
+        }
+        Ok(())
+    }
+
     pub fn read_path_convert_comments(path: &Path) -> Result<Self> {
         let file = std::fs::File::open(path)
                 .map_err(|error| anyhow!("Could not open manifest file {:?} - {:?}", path, error))?;
@@ -86,10 +146,11 @@ impl Manifest {
     pub fn read_file(mut file: File, convert_comments: bool) -> Result<Self> {
         {
             let reader = BufReader::new(&file);
-            let version: VersionDetect = serde_yaml::from_reader(reader)?;
-            if version.version != "1" {
-                bail!("Only manifest version 1 is supported for now.");
-            }
+            let version: VersionDetect = match serde_yaml::from_reader(reader) {
+                Result::Ok(version) => version,
+                Err(error) => bail!("Could not read manifest version\n -> {:?}", error),
+            };
+            version.check()?;
         }
         let _ = file.seek(SeekFrom::Start(0))?;
         let reader = BufReader::new(file);
@@ -118,6 +179,30 @@ impl Manifest {
         }
     }
 
+    /// Locations of the external registries that were ignored (in an assembled manifest) because the same key
+    /// was already defined differently, in `entries` or in an earlier registry.
+    pub fn divergent_locations(&self, manifest_root: &CanonPath) -> Result<Vec<DivergentLocation>> {
+        let mut result = vec![];
+        // the kept definition comes from the first registry defining the key, unless that one was itself
+        // ignored (then it comes from `entries`)
+        let mut kept_from: HashMap<LwcString, Option<&LocationRegistry>> = HashMap::new();
+        for registry in &self.locations.external {
+            let locations = read_external_registry(registry, manifest_root)?;
+            for (key, location) in locations.into_iter().sorted_by(|(left, _), (right, _)| left.as_ref().cmp(right.as_ref())) {
+                let kept = match self.locations.entries.get(&key) {
+                    Some(kept) => kept,
+                    None => continue,
+                };
+                let origin = *kept_from.entry(key.clone())
+                    .or_insert(if *kept == location { Some(registry) } else { None });
+                if *kept != location {
+                    result.push(DivergentLocation { key, registry: registry.clone(), kept_from: origin.cloned() });
+                }
+            }
+        }
+        Ok(result)
+    }
+
     pub fn write(&self, path: &Path, reformat_comments: bool) -> Result<()> {
         let temp_path = pathext::append_extension("new", path);
         let dest = match OpenOptions::new().create(true).truncate(true).write(true).open(&temp_path) {
@@ -202,6 +287,7 @@ lazy_static! {
         r#"(?P<indent1>\s*)-\s+index:\s+(?P<index>\d+)(?:\s+\#\s*(?P<yaml_cmt>.*))?"#,
         r#"\n"#,
         r#"(?P<indent2>\s*)  component_name:\s+(?P<comp_name>.*)$"#,
+        r#"(?:\n(?P<indent3>\s*)  language:\s+(?P<language>\d+)$)?"#,
     )).unwrap();
 }
 
@@ -233,11 +319,18 @@ fn try_replace_component(re: &Regex, haystack: &str) -> Result<String> {
         };
 
         new.push_str(&haystack[last_match..everything.start()]);
-        new.push_str(indent1);
-        new.push_str("- ");
-        new.push_str(index);
-        new.push_str(" # ");
-        new.push_str(&full_comment);
+        match caps.name("language") {
+            // the short form can't hold the language, keep the component as it was
+            Some(_) if caps["indent3"].len() == indent1.len() => new.push_str(everything.as_str()),
+            Some(_) => bail!("indentation error for component (index={}", index),
+            None => {
+                new.push_str(indent1);
+                new.push_str("- ");
+                new.push_str(index);
+                new.push_str(" # ");
+                new.push_str(&full_comment);
+            }
+        }
         last_match = everything.end();
     }
     new.push_str(&haystack[last_match..]);
@@ -283,6 +376,11 @@ mod test_deserialize {
                     local_patches: None,
                     local_mods: None,
                     local_files: None,
+                    post_install_replace: vec![],
+                    game: None,
+                    tlk: None,
+                    ftlk: None,
+                    skip: vec![],
                 },
                 locations : GlobalLocations::default(),
                 manifest_conditions: ManifestConditions::default(),
@@ -305,6 +403,11 @@ mod test_deserialize {
                     local_patches: None,
                     local_mods: Some("mods".to_string()),
                     local_files: None,
+                    post_install_replace: vec![],
+                    game: None,
+                    tlk: None,
+                    ftlk: None,
+                    skip: vec![],
                 },
                 locations : GlobalLocations::default(),
                 manifest_conditions: ManifestConditions::default(),
@@ -345,11 +448,15 @@ mod test_deserialize {
                                 FileModuleOrigin::Local { local: "my_subdir".to_string(), glob: None },
                             ],
                             description: None,
-                            component: GenModComponent { index: 0, name: None },
-                            post_install: Some(PostInstall::WaitSeconds { wait_seconds:10 }),
+                            component: GenModComponent { index: 0, name: None, flags: vec![] },
+                            post_install: Some(PostInstall::WaitSeconds { wait_seconds:10, on: None }),
                             ignore_warnings: true,
                             allow_overwrite: true,
+                            overwrite: None,
+                            backup_on_overwrite: None,
                             disabled_if: None,
+                            backup: None,
+                            phase: None,
                         },
                     },
                     Module::Generated {
@@ -360,10 +467,14 @@ mod test_deserialize {
                             ],
                             description: None,
                             post_install: None,
-                            component: GenModComponent { index: 10, name: Some("Do whatever".to_string()) },
+                            component: GenModComponent { index: 10, name: Some("Do whatever".to_string()), flags: vec![] },
                             ignore_warnings: true,
                             allow_overwrite: true,
+                            overwrite: None,
+                            backup_on_overwrite: None,
                             disabled_if: None,
+                            backup: None,
+                            phase: None,
                         },
                     },
                 ],
@@ -382,6 +493,11 @@ mod test_deserialize {
                 local_patches: None,
                 local_mods: Some("mods".to_string()),
                 local_files: None,
+                post_install_replace: vec![],
+                game: None,
+                tlk: None,
+                ftlk: None,
+                skip: vec![],
             },
             locations : GlobalLocations::default(),
             manifest_conditions: ManifestConditions::default(),
@@ -408,10 +524,14 @@ mod test_deserialize {
                         ],
                         description: None,
                         post_install: None,
-                        component: GenModComponent { index: 0, name: None },
+                        component: GenModComponent { index: 0, name: None, flags: vec![] },
                         ignore_warnings: false,
                         allow_overwrite: false,
+                        overwrite: None,
+                        backup_on_overwrite: None,
                         disabled_if: None,
+                        backup: None,
+                        phase: None,
                     },
                 },
                 Module::Generated {
@@ -422,10 +542,14 @@ mod test_deserialize {
                         ],
                         description: None,
                         post_install: None,
-                        component: GenModComponent { index: 10, name: Some("Do whatever".to_string()) },
+                        component: GenModComponent { index: 10, name: Some("Do whatever".to_string()), flags: vec![] },
                         ignore_warnings: true,
                         allow_overwrite: true,
+                        overwrite: None,
+                        backup_on_overwrite: None,
                         disabled_if: None,
+                        backup: None,
+                        phase: None,
                     },
                 },
             ],
@@ -454,10 +578,15 @@ mod test_deserialize {
                     local_patches: None,
                     local_mods: Some("mods".to_string()),
                     local_files: None,
+                    post_install_replace: vec![],
+                    game: None,
+                    tlk: None,
+                    ftlk: None,
+                    skip: vec![],
                 },
                 locations : GlobalLocations::from([
                     (lwc!("aaa"), ConcreteLocation { source: Source::Http(Http::from("http://example.com/my-mod")), ..Default::default() }),
-                    (lwc!("aaaa"), ConcreteLocation { source: Source::Local { local: "directory/my-other-mod.zip".to_owned() }, ..Default::default() }),
+                    (lwc!("aaaa"), ConcreteLocation { source: Source::Local { local: "directory/my-other-mod.zip".to_owned(), rename: None }, ..Default::default() }),
                     (lwc!("bbb"),ConcreteLocation { source: Source::Github(Github {
                         github_user: "some_user".to_owned(), repository: "mod-repo".to_owned(),
                         descriptor: GithubDescriptor::Tag { tag: "v324".to_owned() },
@@ -484,6 +613,11 @@ mod test_deserialize {
                     local_patches: None,
                     local_mods: Some("mods".to_string()),
                     local_files: None,
+                    post_install_replace: vec![],
+                    game: None,
+                    tlk: None,
+                    ftlk: None,
+                    skip: vec![],
                 },
                 locations : GlobalLocations::from([]),
                 manifest_conditions: ManifestConditions::default(),
@@ -508,10 +642,15 @@ mod test_deserialize {
                     local_patches: None,
                     local_mods: Some("mods".to_string()),
                     local_files: None,
+                    post_install_replace: vec![],
+                    game: None,
+                    tlk: None,
+                    ftlk: None,
+                    skip: vec![],
                 },
                 locations : GlobalLocations::from([
                     (lwc!("aaa"), ConcreteLocation { source: Source::Http(Http::from("http://example.com/my-mod")), ..Default::default() }),
-                    (lwc!("aaaa"), ConcreteLocation { source: Source::Local { local: "directory/my-other-mod.zip".to_owned() }, ..Default::default() }),
+                    (lwc!("aaaa"), ConcreteLocation { source: Source::Local { local: "directory/my-other-mod.zip".to_owned(), rename: None }, ..Default::default() }),
                     (lwc!("bbb"),ConcreteLocation { source: Source::Github(Github {
                         github_user: "some_user".to_owned(), repository: "mod-repo".to_owned(),
                         descriptor: GithubDescriptor::Tag { tag: "v324".to_owned() },
@@ -524,6 +663,49 @@ mod test_deserialize {
         )
     }
 
+    #[test]
+    fn divergent_registry_location() {
+        let manifest_root = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/test");
+        let manifest_path = format!("{}/{}", manifest_root, "manifest_with_real_ext_locations.yml");
+        let manifest_root = CanonPath::new(&manifest_root).unwrap();
+        let manifest = Manifest::assemble_from_path(&manifest_path, &manifest_root).unwrap();
+
+        let divergent = manifest.divergent_locations(&manifest_root).unwrap();
+        // `aaaa` and `bbb` are only in the registry
+        assert_eq!(
+            divergent,
+            vec![super::DivergentLocation {
+                key: lwc!("aaa"),
+                registry: LocationRegistry::Local { local: "registries/external-locations.yml".to_owned() },
+                kept_from: None,
+            }]
+        );
+        assert_eq!(
+            divergent[0].describe(),
+            r#"Location aaa from registry Local { local: "registries/external-locations.yml" } is ignored, it is defined differently in `entries`"#
+        );
+    }
+
+    #[test]
+    fn divergent_locations_of_three_registries() {
+        let manifest_root = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/test");
+        let manifest_path = format!("{}/{}", manifest_root, "manifest_with_three_registries.yml");
+        let manifest_root = CanonPath::new(&manifest_root).unwrap();
+        let manifest = Manifest::assemble_from_path(&manifest_path, &manifest_root).unwrap();
+        let registry = |name: &str| LocationRegistry::Local { local: format!("registries/{}-locations.yml", name) };
+
+        let divergent = manifest.divergent_locations(&manifest_root).unwrap();
+        // `ccc` is kept from `entries` (even if the first registry was ignored), `ddd` from the first registry
+        assert_eq!(
+            divergent,
+            vec![
+                super::DivergentLocation { key: lwc!("ccc"), registry: registry("first"), kept_from: None },
+                super::DivergentLocation { key: lwc!("ddd"), registry: registry("second"), kept_from: Some(registry("first")) },
+                super::DivergentLocation { key: lwc!("ccc"), registry: registry("third"), kept_from: None },
+            ]
+        );
+    }
+
     #[test]
     fn read_manifest_convert_comments() {
         let manifest_root = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/test");
@@ -543,8 +725,8 @@ mod test_deserialize {
                         weidu_mod: WeiduMod {
                             name: lwc!("aaa"),
                             components: Components::List(vec! [
-                                Component::Full(FullComponent { index: 1, component_name: "yaml comment".to_string()}),
-                                Component::Full(FullComponent { index: 2, component_name: "comment as field".to_string()}),
+                                Component::Full(FullComponent { index: 1, component_name: "yaml comment".to_string(), language: None }),
+                                Component::Full(FullComponent { index: 2, component_name: "comment as field".to_string(), language: None }),
                             ]),
                             ..Default::default()
                         },
@@ -584,8 +766,8 @@ mod test_deserialize {
                     weidu_mod: WeiduMod {
                         name: lwc!("aaa"),
                         components: Components::List(vec! [
-                            Component::Full(FullComponent { index: 1, component_name: "yaml comment 123".to_string()}),
-                            Component::Full(FullComponent { index: 2, component_name: "comment as field 789".to_string()}),
+                            Component::Full(FullComponent { index: 1, component_name: "yaml comment 123".to_string(), language: None }),
+                            Component::Full(FullComponent { index: 2, component_name: "comment as field 789".to_string(), language: None }),
                         ]),
                         ..Default::default()
                     },
@@ -614,6 +796,41 @@ mod test_deserialize {
         assert!(content.contains("- 2 # comment as field 789"));
     }
 
+    #[test]
+    fn write_manifest_convert_comments_keeps_component_language() {
+        let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let test_path = project.join("target").join("test_data");
+        let temp_dir = tempfile::tempdir_in(&test_path).unwrap();
+        let output_path = temp_dir.as_ref().join("test.yml");
+
+        let manifest = super::Manifest {
+            version : "1".to_string(),
+            global : super::Global {
+                game_language: "fr_FR".to_string(),
+                ..Default::default()
+            },
+            modules : vec![
+                Module::Mod {
+                    weidu_mod: WeiduMod {
+                        name: lwc!("aaa"),
+                        components: Components::List(vec! [
+                            Component::Full(FullComponent { index: 1, component_name: "in french".to_string(), language: None }),
+                            Component::Full(FullComponent { index: 2, component_name: "in english".to_string(), language: Some(1) }),
+                            Component::Full(FullComponent { index: 3, component_name: "in french again".to_string(), language: None }),
+                        ]),
+                        ..Default::default()
+                    },
+                },
+            ],
+            locations: GlobalLocations::default(),
+            manifest_conditions: ManifestConditions::default(),
+        };
+        manifest.write(&output_path, true).unwrap();
+
+        let read = Manifest::read_path_convert_comments(&output_path).unwrap();
+        assert_eq!(read, manifest);
+    }
+
     #[test]
     fn read_manifest_with_global_condition() {
         let manifest_root = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/test");
@@ -637,7 +854,7 @@ mod test_deserialize {
                     ("c".to_string(), DisableCondition::All {
                         all: vec![
                             DisableCondition::EnvVar { env_is_set: "my_env_var".to_string() },
-                            DisableCondition::File { in_file: "my_file.txt".to_string(), key: "my_key".to_string() },
+                            DisableCondition::File { in_file: "my_file.txt".to_string(), key: "my_key".to_string(), mode: None },
                         ],
                     }),
                     ("d".to_string(), DisableCondition::Any {
@@ -650,4 +867,19 @@ mod test_deserialize {
             }
         );
     }
+
+    #[test]
+    fn reject_unsupported_version() {
+        let read = |content: &str| {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("manifest.yml");
+            std::fs::write(&path, content).unwrap();
+            Manifest::read_path(path.to_str().unwrap()).map_err(|error| error.to_string())
+        };
+        assert_eq!(Manifest::supported_versions(), &["1"]);
+        assert_eq!(read("version: '2'\nglobal:\n  game_language: fr_FR\n").unwrap_err(),
+                    "Manifest version 2 is not supported, supported versions are 1");
+        assert_eq!(read("global:\n  game_language: fr_FR\n").unwrap_err(),
+                    "Manifest has no `version` field, supported versions are 1");
+    }
 }
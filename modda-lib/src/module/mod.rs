@@ -10,6 +10,7 @@ pub mod language;
 pub mod location;
 pub mod manifest;
 pub mod manifest_conditions;
+pub mod mod_selection;
 pub mod module;
 pub mod module_conf;
 pub mod pre_copy_command;
@@ -66,6 +67,7 @@ mod test_deserialize {
                         descriptor: GithubDescriptor::Release {
                             release: Some("v1.3".to_string()),
                             asset: "lin-A7-DlcMerger-v1.3.zip".to_string(),
+                            first: false,
                         },
                         ..Default::default()
                     }),
@@ -108,6 +110,7 @@ mod test_deserialize {
                             descriptor: GithubDescriptor::Release {
                                 release: Some("v1.3".to_string()),
                                 asset: "lin-A7-DlcMerger-v1.3.zip".to_string(),
+                                first: false,
                             },
                             ..Default::default()
                         }),
@@ -410,7 +413,7 @@ mod test_deserialize {
         let module = WeiduMod {
             name: lwc!("DlcMerger"),
             components: Components::List(vec! [ Component::Simple(1) ]),
-            post_install: Some(PostInstall::WaitSeconds { wait_seconds: 10 }),
+            post_install: Some(PostInstall::WaitSeconds { wait_seconds: 10, on: None }),
             ..WeiduMod::default()
         };
         println!("{}", serde_yaml::to_string(&module).unwrap());
@@ -431,7 +434,7 @@ mod test_deserialize {
             WeiduMod {
                 name: lwc!("DlcMerger"),
                 components: Components::List(vec! [ Component::Simple(1) ]),
-                post_install: Some(PostInstall::WaitSeconds { wait_seconds: 10 }),
+                post_install: Some(PostInstall::WaitSeconds { wait_seconds: 10, on: None }),
                 ..WeiduMod::default()
             }
         );
@@ -499,10 +502,14 @@ mod test_deserialize {
                     FileModuleOrigin::Local { local: "other_dir".to_string(), glob: Some("*.itm".to_string()) },
                     FileModuleOrigin::Absolute { absolute: "/location".to_string(), glob: None },
                 ],
-                component: GenModComponent { index: 0, name: None },
+                component: GenModComponent { index: 0, name: None, flags: vec![] },
                 allow_overwrite: true,
                 ignore_warnings: true,
+                overwrite: None,
+                backup_on_overwrite: None,
                 disabled_if: None,
+                backup: None,
+                phase: None,
             }
         );
     }
@@ -565,7 +572,7 @@ mod test_deserialize {
             WeiduMod {
                 name: lwc!("DlcMerger"),
                 components: Components::List(vec! [ Component::Simple(1) ]),
-                disabled_if: Some(DisableCondition::File { in_file: "disabling.file".to_string(), key: "dlcmerger".to_string() }),
+                disabled_if: Some(DisableCondition::File { in_file: "disabling.file".to_string(), key: "dlcmerger".to_string(), mode: None }),
                 ..WeiduMod::default()
             }
         );
@@ -616,7 +623,7 @@ mod test_deserialize {
                 disabled_if: Some(DisableCondition::Any {
                     any: vec![
                         DisableCondition::EnvVar { env_is_set: "DISABLE_DLCMERGER".to_string() },
-                        DisableCondition::File { in_file: "disabling.file".to_string(), key: "dlcmerger".to_string() },
+                        DisableCondition::File { in_file: "disabling.file".to_string(), key: "dlcmerger".to_string(), mode: None },
                     ],
                 }),
                 ..WeiduMod::default()
@@ -645,7 +652,7 @@ mod test_deserialize {
                 disabled_if: Some(DisableCondition::All {
                     all: vec![
                         DisableCondition::EnvVar { env_is_set: "DISABLE_DLCMERGER".to_string() },
-                        DisableCondition::File { in_file: "disabling.file".to_string(), key: "dlcmerger".to_string() },
+                        DisableCondition::File { in_file: "disabling.file".to_string(), key: "dlcmerger".to_string(), mode: None },
                     ],
                 }),
                 ..WeiduMod::default()
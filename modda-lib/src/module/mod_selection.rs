@@ -0,0 +1,147 @@
+
+use anyhow::Result;
+
+use crate::canon_path::CanonPath;
+use crate::lowercase::LwcString;
+
+use super::disable_condition::DisableOutCome;
+use super::manifest::Manifest;
+
+/// Evaluates the disable condition of each module of the manifest (in manifest order).
+pub fn evaluate_disabled(manifest: &Manifest, manifest_root: &CanonPath, game_dir: &CanonPath) -> Vec<(LwcString, Result<DisableOutCome>)> {
+    manifest.modules.iter()
+        .map(|module| (module.get_name().to_owned(), module.check_disabled(manifest_root, game_dir, &manifest.manifest_conditions)))
+        .collect()
+}
+
+/// A module of the manifest as shown in a selection list.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ModSelectionItem {
+    /// Index of the module in the manifest, counting from one
+    pub index: usize,
+    pub name: LwcString,
+    /// The disable condition of the module says it's disabled (or couldn't be evaluated)
+    pub disabled: bool,
+    /// Why the module is disabled (or not), when known
+    pub reason: Option<String>,
+    /// Will be kept in the exported manifest, initially `!disabled`
+    pub selected: bool,
+}
+
+/// Modules of a manifest with their disable state, that can be toggled then exported to a filtered manifest.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ModSelection {
+    pub items: Vec<ModSelectionItem>,
+}
+
+impl ModSelection {
+    pub fn new(manifest: &Manifest, manifest_root: &CanonPath, game_dir: &CanonPath) -> Self {
+        Self::from_outcomes(evaluate_disabled(manifest, manifest_root, game_dir))
+    }
+
+    pub fn from_outcomes(outcomes: Vec<(LwcString, Result<DisableOutCome>)>) -> Self {
+        let items = outcomes.into_iter().enumerate()
+            .map(|(index, (name, outcome))| {
+                let (disabled, reason) = match outcome {
+                    Ok(DisableOutCome::Yes(reason)) => (true, Some(reason)),
+                    Ok(DisableOutCome::No(reason)) => (false, reason),
+                    Err(error) => (true, Some(format!("Could not evaluate disable condition\n -> {:?}", error))),
+                };
+                ModSelectionItem { index: index + 1, name, disabled, reason, selected: !disabled }
+            })
+            .collect();
+        Self { items }
+    }
+
+    /// Changes the selection of the module at `index` (counting from one), returns `false` if there is no such module.
+    pub fn toggle(&mut self, index: usize) -> bool {
+        match self.items.iter_mut().find(|item| item.index == index) {
+            Some(item) => {
+                item.selected = !item.selected;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Copy of the manifest with only the selected modules.
+    pub fn filtered_manifest(&self, manifest: &Manifest) -> Manifest {
+        let modules = manifest.modules.iter().zip(self.items.iter())
+            .filter(|(_, item)| item.selected)
+            .map(|(module, _)| module.clone())
+            .collect();
+        Manifest { modules, ..manifest.clone() }
+    }
+}
+
+#[cfg(test)]
+mod test_mod_selection {
+    use anyhow::anyhow;
+
+    use crate::lowercase::lwc;
+    use crate::module::disable_condition::DisableOutCome;
+    use crate::module::gen_mod::GeneratedMod;
+    use crate::module::manifest::Manifest;
+    use crate::module::module::Module;
+    use crate::module::weidu_mod::WeiduMod;
+
+    use super::{ModSelection, ModSelectionItem};
+
+    fn selection() -> ModSelection {
+        ModSelection::from_outcomes(vec![
+            (lwc!("aaa"), Ok(DisableOutCome::No(None))),
+            (lwc!("bbb"), Ok(DisableOutCome::Yes("not on this machine".to_string()))),
+            (lwc!("ccc"), Ok(DisableOutCome::No(Some("Environment variable 'SKIP' is not set".to_string())))),
+            (lwc!("ddd"), Err(anyhow!("bad file"))),
+        ])
+    }
+
+    #[test]
+    fn outcomes_to_items() {
+        let selection = selection();
+        assert_eq!(
+            selection.items[..3],
+            vec![
+                ModSelectionItem { index: 1, name: lwc!("aaa"), disabled: false, reason: None, selected: true },
+                ModSelectionItem {
+                    index: 2, name: lwc!("bbb"), disabled: true,
+                    reason: Some("not on this machine".to_string()), selected: false,
+                },
+                ModSelectionItem {
+                    index: 3, name: lwc!("ccc"), disabled: false,
+                    reason: Some("Environment variable 'SKIP' is not set".to_string()), selected: true,
+                },
+            ]
+        );
+        let failed = &selection.items[3];
+        assert!(failed.disabled && !failed.selected);
+        assert!(failed.reason.as_ref().unwrap().contains("bad file"));
+    }
+
+    #[test]
+    fn toggle_and_filter() {
+        let manifest = Manifest {
+            version: "1".to_string(),
+            global: Default::default(),
+            locations: Default::default(),
+            manifest_conditions: Default::default(),
+            modules: vec![
+                Module::Mod { weidu_mod: WeiduMod { name: lwc!("aaa"), ..WeiduMod::default() } },
+                Module::Mod { weidu_mod: WeiduMod { name: lwc!("bbb"), ..WeiduMod::default() } },
+                Module::Generated { gen: GeneratedMod { gen_mod: lwc!("ccc"), ..GeneratedMod::default() } },
+                Module::Mod { weidu_mod: WeiduMod { name: lwc!("ddd"), ..WeiduMod::default() } },
+            ],
+        };
+        let mut selection = selection();
+        assert!(selection.toggle(1));
+        assert!(selection.toggle(2));
+        assert!(!selection.toggle(5));
+
+        let filtered = selection.filtered_manifest(&manifest);
+        assert_eq!(
+            filtered.modules.iter().map(|module| module.get_name().to_owned()).collect::<Vec<_>>(),
+            vec![lwc!("bbb"), lwc!("ccc")]
+        );
+        assert_eq!(filtered.global, manifest.global);
+    }
+}
@@ -8,7 +8,7 @@ use serde_yaml::Value;
 use crate::canon_path::CanonPath;
 use crate::module::components::{Components, Component};
 use crate::lowercase::LwcString;
-use crate::post_install::{PostInstallExec, PostInstallOutcome};
+use crate::post_install::{InstallStatus, PostInstallExec, PostInstallOutcome};
 
 use super::disable_condition::{DisableCheck, DisableOutCome};
 use super::gen_mod::GeneratedMod;
@@ -37,6 +37,13 @@ impl Module {
         }
     }
 
+    pub fn get_phase(&self) -> Option<&str> {
+        match self {
+            Module::Mod { weidu_mod } => weidu_mod.phase.as_deref(),
+            Module::Generated { gen } => gen.phase.as_deref(),
+        }
+    }
+
     pub fn get_components(&self) -> Components {
         match self {
             Module::Mod { weidu_mod } => weidu_mod.components.clone(),
@@ -51,17 +58,17 @@ impl Module {
         }
     }
 
-    pub fn exec_post_install(&self, mod_name: &LwcString) -> PostInstallOutcome {
+    pub fn exec_post_install(&self, mod_name: &LwcString, status: InstallStatus) -> PostInstallOutcome {
         match self {
-            Module::Mod { weidu_mod } => weidu_mod.post_install.exec(mod_name),
-            Module::Generated { gen } => gen.post_install.exec(mod_name),
+            Module::Mod { weidu_mod } => weidu_mod.post_install.exec(mod_name, status),
+            Module::Generated { gen } => gen.post_install.exec(mod_name, status),
         }
     }
 
-    pub fn check_disabled(&self, manifest_root: &CanonPath, manifest_conditions: &ManifestConditions) -> Result<DisableOutCome> {
+    pub fn check_disabled(&self, manifest_root: &CanonPath, game_dir: &CanonPath, manifest_conditions: &ManifestConditions) -> Result<DisableOutCome> {
         match self {
-            Module::Mod { weidu_mod } => weidu_mod.disabled_if.check(manifest_root, manifest_conditions),
-            Module::Generated { gen } => gen.disabled_if.check(manifest_root, manifest_conditions),
+            Module::Mod { weidu_mod } => weidu_mod.disabled_if.check(manifest_root, game_dir, manifest_conditions),
+            Module::Generated { gen } => gen.disabled_if.check(manifest_root, game_dir, manifest_conditions),
         }
     }
 }
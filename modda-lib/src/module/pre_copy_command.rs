@@ -2,10 +2,15 @@
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 
+/// Command run on the mod content before it is copied to the game directory.
+///
+/// It runs in the directory where the archive was extracted (for directory sources, in a temporary copy
+/// so the original files are left untouched), or in `subdir` of it. A failure stops the installation.
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 #[skip_serializing_none]
 pub struct PrecopyCommand {
     pub command: String,
     pub args: Option<Vec<String>>,
+    /// Sub-directory of the extracted content where the command is run
     pub subdir: Option<String>,
 }
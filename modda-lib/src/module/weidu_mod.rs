@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
@@ -5,6 +6,7 @@ use serde_with::skip_serializing_none;
 
 use crate::module::components::{Components, Component, FullComponent};
 use crate::lowercase::LwcString;
+use crate::patch_source::PatchEncoding;
 use crate::post_install::PostInstall;
 
 use super::disable_condition::DisableCondition;
@@ -29,6 +31,11 @@ pub struct WeiduMod {
     pub description: Option<String>,
     /// Which language index to use (has precedence over manifest-level lang_prefs)
     pub language: Option<u32>,
+    /// Encoding of the mod tra files.
+    ///
+    /// Only used with `--check-encoding`, to warn when the tra files don't decode correctly with it
+    /// (which would make weidu install garbled text).
+    pub encoding: Option<PatchEncoding>,
     /// List of components to be auto-installed.
     /// Can be `ask`, `none`, a list of components or absent/not set/null (which is the same as `ask`)
     ///   - `ask` (or empty) will use weidu in interactive mode (weidu itself asks how to install components)
@@ -44,6 +51,23 @@ pub struct WeiduMod {
     #[serde(default)]
     #[serde(skip_serializing_if = "is_false")]
     pub ignore_warnings: bool,
+    /// Warnings that don't interrupt the installation (regular expressions, case-insensitive).
+    ///
+    /// Only used when `ignore_warnings` is not set: when weidu ends with warnings, they are read from the
+    /// weidu debug log and the installation proceeds only if each of them matches one of these patterns.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed_warnings: Vec<String>,
+    /// How many times weidu is run again when it fails (exit code other than 0 or 3), for mods known to
+    /// succeed on a second attempt.
+    ///
+    /// The components installed by the failed attempt are uninstalled before the next one.
+    /// Defaults to 0 (no retry).
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_zero")]
+    pub retry_on_failure: u32,
+    /// Environment variables set when weidu installs the mod (values are expanded like `weidu_path`, `~` and `$VAR`).
+    pub env: Option<BTreeMap<String, String>>,
     pub add_conf: Option<ModuleConf>,
     /// Where we can obtain the module.
     ///
@@ -64,10 +88,14 @@ pub struct WeiduMod {
     pub installation: Option<InstallationComments>,
     /// Condition that disables the mod installation (if absent, not disabled)
     pub disabled_if: Option<DisableCondition>,
+    /// Installation phase (for example `early`, `late`), `install --phase <phase>` installs only the mods of this phase.
+    pub phase: Option<String>,
 }
 
 fn is_false(value: &bool) -> bool { !value }
+fn is_zero(value: &u32) -> bool { *value == 0 }
 
+#[derive(Debug, PartialEq, Clone)]
 pub struct BareMod {
     pub name: LwcString,
     pub components: Vec<FullComponent>,
@@ -0,0 +1,89 @@
+
+use crate::lowercase::LwcString;
+use crate::module::location::github::{GitBranch, Github, GithubDescriptor};
+use crate::module::location::location::ConcreteLocation;
+use crate::module::location::source::Source;
+use crate::module::module::Module;
+use crate::module::refresh::RefreshCondition;
+
+use super::get_module::ModuleDownload;
+
+/// Warning for a mod obtained from a github branch: its content changes over time, so two installations
+/// of the same manifest may not get the same mod.
+pub fn branch_warning(mod_name: &LwcString, location: &ConcreteLocation) -> Option<String> {
+    let (user, repository, branch, refresh) = match &location.source {
+        Source::Github(Github { github_user, repository, descriptor: GithubDescriptor::Branch(GitBranch { branch, refresh }), .. }) =>
+            (github_user, repository, branch, refresh),
+        _ => return None,
+    };
+    let refresh = match refresh {
+        RefreshCondition::Never => "the archive in the cache is kept, but other computers (or an empty cache) may get another content".to_string(),
+        RefreshCondition::Always => "it is downloaded again at each installation".to_string(),
+        RefreshCondition::Ask => "the installation asks whether to download it again".to_string(),
+        RefreshCondition::Duration(duration) =>
+            format!("it is downloaded again when the archive in the cache is older than {}", humantime::format_duration(*duration)),
+    };
+    Some(format!("mod {} is obtained from branch {} of github {}/{}, the installation is not reproducible ({}).\n  Use a tag or a commit instead (or `--allow-branches`).",
+                    mod_name, branch, user, repository, refresh))
+}
+
+/// Warnings for all the mods (among `modules`) that are obtained from a github branch.
+pub fn branch_source_warnings(modules: &[&Module], module_downloader: &ModuleDownload) -> Vec<String> {
+    modules.iter()
+        .filter_map(|module| match module {
+            Module::Mod { weidu_mod } => Some(weidu_mod),
+            Module::Generated { .. } => None,
+        })
+        .filter_map(|weidu_mod| {
+            let location = module_downloader.get_module_location(weidu_mod).ok()?;
+            branch_warning(&weidu_mod.name, location)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test_branch_sources {
+    use crate::lowercase::lwc;
+    use crate::module::location::location::ConcreteLocation;
+
+    use super::branch_warning;
+
+    fn location(yaml: &str) -> ConcreteLocation {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn branch_source_is_not_reproducible() {
+        let branch = location(r#"
+            github_user: my_user
+            repository: my_repo
+            branch: main
+            refresh: 1day
+        "#);
+        let warning = branch_warning(&lwc!("my_mod"), &branch).unwrap();
+        assert!(warning.contains("mod my_mod is obtained from branch main of github my_user/my_repo"));
+        assert!(warning.contains("older than 1day"));
+    }
+
+    #[test]
+    fn tag_and_commit_sources_are_reproducible() {
+        let tag = location(r#"
+            github_user: my_user
+            repository: my_repo
+            tag: v1.0
+        "#);
+        assert_eq!(branch_warning(&lwc!("my_mod"), &tag), None);
+
+        let commit = location(r#"
+            github_user: my_user
+            repository: my_repo
+            commit: 0123abcd
+        "#);
+        assert_eq!(branch_warning(&lwc!("my_mod"), &commit), None);
+
+        let http = location(r#"
+            http: https://example.com/my_mod.zip
+        "#);
+        assert_eq!(branch_warning(&lwc!("my_mod"), &http), None);
+    }
+}
@@ -3,26 +3,28 @@ use std::path::PathBuf;
 
 use anyhow::{bail, Result};
 use chrono::Local;
-use log::info;
-use path_clean::PathClean;
+use log::{info, warn};
 
 use crate::apply_patch::patch_module;
-use crate::archive_extractor::Extractor;
+use crate::archive_extractor::{Extractor, TempDirOptions};
 use crate::args::Install;
-use crate::cache::Cache;
+use crate::cache::{cache_file_name, read_asset_sidecar, write_asset_sidecar, write_name_sidecar, Cache, CacheLayout};
 use crate::canon_path::CanonPath;
 use crate::download::Downloader;
 use crate::global::Global;
+use crate::install_events::{EventSender, InstallEvent};
 use crate::module::global_locations::GlobalLocations;
 use crate::module::location::location::{ConcreteLocation, Location};
 use crate::module::location::replace::ReplaceSpec;
 use crate::module::location::source::Source;
 use crate::timeline::SetupTimeline;
+use crate::tp2::find_tp2;
 use crate::lowercase::LwcString;
 use crate::module::weidu_mod::WeiduMod;
 use crate::config::Config;
 
 use super::get_options::GetOptions;
+use super::verify::verify_archive;
 
 pub struct ModuleDownload<'a> {
     pub global: &'a Global,
@@ -32,6 +34,9 @@ pub struct ModuleDownload<'a> {
     pub extractor: Extractor<'a>,
     pub cache: &'a Cache,
     pub game_dir: &'a CanonPath,
+    pub events: EventSender,
+    pub cache_path_max_length: Option<usize>,
+    pub cache_layout: CacheLayout,
 }
 
 impl <'a> ModuleDownload<'a> {
@@ -44,12 +49,21 @@ impl <'a> ModuleDownload<'a> {
             global_locations,
             opts,
             downloader,
-            extractor: Extractor::new(game_dir, config),
+            extractor: Extractor::new(game_dir, config,
+                                        TempDirOptions { keep: opts.keep_temp, reuse: opts.resume_extract }),
             cache,
             game_dir,
+            events: EventSender::default(),
+            cache_path_max_length: config.cache_path_max_length,
+            cache_layout: config.cache_layout.unwrap_or_default(),
         }
     }
 
+    /// Download and extraction progress will be sent to `events`.
+    pub fn with_events(self, events: EventSender) -> Self {
+        Self { events, ..self }
+    }
+
     // at some point, I'd like to have a pool of downloads with installations done
     // concurrently as soon as modules are there
     #[tokio::main]
@@ -92,19 +106,21 @@ impl <'a> ModuleDownload<'a> {
 
         let dest = std::env::current_dir()?;
         let dest = CanonPath::new(dest)?;
-        self.extractor.extract_files(&archive, &mod_name , location)?;
+        self.events.emit(InstallEvent::ExtractStarted { mod_name: mod_name.to_owned() });
+        let precopy_output = self.extractor.extract_files(&archive, &mod_name , location)?;
+        self.events.emit(InstallEvent::ExtractFinished { mod_name: mod_name.to_owned() });
         let copied = Some(Local::now());
 
         // modifications : patch then patches (in order) the replace
         if let Some(patch) = &location.patch {
-            patch_module(&dest, &mod_name , &patch, &self.opts, &self.global).await?;
+            patch_module(&dest, &mod_name , &patch, &self.opts, &self.global, self.downloader, self.cache).await?;
             info!("Single patch applied (`patch` property)")
         }
         if location.patches.is_empty() {
             info!("No `patches` property (or empty).")
         } else {
             for patch in &location.patches {
-                patch_module(&dest, &mod_name , &patch, &self.opts,&self.global).await?;
+                patch_module(&dest, &mod_name , &patch, &self.opts, &self.global, self.downloader, self.cache).await?;
             }
             info!("Patches applied (`patches` property)")
         }
@@ -112,34 +128,120 @@ impl <'a> ModuleDownload<'a> {
         replace_module(&dest, &mod_name , &location.replace, get_options)?;
         let replaced = Some(Local::now());
 
-        Ok(SetupTimeline { start, downloaded, copied, patched, replaced, configured: None })
+        Ok(SetupTimeline { start, downloaded, copied, patched, replaced, configured: None, precopy_output })
     }
 
     pub async fn retrieve_location(&self, loc: &ConcreteLocation, mod_name: &LwcString) -> Result<PathBuf> {
-        let dest = self.cache.join(loc.source.save_subdir()?);
-        let save_name = loc.source.save_name(mod_name)?;
+        if self.opts.no_download {
+            let archive = self.local_archive(loc, mod_name)?;
+            if !archive.exists() {
+                bail!("Archive for mod {} is not available locally (--no-download)\n  expected {:?}", mod_name, archive);
+            }
+            if let (Source::Http(_) | Source::Github(_), false) = (&loc.source, self.opts.no_verify) {
+                verify_archive(&archive, loc)?;
+            }
+            return Ok(archive);
+        }
+        let dest = self.cache.join(loc.source.save_subdir(self.cache_layout)?);
+        let save_name = loc.source.save_name(mod_name, self.cache_layout)?;
+        let file_name = cache_file_name(&dest, &save_name, self.cache_path_max_length);
+        let downloaded = match &loc.source {
+            Source::Http(http) => {
+                self.events.emit(InstallEvent::DownloadStarted { mod_name: mod_name.to_owned() });
+                write_name_sidecar(&dest, &file_name, &save_name)?;
+                http.download(self.downloader, &dest, file_name).await?
+            }
+            Source::Github(github) => {
+                self.events.emit(InstallEvent::DownloadStarted { mod_name: mod_name.to_owned() });
+                let mut matched_asset = false;
+                let downloaded = github.get_github(&self.downloader, &dest, |asset_name| {
+                    // the archive of an asset pattern is named after the matching asset, a new release isn't
+                    // mistaken for the one already in the cache
+                    let save_name = match asset_name {
+                        None => save_name,
+                        Some(asset_name) => {
+                            matched_asset = true;
+                            loc.source.asset_save_name(asset_name, self.cache_layout)?
+                        }
+                    };
+                    let asset_file_name = cache_file_name(&dest, &save_name, self.cache_path_max_length);
+                    write_name_sidecar(&dest, &asset_file_name, &save_name)?;
+                    Ok(asset_file_name)
+                }).await?;
+                if matched_asset {
+                    write_asset_sidecar(&dest.join(&file_name), &downloaded)?;
+                }
+                downloaded
+            }
+            Source::Absolute { path, .. } => return Ok(PathBuf::from(path)),
+            Source::Local { local, .. } => return self.get_local_mod_path(local),
+        };
+        if !self.opts.no_verify {
+            if let Err(error) = verify_archive(&downloaded, loc) {
+                if let Err(remove_error) = std::fs::remove_file(&downloaded) {
+                    warn!("Could not remove archive {:?} from the cache\n -> {:?}", downloaded, remove_error);
+                }
+                bail!("Downloaded archive for mod {} is not the expected one (it was removed from the cache)\n -> {}",
+                        mod_name, error);
+            }
+        }
+        self.events.emit(InstallEvent::DownloadFinished { mod_name: mod_name.to_owned(), path: downloaded.clone() });
+        Ok(downloaded)
+    }
+
+    /// Where the mod "archive" is expected to be without downloading it: the archive cache for downloaded sources,
+    /// the original location for the others.
+    pub fn local_archive(&self, loc: &ConcreteLocation, mod_name: &LwcString) -> Result<PathBuf> {
         match &loc.source {
-            Source::Http(http) => http.download(self.downloader, &dest, save_name).await,
-            Source::Github(github) => github.get_github(&self.downloader, &dest, save_name).await,
-            Source::Absolute { path } => Ok(PathBuf::from(path)),
-            Source::Local { local } => self.get_local_mod_path(local),
+            Source::Http(_) | Source::Github(_) => {
+                let dest = self.cache.join(loc.source.save_subdir(self.cache_layout)?);
+                let save_name = loc.source.save_name(mod_name, self.cache_layout)?;
+                let archive = dest.join(cache_file_name(&dest, &save_name, self.cache_path_max_length));
+                // for a release asset pattern, the archive of the last matching asset
+                Ok(read_asset_sidecar(&archive).unwrap_or(archive))
+            }
+            Source::Absolute { path, .. } => Ok(PathBuf::from(path)),
+            Source::Local { local, .. } => self.get_local_mod_path(local),
+        }
+    }
+
+    /// Mods that are not in the game directory and whose archive is not available locally
+    /// (the missing ones with their expected location).
+    pub fn missing_archives(&self, modules: &[&WeiduMod]) -> Result<Vec<(LwcString, PathBuf)>> {
+        let mut missing = vec![];
+        for module in modules {
+            if find_tp2(self.game_dir, &module.name).is_ok() {
+                continue;
+            }
+            let location = self.get_module_location(module)?;
+            let archive = self.local_archive(location, &module.name)?;
+            if !archive.exists() {
+                missing.push((module.name.to_owned(), archive));
+            }
+        }
+        Ok(missing)
+    }
+
+    /// Same resolution as `retrieve_location` but only checks the source can be obtained (nothing is downloaded).
+    pub async fn check_location(&self, loc: &ConcreteLocation) -> Result<bool> {
+        match &loc.source {
+            Source::Http(http) => http.check_available(self.downloader).await,
+            Source::Github(github) => github.check_available(self.downloader).await,
+            Source::Absolute { path, .. } => Ok(PathBuf::from(path).exists()),
+            Source::Local { local, .. } => Ok(self.get_local_mod_path(local)?.exists()),
         }
     }
 
     fn get_local_mod_path(&self, local_mod_name: &String) -> Result<PathBuf, anyhow::Error> {
         let manifest_path = self.opts.get_manifest_root(self.game_dir);
-        let local_mods = match &self.global.local_mods {
-            None => PathBuf::new(),
-            Some(path) => PathBuf::from(path).clean(),
+        let local_mods = match manifest_path.safe_join(self.global.local_mods.as_deref().unwrap_or_default()) {
+            Ok(local_mods) => local_mods,
+            Err(error) => bail!("Invalid local_mods value\n -> {:?}", error),
         };
-        if local_mods.is_absolute() || local_mods.starts_with("..") {
-            bail!("Invalid local_mods value");
+        match local_mods.safe_join(local_mod_name) {
+            Ok(path) => Ok(path.to_path_buf()),
+            Err(error) => bail!("Invalid local value\n -> {:?}", error),
         }
-        let mod_name = PathBuf::from(local_mod_name).clean();
-        if mod_name.is_absolute() || local_mods.starts_with("..") {
-            bail!("Invalid local value");
-        }
-        Ok(manifest_path.join(local_mods)?.join(mod_name)?.to_path_buf())
     }
 }
 
@@ -165,6 +267,7 @@ mod test_retrieve_location {
     use crate::args::Install;
     use crate::lowercase::lwc;
     use crate::module::global_locations::GlobalLocations;
+    use crate::module::location::github::{Github, GithubDescriptor};
     use crate::module::location::http::Http;
     use crate::module::location::location::{ConcreteLocation, Location};
     use crate::module::location::source::Source;
@@ -177,6 +280,9 @@ mod test_retrieve_location {
     use anyhow::bail;
     use faux::when;
 
+    use crate::archive_extractor::Extractor;
+    use crate::install_events::{event_channel, InstallEvent};
+
     /**
      * Check http location.
      * Should be <cache_path>/http/<host_name>/<file_name>
@@ -204,6 +310,7 @@ mod test_retrieve_location {
             ignore_current_dir_weidu: None,
             extractors: HashMap::new(),
             code_editor: None,
+            ..Default::default()
         };
 
         let expected_dest = PathBuf::from("/cache_path/http/example.com");
@@ -239,7 +346,7 @@ mod test_retrieve_location {
     #[tokio::test]
     async fn retrieve_absolute_location() {
         let location = ConcreteLocation {
-            source: Source::Absolute { path: "/some/path/file.zip".to_string() },
+            source: Source::Absolute { path: "/some/path/file.zip".to_string(), rename: None },
             ..ConcreteLocation::default()
         };
         let module = WeiduMod {
@@ -279,7 +386,7 @@ mod test_retrieve_location {
     #[tokio::test]
     async fn retrieve_local_location() {
         let location = ConcreteLocation {
-            source: Source:: Local { local: "some/path/file.zip".to_string() },
+            source: Source::Local { local: "some/path/file.zip".to_string(), rename: None },
             ..ConcreteLocation::default()
         };
         let module = WeiduMod {
@@ -345,6 +452,7 @@ mod test_retrieve_location {
             ignore_current_dir_weidu: None,
             extractors: HashMap::new(),
             code_editor: None,
+            ..Default::default()
         };
 
 
@@ -395,6 +503,7 @@ mod test_retrieve_location {
             ignore_current_dir_weidu: None,
             extractors: HashMap::new(),
             code_editor: None,
+            ..Default::default()
         };
 
 
@@ -412,4 +521,225 @@ mod test_retrieve_location {
             &other_location
         )
     }
+
+    fn no_download_opts() -> Install {
+        Install { no_download: true, ..Install::default() }
+    }
+
+    /**
+     * With `--no-download`, the cached archive is used without calling the downloader
+     * (the faux downloader fails if it is called).
+     */
+    #[tokio::test]
+    async fn no_download_uses_cached_archive() {
+        let location = ConcreteLocation {
+            source: Source::Http(Http {
+                http: "http://example.com/some_mod.zip".to_string(),
+                ..Default::default()
+            }),
+            ..ConcreteLocation::default()
+        };
+        let global = Global::default();
+        let global_locations = GlobalLocations::default();
+        let opts = no_download_opts();
+        let config = Config::default();
+
+        let game_dir = CanonPath::new("some_dir").unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache = Cache::Path(cache_dir.path().to_path_buf());
+        let downloader = Downloader::faux();
+
+        let module_download = ModuleDownload::new(&config, &global, &global_locations, &opts,
+                                                    &downloader, &game_dir, &cache);
+        let expected = cache_dir.path().join("http").join("example.com").join("some_mod.zip");
+        std::fs::create_dir_all(expected.parent().unwrap()).unwrap();
+        std::fs::write(&expected, b"archive").unwrap();
+
+        let result = module_download.retrieve_location(&location, &lwc!("some_mod")).await;
+        assert_eq!(result.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn no_download_fails_on_missing_archive() {
+        let location = ConcreteLocation {
+            source: Source::Github(Github {
+                github_user: "some_user".to_string(),
+                repository: "some_mod".to_string(),
+                descriptor: GithubDescriptor::Tag { tag: "v1.0".to_string() },
+                ..Default::default()
+            }),
+            ..ConcreteLocation::default()
+        };
+        let module = WeiduMod {
+            name: lwc!("some_mod"),
+            location: Some(Location::Concrete { concrete: location.clone() }),
+            ..WeiduMod::default()
+        };
+        let global = Global::default();
+        let global_locations = GlobalLocations::default();
+        let opts = no_download_opts();
+        let config = Config::default();
+
+        let game_dir = tempfile::tempdir().unwrap();
+        let game_dir = CanonPath::new(game_dir.path()).unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache = Cache::Path(cache_dir.path().to_path_buf());
+        let downloader = Downloader::faux();
+
+        let module_download = ModuleDownload::new(&config, &global, &global_locations, &opts,
+                                                    &downloader, &game_dir, &cache);
+
+        let error = module_download.retrieve_location(&location, &module.name).await.unwrap_err();
+        assert!(error.to_string().contains("not available locally"));
+
+        let expected = module_download.local_archive(&location, &module.name).unwrap();
+        assert!(expected.starts_with(cache_dir.path()));
+        assert_eq!(module_download.missing_archives(&[&module]).unwrap(), vec![(lwc!("some_mod"), expected)]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn emit_events_for_single_mod_setup() {
+        use std::cell::RefCell;
+        use std::os::unix::fs::PermissionsExt;
+        use crate::file_installer::FileInstaller;
+        use crate::modda_context::ModdaContext;
+        use crate::module::components::{Component, Components};
+        use crate::module::manifest::Manifest;
+        use crate::module::manifest_conditions::ManifestConditions;
+        use crate::module::module::Module;
+        use crate::process_weidu_mod::process_weidu_mod;
+
+        let location = ConcreteLocation {
+            source: Source::Http(Http {
+                http: "http://example.com/some_mod.zip".to_string(),
+                ..Default::default()
+            }),
+            ..ConcreteLocation::default()
+        };
+        let weidu_mod = WeiduMod {
+            name: lwc!("some_mod"),
+            language: Some(0),
+            components: Components::List(vec![Component::Simple(0)]),
+            location: Some(Location::Concrete { concrete: location }),
+            ..WeiduMod::default()
+        };
+        let manifest = Manifest {
+            version: "1".to_string(),
+            global: Global::default(),
+            locations: GlobalLocations::default(),
+            manifest_conditions: ManifestConditions::default(),
+            modules: vec![Module::Mod { weidu_mod: weidu_mod.clone() }],
+        };
+        let opts = Install::default();
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let game_dir = temp_dir.path().join("game");
+        std::fs::create_dir_all(&game_dir).unwrap();
+        let game_dir = CanonPath::new(game_dir).unwrap();
+        let weidu = temp_dir.path().join("weidu-stub");
+        std::fs::write(&weidu, format!("#!/bin/sh\necho '~SOME_MOD/SETUP-SOME_MOD.TP2~ #0 #0 // Component' >> '{}'\n",
+                                        game_dir.join_path("weidu.log").to_string_lossy())).unwrap();
+        std::fs::set_permissions(&weidu, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let config = Config {
+            weidu_path: Some(weidu.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let cache_dir = temp_dir.path().join("cache");
+        let cache = Cache::Path(cache_dir.clone());
+        let archive = cache_dir.join("http/example.com/some_mod.zip");
+
+        let mut downloader = Downloader::faux();
+        let downloaded = archive.clone();
+        when!(
+            downloader.download(_, _, _, _, _)
+        ).then(move |(_, _, _, _, _)| Ok(downloaded.clone()));
+        // the extracted mod is its tp2
+        let mut extractor = Extractor::faux();
+        let mod_dir = game_dir.join_path("some_mod");
+        when!(
+            extractor.extract_files(_, _, _)
+        ).then(move |(_, _, _)| {
+            std::fs::create_dir_all(&mod_dir)?;
+            std::fs::write(mod_dir.join("setup-some_mod.tp2"), "BACKUP ~weidu_external/backup/some_mod~\n")?;
+            Ok(None)
+        });
+
+        let (events, mut receiver) = event_channel();
+        let mut module_download = ModuleDownload::new(&config, &manifest.global, &manifest.locations, &opts,
+                                                        &downloader, &game_dir, &cache)
+                                                        .with_events(events.clone());
+        module_download.extractor = extractor;
+        let file_installer = FileInstaller::new(&manifest.global, &opts, &game_dir);
+        let modda_context = ModdaContext { current_dir: &game_dir, config: &config, opts: &opts,
+                                            module_downloader: &module_download, file_installer: &file_installer,
+                                            log: RefCell::new(None), events, occurrences: RefCell::default() };
+
+        let result = process_weidu_mod(&weidu_mod, &modda_context, &manifest, 1).unwrap();
+        assert!(!result.stop);
+
+        let mut received = vec![];
+        while let Ok(event) = receiver.try_recv() {
+            received.push(event);
+        }
+        assert_eq!(
+            received,
+            vec![
+                InstallEvent::DownloadStarted { mod_name: lwc!("some_mod") },
+                InstallEvent::DownloadFinished { mod_name: lwc!("some_mod"), path: archive },
+                InstallEvent::ExtractStarted { mod_name: lwc!("some_mod") },
+                InstallEvent::ExtractFinished { mod_name: lwc!("some_mod") },
+                InstallEvent::WeiduStarted { mod_name: lwc!("some_mod") },
+                InstallEvent::WeiduFinished { mod_name: lwc!("some_mod"), status: Some(0) },
+            ]
+        );
+    }
+
+    async fn retrieve_checked(archive_content: &str, no_verify: bool) -> (anyhow::Result<PathBuf>, PathBuf, tempfile::TempDir) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache_dir = temp_dir.path().to_path_buf();
+        let archive = cache_dir.join("some_mod.zip");
+        std::fs::write(&archive, archive_content).unwrap();
+        let location = ConcreteLocation {
+            source: Source::Http(Http { http: "http://example.com/some_mod.zip".to_string(), ..Default::default() }),
+            // sha256 of "hello"
+            sha256: Some("2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824".to_string()),
+            ..ConcreteLocation::default()
+        };
+        let global = Global::default();
+        let global_locations = GlobalLocations::default();
+        let opts = Install { no_verify, ..Install::default() };
+        let config = Config::default();
+        let game_dir = CanonPath::new(&cache_dir).unwrap();
+        let cache = Cache::Path(cache_dir.clone());
+
+        let mut downloader = Downloader::faux();
+        let downloaded = archive.clone();
+        when!(downloader.download(_, _, _, _, _)).then(move |(_, _, _, _, _)| Ok(downloaded.clone()));
+        let module_download = ModuleDownload::new(&config, &global, &global_locations, &opts,
+                                                    &downloader, &game_dir, &cache);
+        let result = module_download.retrieve_location(&location, &lwc!("some_mod")).await;
+        (result, archive, temp_dir)
+    }
+
+    #[tokio::test]
+    async fn verified_download() {
+        let (result, archive, _temp_dir) = retrieve_checked("hello", false).await;
+        assert_eq!(result.unwrap(), archive);
+    }
+
+    #[tokio::test]
+    async fn corrupted_download_is_removed() {
+        let (result, archive, _temp_dir) = retrieve_checked("hullo", false).await;
+        let message = format!("{:?}", result.unwrap_err());
+        assert!(message.contains("SHA-256 mismatch"), "{}", message);
+        assert!(!archive.exists());
+    }
+
+    #[tokio::test]
+    async fn no_verify() {
+        let (result, archive, _temp_dir) = retrieve_checked("hullo", true).await;
+        assert_eq!(result.unwrap(), archive);
+        assert!(archive.exists());
+    }
 }
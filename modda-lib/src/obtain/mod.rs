@@ -1,3 +1,6 @@
 
+pub mod branch_sources;
 pub mod get_module;
 pub mod get_options;
+pub mod remote_manifest;
+pub mod verify;
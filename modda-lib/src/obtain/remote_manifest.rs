@@ -0,0 +1,153 @@
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+
+use crate::cache::Cache;
+use crate::download::{Downloader, DownloadOpts};
+use crate::module::file_module_origin::FileModuleOrigin;
+use crate::module::global_locations::LocationRegistry;
+use crate::module::location::location::{ConcreteLocation, Location};
+use crate::module::location::source::Source;
+use crate::module::manifest::Manifest;
+use crate::module::module::Module;
+use crate::module::refresh::RefreshCondition;
+use crate::patch_source::PatchSource;
+
+const DEFAULT_MANIFEST_NAME: &str = "manifest.yml";
+
+/// Downloads a manifest shared by URL, to the `manifests/<host>` sub-directory of the cache.<br>
+/// It is always downloaded again (the remote manifest may have changed since the last time).
+pub async fn fetch_manifest(url: &str, downloader: &Downloader, cache: &Cache) -> Result<PathBuf> {
+    let parsed = match url::Url::parse(url) {
+        Err(error) => bail!("Invalid manifest url {}\n -> {:?}", url, error),
+        Ok(parsed) => parsed,
+    };
+    let host = match parsed.host_str() {
+        None => bail!("Invalid manifest url {} (no host)", url),
+        Some(host) => host.to_owned(),
+    };
+    let file_name = parsed.path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|segment| !segment.is_empty())
+        .unwrap_or(DEFAULT_MANIFEST_NAME)
+        .to_owned();
+    let dest = cache.join("manifests").join(host);
+    let opts = DownloadOpts { no_cache: false, refresh: RefreshCondition::Always };
+    downloader.download(url, &dest, PathBuf::from(file_name), &opts, &None).await
+}
+
+/// A remote manifest can't refer to files relative to its own location (local mods, relative patches,
+/// local location registries, generated mod files), they only exist on the computer of the manifest author.<br>
+/// Absolute paths are refused too, a shared manifest has no business reading files anywhere on this computer.
+pub fn check_remote_manifest(manifest: &Manifest) -> Result<()> {
+    let mut errors = vec![];
+    if let Some(local_files) = &manifest.global.local_files {
+        errors.push(format!("`local_files` is set ({})", local_files));
+    }
+    for registry in &manifest.locations.external {
+        if let LocationRegistry::Local { local } = registry {
+            errors.push(format!("location registry `{}` is local", local));
+        }
+    }
+    for (name, location) in manifest.locations.entries.iter().sorted_by(|(name1, _), (name2, _)| name1.as_ref().cmp(name2.as_ref())) {
+        errors.extend(check_location(location).into_iter().map(|error| format!("location {}: {}", name, error)));
+    }
+    for module in &manifest.modules {
+        match module {
+            Module::Mod { weidu_mod } => if let Some(Location::Concrete { concrete }) = &weidu_mod.location {
+                errors.extend(check_location(concrete).into_iter().map(|error| format!("mod {}: {}", weidu_mod.name, error)));
+            }
+            Module::Generated { gen } => for file in &gen.files {
+                let error = match file {
+                    FileModuleOrigin::Local { local, .. } => format!("`local` file {}", local),
+                    FileModuleOrigin::Absolute { absolute, .. } => format!("`absolute` file {}", absolute),
+                };
+                errors.push(format!("generated mod {}: {}", gen.gen_mod, error));
+            }
+        }
+    }
+    if !errors.is_empty() {
+        bail!("Remote manifest refers to local files, which can't be used:\n  - {}", errors.join("\n  - "));
+    }
+    Ok(())
+}
+
+fn check_location(location: &ConcreteLocation) -> Vec<String> {
+    let mut errors = vec![];
+    match &location.source {
+        Source::Local { local, .. } => errors.push(format!("`local` source {}", local)),
+        Source::Absolute { path, .. } => errors.push(format!("absolute source {}", path)),
+        _ => {}
+    }
+    for patch in location.patch.iter().chain(location.patches.iter()) {
+        if let PatchSource::Relative { relative } = &patch.patch_source {
+            errors.push(format!("`relative` patch {}", relative));
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod test_remote_manifest {
+    use std::path::PathBuf;
+
+    use faux::when;
+
+    use crate::cache::Cache;
+    use crate::download::Downloader;
+    use crate::module::manifest::Manifest;
+
+    use super::{check_remote_manifest, fetch_manifest};
+
+    fn fixture(name: &str) -> String {
+        format!("{}/resources/test/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    #[tokio::test]
+    async fn fetch_and_parse_remote_manifest() {
+        let cache = Cache::Path(PathBuf::from("/cache_path"));
+        let expected_dest = PathBuf::from("/cache_path/manifests/example.com");
+        let mut downloader = Downloader::faux();
+        when!(
+            downloader.download(_, {expected_dest}, _, _, _)
+        ).then(|(_, _, _, _, _)| Ok(PathBuf::from(fixture("remote_manifest/shared_setup.yml"))));
+
+        let path = fetch_manifest("https://example.com/setups/my_setup.yml", &downloader, &cache).await.unwrap();
+        let manifest = Manifest::read_path(&path.to_string_lossy()).unwrap();
+
+        assert_eq!(manifest.modules.len(), 1);
+        check_remote_manifest(&manifest).unwrap();
+    }
+
+    #[tokio::test]
+    async fn invalid_url() {
+        let cache = Cache::Path(PathBuf::from("/cache_path"));
+        let downloader = Downloader::faux();
+        assert!(fetch_manifest("not an url", &downloader, &cache).await.is_err());
+    }
+
+    #[test]
+    fn local_references_are_rejected() {
+        let manifest = Manifest::read_path(&fixture("remote_manifest/with_local_references.yml")).unwrap();
+        let error = check_remote_manifest(&manifest).unwrap_err().to_string();
+
+        assert!(error.contains("mod local_mod: `local` source local_mod"));
+        assert!(error.contains("mod patched_mod: `relative` patch patches/patched_mod.diff"));
+        assert!(error.contains("location registry `registry.yml` is local"));
+        assert!(!error.contains("remote_mod"));
+    }
+
+    #[test]
+    fn absolute_paths_and_local_files_are_rejected() {
+        let manifest = Manifest::read_path(&fixture("remote_manifest/with_absolute_references.yml")).unwrap();
+        let error = check_remote_manifest(&manifest).unwrap_err().to_string();
+
+        assert!(error.contains("`local_files` is set (files)"));
+        assert!(error.contains("mod absolute_mod: absolute source /home/someone/absolute_mod.zip"));
+        assert!(error.contains("generated mod local_gen: `local` file some_files"));
+        assert!(error.contains("generated mod absolute_gen: `absolute` file /etc"));
+        assert!(!error.contains("remote_mod"));
+    }
+}
@@ -0,0 +1,90 @@
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use sha2::{Digest, Sha256};
+
+use crate::module::location::location::ConcreteLocation;
+
+/// Checks the archive against the `size` and `sha256` given in the location (when they are).
+pub fn verify_archive(archive: &Path, location: &ConcreteLocation) -> Result<()> {
+    if let Some(expected) = location.size {
+        let actual = match std::fs::metadata(archive) {
+            Ok(metadata) => metadata.len(),
+            Err(error) => bail!("Could not read archive {:?}\n -> {:?}", archive, error),
+        };
+        if actual != expected {
+            bail!("Size mismatch for archive {:?}\n  expected {} bytes\n  actual   {} bytes", archive, expected, actual);
+        }
+    }
+    if let Some(expected) = &location.sha256 {
+        let actual = sha256_digest(archive)?;
+        if !actual.eq_ignore_ascii_case(expected.trim()) {
+            bail!("SHA-256 mismatch for archive {:?}\n  expected {}\n  actual   {}", archive, expected, actual);
+        }
+    }
+    Ok(())
+}
+
+pub fn sha256_digest(path: &Path) -> Result<String> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) => bail!("Could not open archive {:?}\n -> {:?}", path, error),
+    };
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut BufReader::new(file), &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod test_verify_archive {
+    use crate::module::location::location::ConcreteLocation;
+
+    use super::verify_archive;
+
+    // sha256 of "hello"
+    const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    fn archive() -> (tempfile::TempDir, std::path::PathBuf) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let archive = temp_dir.path().join("my_mod.zip");
+        std::fs::write(&archive, "hello").unwrap();
+        (temp_dir, archive)
+    }
+
+    #[test]
+    fn nothing_to_verify() {
+        let (_temp_dir, archive) = archive();
+        verify_archive(&archive, &ConcreteLocation::default()).unwrap();
+    }
+
+    #[test]
+    fn matching_archive() {
+        let (_temp_dir, archive) = archive();
+        let location = ConcreteLocation {
+            sha256: Some(HELLO_SHA256.to_uppercase()),
+            size: Some(5),
+            ..ConcreteLocation::default()
+        };
+        verify_archive(&archive, &location).unwrap();
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let (_temp_dir, archive) = archive();
+        let wrong = "0".repeat(64);
+        let location = ConcreteLocation { sha256: Some(wrong.clone()), ..ConcreteLocation::default() };
+        let message = verify_archive(&archive, &location).unwrap_err().to_string();
+        assert!(message.contains(&wrong) && message.contains(HELLO_SHA256), "{}", message);
+    }
+
+    #[test]
+    fn size_mismatch() {
+        let (_temp_dir, archive) = archive();
+        let location = ConcreteLocation { size: Some(6), sha256: Some(HELLO_SHA256.to_string()), ..ConcreteLocation::default() };
+        let message = verify_archive(&archive, &location).unwrap_err().to_string();
+        assert!(message.contains("expected 6 bytes"), "{}", message);
+    }
+}
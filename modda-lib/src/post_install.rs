@@ -1,7 +1,7 @@
 use std::time::Duration;
 
 use nu_ansi_term::Color::Green;
-use log::info;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 
 use crate::lowercase::LwcString;
@@ -9,6 +9,7 @@ use crate::lowercase::LwcString;
 
 mod post_install_variants {
     named_unit_variant!(interrupt);
+    named_unit_variant!(wait_enter);
     named_unit_variant!(none);
 }
 
@@ -19,7 +20,64 @@ pub enum PostInstall {
     None,
     #[serde(with = "post_install_variants::interrupt")]
     Interrupt,
-    WaitSeconds { wait_seconds: u16 },
+    /// Waits until the user presses Enter, then continues with the next mod
+    #[serde(with = "post_install_variants::wait_enter")]
+    WaitEnter,
+    WaitSeconds {
+        wait_seconds: u16,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        on: Option<PostInstallOn>,
+    },
+    /// `interrupt`, `wait_enter` or `none`, only for some outcomes of the installation (`{ action: interrupt, on: warning }`)
+    Conditional { action: PostInstallAction, on: PostInstallOn },
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum PostInstallAction {
+    None,
+    Interrupt,
+    WaitEnter,
+}
+
+/// Outcome(s) of the weidu run for which a post-install action is done.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum PostInstallOn {
+    /// Weidu ended without warning
+    Success,
+    /// Weidu ended with warnings (that didn't stop the installation)
+    Warning,
+    Always,
+}
+
+impl PostInstallOn {
+    fn matches(&self, status: InstallStatus) -> bool {
+        match self {
+            PostInstallOn::Success => status == InstallStatus::Success,
+            PostInstallOn::Warning => status == InstallStatus::Warning,
+            PostInstallOn::Always => true,
+        }
+    }
+}
+
+/// How the installation of a mod ended.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum InstallStatus {
+    #[default]
+    Success,
+    Warning,
+    /// The installation of the mod failed (it only goes on with `--continue-on-error`)
+    Failed,
+}
+
+impl InstallStatus {
+    pub fn from_status_code(status_code: Option<i32>) -> Self {
+        match status_code {
+            Some(3) => InstallStatus::Warning,
+            _ => InstallStatus::Success,
+        }
+    }
 }
 
 impl Default for PostInstall {
@@ -29,15 +87,21 @@ impl Default for PostInstall {
 }
 
 pub trait PostInstallExec {
-    fn exec(&self, mod_name: &LwcString) -> PostInstallOutcome;
+    fn exec(&self, mod_name: &LwcString, status: InstallStatus) -> PostInstallOutcome;
 }
 
 impl PostInstallExec for PostInstall {
-    fn exec(&self, mod_name: &LwcString) -> PostInstallOutcome {
+    fn exec(&self, mod_name: &LwcString, status: InstallStatus) -> PostInstallOutcome {
         match self {
             PostInstall::None => PostInstallOutcome::Continue,
             PostInstall::Interrupt => PostInstallOutcome::Stop,
-            PostInstall::WaitSeconds { wait_seconds } => {
+            PostInstall::WaitEnter => wait_enter(mod_name),
+            PostInstall::Conditional { on, .. } if !on.matches(status) => PostInstallOutcome::Continue,
+            PostInstall::Conditional { action: PostInstallAction::None, .. } => PostInstallOutcome::Continue,
+            PostInstall::Conditional { action: PostInstallAction::Interrupt, .. } => PostInstallOutcome::Stop,
+            PostInstall::Conditional { action: PostInstallAction::WaitEnter, .. } => wait_enter(mod_name),
+            PostInstall::WaitSeconds { on: Some(on), .. } if !on.matches(status) => PostInstallOutcome::Continue,
+            PostInstall::WaitSeconds { wait_seconds, .. } => {
                 // would be nice to implement a countdown and a hotkey to interrupt install
                 info!("{}", Green.bold().paint(format!("Post-install wait of {} s for mod {}",
                                                         wait_seconds, mod_name)));
@@ -50,10 +114,10 @@ impl PostInstallExec for PostInstall {
 }
 
 impl PostInstallExec for Option<PostInstall> {
-    fn exec(&self, mod_name: &LwcString) -> PostInstallOutcome {
+    fn exec(&self, mod_name: &LwcString, status: InstallStatus) -> PostInstallOutcome {
         match self {
             None => PostInstallOutcome::Continue,
-            Some(post_install) => post_install.exec(mod_name),
+            Some(post_install) => post_install.exec(mod_name, status),
         }
     }
 }
@@ -62,7 +126,75 @@ fn wait(seconds: u16) {
     std::thread::sleep(Duration::from_secs(seconds as u64))
 }
 
+fn wait_enter(mod_name: &LwcString) -> PostInstallOutcome {
+    info!("{}", Green.bold().paint(format!("Mod {} is installed, press Enter to continue", mod_name)));
+    info!("Ctrl+C to stop the installation");
+    let mut line = String::new();
+    if let Err(error) = std::io::stdin().read_line(&mut line) {
+        warn!("Could not read from the console, continuing\n -> {:?}", error);
+    }
+    PostInstallOutcome::Continue
+}
+
+#[derive(Debug, PartialEq)]
 pub enum PostInstallOutcome {
     Stop,
     Continue,
 }
+
+#[cfg(test)]
+mod test_post_install_on {
+    use crate::lowercase::lwc;
+
+    use super::{InstallStatus, PostInstall, PostInstallAction, PostInstallExec, PostInstallOn, PostInstallOutcome};
+
+    #[test]
+    fn deserialize_conditional() {
+        let post_install: PostInstall = serde_yaml::from_str("{ action: interrupt, on: warning }").unwrap();
+        assert_eq!(post_install, PostInstall::Conditional { action: PostInstallAction::Interrupt, on: PostInstallOn::Warning });
+
+        let post_install: PostInstall = serde_yaml::from_str("{ wait_seconds: 5, on: success }").unwrap();
+        assert_eq!(post_install, PostInstall::WaitSeconds { wait_seconds: 5, on: Some(PostInstallOn::Success) });
+
+        let post_install: PostInstall = serde_yaml::from_str("interrupt").unwrap();
+        assert_eq!(post_install, PostInstall::Interrupt);
+
+        let post_install: PostInstall = serde_yaml::from_str("wait_enter").unwrap();
+        assert_eq!(post_install, PostInstall::WaitEnter);
+
+        let post_install: PostInstall = serde_yaml::from_str("{ action: wait_enter, on: warning }").unwrap();
+        assert_eq!(post_install, PostInstall::Conditional { action: PostInstallAction::WaitEnter, on: PostInstallOn::Warning });
+    }
+
+    #[test]
+    fn serialize_conditional() {
+        let post_install = PostInstall::Conditional { action: PostInstallAction::Interrupt, on: PostInstallOn::Always };
+        assert_eq!(serde_yaml::to_string(&post_install).unwrap(), "action: interrupt\non: always\n");
+
+        let post_install = PostInstall::WaitSeconds { wait_seconds: 5, on: None };
+        assert_eq!(serde_yaml::to_string(&post_install).unwrap(), "wait_seconds: 5\n");
+
+        assert_eq!(serde_yaml::to_string(&PostInstall::WaitEnter).unwrap(), "wait_enter\n");
+    }
+
+    #[test]
+    fn warning_action_runs_only_on_status_3() {
+        let post_install = PostInstall::Conditional { action: PostInstallAction::Interrupt, on: PostInstallOn::Warning };
+        let outcome = |status_code| post_install.exec(&lwc!("my_mod"), InstallStatus::from_status_code(status_code));
+
+        assert_eq!(outcome(Some(3)), PostInstallOutcome::Stop);
+        assert_eq!(outcome(Some(0)), PostInstallOutcome::Continue);
+        // dry run
+        assert_eq!(outcome(None), PostInstallOutcome::Continue);
+    }
+
+    #[test]
+    fn success_action_is_skipped_on_warnings() {
+        let post_install = PostInstall::WaitSeconds { wait_seconds: 0, on: Some(PostInstallOn::Success) };
+        assert_eq!(post_install.exec(&lwc!("my_mod"), InstallStatus::Warning), PostInstallOutcome::Continue);
+
+        let always = PostInstall::Conditional { action: PostInstallAction::Interrupt, on: PostInstallOn::Always };
+        assert_eq!(always.exec(&lwc!("my_mod"), InstallStatus::Success), PostInstallOutcome::Stop);
+        assert_eq!(always.exec(&lwc!("my_mod"), InstallStatus::Warning), PostInstallOutcome::Stop);
+    }
+}
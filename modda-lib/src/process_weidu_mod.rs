@@ -1,33 +1,104 @@
 
 use std::io::BufWriter;
-use std::io::Write;
+use std::io::{IsTerminal, Write};
 use std::path::Path;
 
 
 use nu_ansi_term::Color;
 use nu_ansi_term::Color::{Green, Red, Yellow};
 use anyhow::Result;
+use itertools::Itertools;
 use anyhow::anyhow;
 use anyhow::bail;
 use chrono::Local;
-use log::info;
+use log::{info, warn};
 
+use crate::file_ledger::FileLedger;
+use crate::install_events::{EventSender, InstallEvent};
+use crate::lowercase::LwcString;
 use crate::module::manifest::Manifest;
 use crate::obtain::get_options::GetOptions;
-use crate::timeline::InstallTimeline;
+use crate::post_install::InstallStatus;
+use crate::timeline::{read_weidu_install_time, InstallTimeline};
 use crate::timeline::SetupTimeline;
+use crate::module::components::Components;
 use crate::module::gen_mod::GeneratedMod;
 use crate::module::module_conf::ModuleContent;
 use crate::module::weidu_mod::WeiduMod;
-use crate::run_weidu::format_install_result;
+use crate::run_weidu::{check_weidu_effect, collect_debug_log, format_install_result, installed_components, read_debug_log_since};
 use crate::tp2::find_tp2;
 use crate::tp2_template::create_tp2;
-use crate::run_weidu::run_weidu_install;
+use crate::tra_encoding::check_tra_encoding;
+use crate::weidu_warnings::{extract_warnings, unmatched_warnings};
+use crate::run_result::RunResult;
+use crate::run_weidu::run_weidu_install_with_retry;
 use crate::modda_context::ModdaContext;
 
+/// Warns when the tra files don't match the declared `encoding` (does nothing if there is none).
+fn check_mod_encoding(weidu_mod: &WeiduMod, tp2: &Path) -> Result<()> {
+    let encoding = match weidu_mod.encoding {
+        None => return Ok(()),
+        Some(encoding) => encoding,
+    };
+    let parent = match tp2.parent() {
+        None => return Ok(()),
+        Some(parent) => parent,
+    };
+    // tp2 is either in the mod directory or next to it (`setup-<mod>.tp2`)
+    let stem = tp2.file_stem().unwrap_or_default().to_string_lossy();
+    let mod_dir = match stem.get(..6) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("setup-") => parent.join(&stem[6..]),
+        _ => parent.to_path_buf(),
+    };
+    for mismatch in check_tra_encoding(&mod_dir, encoding)? {
+        warn!("{}", Yellow.bold().paint(format!(
+            "module {name}: {file:?} doesn't look like {encoding:?} ({ratio:.1}% of invalid characters), it may be {suggestion}",
+            name = weidu_mod.name, file = mismatch.file, encoding = encoding,
+            ratio = mismatch.replacement_ratio * 100.0, suggestion = mismatch.suggestion(),
+        )));
+    }
+    Ok(())
+}
+
 pub struct ProcessResult {
     pub stop: bool,
     pub timeline: InstallTimeline,
+    /// Decides which post-install actions are done
+    pub status: InstallStatus,
+    /// Set when the module was not installed
+    pub skipped: Option<SkipReason>,
+}
+
+/// Why a module of the manifest was not installed, reported in the installation summary.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SkipReason {
+    /// The `disabled_if` condition is true (with the explanation)
+    Disabled(String),
+    /// Named in `--skip` or in the `skip` list of the manifest `global`
+    Requested,
+    /// Not selected by an install option (`--phase`, `--reinstall`)
+    Filtered(String),
+    /// Outside the range given with `--from-index`, `--to-index`, `--just-one` or `--count`
+    OutOfRange,
+}
+
+impl SkipReason {
+    pub fn describe(&self) -> String {
+        match self {
+            SkipReason::Disabled(reason) => format!("disabled - {}", reason),
+            SkipReason::Requested => "skipped (requested)".to_string(),
+            SkipReason::Filtered(option) => format!("filtered out by {}", option),
+            SkipReason::OutOfRange => "out of range".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SkippedModule {
+    /// index in the manifest, counting from one
+    pub index: usize,
+    pub name: LwcString,
+    pub reason: SkipReason,
 }
 
 pub fn process_weidu_mod(weidu_mod: &WeiduMod, modda_context: &ModdaContext, manifest: &Manifest,
@@ -48,10 +119,15 @@ pub fn process_weidu_mod(weidu_mod: &WeiduMod, modda_context: &ModdaContext, man
                     let message = format!("module {name} (index={idx}/{len}) download/installation failed, stopping.",
                                                     name = weidu_mod.name, idx = real_index, len = mod_count);
                     modda_context.log(&message)?;
+                    // keeps the details (captured output of a failed precopy command for example) in the modda log
+                    modda_context.log(&format!("{:?}", error))?;
                     info!("{}", Red.bold().paint(message));
                     return Err(error)
                 }
                 Ok(setup_log) => {
+                    if let Some(output) = &setup_log.precopy_output {
+                        modda_context.log(&format!("module {name} precopy command output:{output}", name = weidu_mod.name))?;
+                    }
                     configure_module(weidu_mod)?;
                     SetupTimeline {
                         configured: Some(Local::now()),
@@ -74,6 +150,9 @@ pub fn process_weidu_mod(weidu_mod: &WeiduMod, modda_context: &ModdaContext, man
             }
         }
     };
+    if opts.check_encoding {
+        check_mod_encoding(weidu_mod, &tp2)?;
+    }
     let tp2_string = match tp2.into_os_string().into_string() {
         Ok(string) => string,
         Err(os_string) => {
@@ -83,25 +162,64 @@ pub fn process_weidu_mod(weidu_mod: &WeiduMod, modda_context: &ModdaContext, man
         }
     };
 
+    // weidu appends to its debug log, only what is added by this run is looked at
+    let debug_log_name = modda_context.next_debug_log_name(&weidu_mod.name);
+    let debug_log = current.join_path(&debug_log_name);
+    let debug_log_start = std::fs::metadata(&debug_log).map(|metadata| metadata.len()).unwrap_or(0);
+    // weidu doesn't create the directories of its debug log
+    if let Some(debug_log_dir) = debug_log.parent() {
+        if let Err(error) = std::fs::create_dir_all(debug_log_dir) {
+            bail!("Could not create debug log directory {:?}\n -> {:?}", debug_log_dir, error);
+        }
+    }
+
+    // `components: none` doesn't run weidu
+    let log_before = if opts.reinstall.is_empty() && weidu_mod.components != Components::None {
+        Some(installed_components(current, &weidu_mod.name)?)
+    } else {
+        None
+    };
     install_timeline.start_install = Some(Local::now());
-    let single_result = run_weidu_install(&tp2_string, weidu_mod, &opts, &manifest.global, &modda_context.as_weidu_context())?;
+    let single_result = run_with_events(&modda_context.events, &weidu_mod.name, || {
+        run_weidu_install_with_retry(&tp2_string, weidu_mod, &opts, &manifest.global,
+                                        &modda_context.as_weidu_context().with_debug_log(&debug_log_name))
+    })?;
     install_timeline.installed = Some(Local::now());
+    install_timeline.weidu_reported = read_weidu_install_time(&debug_log, debug_log_start);
+    if let Some(gap) = install_timeline.install_stall() {
+        warn!("{}", Yellow.bold().paint(format!(
+            "module {name}: installation took {gap}s longer than reported by weidu, it was probably waiting for an answer",
+            name = weidu_mod.name, gap = gap.num_seconds(),
+        )));
+    }
 
     let run_result = format_install_result(&single_result, weidu_mod);
 
     modda_context.log_bytes(&run_result)?;
-    let must_stop = match single_result.status_code() {
-        Some(0) => {
+    let mut must_stop = match (&single_result, single_result.status_code()) {
+        (RunResult::TimedOut(_), _) => {
+            let message = format!("module {name} (index={idx}/{len}) timed out (weidu was killed), stopping.",
+                                    name = weidu_mod.name, idx = real_index, len = mod_count);
+            modda_context.log(&message)?;
+            info!("{}", Red.bold().paint(message));
+            true
+        }
+        (_, Some(0)) => {
             let message = format!("module {name} (index={index}/{len}) finished with success.",
                             name = weidu_mod.name, index = real_index, len = mod_count);
             modda_context.log(&message)?;
             info!("{}", Green.bold().paint(message));
             false
         }
-        Some(3) => {
+        (_, Some(3)) => {
             let mut finished = false;
             let (message, color) = if opts.no_stop_on_warn || weidu_mod.ignore_warnings {
                 ignore_warnings(weidu_mod, real_index, mod_count)
+            } else if !weidu_mod.allowed_warnings.is_empty() {
+                let (message, color, stop) =
+                    check_allowed_warnings(weidu_mod, &debug_log, debug_log_start, real_index, mod_count)?;
+                finished = stop;
+                (message, color)
             } else {
                 finished = true;
                 fail_warnings(weidu_mod, real_index, mod_count)
@@ -110,14 +228,14 @@ pub fn process_weidu_mod(weidu_mod: &WeiduMod, modda_context: &ModdaContext, man
             info!("{}", color.bold().paint(message));
             finished
         }
-        Some(value) => {
+        (_, Some(value)) => {
             let message = format!("module {name} (index={idx}/{len}) finished with error (status={status}), stopping.",
                                     name = weidu_mod.name, idx = real_index, len = mod_count, status = value);
             modda_context.log(&message)?;
             info!("{}", Red.bold().paint(message));
             true
         }
-        None => if !single_result.success() {
+        (_, None) => if !single_result.success() {
             let message = format!("module {name} (index={idx}/{len}) finished with success.",
                                     name = weidu_mod.name, idx = real_index, len = mod_count);
             modda_context.log(&message)?;
@@ -131,7 +249,31 @@ pub fn process_weidu_mod(weidu_mod: &WeiduMod, modda_context: &ModdaContext, man
             true
         }
     };
-    Ok(ProcessResult { stop: must_stop, timeline: install_timeline })
+    if let Some(output_dir) = &opts.output_dir {
+        if let Some(moved) = collect_debug_log(current, &debug_log_name, Path::new(output_dir))? {
+            info!("debug log moved to {:?}", moved);
+        }
+    }
+    if let (false, Some(before)) = (must_stop, log_before) {
+        match check_weidu_effect(current, &weidu_mod.name, &before) {
+            Err(error) if opts.strict_effect => {
+                let message = format!("module {name} (index={idx}/{len}) {error}, stopping (--strict-effect).",
+                                        name = weidu_mod.name, idx = real_index, len = mod_count, error = error);
+                modda_context.log(&message)?;
+                info!("{}", Red.bold().paint(message));
+                must_stop = true;
+            }
+            Err(error) => {
+                let message = format!("module {name} (index={idx}/{len}) {error}.",
+                                        name = weidu_mod.name, idx = real_index, len = mod_count, error = error);
+                modda_context.log(&message)?;
+                warn!("{}", Yellow.bold().paint(message));
+            }
+            Ok(()) => {}
+        }
+    }
+    let status = InstallStatus::from_status_code(single_result.status_code());
+    Ok(ProcessResult { stop: must_stop, timeline: install_timeline, status, skipped: None })
 }
 
 pub fn process_generated_mod(gen_mod: &GeneratedMod, modda_context: &ModdaContext,
@@ -147,13 +289,19 @@ pub fn process_generated_mod(gen_mod: &GeneratedMod, modda_context: &ModdaContex
         if let Err(err) = std::fs::create_dir(&data_dir) {
             bail!("Could not create data directory {:?} for generated mod '{}'\n  {}", data_dir, gen_mod.gen_mod, err);
         }
+        let overwrite = file_installer.overwrite_options(gen_mod.overwrite, gen_mod.allow_overwrite,
+                                                            gen_mod.backup_on_overwrite.as_deref())?;
         if let Err(err) = file_installer.copy_from_origins(&gen_mod.files.iter().collect::<Vec<_>>(),
-                                                                        &data_dir.path().to_path_buf(), gen_mod.allow_overwrite) {
+                                                                        &data_dir.path().to_path_buf(), &overwrite) {
             bail!("Could not copy files to target for generated mod {}\n  {}", gen_mod.gen_mod, err);
         }
         if let Err(err) = create_tp2(gen_mod, &mod_dir) {
             bail!("Could not generate tp2 file for {}\n  {}", gen_mod.gen_mod, err);
         }
+        // weidu doesn't know about these files, `reset` uses the ledger to remove them
+        let mut ledger = FileLedger::read(current)?;
+        ledger.record_dir(current, &gen_mod.gen_mod, mod_dir.path())?;
+        ledger.write(current)?;
     } else {
         info!("Skip generated mod creation (already present)");
     }
@@ -162,12 +310,43 @@ pub fn process_generated_mod(gen_mod: &GeneratedMod, modda_context: &ModdaContex
 }
 
 
+/// Runs weidu (`run`) between the `WeiduStarted` and `WeiduFinished` events (without status if weidu couldn't be run).
+fn run_with_events(events: &EventSender, mod_name: &LwcString,
+                    run: impl FnOnce() -> Result<RunResult>) -> Result<RunResult> {
+    events.emit(InstallEvent::WeiduStarted { mod_name: mod_name.clone() });
+    let result = run();
+    let status = result.as_ref().ok().and_then(RunResult::status_code);
+    events.emit(InstallEvent::WeiduFinished { mod_name: mod_name.clone(), status });
+    result
+}
+
 fn ignore_warnings(module: &WeiduMod, index: usize, total: usize) -> (String, Color) {
     let message = format!("module {modname} (index={idx}/{total}) finished with warning (status=3), ignoring as requested",
                                 modname =  module.name, idx = index, total = total);
     (message, Yellow)
 }
 
+/// Decides whether the installation proceeds, depending on whether the warnings of this run are all allowed.
+fn check_allowed_warnings(module: &WeiduMod, debug_log: &Path, debug_log_start: u64,
+                            index: usize, total: usize) -> Result<(String, Color, bool)> {
+    let warnings = extract_warnings(&read_debug_log_since(debug_log, debug_log_start).unwrap_or_default());
+    if warnings.is_empty() {
+        let message = format!("module {modname} (index={idx}/{total}) finished with warning (status=3), stopping because no warning was found in {log:?} to check against `allowed_warnings`",
+                                modname = module.name, idx = index, total = total, log = debug_log);
+        return Ok((message, Red, true));
+    }
+    let unmatched = unmatched_warnings(&warnings, &module.allowed_warnings)?;
+    if unmatched.is_empty() {
+        let message = format!("module {modname} (index={idx}/{total}) finished with warning (status=3), all warnings are allowed",
+                                modname = module.name, idx = index, total = total);
+        Ok((message, Yellow, false))
+    } else {
+        let message = format!("module {modname} (index={idx}/{total}) finished with warning (status=3), stopping on warnings not in `allowed_warnings`:\n  {list}",
+                                modname = module.name, idx = index, total = total, list = unmatched.iter().join("\n  "));
+        Ok((message, Red, true))
+    }
+}
+
 fn fail_warnings(module: &WeiduMod, index: usize, total: usize) -> (String, Color) {
     let message = format!("module {modname} (index={idx}/{total}) finished with warning (status=3), stopping as requested",
                                 modname =  module.name, idx = index, total = total);
@@ -176,6 +355,10 @@ fn fail_warnings(module: &WeiduMod, index: usize, total: usize) -> (String, Colo
 
 fn configure_module(module: &WeiduMod) -> Result<()> {
     if let Some(conf) = &module.add_conf {
+        // asked before the file is created, so that a failure doesn't leave an empty conf file
+        let content = conf_content(&conf.content, &module.name, std::io::stdin().is_terminal(), |prompt| {
+            Ok(dialoguer::Input::<String>::new().with_prompt(prompt).allow_empty(true).interact_text()?)
+        })?;
         let conf_path = Path::new(module.name.as_ref()).join(&conf.file_name);
         let file = match std::fs::OpenOptions::new()
                         .create(true).write(true).truncate(true)
@@ -186,15 +369,155 @@ fn configure_module(module: &WeiduMod) -> Result<()> {
             Ok(file) => file,
         };
         let mut buffered = BufWriter::new(file);
-        let content = match &conf.content {
-            ModuleContent::Content { content } => content,
-            ModuleContent::Prompt { .. } => {
-                // print the prompt and read the content line
-                bail!("not implemented yet")
-            }
-        };
         write!(buffered, "{}", content)?;
         buffered.flush()?;
         Ok(())
     } else { Ok(()) }
 }
+
+/// Content of the `add_conf` file, read with `ask` (which shows the prompt and reads a line) for `prompt` content.<br>
+/// Fails when there is no terminal to answer the prompt (`interactive` is false) instead of waiting forever.
+fn conf_content(content: &ModuleContent, mod_name: &LwcString, interactive: bool,
+                ask: impl FnOnce(&str) -> Result<String>) -> Result<String> {
+    match content {
+        ModuleContent::Content { content } => Ok(content.to_owned()),
+        ModuleContent::Prompt { prompt } if !interactive =>
+            bail!("Mod {} asks for its configuration (add_conf prompt {:?}) but the input is not a terminal\n\
+                    Run the installation interactively or give the content in the manifest (`content` instead of `prompt`)",
+                    mod_name, prompt),
+        ModuleContent::Prompt { prompt } => ask(prompt),
+    }
+}
+
+#[cfg(test)]
+mod test_conf_content {
+    use crate::lowercase::lwc;
+    use crate::module::module_conf::ModuleContent;
+
+    use super::conf_content;
+
+    #[test]
+    fn given_content() {
+        let content = ModuleContent::Content { content: "key=value".to_string() };
+        let result = conf_content(&content, &lwc!("my_mod"), false, |_| panic!("not asked"));
+        assert_eq!(result.unwrap(), "key=value");
+    }
+
+    #[test]
+    fn prompted_content() {
+        let content = ModuleContent::Prompt { prompt: "Path of the original game".to_string() };
+        let result = conf_content(&content, &lwc!("my_mod"), true, |prompt| {
+            assert_eq!(prompt, "Path of the original game");
+            Ok("/games/bg1".to_string())
+        });
+        assert_eq!(result.unwrap(), "/games/bg1");
+    }
+
+    #[test]
+    fn prompt_without_terminal_fails() {
+        let content = ModuleContent::Prompt { prompt: "Path of the original game".to_string() };
+        let error = conf_content(&content, &lwc!("my_mod"), false, |_| panic!("not asked")).unwrap_err();
+        assert!(error.to_string().starts_with("Mod my_mod asks for its configuration"), "{}", error);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test_weidu_events {
+    use std::cell::RefCell;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    use anyhow::Result;
+
+    use crate::args::Install;
+    use crate::cache::Cache;
+    use crate::canon_path::CanonPath;
+    use crate::config::Config;
+    use crate::download::Downloader;
+    use crate::file_installer::FileInstaller;
+    use crate::global::Global;
+    use crate::install_events::{event_channel, InstallEvent};
+    use crate::lowercase::lwc;
+    use crate::modda_context::ModdaContext;
+    use crate::module::components::{Component, Components};
+    use crate::module::global_locations::GlobalLocations;
+    use crate::module::manifest::Manifest;
+    use crate::module::manifest_conditions::ManifestConditions;
+    use crate::module::module::Module;
+    use crate::module::weidu_mod::WeiduMod;
+    use crate::obtain::get_module::ModuleDownload;
+
+    use super::{process_weidu_mod, ProcessResult};
+
+    /// Installs `my_mod`, already in the game directory, with `weidu_path` as the weidu binary.
+    fn install_with_weidu(weidu_path: &Path) -> (Result<ProcessResult>, Vec<InstallEvent>) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let game_dir = temp_dir.path().join("game");
+        std::fs::create_dir_all(game_dir.join("my_mod")).unwrap();
+        std::fs::write(game_dir.join("my_mod/setup-my_mod.tp2"), "BACKUP ~weidu_external/backup/my_mod~\n").unwrap();
+        let game_dir = CanonPath::new(game_dir).unwrap();
+
+        let weidu_mod = WeiduMod {
+            name: lwc!("my_mod"),
+            language: Some(0),
+            components: Components::List(vec![Component::Simple(0)]),
+            ..WeiduMod::default()
+        };
+        let manifest = Manifest {
+            version: "1".to_string(),
+            global: Global::default(),
+            locations: GlobalLocations::default(),
+            manifest_conditions: ManifestConditions::default(),
+            modules: vec![Module::Mod { weidu_mod: weidu_mod.clone() }],
+        };
+        let config = Config {
+            weidu_path: Some(weidu_path.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let opts = Install::default();
+        let (events, mut receiver) = event_channel();
+        let downloader = Downloader::from_config(&config, events.clone());
+        let cache = Cache::Path(temp_dir.path().join("cache"));
+        let module_download = ModuleDownload::new(&config, &manifest.global, &manifest.locations, &opts,
+                                                    &downloader, &game_dir, &cache);
+        let file_installer = FileInstaller::new(&manifest.global, &opts, &game_dir);
+        let modda_context = ModdaContext { current_dir: &game_dir, config: &config, opts: &opts,
+                                            module_downloader: &module_download, file_installer: &file_installer,
+                                            log: RefCell::new(None), events, occurrences: RefCell::default() };
+
+        let result = process_weidu_mod(&weidu_mod, &modda_context, &manifest, 1);
+        let mut received = vec![];
+        while let Ok(event) = receiver.try_recv() {
+            received.push(event);
+        }
+        (result, received)
+    }
+
+    #[test]
+    fn weidu_events_around_the_installation() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let weidu = temp_dir.path().join("weidu-stub");
+        std::fs::write(&weidu, "#!/bin/sh\nexit 3\n").unwrap();
+        std::fs::set_permissions(&weidu, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let (result, events) = install_with_weidu(&weidu);
+        assert!(result.unwrap().stop);
+        assert_eq!(events, vec![
+            InstallEvent::WeiduStarted { mod_name: lwc!("my_mod") },
+            InstallEvent::WeiduFinished { mod_name: lwc!("my_mod"), status: Some(3) },
+        ]);
+    }
+
+    #[test]
+    fn weidu_finished_when_weidu_cannot_be_run() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let (result, events) = install_with_weidu(&temp_dir.path().join("missing-weidu"));
+        let error = result.err().unwrap();
+        assert_eq!(error.downcast_ref::<std::io::Error>().map(std::io::Error::kind), Some(std::io::ErrorKind::NotFound));
+        assert_eq!(events, vec![
+            InstallEvent::WeiduStarted { mod_name: lwc!("my_mod") },
+            InstallEvent::WeiduFinished { mod_name: lwc!("my_mod"), status: None },
+        ]);
+    }
+}
@@ -0,0 +1,145 @@
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::warn;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{RequestBuilder, Response, StatusCode};
+
+/// Waits longer than this are not done, the request fails instead.
+pub const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(300);
+/// Number of times a rate-limited request is retried.
+const MAX_RATE_LIMIT_RETRIES: usize = 3;
+/// Used when the server says the client is rate-limited but not for how long.
+const DEFAULT_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+const RATE_LIMIT_REMAINING: &str = "x-ratelimit-remaining";
+const RATE_LIMIT_RESET: &str = "x-ratelimit-reset";
+
+/// How long to wait before retrying if the response tells the client is rate-limited, `None` otherwise.<br>
+/// This is HTTP 429, or 403 with `X-RateLimit-Remaining: 0` (github). The wait comes from `Retry-After`
+/// (seconds or HTTP date) or `X-RateLimit-Reset` (unix time).
+pub fn rate_limit_wait(status: StatusCode, headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).map(|value| value.trim());
+    let limited = match status {
+        StatusCode::TOO_MANY_REQUESTS => true,
+        StatusCode::FORBIDDEN => header(RATE_LIMIT_REMAINING) == Some("0"),
+        _ => false,
+    };
+    if !limited {
+        return None;
+    }
+    let wait = header(RETRY_AFTER.as_str()).and_then(|value| parse_retry_after(value, now))
+        .or_else(|| header(RATE_LIMIT_RESET).and_then(|value| parse_rate_limit_reset(value, now)))
+        .unwrap_or(DEFAULT_RATE_LIMIT_WAIT);
+    Some(wait)
+}
+
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let date = UNIX_EPOCH.checked_add(Duration::from_secs(u64::try_from(date.timestamp()).ok()?))?;
+    Some(date.duration_since(now).unwrap_or_default())
+}
+
+fn parse_rate_limit_reset(value: &str, now: SystemTime) -> Option<Duration> {
+    let reset = UNIX_EPOCH.checked_add(Duration::from_secs(value.parse::<u64>().ok()?))?;
+    Some(reset.duration_since(now).unwrap_or_default())
+}
+
+/// Sends the request, waiting and retrying (a few times) when the server answers that the client is rate-limited.<br>
+/// The last response is returned as is, the caller handles the error status.
+pub async fn send_with_rate_limit(request: RequestBuilder) -> reqwest::Result<Response> {
+    let mut request = request;
+    let mut attempt = 0;
+    loop {
+        let retry = if attempt < MAX_RATE_LIMIT_RETRIES { request.try_clone() } else { None };
+        let response = request.send().await?;
+        let retry = match retry {
+            None => return Ok(response),
+            Some(retry) => retry,
+        };
+        match rate_limit_wait(response.status(), response.headers(), SystemTime::now()) {
+            None => return Ok(response),
+            Some(wait) if wait > MAX_RATE_LIMIT_WAIT => {
+                warn!("Rate-limited by {} (HTTP {}), the server asks to wait {}, more than the maximum of {}",
+                        response.url(), response.status(),
+                        humantime::format_duration(wait), humantime::format_duration(MAX_RATE_LIMIT_WAIT));
+                return Ok(response);
+            }
+            Some(wait) => {
+                warn!("Rate-limited by {} (HTTP {}), waiting {} before retrying",
+                        response.url(), response.status(), humantime::format_duration(wait));
+                tokio::time::sleep(wait).await;
+                request = retry;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_rate_limit {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use reqwest::StatusCode;
+
+    use super::{rate_limit_wait, DEFAULT_RATE_LIMIT_WAIT};
+
+    fn headers(entries: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in entries {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    // 2015-10-21T07:28:00Z
+    fn now() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1445412480)
+    }
+
+    #[test]
+    fn retry_after_in_seconds() {
+        let wait = rate_limit_wait(StatusCode::TOO_MANY_REQUESTS, &headers(&[("retry-after", "120")]), now());
+        assert_eq!(wait, Some(Duration::from_secs(120)));
+    }
+
+    #[test]
+    fn retry_after_as_http_date() {
+        let wait = rate_limit_wait(StatusCode::TOO_MANY_REQUESTS,
+                                    &headers(&[("retry-after", "Wed, 21 Oct 2015 07:29:30 GMT")]), now());
+        assert_eq!(wait, Some(Duration::from_secs(90)));
+
+        let past = rate_limit_wait(StatusCode::TOO_MANY_REQUESTS,
+                                    &headers(&[("retry-after", "Wed, 21 Oct 2015 07:00:00 GMT")]), now());
+        assert_eq!(past, Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn github_rate_limit_reset() {
+        let limited = headers(&[("x-ratelimit-remaining", "0"), ("x-ratelimit-reset", "1445412510")]);
+        assert_eq!(rate_limit_wait(StatusCode::FORBIDDEN, &limited, now()), Some(Duration::from_secs(30)));
+
+        // `Retry-After` wins
+        let both = headers(&[("retry-after", "5"), ("x-ratelimit-remaining", "0"), ("x-ratelimit-reset", "1445412510")]);
+        assert_eq!(rate_limit_wait(StatusCode::FORBIDDEN, &both, now()), Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn rate_limit_without_duration() {
+        assert_eq!(rate_limit_wait(StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new(), now()), Some(DEFAULT_RATE_LIMIT_WAIT));
+        let invalid = headers(&[("retry-after", "soon")]);
+        assert_eq!(rate_limit_wait(StatusCode::TOO_MANY_REQUESTS, &invalid, now()), Some(DEFAULT_RATE_LIMIT_WAIT));
+    }
+
+    #[test]
+    fn not_rate_limited() {
+        assert_eq!(rate_limit_wait(StatusCode::OK, &headers(&[("retry-after", "120")]), now()), None);
+        assert_eq!(rate_limit_wait(StatusCode::FORBIDDEN, &HeaderMap::new(), now()), None);
+        let remaining = headers(&[("x-ratelimit-remaining", "12"), ("x-ratelimit-reset", "1445412510")]);
+        assert_eq!(rate_limit_wait(StatusCode::FORBIDDEN, &remaining, now()), None);
+    }
+}
@@ -1,8 +1,10 @@
-
+use std::time::Duration;
 
 pub enum RunResult {
     Dry(String),
-    Real(std::process::Output)
+    Real(std::process::Output),
+    /// weidu was killed after running longer than `weidu_timeout_seconds`
+    TimedOut(Duration),
 }
 
 impl RunResult {
@@ -10,12 +12,14 @@ impl RunResult {
         match self {
             RunResult::Dry(_) => Some(0),
             RunResult::Real(output) => output.status.code(),
+            RunResult::TimedOut(_) => None,
         }
     }
     pub fn success(&self) -> bool {
         match self {
             RunResult::Dry(_) => true,
             RunResult::Real(output) => output.status.success(),
+            RunResult::TimedOut(_) => false,
         }
     }
 }
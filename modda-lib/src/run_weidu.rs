@@ -1,21 +1,28 @@
 
-use std::process::{Command, Stdio};
+use std::io::Write;
+use std::path::{Component as PathComponent, Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, Result};
-use chrono::Utc;
+use chrono::{DateTime, Local, Utc};
+use itertools::Itertools;
 use lazy_static::lazy_static;
 use log::{debug, info, warn};
 use regex::Regex;
 
-use crate::args::{Install, Reset};
+use crate::args::Install;
 use crate::canon_path::CanonPath;
 use crate::global::Global;
 use crate::modda_context::WeiduContext;
 use crate::module::language::{LanguageOption, LanguageSelection, select_language};
-use crate::module::components::{Component, Components};
+use crate::log_parser::{parse_weidu_log_file, read_weidu_log_comments, restore_weidu_log_comments};
+use crate::module::components::{Component, Components, FullComponent};
 use crate::lowercase::LwcString;
 use crate::module::weidu_mod::{WeiduMod, BareMod};
 use crate::run_result::RunResult;
+use crate::tp2::find_tp2_str;
+use crate::utils::command_path::find_command;
 
 #[cfg(target_os="windows")]
 const WEIDU_BIN: &str = "weidu.exe";
@@ -23,27 +30,180 @@ const WEIDU_BIN: &str = "weidu.exe";
 #[cfg(not(target_os="windows"))]
 const WEIDU_BIN: &str = "weidu";
 
-pub fn run_weidu_install(tp2: &str, module: &WeiduMod, opts: &Install, global: &Global,
-                        weidu_context: &WeiduContext) -> Result<RunResult> {
+/// How weidu is run for a mod, decided from its components.
+#[derive(Debug, PartialEq)]
+pub enum InstallMode<'a> {
+    /// `components: none`, weidu is not run
+    NoComponents,
+    /// weidu asks which components to install
+    Interactive,
+    /// `--interactive-select`: the components are picked in a list
+    Selected,
+    /// All the components of the mod, as listed by weidu
+    All,
+    /// The components of the manifest
+    List(&'a [Component]),
+}
+
+pub fn install_mode<'a>(components: &'a Components, opts: &Install) -> Result<InstallMode<'a>> {
+    if let Err(error) = components.check_file_resolved() {
+        bail!("Could not select the components to install\n -> {:?}", error);
+    }
+    Ok(match components {
+        Components::None => InstallMode::NoComponents,
+        Components::Ask if opts.interactive_select => InstallMode::Selected,
+        Components::Ask => InstallMode::Interactive,
+        Components::All => InstallMode::All,
+        Components::List(comp) if comp.is_empty() && opts.interactive_select => InstallMode::Selected,
+        Components::List(comp) if comp.is_empty() => InstallMode::Interactive,
+        Components::FromFile(_) => unreachable!("components file checked above"),
+        Components::List(components) => InstallMode::List(components),
+    })
+}
+
+/// Language the mod is installed with: its `language`, or the first of `lang_preferences` that the mod provides.
+pub fn install_language(tp2: &str, module: &WeiduMod, global: &Global, weidu_context: &WeiduContext) -> Result<u32> {
     use LanguageSelection::*;
-    let language_id = match select_language(tp2, module, &global.lang_preferences, weidu_context) {
-        Ok(Selected(id)) => id,
-        Ok(NoMatch(list)) if list.is_empty() => 0,
+    match select_language(tp2, module, &global.lang_preferences, weidu_context) {
+        Ok(Selected(id)) => Ok(id),
+        Ok(NoMatch(list)) if list.is_empty() => Ok(0),
         Ok(NoPrefSet(available))
-        | Ok(NoMatch(available)) => handle_no_language_selected(available, module, global)?,
-        Err(err) => return Err(err),
+        | Ok(NoMatch(available)) => handle_no_language_selected(available, module, global),
+        Err(err) => Err(err),
+    }
+}
+
+pub fn run_weidu_install(tp2: &str, module: &WeiduMod, opts: &Install, global: &Global,
+                        weidu_context: &WeiduContext) -> Result<RunResult> {
+    let language_id = install_language(tp2, module, global, weidu_context)?;
+    match install_mode(&module.components, opts)? {
+        InstallMode::NoComponents => Ok(RunResult::Dry("Explicitly requested no components to be installed".to_string())),
+        InstallMode::Selected =>
+                run_weidu_install_selected(tp2, module, opts, global, language_id, weidu_context),
+        InstallMode::Interactive =>
+                run_weidu_install_interactive(tp2, module, global, weidu_context),
+        InstallMode::All =>
+                run_weidu_install_all(tp2, module, opts, global, language_id, weidu_context),
+        InstallMode::List(components) => {
+            let components = resolve_components(tp2, components, language_id, weidu_context)?;
+            run_weidu_install_auto(tp2, module, &components, opts, global, language_id, weidu_context)
+        }
+    }
+}
+
+/// What `run_weidu_install` would run for the mod (`install --dry-run`), one line per weidu command.<br>
+/// weidu is only run to list the languages of the mod, when its `language` is not set. The mod must be in the
+/// game directory: `tp2` is `None` when it is not obtained yet, the command line is then not known.
+pub fn weidu_install_plan(tp2: Option<&str>, module: &WeiduMod, opts: &Install, global: &Global,
+                            weidu_context: &WeiduContext) -> Result<Vec<String>> {
+    Ok(match (install_mode(&module.components, opts)?, tp2) {
+        (InstallMode::NoComponents, _) => vec!["weidu is not run (no components)".to_string()],
+        (_, None) => vec![format!("weidu: components {} (the command line is known once the mod is obtained)",
+                                    module.components.describe())],
+        (InstallMode::Interactive, Some(tp2)) =>
+            vec![format!("weidu asks for the components: {:?}", interactive_command(tp2, module, global, weidu_context)?)],
+        (InstallMode::Selected, Some(_)) =>
+            vec!["components picked in a list (--interactive-select), then installed by weidu".to_string()],
+        (InstallMode::All, Some(_)) => vec!["weidu installs all the components, as listed by weidu".to_string()],
+        (InstallMode::List(components), Some(tp2)) => {
+            let language_id = install_language(tp2, module, global, weidu_context)?;
+            let mut lines = vec![];
+            if components.iter().any(|component| matches!(component, Component::Group(_))) {
+                lines.push("component groups are resolved with the component list of weidu".to_string());
+            }
+            for (language, indexes) in language_runs(components, language_id) {
+                let command = install_list_command(tp2, module, &indexes, global, language, weidu_context)?;
+                lines.push(format!("weidu: {:?}", command));
+            }
+            lines
+        }
+    })
+}
+
+/// Runs weidu like `run_weidu_install`, then again (up to `retry_on_failure` times) while it fails.<br>
+/// Warnings (exit code 3) are not failures, they are handled by the caller. Before each new attempt, the
+/// components that the failed attempt left in `weidu.log` are uninstalled.
+pub fn run_weidu_install_with_retry(tp2: &str, module: &WeiduMod, opts: &Install, global: &Global,
+                                    weidu_context: &WeiduContext) -> Result<RunResult> {
+    let mut attempt = 0;
+    loop {
+        let before = installed_components(weidu_context.current_dir, &module.name)?;
+        let result = run_weidu_install(tp2, module, opts, global, weidu_context)?;
+        let failed = !matches!(result.status_code(), Some(0) | Some(3));
+        // a hung weidu would most likely hang again
+        if !failed || attempt >= module.retry_on_failure || matches!(result, RunResult::TimedOut(_)) {
+            return Ok(result);
+        }
+        attempt += 1;
+        warn!("module {} failed (status={:?}), retrying ({}/{})",
+                module.name, result.status_code(), attempt, module.retry_on_failure);
+        let partial = installed_components(weidu_context.current_dir, &module.name)?.into_iter()
+            .filter(|component| !before.iter().any(|previous| previous.index == component.index))
+            .collect::<Vec<_>>();
+        if !partial.is_empty() {
+            let bare_mod = BareMod { name: module.name.to_owned(), components: partial, language: 0 };
+            info!("uninstalling partially installed {}", bare_mod.short());
+            uninstall_keeping_comments(tp2, &bare_mod, false, weidu_context)?;
+        }
+    }
+}
+
+/// Components of the mod in the `weidu.log` of the game directory.
+pub fn installed_components(game_dir: &CanonPath, mod_name: &LwcString) -> Result<Vec<FullComponent>> {
+    let rows = parse_weidu_log_file(&game_dir.join_path("weidu.log"), Some(mod_name))?;
+    Ok(rows.into_iter()
+        .map(|row| FullComponent { index: row.component_index, component_name: row.component_name, language: None })
+        .collect())
+}
+
+/// Fails when weidu reported a success but didn't add any component of the mod to `weidu.log`
+/// (compared to `before`, read before running weidu), for example when all components were skipped.
+pub fn check_weidu_effect(game_dir: &CanonPath, mod_name: &LwcString, before: &[FullComponent]) -> Result<()> {
+    let added = installed_components(game_dir, mod_name)?.into_iter()
+        .filter(|component| !before.iter().any(|previous| previous.index == component.index))
+        .count();
+    if added == 0 {
+        bail!("weidu ended with success but no component of {} was added to weidu.log", mod_name);
+    }
+    Ok(())
+}
+
+/// Replaces symbolic components (groups) with the matching component numbers.
+fn resolve_components(tp2: &str, components: &[Component], language_id: u32,
+                        weidu_context: &WeiduContext) -> Result<Vec<Component>> {
+    if !components.iter().any(|component| matches!(component, Component::Group(_))) {
+        return Ok(components.to_vec());
+    }
+    let available = match run_weidu_list_components(tp2, language_id, weidu_context) {
+        Err(error) => bail!("Could not get component list to resolve component groups\n{error}"),
+        Ok(list) => list,
     };
-    match &module.components {
-        Components::None => Ok(RunResult::Dry("Explicitly requested no components to be installed".to_string())),
-        Components::Ask =>
-                run_weidu_install_interactive(tp2, module, opts, &global.game_language, weidu_context),
-        Components::All =>
-                run_weidu_install_all(tp2, module, opts, &global.game_language, language_id, weidu_context),
-        Components::List(comp) if comp.is_empty() =>
-                run_weidu_install_interactive(tp2, module, opts, &global.game_language, weidu_context),
-        Components::List(components) =>
-                run_weidu_install_auto(tp2, module, components, opts, &global.game_language, language_id, weidu_context),
+    let mut result = vec![];
+    for component in components {
+        match component {
+            Component::Group(group) => {
+                let numbers = group.resolve(&available)?;
+                info!("component group {:?} resolved to {:?}", group, numbers);
+                result.extend(numbers.into_iter().map(Component::Simple));
+            }
+            other => result.push(other.clone()),
+        }
     }
+    Ok(result)
+}
+
+/// Component numbers of a component list, with the groups resolved.<br>
+/// Groups can only be resolved when the mod is in the game directory (weidu lists its components).
+pub fn component_indexes(module: &WeiduMod, components: &[Component], global: &Global,
+                            weidu_context: &WeiduContext) -> Result<Vec<u32>> {
+    let components = if components.iter().any(|component| matches!(component, Component::Group(_))) {
+        let tp2 = find_tp2_str(weidu_context.current_dir, &module.name)?;
+        let language_id = install_language(&tp2, module, global, weidu_context)?;
+        resolve_components(&tp2, components, language_id, weidu_context)?
+    } else {
+        components.to_vec()
+    };
+    Ok(components.iter().filter_map(|component| component.index()).collect())
 }
 
 fn handle_no_language_selected(available: Vec<LanguageOption>, module: &WeiduMod, global: &Global) -> Result<u32> {
@@ -55,65 +215,255 @@ fn handle_no_language_selected(available: Vec<LanguageOption>, module: &WeiduMod
         module.name, &global.lang_preferences, available);
 }
 
+/// Debug log written by weidu when installing a mod (relative to the game directory), when `weidu_debug_log`
+/// is not configured.
+pub fn weidu_debug_log_name(mod_name: &LwcString) -> String {
+    format!("setup-{}.debug", mod_name)
+}
+
+/// Debug log name from the `weidu_debug_log` pattern (see `Config`), the default one if there is no pattern.
+pub fn debug_log_name_from_pattern(pattern: Option<&str>, mod_name: &LwcString, occurrence: usize,
+                                    timestamp: DateTime<Local>) -> String {
+    match pattern {
+        None => weidu_debug_log_name(mod_name),
+        Some(pattern) => pattern
+            .replace("${mod}", mod_name.as_ref())
+            .replace("${occurrence}", &occurrence.to_string())
+            .replace("${timestamp}", &timestamp.format("%Y%m%d-%H%M%S").to_string()),
+    }
+}
+
+/// Fails if the `weidu_debug_log` pattern would put debug logs outside of the game directory.
+pub fn check_debug_log_pattern(pattern: &str) -> Result<()> {
+    let inside = Path::new(pattern).components()
+        .all(|component| matches!(component, PathComponent::Normal(_) | PathComponent::CurDir));
+    if !inside {
+        bail!("weidu_debug_log {} must be a path relative to the game directory, without `..`", pattern);
+    }
+    Ok(())
+}
+
+/// Moves the debug log of the mod from the game directory to `output_dir` (appended to the one already
+/// there, like weidu does with `--logapp`).<br>
+/// Returns where the log was moved, or `None` if weidu didn't write one.
+pub fn collect_debug_log(game_dir: &CanonPath, name: &str, output_dir: &Path) -> Result<Option<PathBuf>> {
+    let debug_log = game_dir.join_path(name);
+    if !debug_log.exists() {
+        return Ok(None);
+    }
+    let destination = output_dir.join(name);
+    // the debug log name can have sub-directories
+    let destination_dir = destination.parent().unwrap_or(output_dir);
+    if let Err(error) = std::fs::create_dir_all(destination_dir) {
+        bail!("Could not create log output directory {:?}\n -> {:?}", destination_dir, error);
+    }
+    let content = match std::fs::read(&debug_log) {
+        Ok(content) => content,
+        Err(error) => bail!("Could not read debug log {:?}\n -> {:?}", debug_log, error),
+    };
+    let mut output = match std::fs::OpenOptions::new().create(true).append(true).open(&destination) {
+        Ok(output) => output,
+        Err(error) => bail!("Could not open {:?}\n -> {:?}", destination, error),
+    };
+    if let Err(error) = output.write_all(&content) {
+        bail!("Could not write debug log to {:?}\n -> {:?}", destination, error);
+    }
+    if let Err(error) = std::fs::remove_file(&debug_log) {
+        bail!("Could not remove debug log {:?} after copying it\n -> {:?}", debug_log, error);
+    }
+    Ok(Some(destination))
+}
+
+/// Content added to a weidu debug log after `offset` (weidu appends to it, each run adds to it).
+pub fn read_debug_log_since(debug_log: &Path, offset: u64) -> Option<String> {
+    let content = std::fs::read(debug_log).ok()?;
+    let added = content.get(offset as usize..).unwrap_or(&content);
+    Some(String::from_utf8_lossy(added).into_owned())
+}
+
+/// Components with a `language` override are installed by separate weidu runs, consecutive components with
+/// the same language (in manifest order) share a run.
 fn run_weidu_install_auto(tp2: &str, module: &WeiduMod, components: &[Component], opts: &Install,
-                    game_lang: &str, language_id: u32, weidu_context: &WeiduContext) -> Result<RunResult> {
+                    global: &Global, language_id: u32, weidu_context: &WeiduContext) -> Result<RunResult> {
+
+    let components_max = opts.components_max.or(weidu_context.config.components_max);
+    check_components_max(&module.name, components.len(), components_max, opts.assume_yes, |prompt|
+        Ok(dialoguer::Confirm::new().with_prompt(prompt).interact()?)
+    )?;
+
+    let mut warning = None;
+    let mut last = None;
+    for (language, indexes) in language_runs(components, language_id) {
+        let command = install_list_command(tp2, module, &indexes, global, language, weidu_context)?;
+        let result = run_with_timeout(command, weidu_context.config.weidu_timeout_seconds.map(Duration::from_secs))?;
+        match result.status_code() {
+            Some(0) => last = Some(result),
+            // the remaining components are still installed, the warning is reported at the end
+            Some(3) => warning = warning.or(Some(result)),
+            _ => return Ok(result),
+        }
+    }
+    match warning.or(last) {
+        Some(result) => Ok(result),
+        None => Ok(RunResult::Dry("No component to install".to_string())),
+    }
+}
+
+/// Components installed by each weidu run, with the language of the run (see `run_weidu_install_auto`).
+fn language_runs(components: &[Component], language_id: u32) -> Vec<(u32, Vec<u32>)> {
+    components.iter()
+        .chunk_by(|component| component.language().unwrap_or(language_id))
+        .into_iter()
+        .map(|(language, group)| (language, group.filter_map(|component| component.index()).collect()))
+        .collect()
+}
 
+fn install_list_command(tp2: &str, module: &WeiduMod, indexes: &[u32], global: &Global,
+                    language_id: u32, weidu_context: &WeiduContext) -> Result<Command> {
     let mut command = Command::new(weidu_command(weidu_context)?);
     let mut args = vec![
         tp2.to_owned(),
         "--no-exit-pause".to_owned(),
         "--skip-at-view".to_owned(),
         "--log".to_owned(),    // Log output and details to X.
-        format!("setup-{}.debug", module.name),
+        weidu_context.debug_log_name(&module.name),
         "--logapp".to_owned(), // Append to log file instead of overwriting it.
         "--use-lang".to_owned(),
-        game_lang.to_owned(),
+        global.game_language.to_owned(),
         "--language".to_owned(),
         language_id.to_string(),
     ];
+    args.extend(tlk_args(global, weidu_context.current_dir)?);
     // component list
     args.push("--force-install-list".to_owned());
-    args.extend(components.iter().map(|id| id.index().to_string()));
+    args.extend(indexes.iter().map(|index| index.to_string()));
 
     command.args(&args)
+        .envs(mod_env(module)?)
         .stdin(Stdio::inherit())
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit());
-    if opts.dry_run {
-        println!("would execute {:?}", command);
-        Ok(RunResult::Dry(format!("{:?}", command)))
+    Ok(command)
+}
+
+/// Runs weidu, killing it (with the processes it started) if it is still running after `timeout`.<br>
+/// With a timeout, weidu is started in its own process group (on unix), so that it can be killed as a whole.
+/// This group is given the terminal while weidu runs, so weidu can still read it (and gets the interruptions).
+fn run_with_timeout(mut command: Command, timeout: Option<Duration>) -> Result<RunResult> {
+    let timeout = match timeout {
+        None => return Ok(RunResult::Real(command.output()?)),
+        Some(timeout) => timeout,
+    };
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let mut child = command.spawn()?;
+    let _terminal = ForegroundGroup::give_to(&child);
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(RunResult::Real(Output { status, stdout: vec![], stderr: vec![] }));
+        }
+        if start.elapsed() >= timeout {
+            warn!("weidu is still running after {}s, killing it", timeout.as_secs());
+            kill_process_group(&mut child)?;
+            child.wait()?;
+            return Ok(RunResult::TimedOut(timeout));
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[cfg(unix)]
+fn kill_process_group(child: &mut Child) -> Result<()> {
+    // the group id is the pid of weidu (see `process_group(0)`)
+    if unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) } == 0 {
+        Ok(())
     } else {
-        Ok(RunResult::Real(command.output()?))
+        Ok(child.kill()?)
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_process_group(child: &mut Child) -> Result<()> {
+    Ok(child.kill()?)
+}
+
+/// Makes the process group of weidu the foreground group of the terminal (if modda runs in one),
+/// gives the terminal back to modda when dropped.
+#[cfg(unix)]
+struct ForegroundGroup {
+    terminal: bool,
+}
+
+#[cfg(unix)]
+impl ForegroundGroup {
+    fn give_to(child: &Child) -> Self {
+        let group = child.id() as libc::pid_t;
+        let terminal = unsafe { libc::isatty(libc::STDIN_FILENO) == 1 && libc::tcsetpgrp(libc::STDIN_FILENO, group) == 0 };
+        if terminal {
+            // weidu was stopped if it read the terminal before getting it
+            unsafe { libc::kill(-group, libc::SIGCONT) };
+        }
+        ForegroundGroup { terminal }
     }
 }
 
-fn run_weidu_install_interactive(tp2: &str, module: &WeiduMod, opts: &Install,
-                            game_lang: &str, weidu_context: &WeiduContext) -> Result<RunResult> {
+#[cfg(unix)]
+impl Drop for ForegroundGroup {
+    fn drop(&mut self) {
+        if !self.terminal {
+            return;
+        }
+        // modda is in a background group now, taking the terminal back would stop it without ignoring SIGTTOU
+        unsafe {
+            let previous = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+            if libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpgrp()) != 0 {
+                warn!("could not take the terminal back from weidu");
+            }
+            libc::signal(libc::SIGTTOU, previous);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+struct ForegroundGroup;
+
+#[cfg(not(unix))]
+impl ForegroundGroup {
+    fn give_to(_child: &Child) -> Self {
+        ForegroundGroup
+    }
+}
+
+fn run_weidu_install_interactive(tp2: &str, module: &WeiduMod, global: &Global,
+                            weidu_context: &WeiduContext) -> Result<RunResult> {
+    let mut command = interactive_command(tp2, module, global, weidu_context)?;
+    Ok(RunResult::Real(command.output()?))
+}
+
+fn interactive_command(tp2: &str, module: &WeiduMod, global: &Global, weidu_context: &WeiduContext) -> Result<Command> {
     let mut command = Command::new(weidu_command(weidu_context)?);
-    let args = vec![
+    let mut args = vec![
         tp2.to_owned(),
         "--no-exit-pause".to_owned(),
         "--skip-at-view".to_owned(),
         "--log".to_owned(),    // Log output and details to X.
-        format!("setup-{}.debug", module.name),
+        weidu_context.debug_log_name(&module.name),
         "--logapp".to_owned(), // Append to log file instead of overwriting it.
         "--use-lang".to_owned(),
-        game_lang.to_owned(),
+        global.game_language.to_owned(),
     ];
+    args.extend(tlk_args(global, weidu_context.current_dir)?);
     command.args(&args)
+        .envs(mod_env(module)?)
         .stdin(Stdio::inherit())
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit());
-    if opts.dry_run {
-        println!("would execute {:?}", command);
-        Ok(RunResult::Dry(format!("{:?}", command)))
-    } else {
-        Ok(RunResult::Real(command.output()?))
-    }
+    Ok(command)
 }
 
 fn run_weidu_install_all(tp2: &str, module: &WeiduMod, opts: &Install,
-                    game_lang: &str, language_id: u32, weidu_context: &WeiduContext) -> Result<RunResult> {
+                    global: &Global, language_id: u32, weidu_context: &WeiduContext) -> Result<RunResult> {
     let list = match run_weidu_list_components(tp2, language_id, weidu_context) {
         Err(error) => bail!("Could not get component list for 'All' mod\n{error}"),
         Ok(list) => list,
@@ -121,7 +471,92 @@ fn run_weidu_install_all(tp2: &str, module: &WeiduMod, opts: &Install,
     let components = list.iter()
         .map(|weidu_comp| Component::Simple(weidu_comp.number))
         .collect::<Vec<_>>();
-    run_weidu_install_auto(tp2, module, &components, opts, game_lang, language_id, weidu_context)
+    run_weidu_install_auto(tp2, module, &components, opts, global, language_id, weidu_context)
+}
+
+/// `--interactive-select`: the components of an `ask` mod are picked in a list (instead of weidu's prompts),
+/// then installed like an explicit component list.
+fn run_weidu_install_selected(tp2: &str, module: &WeiduMod, opts: &Install,
+                    global: &Global, language_id: u32, weidu_context: &WeiduContext) -> Result<RunResult> {
+    let list = match run_weidu_list_components(tp2, language_id, weidu_context) {
+        Err(error) => bail!("Could not get component list for mod {}\n{error}", module.name),
+        Ok(list) => list,
+    };
+    let components = select_components(&list, |items| {
+        Ok(dialoguer::MultiSelect::new()
+            .with_prompt(format!("Components of {} to install (space to select, enter to validate)", module.name))
+            .items(items)
+            .interact()?)
+    })?;
+    if components.is_empty() {
+        return Ok(RunResult::Dry(format!("No component of {} was selected", module.name)));
+    }
+    run_weidu_install_auto(tp2, module, &components, opts, global, language_id, weidu_context)
+}
+
+/// Shows the available components with `pick` (which returns the positions of the chosen ones).
+fn select_components(available: &[WeiduComponent],
+                        pick: impl FnOnce(&[String]) -> Result<Vec<usize>>) -> Result<Vec<Component>> {
+    let items = available.iter()
+        .map(|comp| format!("{} - {}", comp.number, comp.name))
+        .collect::<Vec<_>>();
+    let chosen = pick(&items)?;
+    Ok(chosen.iter()
+        .filter_map(|position| available.get(*position))
+        .map(|comp| Component::Simple(comp.number))
+        .collect())
+}
+
+/// `--tlkin`/`--tlkout` (and the female equivalents) for the TLK files set in the manifest, which must exist
+/// in the game directory.
+fn tlk_args(global: &Global, game_dir: &CanonPath) -> Result<Vec<String>> {
+    let mut args = vec![];
+    for (tlk, option_in, option_out) in [(&global.tlk, "--tlkin", "--tlkout"), (&global.ftlk, "--ftlkin", "--ftlkout")] {
+        let tlk = match tlk {
+            None => continue,
+            Some(tlk) => tlk,
+        };
+        let path = match game_dir.safe_join(tlk) {
+            Ok(path) => path,
+            Err(error) => bail!("Invalid TLK path {}\n -> {:?}", tlk, error),
+        };
+        if !path.path().is_file() {
+            bail!("TLK file {} doesn't exist in the game directory", tlk);
+        }
+        args.extend([option_in.to_owned(), tlk.to_owned(), option_out.to_owned(), tlk.to_owned()]);
+    }
+    Ok(args)
+}
+
+/// Environment variables of the mod (`env`), with their values expanded.
+fn mod_env(module: &WeiduMod) -> Result<Vec<(String, String)>> {
+    let mut env = vec![];
+    for (name, value) in module.env.iter().flatten() {
+        match shellexpand::full(value) {
+            Err(error) => bail!("Expansion of environment variable {} of mod {} failed\n  {error}", name, module.name),
+            Ok(expanded) => env.push((name.to_owned(), expanded.to_string())),
+        }
+    }
+    Ok(env)
+}
+
+/// Asks for confirmation (with `confirm`) when the number of components exceeds the limit, unless `assume_yes` is set.
+fn check_components_max(mod_name: &LwcString, count: usize, max: Option<usize>, assume_yes: bool,
+                        confirm: impl FnOnce(String) -> Result<bool>) -> Result<()> {
+    let max = match max {
+        Some(max) if count > max => max,
+        _ => return Ok(()),
+    };
+    if assume_yes {
+        warn!("Installing {} components of mod {} (more than the {} limit)", count, mod_name, max);
+        return Ok(());
+    }
+    let prompt = format!("Mod {} will install {} components (limit is {}), proceed?", mod_name, count, max);
+    if confirm(prompt)? {
+        Ok(())
+    } else {
+        bail!("Aborted, mod {} has more components ({}) than the limit ({})", mod_name, count, max)
+    }
 }
 
 pub fn format_install_result(result: &RunResult, module: &WeiduMod) -> Vec<u8> {
@@ -138,10 +573,13 @@ pub fn format_install_result(result: &RunResult, module: &WeiduMod) -> Vec<u8> {
         RunResult::Dry(cmd) => {
             format!("dry-run: {}\n", cmd).into_bytes()
         }
+        RunResult::TimedOut(timeout) => {
+            format!("\n==\nmodule {} was killed after {}s (weidu_timeout_seconds)\n", module.name, timeout.as_secs()).into_bytes()
+        }
     }
 }
 
-#[derive(Debug, Clone, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct WeiduComponent {
     // apparition order in component list
     pub index: u32,
@@ -236,6 +674,32 @@ pub fn check_weidu_exe(weidu_context: &WeiduContext) -> Result<()> {
     }
 }
 
+/// The weidu executable that will be run: `weidu_path`, the weidu binary of the game directory or
+/// the one found on the `PATH` (the bare command name if there is none).
+pub fn weidu_executable(weidu_context: &WeiduContext) -> Result<String> {
+    let weidu = weidu_command(weidu_context)?;
+    Ok(match find_command(&weidu) {
+        Some(path) => path.to_string_lossy().to_string(),
+        None => weidu,
+    })
+}
+
+/// Returns the weidu executable that will be used, and the version it reports.
+pub fn weidu_version(weidu_context: &WeiduContext) -> Result<(String, String)> {
+    let weidu = weidu_command(weidu_context)?;
+    let mut command = Command::new(&weidu);
+    command.arg("--version");
+    let output = match command.output() {
+        Err(error) => bail!("Could not run weidu ({})\n  {:?}", weidu, error),
+        Ok(output) => output,
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout.lines().map(str::trim).find(|line| !line.is_empty()) {
+        None => bail!("weidu ({}) didn't report a version", weidu),
+        Some(version) => Ok((weidu.clone(), version.to_owned())),
+    }
+}
+
 fn weidu_command(weidu_context: &WeiduContext) -> Result<String> {
     match &weidu_context.config.weidu_path {
         Some(path) => {
@@ -279,7 +743,7 @@ fn fallback_weidu(game_loc: &CanonPath) -> Option<String> {
     }
 }
 
-pub fn run_weidu_uninstall(tp2: &str, module: &BareMod, opts: &Reset, weidu_context: &WeiduContext) -> Result<()> {
+pub fn run_weidu_uninstall(tp2: &str, module: &BareMod, dry_run: bool, weidu_context: &WeiduContext) -> Result<()> {
     let now = Utc::now().naive_local().format("%Y-%m-%d_%H:%M:%S");
 
     let mut command = Command::new(weidu_command(weidu_context)?);
@@ -302,7 +766,7 @@ pub fn run_weidu_uninstall(tp2: &str, module: &BareMod, opts: &Reset, weidu_cont
         .stderr(Stdio::inherit());
     debug!("uninstall command:\n{:?}", command);
 
-    if opts.dry_run {
+    if dry_run {
         println!("would execute {:?}", command);
         Ok(())
     } else {
@@ -311,10 +775,21 @@ pub fn run_weidu_uninstall(tp2: &str, module: &BareMod, opts: &Reset, weidu_cont
     }
 }
 
+/// Uninstalls with `run_weidu_uninstall`, then puts back the comments of `weidu.log` (weidu rewrites it when
+/// uninstalling and drops the comments that were added to it).
+pub fn uninstall_keeping_comments(tp2: &str, module: &BareMod, dry_run: bool, weidu_context: &WeiduContext) -> Result<()> {
+    let (comments, previous_order) = read_weidu_log_comments(weidu_context.current_dir)?;
+    run_weidu_uninstall(tp2, module, dry_run, weidu_context)?;
+    if !dry_run {
+        restore_weidu_log_comments(weidu_context.current_dir, &comments, &previous_order)?;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::modda_context::WeiduContext;
-    use crate::run_weidu::weidu_command;
+    use crate::run_weidu::{weidu_command, weidu_executable};
     use crate::config::Config;
     use super::WEIDU_BIN;
 
@@ -334,6 +809,7 @@ mod tests {
         let weidu_context = WeiduContext {
             config: &config,
             current_dir: &test_game_dir,
+            debug_log: None,
         };
 
         let temp_dir_path = temp_dir.as_ref();
@@ -362,6 +838,7 @@ mod tests {
         let weidu_context = WeiduContext {
             config: &config,
             current_dir: &test_game_dir,
+            debug_log: None,
         };
 
         let temp_dir_path = temp_dir.as_ref();
@@ -390,6 +867,7 @@ mod tests {
         let weidu_context = WeiduContext {
             config: &config,
             current_dir: &test_game_dir,
+            debug_log: None,
         };
 
         assert_eq!(
@@ -410,6 +888,7 @@ mod tests {
         let weidu_context = WeiduContext {
             config: &config,
             current_dir: &test_game_dir,
+            debug_log: None,
         };
 
         assert_eq!(
@@ -431,6 +910,7 @@ mod tests {
         let weidu_context = WeiduContext {
             config: &config,
             current_dir: &test_game_dir,
+            debug_log: None,
         };
 
         assert_eq!(
@@ -452,6 +932,7 @@ mod tests {
         let weidu_context = WeiduContext {
             config: &config,
             current_dir: &test_game_dir,
+            debug_log: None,
         };
 
         assert_eq!(
@@ -476,6 +957,7 @@ mod tests {
         let weidu_context = WeiduContext {
             config: &config,
             current_dir: &test_game_dir,
+            debug_log: None,
         };
 
         assert_eq!(
@@ -497,6 +979,7 @@ mod tests {
         let weidu_context = WeiduContext {
             config: &config,
             current_dir: &test_game_dir,
+            debug_log: None,
         };
 
         let user_dirs = directories::UserDirs::new().unwrap();
@@ -508,6 +991,492 @@ mod tests {
         )
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn uninstall_dry_run_doesnt_run_weidu() {
+        use std::os::unix::fs::PermissionsExt;
+
+        use crate::module::components::FullComponent;
+        use crate::module::weidu_mod::BareMod;
+        use crate::lowercase::lwc;
+        use super::run_weidu_uninstall;
+
+        let (temp_dir, test_game_dir) = setup_test_game_dir();
+        // the stub leaves a marker file if it is executed
+        let marker = temp_dir.path().join("weidu-was-run");
+        let weidu = temp_dir.path().join("weidu-stub");
+        std::fs::write(&weidu, format!("#!/bin/sh\ntouch '{}'\n", marker.to_string_lossy())).unwrap();
+        std::fs::set_permissions(&weidu, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let config = Config {
+            weidu_path: Some(weidu.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let weidu_context = WeiduContext {
+            config: &config,
+            current_dir: &test_game_dir,
+            debug_log: None,
+        };
+        let module = BareMod {
+            name: lwc!("my_mod"),
+            components: vec![FullComponent { index: 1, component_name: "My component".to_string(), language: None }],
+            language: 0,
+        };
+
+        run_weidu_uninstall("my_mod/setup-my_mod.tp2", &module, true, &weidu_context).unwrap();
+        assert!(!marker.exists());
+
+        run_weidu_uninstall("my_mod/setup-my_mod.tp2", &module, false, &weidu_context).unwrap();
+        assert!(marker.exists());
+    }
+
+    #[test]
+    fn components_max_exceeded_asks_confirmation() {
+        use std::cell::Cell;
+        use crate::lowercase::lwc;
+        use super::check_components_max;
+
+        let asked = Cell::new(false);
+        let refuse = |_: String| { asked.set(true); Ok(false) };
+        assert!(check_components_max(&lwc!("big_mod"), 150, Some(100), false, refuse).is_err());
+        assert!(asked.get());
+
+        let asked = Cell::new(false);
+        let accept = |prompt: String| { asked.set(true); assert!(prompt.contains("150 components")); Ok(true) };
+        assert!(check_components_max(&lwc!("big_mod"), 150, Some(100), false, accept).is_ok());
+        assert!(asked.get());
+    }
+
+    #[test]
+    fn select_components_from_list() {
+        use super::{select_components, WeiduComponent};
+        use crate::module::components::Component;
+
+        let available = [(0, "First"), (10, "Tenth"), (20, "Twentieth")].iter()
+            .enumerate()
+            .map(|(index, (number, name))| WeiduComponent {
+                index: index as u32, number: *number, forced: false, name: name.to_string(),
+                subgroup: None, group: vec![],
+            })
+            .collect::<Vec<_>>();
+
+        let selected = select_components(&available, |items| {
+            assert_eq!(items, ["0 - First", "10 - Tenth", "20 - Twentieth"]);
+            Ok(vec![2, 0])
+        }).unwrap();
+        assert_eq!(selected, vec![Component::Simple(20), Component::Simple(0)]);
+
+        assert!(select_components(&available, |_| Ok(vec![])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn components_max_not_exceeded_or_assumed() {
+        use crate::lowercase::lwc;
+        use super::check_components_max;
+
+        let never = |_: String| -> anyhow::Result<bool> { panic!("should not ask") };
+        assert!(check_components_max(&lwc!("big_mod"), 100, Some(100), false, never).is_ok());
+        assert!(check_components_max(&lwc!("big_mod"), 150, None, false, never).is_ok());
+        assert!(check_components_max(&lwc!("big_mod"), 150, Some(100), true, never).is_ok());
+    }
+
+    #[cfg(unix)]
+    fn retry_stub(temp_dir: &tempfile::TempDir, game_dir: &crate::canon_path::CanonPath) -> std::path::PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        // fails the first time after writing a component to weidu.log, then succeeds
+        let weidu = temp_dir.path().join("weidu-stub");
+        let calls = temp_dir.path().join("calls");
+        let weidu_log = game_dir.join_path("weidu.log");
+        std::fs::write(&weidu, format!(r#"#!/bin/sh
+echo "$*" >> '{calls}'
+case "$*" in
+    *--force-uninstall-list*) echo "// Log of Currently Installed WeiDU Mods" > '{log}'; exit 0;;
+esac
+if [ "$(grep -c -- --force-install-list '{calls}')" = "1" ]; then
+    echo "~MY_MOD/SETUP-MY_MOD.TP2~ #0 #1 // First component" >> '{log}'
+    exit 1
+fi
+exit 0
+"#, calls = calls.to_string_lossy(), log = weidu_log.to_string_lossy())).unwrap();
+        std::fs::set_permissions(&weidu, std::fs::Permissions::from_mode(0o755)).unwrap();
+        weidu
+    }
+
+    #[cfg(unix)]
+    fn run_with_retry(retry_on_failure: u32) -> (Option<i32>, Vec<String>) {
+        use crate::args::Install;
+        use crate::global::Global;
+        use crate::lowercase::lwc;
+        use crate::module::components::{Component, Components};
+        use crate::module::weidu_mod::WeiduMod;
+        use super::run_weidu_install_with_retry;
+
+        let (temp_dir, test_game_dir) = setup_test_game_dir();
+        let weidu = retry_stub(&temp_dir, &test_game_dir);
+        let config = Config {
+            weidu_path: Some(weidu.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let weidu_context = WeiduContext {
+            config: &config,
+            current_dir: &test_game_dir,
+            debug_log: None,
+        };
+        let module = WeiduMod {
+            name: lwc!("my_mod"),
+            language: Some(0),
+            components: Components::List(vec![Component::Simple(1)]),
+            retry_on_failure,
+            ..WeiduMod::default()
+        };
+
+        let result = run_weidu_install_with_retry("my_mod/setup-my_mod.tp2", &module, &Install::default(),
+                                                    &Global::default(), &weidu_context).unwrap();
+        let calls = std::fs::read_to_string(temp_dir.path().join("calls")).unwrap();
+        let calls = calls.lines().map(|call| {
+            if call.contains("--force-uninstall-list") { "uninstall" } else { "install" }
+        }).map(str::to_string).collect();
+        (result.status_code(), calls)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn retry_uninstalls_partial_components_then_succeeds() {
+        let (status, calls) = run_with_retry(2);
+        assert_eq!(status, Some(0));
+        assert_eq!(calls, vec!["install", "uninstall", "install"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn no_retry_by_default() {
+        let (status, calls) = run_with_retry(0);
+        assert_eq!(status, Some(1));
+        assert_eq!(calls, vec!["install"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn uninstall_restores_weidu_log_comments() {
+        use std::os::unix::fs::PermissionsExt;
+        use crate::lowercase::lwc;
+        use crate::module::components::FullComponent;
+        use crate::module::weidu_mod::BareMod;
+        use super::uninstall_keeping_comments;
+
+        let (temp_dir, test_game_dir) = setup_test_game_dir();
+        let weidu_log = test_game_dir.join_path("weidu.log");
+        std::fs::write(&weidu_log, "// Log of Currently Installed WeiDU Mods\n\
+                                    ~FIRST/SETUP-FIRST.TP2~ #0 #0 // First\n\
+                                    // installed from the first fragment\n\
+                                    ~MY_MOD/SETUP-MY_MOD.TP2~ #0 #1 // Component\n").unwrap();
+        // rewrites weidu.log without the comments, like weidu
+        let weidu = temp_dir.path().join("weidu-stub");
+        std::fs::write(&weidu, format!("#!/bin/sh\nprintf '%s\\n' '// Log of Currently Installed WeiDU Mods' \
+                                        '~FIRST/SETUP-FIRST.TP2~ #0 #0 // First' > '{}'\n", weidu_log.to_string_lossy())).unwrap();
+        std::fs::set_permissions(&weidu, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let config = Config {
+            weidu_path: Some(weidu.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let weidu_context = WeiduContext {
+            config: &config,
+            current_dir: &test_game_dir,
+            debug_log: None,
+        };
+        let bare_mod = BareMod {
+            name: lwc!("my_mod"),
+            components: vec![FullComponent { index: 1, component_name: String::new(), language: None }],
+            language: 0,
+        };
+
+        uninstall_keeping_comments("my_mod/setup-my_mod.tp2", &bare_mod, false, &weidu_context).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&weidu_log).unwrap(), "// Log of Currently Installed WeiDU Mods\n\
+                                                                ~FIRST/SETUP-FIRST.TP2~ #0 #0 // First\n\
+                                                                // installed from the first fragment\n");
+    }
+
+    #[cfg(unix)]
+    fn install_with_tlk(tlk: Option<&str>, ftlk: Option<&str>) -> anyhow::Result<String> {
+        use std::os::unix::fs::PermissionsExt;
+        use crate::args::Install;
+        use crate::global::Global;
+        use crate::lowercase::lwc;
+        use crate::module::components::{Component, Components};
+        use crate::module::weidu_mod::WeiduMod;
+        use super::run_weidu_install;
+
+        let (temp_dir, test_game_dir) = setup_test_game_dir();
+        std::fs::create_dir_all(test_game_dir.join_path("lang/en_us")).unwrap();
+        std::fs::write(test_game_dir.join_path("lang/en_us/dialog.tlk"), "").unwrap();
+        std::fs::write(test_game_dir.join_path("lang/en_us/dialogf.tlk"), "").unwrap();
+        let weidu = temp_dir.path().join("weidu-stub");
+        let calls = temp_dir.path().join("calls");
+        std::fs::write(&weidu, format!("#!/bin/sh\necho \"$*\" >> '{}'\n", calls.to_string_lossy())).unwrap();
+        std::fs::set_permissions(&weidu, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let config = Config {
+            weidu_path: Some(weidu.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let weidu_context = WeiduContext {
+            config: &config,
+            current_dir: &test_game_dir,
+            debug_log: None,
+        };
+        let module = WeiduMod {
+            name: lwc!("my_mod"),
+            language: Some(0),
+            components: Components::List(vec![Component::Simple(1)]),
+            ..WeiduMod::default()
+        };
+        let global = Global {
+            game_language: "en_US".to_string(),
+            tlk: tlk.map(str::to_string),
+            ftlk: ftlk.map(str::to_string),
+            ..Global::default()
+        };
+        run_weidu_install("my_mod/setup-my_mod.tp2", &module, &Install::default(), &global, &weidu_context)?;
+        Ok(std::fs::read_to_string(calls).unwrap())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn component_language_override_splits_weidu_runs() {
+        use std::os::unix::fs::PermissionsExt;
+        use crate::args::Install;
+        use crate::global::Global;
+        use crate::lowercase::lwc;
+        use crate::module::components::{Component, Components, FullComponent};
+        use crate::module::weidu_mod::WeiduMod;
+        use super::run_weidu_install;
+
+        let (temp_dir, test_game_dir) = setup_test_game_dir();
+        let weidu = temp_dir.path().join("weidu-stub");
+        let calls = temp_dir.path().join("calls");
+        std::fs::write(&weidu, format!("#!/bin/sh\necho \"$*\" >> '{}'\n", calls.to_string_lossy())).unwrap();
+        std::fs::set_permissions(&weidu, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let config = Config {
+            weidu_path: Some(weidu.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let weidu_context = WeiduContext {
+            config: &config,
+            current_dir: &test_game_dir,
+            debug_log: None,
+        };
+        let full = |index: u32, language: Option<u32>| Component::Full(FullComponent {
+            index, component_name: String::new(), language,
+        });
+        let module = WeiduMod {
+            name: lwc!("my_mod"),
+            language: Some(0),
+            components: Components::List(vec![
+                Component::Simple(0), full(1, Some(1)), full(2, Some(1)), full(3, None), full(4, Some(0)),
+            ]),
+            ..WeiduMod::default()
+        };
+        let global = Global { game_language: "en_US".to_string(), ..Global::default() };
+
+        let result = run_weidu_install("my_mod/setup-my_mod.tp2", &module, &Install::default(), &global, &weidu_context).unwrap();
+
+        assert_eq!(result.status_code(), Some(0));
+        let calls = std::fs::read_to_string(calls).unwrap();
+        let calls = calls.lines()
+            .map(|call| call.split_once("--language ").unwrap().1.replace(" --force-install-list", ""))
+            .collect::<Vec<_>>();
+        assert_eq!(calls, vec!["0 0", "1 1 2", "0 3 4"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hung_weidu_is_killed() {
+        use std::process::Command;
+        use std::time::{Duration, Instant};
+        use crate::run_result::RunResult;
+        use super::run_with_timeout;
+
+        let work_dir = tempfile::tempdir().unwrap();
+        let pid_file = work_dir.path().join("background.pid");
+        let mut command = Command::new("sh");
+        // like weidu running another program, which must be killed too
+        command.args(["-c", &format!("sleep 30 & echo $! > {}; sleep 30", pid_file.display())]);
+        let start = Instant::now();
+        let result = run_with_timeout(command, Some(Duration::from_millis(300))).unwrap();
+        assert!(matches!(result, RunResult::TimedOut(_)));
+        assert!(!result.success());
+        assert_eq!(result.status_code(), None);
+        assert!(start.elapsed() < Duration::from_secs(10));
+        let background = std::fs::read_to_string(&pid_file).unwrap().trim().to_string();
+        let running = || {
+            let ps = Command::new("ps").args(["-o", "stat=", "-p", &background]).output().unwrap();
+            // a killed process may stay a zombie when nothing reaps it
+            let state = String::from_utf8_lossy(&ps.stdout).trim().to_string();
+            !state.is_empty() && !state.starts_with('Z')
+        };
+        let killed_at = Instant::now();
+        while running() && killed_at.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        assert!(!running(), "process {} started by weidu is still running", background);
+
+        let mut command = Command::new("sh");
+        command.args(["-c", "exit 3"]);
+        let result = run_with_timeout(command, Some(Duration::from_secs(30))).unwrap();
+        assert_eq!(result.status_code(), Some(3));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mod_env_is_given_to_weidu() {
+        use std::collections::BTreeMap;
+        use std::os::unix::fs::PermissionsExt;
+        use crate::args::Install;
+        use crate::global::Global;
+        use crate::lowercase::lwc;
+        use crate::module::components::{Component, Components};
+        use crate::module::weidu_mod::WeiduMod;
+        use super::run_weidu_install;
+
+        let (temp_dir, test_game_dir) = setup_test_game_dir();
+        let weidu = temp_dir.path().join("weidu-stub");
+        let calls = temp_dir.path().join("calls");
+        std::fs::write(&weidu, format!("#!/bin/sh\necho \"$MY_MOD_DEBUG $MY_MOD_DIR\" >> '{}'\n", calls.to_string_lossy())).unwrap();
+        std::fs::set_permissions(&weidu, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let config = Config {
+            weidu_path: Some(weidu.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let weidu_context = WeiduContext {
+            config: &config,
+            current_dir: &test_game_dir,
+            debug_log: None,
+        };
+        let module = WeiduMod {
+            name: lwc!("my_mod"),
+            language: Some(0),
+            components: Components::List(vec![Component::Simple(1)]),
+            env: Some(BTreeMap::from([
+                ("MY_MOD_DEBUG".to_string(), "1".to_string()),
+                ("MY_MOD_DIR".to_string(), "${HOME}/my_mod".to_string()),
+            ])),
+            ..WeiduMod::default()
+        };
+
+        run_weidu_install("my_mod/setup-my_mod.tp2", &module, &Install::default(), &Global::default(), &weidu_context).unwrap();
+
+        let home = std::env::var("HOME").unwrap();
+        assert_eq!(std::fs::read_to_string(calls).unwrap(), format!("1 {}/my_mod\n", home));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tlk_flags_are_passed_to_weidu() {
+        let call = install_with_tlk(Some("lang/en_us/dialog.tlk"), Some("lang/en_us/dialogf.tlk")).unwrap();
+        assert!(call.contains("--tlkin lang/en_us/dialog.tlk --tlkout lang/en_us/dialog.tlk \
+                                --ftlkin lang/en_us/dialogf.tlk --ftlkout lang/en_us/dialogf.tlk --force-install-list 1"),
+                "{}", call);
+
+        let call = install_with_tlk(None, None).unwrap();
+        assert!(!call.contains("tlk"), "{}", call);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn invalid_tlk_paths() {
+        let error = install_with_tlk(Some("lang/fr_fr/dialog.tlk"), None).unwrap_err();
+        assert!(error.to_string().contains("doesn't exist"), "{}", error);
+        assert!(install_with_tlk(None, Some("../dialogf.tlk")).is_err());
+    }
+
+    #[test]
+    fn success_without_new_log_rows_is_detected() {
+        use crate::lowercase::lwc;
+        use super::{check_weidu_effect, installed_components};
+
+        let (_temp_dir, test_game_dir) = setup_test_game_dir();
+        let weidu_log = test_game_dir.join_path("weidu.log");
+        std::fs::write(&weidu_log, "// Log of Currently Installed WeiDU Mods\n~MY_MOD/SETUP-MY_MOD.TP2~ #0 #0 // Core\n").unwrap();
+        let before = installed_components(&test_game_dir, &lwc!("my_mod")).unwrap();
+        assert_eq!(before.len(), 1);
+
+        // weidu "succeeded" without installing anything
+        assert!(check_weidu_effect(&test_game_dir, &lwc!("my_mod"), &before).is_err());
+        // rows for other mods don't count
+        std::fs::write(&weidu_log, "~MY_MOD/SETUP-MY_MOD.TP2~ #0 #0 // Core\n~OTHER/SETUP-OTHER.TP2~ #0 #1 // Other\n").unwrap();
+        assert!(check_weidu_effect(&test_game_dir, &lwc!("my_mod"), &before).is_err());
+
+        std::fs::write(&weidu_log, "~MY_MOD/SETUP-MY_MOD.TP2~ #0 #0 // Core\n~MY_MOD/SETUP-MY_MOD.TP2~ #0 #2 // Option\n").unwrap();
+        assert!(check_weidu_effect(&test_game_dir, &lwc!("my_mod"), &before).is_ok());
+    }
+
+    #[test]
+    fn debug_log_name_pattern() {
+        use chrono::{Local, TimeZone};
+        use crate::lowercase::lwc;
+        use super::debug_log_name_from_pattern;
+
+        let timestamp = Local.with_ymd_and_hms(2024, 3, 1, 14, 5, 9).unwrap();
+        let pattern = Some("logs/setup-${mod}-${occurrence}.debug");
+        let first = debug_log_name_from_pattern(pattern, &lwc!("my_mod"), 1, timestamp);
+        let second = debug_log_name_from_pattern(pattern, &lwc!("my_mod"), 2, timestamp);
+        assert_eq!(first, "logs/setup-my_mod-1.debug");
+        assert_eq!(second, "logs/setup-my_mod-2.debug");
+
+        assert_eq!(debug_log_name_from_pattern(Some("${mod}-${timestamp}.debug"), &lwc!("my_mod"), 1, timestamp),
+                    "my_mod-20240301-140509.debug");
+        // default name, shared by all occurrences
+        assert_eq!(debug_log_name_from_pattern(None, &lwc!("my_mod"), 2, timestamp), "setup-my_mod.debug");
+    }
+
+    #[test]
+    fn debug_logs_are_moved_to_output_dir() {
+        use super::collect_debug_log;
+
+        let (temp_dir, test_game_dir) = setup_test_game_dir();
+        let output_dir = temp_dir.path().join("logs");
+        let debug_log = test_game_dir.join_path("setup-my_mod.debug");
+
+        assert_eq!(collect_debug_log(&test_game_dir, "setup-my_mod.debug", &output_dir).unwrap(), None);
+
+        std::fs::write(&debug_log, "first run\n").unwrap();
+        let collected = collect_debug_log(&test_game_dir, "setup-my_mod.debug", &output_dir).unwrap();
+        assert_eq!(collected, Some(output_dir.join("setup-my_mod.debug")));
+        assert!(!debug_log.exists());
+
+        std::fs::write(&debug_log, "second run\n").unwrap();
+        collect_debug_log(&test_game_dir, "setup-my_mod.debug", &output_dir).unwrap();
+        assert_eq!(std::fs::read_to_string(output_dir.join("setup-my_mod.debug")).unwrap(), "first run\nsecond run\n");
+
+        std::fs::create_dir_all(test_game_dir.join_path("debug")).unwrap();
+        std::fs::write(test_game_dir.join_path("debug/my_mod-1.debug"), "in a sub-directory\n").unwrap();
+        let collected = collect_debug_log(&test_game_dir, "debug/my_mod-1.debug", &output_dir).unwrap();
+        assert_eq!(collected, Some(output_dir.join("debug/my_mod-1.debug")));
+        assert_eq!(std::fs::read_to_string(output_dir.join("debug/my_mod-1.debug")).unwrap(), "in a sub-directory\n");
+    }
+
+    #[test]
+    fn debug_log_pattern_stays_in_game_dir() {
+        use super::check_debug_log_pattern;
+
+        assert!(check_debug_log_pattern("setup-${mod}.debug").is_ok());
+        assert!(check_debug_log_pattern("./logs/${mod}-${occurrence}.debug").is_ok());
+        assert!(check_debug_log_pattern("logs/../../${mod}.debug").is_err());
+        assert!(check_debug_log_pattern("/tmp/${mod}.debug").is_err());
+    }
+
+    #[test]
+    fn weidu_executable_in_game_dir() {
+        let (_temp_dir, test_game_dir) = setup_test_game_dir();
+        let weidu_bin = test_game_dir.join(WEIDU_BIN).unwrap();
+        std::fs::File::create(&weidu_bin).unwrap();
+        let config = Config::default();
+        let weidu_context = WeiduContext { config: &config, current_dir: &test_game_dir, debug_log: None };
+
+        assert_eq!(weidu_executable(&weidu_context).unwrap(), weidu_bin.to_path_buf().to_string_lossy());
+    }
+
     fn setup_test_game_dir() -> (tempfile::TempDir, crate::canon_path::CanonPath) {
         let tempdir = tempfile::tempdir().unwrap();
         let test_game_dir = tempdir.path().join("game");
@@ -1,10 +1,10 @@
 
 use std::fs::OpenOptions;
-use std::io::{ErrorKind, BufWriter};
+use std::io::{ErrorKind, BufWriter, Write};
 use std::path::PathBuf;
 
 use anyhow::{bail, Result};
-use log::debug;
+use log::{debug, info};
 
 use crate::args::AppendMod;
 use crate::modda_context::WeiduContext;
@@ -56,19 +56,29 @@ pub fn append_mod(params: &AppendMod, weidu_context: &WeiduContext) -> Result<()
     };
     let modified = modified?;
 
+    write_manifest(&modified, &params.output, params.dry_run, &mut std::io::stdout())
+}
+
+/// Writes the manifest to the output file, or only prints it to `out` in dry-run mode.
+fn write_manifest(modified: &Manifest, output: &str, dry_run: bool, out: &mut impl Write) -> Result<()> {
+    if dry_run {
+        info!("Dry run, {} is not modified", output);
+        serde_yaml::to_writer(out, modified)?;
+        return Ok(());
+    }
     // write back to file (create + replace)
-    let output_path = PathBuf::from(&params.output);
+    let output_path = PathBuf::from(output);
     let temp_path = append_extension("new", &output_path);
     let dest = match OpenOptions::new().create(true).truncate(true).write(true).open(&temp_path) {
         Err(err) => bail!("Could not create temp output file\n  {}", err),
         Ok(file) => file,
     };
     let buf_writer = BufWriter::new(&dest);
-    serde_yaml::to_writer(buf_writer, &modified)?;
+    serde_yaml::to_writer(buf_writer, modified)?;
     if let Err(error) = std::fs::rename(&temp_path, output_path) {
-        bail!("Failed to rename temp output file {:?} to {:?}\n -> {:?}", temp_path, params.output, error);
+        bail!("Failed to rename temp output file {:?} to {:?}\n -> {:?}", temp_path, output, error);
     } else {
-        debug!("renamed temp output file to {:?}", params.output);
+        debug!("renamed temp output file to {:?}", output);
     }
     Ok(())
 }
@@ -81,7 +91,7 @@ fn generate_mod(mod_name: &LwcString, components: Vec<WeiduComponent>, generate_
                 components.iter()
                     .map(|comp| {
                         match generate_comment {
-                            true => Component::Full(FullComponent { index: comp.index, component_name: comp.name.to_owned() }),
+                            true => Component::Full(FullComponent { index: comp.index, component_name: comp.name.to_owned(), language: None }),
                             false => Component::Simple(comp.index),
                         }
                     })
@@ -101,3 +111,62 @@ fn append_to_manifest(original: &Manifest, mod_name: &LwcString, components: Vec
         ..original.to_owned()
     }
 }
+
+#[cfg(test)]
+mod test_append_mod {
+    use crate::lowercase::lwc;
+    use crate::module::manifest::Manifest;
+    use crate::run_weidu::WeiduComponent;
+
+    use super::{append_to_manifest, write_manifest};
+
+    fn modified_manifest() -> Manifest {
+        let manifest = Manifest::read_path(
+            &format!("{}/resources/test/manifest.yml", env!("CARGO_MANIFEST_DIR"))
+        ).unwrap();
+        let components = vec![WeiduComponent {
+            index: 3, number: 3, forced: false, name: "Some component".to_string(), subgroup: None, group: vec![],
+        }];
+        append_to_manifest(&manifest, &lwc!("appended_mod"), components, false)
+    }
+
+    #[test]
+    fn dry_run_doesnt_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("manifest.yml");
+        std::fs::write(&output, "original content").unwrap();
+        let mut out = vec![];
+
+        write_manifest(&modified_manifest(), &output.to_string_lossy(), true, &mut out).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "original content");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+        let printed = String::from_utf8(out).unwrap();
+        assert!(printed.contains("appended_mod"));
+    }
+
+    #[test]
+    fn dry_run_doesnt_create_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("new_manifest.yml");
+        let mut out = vec![];
+
+        write_manifest(&modified_manifest(), &output.to_string_lossy(), true, &mut out).unwrap();
+
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+        assert!(!out.is_empty());
+    }
+
+    #[test]
+    fn manifest_is_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("manifest.yml");
+        let mut out = vec![];
+
+        write_manifest(&modified_manifest(), &output.to_string_lossy(), false, &mut out).unwrap();
+
+        assert!(out.is_empty());
+        let written = Manifest::read_path(&output.to_string_lossy()).unwrap();
+        assert_eq!(written.modules.last().unwrap().get_name(), &lwc!("appended_mod"));
+    }
+}
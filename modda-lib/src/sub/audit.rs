@@ -0,0 +1,205 @@
+
+use anyhow::{bail, Result};
+use log::{info, warn};
+use nu_ansi_term::Color::{Green, Red, Yellow};
+
+use crate::args::{Audit, Install};
+use crate::cache::Cache;
+use crate::canon_path::CanonPath;
+use crate::config::Config;
+use crate::download::Downloader;
+use crate::install_events::EventSender;
+use crate::lowercase::LwcString;
+use crate::module::location::github::{GitBranch, Github, GithubDescriptor};
+use crate::module::location::source::Source;
+use crate::module::manifest::Manifest;
+use crate::module::module::Module;
+use crate::obtain::branch_sources::branch_source_warnings;
+use crate::obtain::get_module::ModuleDownload;
+
+#[derive(Debug, PartialEq)]
+pub enum AuditStatus {
+    Reachable,
+    Unreachable(String),
+    /// Nothing to check (generated mods, or no location)
+    Skipped(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AuditEntry {
+    /// index in the manifest (counting from one)
+    pub index: usize,
+    pub name: LwcString,
+    pub source: Option<String>,
+    pub status: AuditStatus,
+}
+
+#[tokio::main]
+pub async fn audit(params: &Audit, config: &Config, game_dir: &CanonPath, cache: &Cache) -> Result<()> {
+    // only used to resolve the manifest root
+    let opts = Install { manifest_path: params.manifest_path.to_owned(), ..Install::default() };
+    let manifest = Manifest::assemble_from_path(&opts.manifest_path, &opts.get_manifest_root(game_dir))?;
+
+    let downloader = Downloader::from_config(config, EventSender::default());
+    let module_downloader = ModuleDownload::new(config, &manifest.global, &manifest.locations, &opts,
+                                                &downloader, game_dir, cache);
+
+    if !params.allow_branches {
+        let modules = manifest.modules.iter().collect::<Vec<_>>();
+        for warning in branch_source_warnings(&modules, &module_downloader) {
+            warn!("{}", Yellow.paint(warning));
+        }
+    }
+    let entries = audit_modules(&manifest.modules, &module_downloader).await;
+    for entry in &entries {
+        let source = entry.source.as_deref().unwrap_or("-");
+        match &entry.status {
+            AuditStatus::Reachable =>
+                info!("{} {} - {} ({})", Green.paint("reachable  "), entry.index, entry.name, source),
+            AuditStatus::Unreachable(reason) =>
+                info!("{} {} - {} ({})\n  {}", Red.bold().paint("unreachable"), entry.index, entry.name, source, reason),
+            AuditStatus::Skipped(reason) =>
+                info!("{} {} - {} ({})", Yellow.paint("skipped    "), entry.index, entry.name, reason),
+        }
+    }
+    let unreachable = entries.iter().filter(|entry| matches!(entry.status, AuditStatus::Unreachable(_))).count();
+    if unreachable > 0 {
+        bail!("{} module(s) out of {} have an unreachable source", unreachable, entries.len());
+    }
+    info!("{}", Green.paint(format!("All {} module(s) checked, no unreachable source", entries.len())));
+    Ok(())
+}
+
+pub async fn audit_modules(modules: &[Module], module_downloader: &ModuleDownload<'_>) -> Vec<AuditEntry> {
+    let mut entries = vec![];
+    for (index, module) in modules.iter().enumerate() {
+        let index = index + 1;
+        let name = module.get_name().to_owned();
+        let weidu_mod = match module {
+            Module::Generated { .. } => {
+                entries.push(AuditEntry { index, name, source: None, status: AuditStatus::Skipped("generated mod".to_string()) });
+                continue;
+            }
+            Module::Mod { weidu_mod } => weidu_mod,
+        };
+        let location = match module_downloader.get_module_location(weidu_mod) {
+            Err(error) => {
+                entries.push(AuditEntry { index, name, source: None, status: AuditStatus::Skipped(error.to_string()) });
+                continue;
+            }
+            Ok(location) => location,
+        };
+        let status = match module_downloader.check_location(location).await {
+            Ok(true) => AuditStatus::Reachable,
+            Ok(false) => AuditStatus::Unreachable("source could not be obtained".to_string()),
+            Err(error) => AuditStatus::Unreachable(format!("{:?}", error)),
+        };
+        entries.push(AuditEntry { index, name, source: Some(describe_source(&location.source)), status });
+    }
+    entries
+}
+
+pub fn describe_source(source: &Source) -> String {
+    match source {
+        Source::Http(http) => http.http.to_owned(),
+        Source::Github(Github { github_user, repository, descriptor, .. }) => match descriptor {
+            GithubDescriptor::Release { release, asset, .. } =>
+                format!("github {github_user}/{repository} release {} asset {asset}", release.as_deref().unwrap_or("latest")),
+            GithubDescriptor::Commit { commit } => format!("github {github_user}/{repository} commit {commit}"),
+            GithubDescriptor::Branch(GitBranch { branch, .. }) => format!("github {github_user}/{repository} branch {branch}"),
+            GithubDescriptor::Tag { tag } => format!("github {github_user}/{repository} tag {tag}"),
+        }
+        Source::Absolute { path, .. } => path.to_owned(),
+        Source::Local { local, .. } => format!("local {local}"),
+    }
+}
+
+#[cfg(test)]
+mod test_audit {
+    use std::path::PathBuf;
+
+    use anyhow::bail;
+    use faux::when;
+
+    use crate::args::Install;
+    use crate::cache::Cache;
+    use crate::canon_path::CanonPath;
+    use crate::config::Config;
+    use crate::download::Downloader;
+    use crate::global::Global;
+    use crate::lowercase::lwc;
+    use crate::module::gen_mod::GeneratedMod;
+    use crate::module::global_locations::GlobalLocations;
+    use crate::module::location::http::Http;
+    use crate::module::location::location::{ConcreteLocation, Location};
+    use crate::module::location::source::Source;
+    use crate::module::module::Module;
+    use crate::module::weidu_mod::WeiduMod;
+    use crate::obtain::get_module::ModuleDownload;
+
+    use super::{audit_modules, AuditEntry, AuditStatus};
+
+    fn module_with_source(name: &str, source: Source) -> Module {
+        Module::Mod {
+            weidu_mod: WeiduMod {
+                name: lwc!(name),
+                location: Some(Location::Concrete { concrete: ConcreteLocation { source, ..ConcreteLocation::default() } }),
+                ..WeiduMod::default()
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn audit_mixed_availability() {
+        let existing = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/test");
+        let missing = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/test/does_not_exist.zip");
+        let modules = vec![
+            module_with_source("alive", Source::Http(Http::from("http://example.com/alive.zip"))),
+            module_with_source("dead", Source::Http(Http::from("http://example.com/dead.zip"))),
+            module_with_source("broken", Source::Http(Http::from("http://broken.example/broken.zip"))),
+            module_with_source("here", Source::Absolute { path: existing, rename: None }),
+            module_with_source("gone", Source::Absolute { path: missing, rename: None }),
+            Module::Mod { weidu_mod: WeiduMod { name: lwc!("nowhere"), ..WeiduMod::default() } },
+            Module::Generated { gen: GeneratedMod { gen_mod: lwc!("generated"), ..GeneratedMod::default() } },
+        ];
+
+        let mut downloader = Downloader::faux();
+        when!(
+            downloader.check_url(_, _)
+        ).then(|(url, _)| match url {
+            "http://example.com/alive.zip" => Ok(true),
+            "http://example.com/dead.zip" => Ok(false),
+            _ => bail!("connection refused"),
+        });
+
+        let global = Global::default();
+        let global_locations = GlobalLocations::default();
+        let opts = Install::default();
+        let config = Config::default();
+        let game_dir = CanonPath::new("some_dir").unwrap();
+        let cache = Cache::Path(PathBuf::from("/cache_path"));
+        let module_download = ModuleDownload::new(&config, &global, &global_locations, &opts,
+                                                    &downloader, &game_dir, &cache);
+
+        let entries = audit_modules(&modules, &module_download).await;
+
+        let names = entries.iter().map(|entry| entry.name.to_string()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["alive", "dead", "broken", "here", "gone", "nowhere", "generated"]);
+        assert!(matches!(entries[0].status, AuditStatus::Reachable));
+        assert!(matches!(entries[1].status, AuditStatus::Unreachable(_)));
+        assert!(matches!(entries[2].status, AuditStatus::Unreachable(_)));
+        assert!(matches!(entries[3].status, AuditStatus::Reachable));
+        assert!(matches!(entries[4].status, AuditStatus::Unreachable(_)));
+        assert!(matches!(entries[5].status, AuditStatus::Skipped(_)));
+        assert!(matches!(entries[6].status, AuditStatus::Skipped(_)));
+        assert_eq!(
+            entries[0],
+            AuditEntry {
+                index: 1,
+                name: lwc!("alive"),
+                source: Some("http://example.com/alive.zip".to_string()),
+                status: AuditStatus::Reachable,
+            }
+        );
+    }
+}
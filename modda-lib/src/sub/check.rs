@@ -0,0 +1,285 @@
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use log::info;
+use nu_ansi_term::Color::{Green, Red, Yellow};
+
+use crate::apply_patch::check_patch;
+use crate::args::{Check, Install};
+use crate::cache::Cache;
+use crate::canon_path::CanonPath;
+use crate::config::Config;
+use crate::download::Downloader;
+use crate::install_events::EventSender;
+use crate::lowercase::LwcString;
+use crate::module::disable_condition::DisableOutCome;
+use crate::module::location::source::Source;
+use crate::module::manifest::Manifest;
+use crate::module::module::Module;
+use crate::module::weidu_mod::WeiduMod;
+use crate::obtain::get_module::ModuleDownload;
+use crate::run_weidu::check_debug_log_pattern;
+use crate::tp2::find_tp2;
+use crate::utils::command_path::{find_command, is_relative_command_path};
+
+/// Something that would make the installation fail.
+#[derive(Debug, PartialEq)]
+pub struct CheckProblem {
+    /// index in the manifest (counting from one) and name of the module, `None` for the whole manifest
+    pub module: Option<(usize, LwcString)>,
+    pub message: String,
+}
+
+impl CheckProblem {
+    pub fn describe(&self) -> String {
+        match &self.module {
+            None => self.message.to_owned(),
+            Some((index, name)) => format!("{} - {}: {}", index, name, self.message),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    /// Format version of the manifest, when it could be read
+    pub version: Option<String>,
+    pub modules: usize,
+    pub disabled: usize,
+    pub problems: Vec<CheckProblem>,
+    /// Reported, but don't make the check fail
+    pub warnings: Vec<CheckProblem>,
+}
+
+/// Reads the manifest and checks everything that can be checked without installing (or downloading) anything:
+/// assembly, locations, disable conditions, relative patches and local archives, `replace` regexes,
+/// `precopy` and extractor commands. Locations of external registries shadowed by another definition are reported
+/// as warnings.
+pub fn check(params: &Check, config: &Config, game_dir: &CanonPath, cache: &Cache) -> Result<()> {
+    let report = check_manifest(&params.manifest_path, config, game_dir, cache);
+    for warning in &report.warnings {
+        info!("{} {}", Yellow.bold().paint("warning"), warning.describe());
+    }
+    for problem in &report.problems {
+        info!("{} {}", Red.bold().paint("problem"), problem.describe());
+    }
+    if !report.problems.is_empty() {
+        bail!("Manifest check failed, {} problem(s) found", report.problems.len());
+    }
+    info!("{}", Green.paint(format!("Manifest check passed (version {}, {} module(s), {} disabled)",
+                                    report.version.as_deref().unwrap_or("?"), report.modules, report.disabled)));
+    Ok(())
+}
+
+pub fn check_manifest(manifest_path: &str, config: &Config, game_dir: &CanonPath, cache: &Cache) -> CheckReport {
+    let mut report = CheckReport::default();
+    // only used to resolve the manifest root and relative patches
+    let opts = Install { manifest_path: manifest_path.to_owned(), ..Install::default() };
+    let manifest_root = opts.get_manifest_root(game_dir);
+    let mut manifest = match Manifest::assemble_from_path(manifest_path, &manifest_root) {
+        Ok(manifest) => manifest,
+        Err(error) => {
+            report.problems.push(CheckProblem { module: None, message: format!("Could not read manifest\n -> {:?}", error) });
+            return report;
+        }
+    };
+    report.version = Some(manifest.version.to_owned());
+    if let Err(error) = manifest.resolve_components_files(&manifest_root) {
+        report.problems.push(CheckProblem { module: None, message: format!("{:?}", error) });
+    }
+    match manifest.divergent_locations(&manifest_root) {
+        Ok(divergent) => report.warnings.extend(divergent.iter()
+            .map(|location| CheckProblem { module: None, message: location.describe() })),
+        Err(error) => report.problems.push(CheckProblem { module: None, message: format!("{:?}", error) }),
+    }
+
+    let downloader = Downloader::from_config(config, EventSender::default());
+    let module_downloader = ModuleDownload::new(config, &manifest.global, &manifest.locations, &opts,
+                                                &downloader, game_dir, cache);
+    report.modules = manifest.modules.len();
+    for (index, module) in manifest.modules.iter().enumerate() {
+        let mut problem = |message: String| report.problems.push(CheckProblem {
+            module: Some((index + 1, module.get_name().to_owned())),
+            message,
+        });
+        match module.check_disabled(&manifest_root, game_dir, &manifest.manifest_conditions) {
+            Err(error) => problem(format!("Could not evaluate disable condition\n -> {:?}", error)),
+            Ok(DisableOutCome::Yes(_)) => report.disabled += 1,
+            Ok(DisableOutCome::No(_)) => {}
+        }
+        if let Module::Mod { weidu_mod } = module {
+            for message in check_weidu_mod(weidu_mod, &module_downloader) {
+                problem(message);
+            }
+        }
+    }
+    report.problems.extend(missing_commands(config, &manifest.modules, &module_downloader));
+    if let Some(Err(error)) = config.weidu_debug_log.as_deref().map(check_debug_log_pattern) {
+        report.problems.push(CheckProblem { module: None, message: error.to_string() });
+    }
+    report
+}
+
+/// Checks that the `precopy` and extractor commands (configured or in a location) are found on PATH,
+/// or are existing files.<br>
+/// Relative paths (`./fix.sh`) are run from the extracted mod content, so they can't be checked beforehand.
+pub fn missing_commands(config: &Config, modules: &[Module], module_downloader: &ModuleDownload) -> Vec<CheckProblem> {
+    let mut problems = vec![];
+    for (extension, extractor) in config.extractors.iter().sorted_by_key(|(extension, _)| extension.as_ref().to_owned()) {
+        if let Some(message) = missing_command(&extractor.command, &format!("extractor for {}", extension)) {
+            problems.push(CheckProblem { module: None, message });
+        }
+    }
+    for (index, module) in modules.iter().enumerate() {
+        let location = match module {
+            Module::Mod { weidu_mod } => match module_downloader.get_module_location(weidu_mod) {
+                Ok(location) => location,
+                Err(_) => continue,
+            },
+            Module::Generated { .. } => continue,
+        };
+        let commands = location.precopy.iter().map(|precopy| (&precopy.command, "precopy"))
+            .chain(location.extractor.iter().map(|extractor| (&extractor.command, "extractor")));
+        for (command, usage) in commands {
+            if let Some(message) = missing_command(command, usage) {
+                problems.push(CheckProblem { module: Some((index + 1, module.get_name().to_owned())), message });
+            }
+        }
+    }
+    problems
+}
+
+fn missing_command(command: &str, usage: &str) -> Option<String> {
+    if is_relative_command_path(command) || find_command(command).is_some() {
+        None
+    } else {
+        Some(format!("Command `{}` ({}) was not found on PATH", command, usage))
+    }
+}
+
+fn check_weidu_mod(weidu_mod: &WeiduMod, module_downloader: &ModuleDownload) -> Vec<String> {
+    let mut problems = vec![];
+    let location = match module_downloader.get_module_location(weidu_mod) {
+        Ok(location) => location,
+        // no location is needed when the mod is already in the game directory
+        Err(_) if find_tp2(module_downloader.game_dir, &weidu_mod.name).is_ok() => return problems,
+        Err(error) => {
+            problems.push(error.to_string());
+            return problems;
+        }
+    };
+    if let Source::Local { .. } | Source::Absolute { .. } = location.source {
+        match module_downloader.local_archive(location, &weidu_mod.name) {
+            Ok(archive) if archive.exists() => {}
+            Ok(archive) => problems.push(format!("Mod archive {:?} doesn't exist", archive)),
+            Err(error) => problems.push(format!("{:?}", error)),
+        }
+    }
+    for patch in location.patch.iter().chain(location.patches.iter()) {
+        if let Err(error) = check_patch(module_downloader.game_dir, &weidu_mod.name, patch,
+                                        module_downloader.opts, module_downloader.global) {
+            problems.push(format!("{:?}", error));
+        }
+    }
+    for spec in location.replace.iter().flatten() {
+        if let Err(error) = spec.check_patterns() {
+            problems.push(error.to_string());
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod test_check {
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+
+    use crate::cache::Cache;
+    use crate::canon_path::CanonPath;
+    use crate::config::{Config, ExtractorCommand};
+    use crate::lowercase::lwc;
+
+    use super::check_manifest;
+
+    fn check(manifest: &str) -> super::CheckReport {
+        let game_dir = tempfile::tempdir().unwrap();
+        let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/check").join(manifest);
+        check_manifest(manifest.to_str().unwrap(), &Config::default(), &CanonPath::new(game_dir.path()).unwrap(),
+                        &Cache::Path(PathBuf::from("/cache_path")))
+    }
+
+    #[test]
+    fn valid_manifest() {
+        let report = check("valid.yml");
+        assert_eq!(report.problems, vec![]);
+        assert_eq!((report.modules, report.disabled), (2, 1));
+        assert_eq!(report.version.as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn invalid_manifest() {
+        let report = check("invalid.yml");
+        let problems = report.problems.iter()
+            .map(|problem| (problem.module.clone().unwrap(), problem.message.lines().next().unwrap_or_default().to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(problems.len(), 5, "{:#?}", problems);
+        assert!(problems[0].0 == (1, lwc!("my_mod")) && problems[0].1.contains("Error reading relative patch at missing.diff"));
+        assert!(problems[1].0 == (1, lwc!("my_mod")) && problems[1].1.contains("Incorrect regex"));
+        assert!(problems[2].0 == (2, lwc!("other_mod")) && problems[2].1.contains("unknown_location"));
+        assert!(problems[3].0 == (3, lwc!("third_mod")) && problems[3].1.contains("disable condition"));
+        assert!(problems[4].0 == (3, lwc!("third_mod")) && problems[4].1.contains("doesn't exist"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn missing_commands() {
+        let report = check("commands.yml");
+        let problems = report.problems.iter()
+            .map(|problem| (problem.module.clone().map(|(index, _)| index), problem.message.to_owned()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            problems,
+            vec![
+                (Some(2), "Command `modda-no-such-precopy` (precopy) was not found on PATH".to_string()),
+                (Some(3), "Command `modda-no-such-extractor` (extractor) was not found on PATH".to_string()),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn missing_configured_extractor() {
+        let game_dir = tempfile::tempdir().unwrap();
+        let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/check/valid.yml");
+        let config = Config {
+            extractors: HashMap::from([
+                (lwc!("rar"), ExtractorCommand { command: "modda-no-such-unrar".to_string(), args: vec![] }),
+                (lwc!("7z"), ExtractorCommand { command: "sh".to_string(), args: vec![] }),
+            ]),
+            ..Config::default()
+        };
+        let report = check_manifest(manifest.to_str().unwrap(), &config, &CanonPath::new(game_dir.path()).unwrap(),
+                                    &Cache::Path(PathBuf::from("/cache_path")));
+        assert_eq!(report.problems.len(), 1);
+        assert!(report.problems[0].module.is_none());
+        assert_eq!(report.problems[0].message, "Command `modda-no-such-unrar` (extractor for rar) was not found on PATH");
+    }
+
+    #[test]
+    fn debug_log_outside_game_dir() {
+        let game_dir = tempfile::tempdir().unwrap();
+        let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/test/check/valid.yml");
+        let config = Config { weidu_debug_log: Some("../logs/${mod}.debug".to_string()), ..Config::default() };
+        let report = check_manifest(manifest.to_str().unwrap(), &config, &CanonPath::new(game_dir.path()).unwrap(),
+                                    &Cache::Path(PathBuf::from("/cache_path")));
+        assert_eq!(report.problems.len(), 1);
+        assert!(report.problems[0].module.is_none());
+        assert!(report.problems[0].message.starts_with("weidu_debug_log ../logs/${mod}.debug must be"));
+    }
+
+    #[test]
+    fn unreadable_manifest() {
+        let report = check("does_not_exist.yml");
+        assert_eq!(report.problems.len(), 1);
+        assert!(report.problems[0].module.is_none());
+    }
+}
@@ -4,42 +4,50 @@ use std::fs::OpenOptions;
 use std::io::BufWriter;
 
 use anyhow::{Result, bail};
+use itertools::Itertools;
 
-use crate::args::Reverse;
+use crate::args::{manifest_root, Export, ExportFormat, Reverse};
 use crate::canon_path::CanonPath;
-use crate::module::components::FullComponent;
+use crate::module::components::{Component, Components, FullComponent};
+use crate::module::disable_condition::DisableOutCome;
 use crate::global::Global;
 use crate::log_parser::{parse_weidu_log, LogRow};
-use crate::lowercase::lwc;
+use crate::lowercase::{lwc, LwcString};
 use crate::module::global_locations::GlobalLocations;
 use crate::module::manifest::Manifest;
 use crate::module::manifest_conditions::ManifestConditions;
 use crate::module::module::Module;
-use crate::module::weidu_mod::BareMod;
+use crate::module::weidu_mod::{BareMod, WeiduMod};
+use crate::tp2::find_tp2;
 use crate::unique_component::UniqueComponent;
 use crate::weidu_conf::read_weidu_conf_lang_dir;
 
 pub fn extract_bare_mods() -> Result<Vec<BareMod>> {
     let log_rows = parse_weidu_log(None)?;
+    Ok(bare_mods_from_log_rows(&log_rows))
+}
+
+/// Groups consecutive `weidu.log` rows of the same mod, installed with the same language, in fragments.
+fn bare_mods_from_log_rows(log_rows: &[LogRow]) -> Vec<BareMod> {
     let init: Vec<BareMod> = vec![];
-    let mod_fragments = log_rows.iter().fold(init, |mut accumulator, row| {
+    log_rows.iter().fold(init, |mut accumulator, row| {
         let current_mod = row.module.to_lowercase();
-        let last_mod = accumulator.last().map(|module| module.name.clone());
+        let last_mod = accumulator.last().map(|module| (module.name.clone(), module.language));
         match last_mod {
             None => accumulator.push(bare_mod_from_log_row(row)),
-            Some(mod_name) if mod_name == current_mod => {
+            Some((mod_name, language)) if mod_name == current_mod && language == row.lang_index => {
                 let last_index = accumulator.len() - 1;
                 let last = accumulator.get_mut(last_index).unwrap();
                 last.components.push(FullComponent {
                     index: row.component_index,
                     component_name: row.component_name.to_string(),
+                    language: None,
                 });
             }
             _ => accumulator.push(bare_mod_from_log_row(row)),
         }
         accumulator
-    });
-    Ok(mod_fragments)
+    })
 }
 
 fn format_modules(bare_mods: Vec<BareMod>, export_component_name: Option<bool>, export_language: Option<bool>) -> Vec<Module> {
@@ -48,7 +56,8 @@ fn format_modules(bare_mods: Vec<BareMod>, export_component_name: Option<bool>,
 
 pub fn extract_manifest(args: &Reverse, game_dir: &CanonPath) -> Result<()> {
     let mods = extract_bare_mods()?;
-    let mods = format_modules(mods, args.export_component_name, args.export_language);
+    let export_language = if args.no_language { Some(false) } else { Some(args.export_language.unwrap_or(true)) };
+    let mods = format_modules(mods, args.export_component_name, export_language);
     let manifest = generate_manifest(game_dir, mods)?;
 
     let output_file = OpenOptions::new().create_new(true).write(true).open(&args.output)?;
@@ -56,6 +65,79 @@ pub fn extract_manifest(args: &Reverse, game_dir: &CanonPath) -> Result<()> {
     Ok(serde_yaml::to_writer(buf_writer, &manifest)?)
 }
 
+/// Writes the manifest in another format (`export` subcommand), to `output` or to the standard output.
+pub fn export_manifest(args: &Export, game_dir: &CanonPath) -> Result<()> {
+    let manifest_root = manifest_root(&args.manifest_path, game_dir);
+    let mut manifest = Manifest::assemble_from_path(&args.manifest_path, &manifest_root)?;
+    manifest.resolve_components_files(&manifest_root)?;
+    let content = match args.format {
+        ExportFormat::WeiduLog => weidu_log_lines(&manifest, &manifest_root, game_dir)?.join("\n") + "\n",
+    };
+    match &args.output {
+        None => print!("{}", content),
+        Some(output) => if let Err(error) = std::fs::write(output, content) {
+            bail!("Could not write export file {}\n -> {:?}", output, error);
+        }
+    }
+    Ok(())
+}
+
+/// The manifest as a `weidu.log` (`~TP2~ #language #component // name`), in installation order.<br>
+/// Modules that can't be written this way (generated mods, components that are not listed explicitly,
+/// disabled modules) are represented by comments.
+pub fn weidu_log_lines(manifest: &Manifest, manifest_root: &CanonPath, game_dir: &CanonPath) -> Result<Vec<String>> {
+    let mut lines = vec![
+        "// Log of Currently Installed WeiDU Mods".to_string(),
+        "// The top of the file is the 'oldest' mod".to_string(),
+        "// ~TP2_File~ #language_number #component_number // [Subcomponent Name -> ] Component Name [ : Version]".to_string(),
+    ];
+    for module in &manifest.modules {
+        if let DisableOutCome::Yes(reason) = module.check_disabled(manifest_root, game_dir, &manifest.manifest_conditions)? {
+            lines.push(format!("// {} is disabled ({})", module.get_name(), reason.replace('\n', " ")));
+            continue;
+        }
+        let weidu_mod = match module {
+            Module::Mod { weidu_mod } => weidu_mod,
+            Module::Generated { gen } => {
+                lines.push(format!("// {} is generated by modda (not a weidu mod)", gen.gen_mod));
+                continue;
+            }
+        };
+        lines.extend(weidu_mod_lines(weidu_mod, game_dir));
+    }
+    Ok(lines)
+}
+
+fn weidu_mod_lines(weidu_mod: &WeiduMod, game_dir: &CanonPath) -> Vec<String> {
+    let list = match &weidu_mod.components {
+        Components::List(list) if !list.is_empty() && list.iter().all(|component| component.index().is_some()) => list,
+        Components::None => return vec![],
+        other => return vec![format!("// {} with components {} (chosen at installation)",
+                                        weidu_mod.name, other.describe())],
+    };
+    let tp2 = export_tp2_path(&weidu_mod.name, game_dir);
+    let language = weidu_mod.language.unwrap_or(0);
+    list.iter()
+        .map(|component| {
+            let line = format!("~{}~ #{} #{}", tp2, component.language().unwrap_or(language),
+                               component.index().unwrap_or_default());
+            match component {
+                Component::Full(FullComponent { component_name, .. }) if !component_name.is_empty() =>
+                    format!("{} // {}", line, component_name),
+                _ => line,
+            }
+        })
+        .collect()
+}
+
+/// Path of the tp2 file relative to the game directory, when the mod is there, `<mod>/setup-<mod>.tp2` otherwise.
+fn export_tp2_path(mod_name: &LwcString, game_dir: &CanonPath) -> String {
+    match find_tp2(game_dir, mod_name) {
+        Ok(path) => path.components().map(|component| component.as_os_str().to_string_lossy()).join("/"),
+        Err(_) => format!("{}/setup-{}.tp2", mod_name, mod_name),
+    }
+}
+
 pub fn generate_manifest(game_dir: &CanonPath, modules: Vec<Module>) -> Result<Manifest> {
     let lang_dir = match read_weidu_conf_lang_dir(game_dir)? {
         None => "en_US".to_string(),
@@ -89,6 +171,7 @@ fn bare_mod_from_log_row(row: &LogRow) -> BareMod {
         FullComponent {
             index: row.component_index,
             component_name: row.component_name.to_string(),
+            language: None,
         }
     ];
     BareMod {
@@ -109,3 +192,96 @@ pub fn extract_unique_components() -> Result<HashSet<UniqueComponent>> {
         }
     })
 }
+
+#[cfg(test)]
+mod test_reverse {
+    use crate::log_parser::LogRow;
+    use crate::lowercase::lwc;
+    use crate::module::components::FullComponent;
+    use crate::module::weidu_mod::BareMod;
+
+    use super::bare_mods_from_log_rows;
+
+    fn row(module: &str, lang_index: u32, component_index: u32) -> LogRow {
+        LogRow { module: module.to_string(), lang_index, component_index, component_name: format!("comp {}", component_index) }
+    }
+
+    fn bare_mod(name: &str, language: u32, components: &[u32]) -> BareMod {
+        BareMod {
+            name: lwc!(name),
+            language,
+            components: components.iter()
+                .map(|index| FullComponent { index: *index, component_name: format!("comp {}", index), language: None })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn fragments_keep_the_language() {
+        let rows = vec![row("Aaa", 1, 0), row("aaa", 1, 2), row("aaa", 0, 3), row("bbb", 2, 0), row("aaa", 1, 4)];
+        let bare_mods = bare_mods_from_log_rows(&rows);
+        assert_eq!(bare_mods, vec![
+            bare_mod("aaa", 1, &[0, 2]),
+            bare_mod("aaa", 0, &[3]),
+            bare_mod("bbb", 2, &[0]),
+            bare_mod("aaa", 1, &[4]),
+        ]);
+        assert_eq!(bare_mods[0].to_weidu_mod(Some(false), Some(true)).language, Some(1));
+        assert_eq!(bare_mods[0].to_weidu_mod(Some(false), Some(false)).language, None);
+    }
+}
+
+#[cfg(test)]
+mod test_export {
+    use crate::canon_path::CanonPath;
+    use crate::lowercase::lwc;
+    use crate::module::components::{Component, Components, FullComponent};
+    use crate::module::disable_condition::DisableCondition;
+    use crate::module::gen_mod::GeneratedMod;
+    use crate::module::manifest::Manifest;
+    use crate::module::module::Module;
+    use crate::module::weidu_mod::WeiduMod;
+
+    use super::weidu_log_lines;
+
+    #[test]
+    fn export_weidu_log() {
+        let manifest = Manifest {
+            version: "1".to_string(),
+            global: Default::default(),
+            locations: Default::default(),
+            manifest_conditions: Default::default(),
+            modules: vec![
+                Module::Mod { weidu_mod: WeiduMod {
+                    name: lwc!("aaa"),
+                    language: Some(1),
+                    components: Components::List(vec![
+                        Component::Simple(0),
+                        Component::Full(FullComponent { index: 2, component_name: "Second".to_string(), language: Some(3) }),
+                    ]),
+                    ..WeiduMod::default()
+                } },
+                Module::Mod { weidu_mod: WeiduMod { name: lwc!("bbb"), components: Components::Ask, ..WeiduMod::default() } },
+                Module::Generated { gen: GeneratedMod { gen_mod: lwc!("ccc"), ..GeneratedMod::default() } },
+                Module::Mod { weidu_mod: WeiduMod {
+                    name: lwc!("ddd"),
+                    components: Components::List(vec![Component::Simple(5)]),
+                    disabled_if: Some(DisableCondition::Because { because: "not now".to_string() }),
+                    ..WeiduMod::default()
+                } },
+            ],
+        };
+        let root = CanonPath::new(std::env::current_dir().unwrap()).unwrap();
+        let lines = weidu_log_lines(&manifest, &root, &root).unwrap();
+        assert_eq!(
+            &lines[3..],
+            &[
+                "~aaa/setup-aaa.tp2~ #1 #0".to_string(),
+                "~aaa/setup-aaa.tp2~ #3 #2 // Second".to_string(),
+                "// bbb with components ask (chosen at installation)".to_string(),
+                "// ccc is generated by modda (not a weidu mod)".to_string(),
+                "// ddd is disabled (not now)".to_string(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,85 @@
+
+use itertools::Itertools;
+
+use crate::module::components::{Component, Components};
+use crate::module::module::Module;
+use crate::module::weidu_mod::BareMod;
+use crate::unique_component::UniqueComponent;
+
+/// Components in `weidu.log` that the manifest doesn't account for, most likely installed by another tool
+/// (or by hand) before modda.<br>
+/// A mod whose components are not explicit in the manifest (`ask`, `all`, component groups) accounts
+/// for all its components.
+pub fn foreign_components(installed: &[BareMod], modules: &[Module]) -> Vec<UniqueComponent> {
+    installed.iter()
+        .flat_map(|bare_mod| bare_mod.components.iter()
+            .map(|component| UniqueComponent { mod_key: bare_mod.name.to_owned(), index: component.index }))
+        .filter(|component| !accounted_for(component, modules))
+        .unique()
+        .collect()
+}
+
+fn accounted_for(component: &UniqueComponent, modules: &[Module]) -> bool {
+    modules.iter()
+        .filter(|module| *module.get_name() == component.mod_key)
+        .any(|module| match module.get_components() {
+            Components::None => false,
+            Components::Ask | Components::All | Components::FromFile(_) => true,
+            Components::List(list) if list.is_empty() => true,
+            Components::List(list) => list.iter().any(|item| match item {
+                Component::Group(_) => true,
+                other => other.index() == Some(component.index),
+            }),
+        })
+}
+
+#[cfg(test)]
+mod test_foreign {
+    use crate::lowercase::lwc;
+    use crate::module::components::{Component, Components, GroupComponent};
+    use crate::module::gen_mod::{GeneratedMod, GenModComponent};
+    use crate::module::module::Module;
+    use crate::sub::test_fixtures::{bare_mod, list, module};
+    use crate::unique_component::UniqueComponent;
+
+    use super::foreign_components;
+
+    fn unique(name: &str, index: u32) -> UniqueComponent {
+        UniqueComponent { mod_key: lwc!(name), index }
+    }
+
+    #[test]
+    fn report_entries_not_in_manifest() {
+        let installed = vec![
+            bare_mod("eeex", &[0]),
+            bare_mod("fixpack", &[0, 1, 2]),
+            bare_mod("tweaks", &[5, 100]),
+            bare_mod("asked", &[3]),
+            bare_mod("grouped", &[7]),
+            bare_mod("generated", &[0]),
+        ];
+        let modules = vec![
+            module("fixpack", list(&[0, 1])),
+            module("fixpack", list(&[2])),
+            module("tweaks", list(&[5])),
+            module("asked", Components::Ask),
+            module("grouped", Components::List(vec![
+                Component::Group(GroupComponent { group: "NPC tweaks".to_string(), subgroup: None }),
+            ])),
+            Module::Generated { gen: GeneratedMod {
+                gen_mod: lwc!("generated"),
+                component: GenModComponent { index: 0, ..GenModComponent::default() },
+                ..GeneratedMod::default()
+            } },
+        ];
+
+        assert_eq!(foreign_components(&installed, &modules), vec![unique("eeex", 0), unique("tweaks", 100)]);
+    }
+
+    #[test]
+    fn nothing_foreign() {
+        let installed = vec![bare_mod("fixpack", &[0])];
+        assert!(foreign_components(&installed, &[module("fixpack", list(&[0, 1]))]).is_empty());
+        assert!(foreign_components(&[], &[module("fixpack", list(&[0]))]).is_empty());
+    }
+}
@@ -3,9 +3,10 @@ use std::cell::RefCell;
 use std::io::BufWriter;
 use std::path::{PathBuf, Path};
 
-use nu_ansi_term::Color::{Blue, Green, Red};
+use nu_ansi_term::Color::{Blue, Green, Red, Yellow};
 use anyhow::{Result, anyhow, bail};
-use chrono::Local;
+use chrono::{DateTime, Local};
+use futures_util::stream::StreamExt;
 use itertools::Itertools;
 use log::{debug, info, error, warn};
 
@@ -13,32 +14,107 @@ use crate::args::Install;
 use crate::cache::Cache;
 use crate::canon_path::CanonPath;
 use crate::module::components::{Components, Component, FullComponent};
+use crate::disk_usage::{dir_size, format_size_delta};
 use crate::download::Downloader;
 use crate::file_installer::FileInstaller;
+use crate::game_detect::check_expected_game;
+use crate::global::Global;
+use crate::install_events::{EventSender, InstallEvent};
+use crate::install_report::{write_report, ReportEntry, ReportStatus};
+use crate::install_summary::{SummaryOutcome, SummaryRecord, SummaryWriter};
 use crate::lowercase::{lwc, LwcString};
 use crate::module::disable_condition::DisableOutCome;
+use crate::module::location::location::ConcreteLocation;
+use crate::module::location::source::Source;
+use crate::module::manifest_conditions::ManifestConditions;
 use crate::module::module::Module;
 use crate::module::weidu_mod::WeiduMod;
+use crate::obtain::branch_sources::branch_source_warnings;
 use crate::obtain::get_module::ModuleDownload;
-use crate::post_install::PostInstallOutcome;
-use crate::log_parser::{check_install_complete, parse_weidu_log, LogRow};
+use crate::obtain::remote_manifest::{check_remote_manifest, fetch_manifest};
+use crate::obtain::get_options::GetOptions;
+use crate::post_install::{InstallStatus, PostInstallOutcome};
+use crate::log_parser::{check_install_complete, check_weidu_log_not_locked, parse_weidu_log, LogRow};
 use crate::module::manifest::Manifest;
-use crate::process_weidu_mod::{process_generated_mod, process_weidu_mod, ProcessResult};
+use crate::run_weidu::check_debug_log_pattern;
+use crate::process_weidu_mod::{process_generated_mod, process_weidu_mod, ProcessResult, SkipReason, SkippedModule};
 use crate::config::Config;
-use crate::timeline::InstallTimeline;
+use crate::timeline::{write_chrome_trace, InstallTimeline};
+use crate::tp2::find_tp2;
 use crate::unique_component::UniqueComponent;
 use crate::weidu_conf::check_weidu_conf_lang;
-use crate::modda_context::ModdaContext;
+use crate::modda_context::{ModdaContext, WeiduContext};
 
-use super::extract_manifest::extract_unique_components;
+use super::check::missing_commands;
+use super::extract_manifest::{extract_bare_mods, extract_unique_components};
+use super::foreign::foreign_components;
+use super::install_plan::module_plan;
+use super::preview_transforms::preview_transforms;
+use super::reinstall::{reinstall_plan, select_reinstalled, uninstall_removed};
+use super::resume::{resume_plan, uninstall_diverging};
 
 pub fn install(opts: &Install, settings: &Config, game_dir: &CanonPath, cache: &Cache) -> Result<()> {
+    install_with_events(opts, settings, game_dir, cache, EventSender::default())
+}
+
+/// Runs `install` on a blocking thread, for applications that embed the installer (the GUI).<br>
+/// Progress is reported through `events`.
+pub async fn install_async(opts: Install, settings: Config, game_dir: CanonPath, cache: Cache,
+                            events: EventSender) -> Result<()> {
+    let task = tokio::task::spawn_blocking(move ||
+        install_with_events(&opts, &settings, &game_dir, &cache, events)
+    );
+    match task.await {
+        Ok(result) => result,
+        Err(error) => bail!("Installation task failed\n -> {:?}", error),
+    }
+}
 
-    let manifest = Manifest::assemble_from_path(&opts.manifest_path, &opts.get_manifest_root(game_dir))?;
+pub fn install_with_events(opts: &Install, settings: &Config, game_dir: &CanonPath, cache: &Cache,
+                            events: EventSender) -> Result<()> {
+    let remote_opts;
+    let opts = match &opts.from_manifest_url {
+        None => opts,
+        Some(url) => {
+            remote_opts = remote_manifest_opts(opts, url, settings, cache)?;
+            &remote_opts
+        }
+    };
+
+    let mut manifest = Manifest::assemble_from_path(&opts.manifest_path, &opts.get_manifest_root(game_dir))?;
+    manifest.resolve_components_files(&opts.get_manifest_root(game_dir))?;
+    if opts.list_disabled {
+        return list_disabled(&manifest, &opts.get_manifest_root(game_dir), game_dir);
+    }
+    if let Some(mod_name) = &opts.preview_transforms {
+        let downloader = Downloader::from_config(settings, events.clone());
+        let module_downloader = ModuleDownload::new(settings, &manifest.global, &manifest.locations, opts,
+                                                    &downloader, game_dir, cache);
+        return preview_transforms(&manifest, mod_name, &module_downloader, settings);
+    }
+    if opts.print_order {
+        let (_, modules) = select_modules(&manifest.modules, opts)?;
+        for line in install_order(&modules, &opts.get_manifest_root(game_dir), game_dir,
+                                    &manifest.manifest_conditions) {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+    match manifest.global.game {
+        Some(game) if !opts.ignore_game_check => check_expected_game(game_dir.path(), game)?,
+        _ => {}
+    }
     check_weidu_conf_lang(game_dir, &manifest.global.game_language)?;
+    let resumed_opts;
+    let opts = if opts.resume {
+        resumed_opts = prepare_resume(&manifest, opts, settings, game_dir)?;
+        &resumed_opts
+    } else {
+        opts
+    };
     let modules = &manifest.modules;
 
-    let log = if let Some(output) = &opts.output {
+    let log = if let (Some(output), false) = (&opts.output, opts.dry_run) {
         let file = match std::fs::OpenOptions::new().create(true).write(true).truncate(true).open(output) {
             Err(error) => return Err(
                 anyhow!(format!("Could not create log file {} - {:?}", output, error)
@@ -51,102 +127,583 @@ pub fn install(opts: &Install, settings: &Config, game_dir: &CanonPath, cache: &
         None
     };
 
-    let modules = get_modules_range(&modules, opts)?;
+    let (range, modules) = select_modules(modules, opts)?;
     if modules.is_empty() {
         info!("{}", Green.paint("Nothing to install with given range"));
         return Ok(())
     }
+    let skipped = unselected_modules(&manifest.modules, range, &modules, opts);
 
-    let downloader = Downloader::new();
-    let module_downloader = ModuleDownload::new(&settings, &manifest.global, &manifest.locations, &opts,
-                                                                        &downloader, &game_dir, cache);
-    let file_installer = FileInstaller::new(&manifest.global, &opts, &game_dir);
+    if let Some(pattern) = &settings.weidu_debug_log {
+        check_debug_log_pattern(pattern)?;
+    }
+    let downloader = Downloader::from_config(settings, events.clone());
+    let module_downloader = ModuleDownload::new(settings, &manifest.global, &manifest.locations, opts,
+                                                                        &downloader, game_dir, cache)
+                                                                        .with_events(events.clone());
+    if !opts.ignore_foreign {
+        check_no_foreign_components(&manifest, &opts.reinstall)?;
+    }
+    if !opts.allow_branches {
+        let selected = modules.iter().map(|(_, module)| *module).collect_vec();
+        for warning in branch_source_warnings(&selected, &module_downloader) {
+            warn!("{}", Yellow.paint(warning));
+        }
+    }
+    if opts.dry_run {
+        for problem in missing_commands(settings, &manifest.modules, &module_downloader) {
+            warn!("{}", Yellow.paint(problem.describe()));
+        }
+    }
+    if opts.no_download {
+        check_archives_available(&modules, &module_downloader, &manifest, &opts.get_manifest_root(game_dir), game_dir)?;
+    }
+    if let (Some(concurrency), false) = (opts.download_concurrency, opts.dry_run) {
+        prefetch_archives(&prefetch_locations(&modules, &module_downloader, game_dir), &module_downloader, concurrency);
+    }
+    let file_installer = FileInstaller::new(&manifest.global, opts, game_dir);
 
-    let modda_context = ModdaContext { current_dir: game_dir, config: &settings, opts: &opts,
+    let modda_context = ModdaContext { current_dir: game_dir, config: settings, opts,
                                                     module_downloader: &module_downloader, file_installer: &file_installer,
-                                                    log: RefCell::from(log) };
+                                                    log: RefCell::from(log), events, occurrences: RefCell::default() };
 
-    let mut timelines = vec![];
-    for (index, module) in modules.iter().enumerate() {
-        let real_index = index + opts.from_index.unwrap_or(0) + 1;
+    if !opts.reinstall.is_empty() {
+        prepare_reinstall(&manifest, &modda_context)?;
+    }
+    if opts.dry_run {
+        return print_install_plan(&modules, &manifest, &modda_context, &skipped);
+    }
+
+    let mut records = InstallRecords::new(opts, skipped)?;
+    let skips = requested_skips(opts, &manifest.global);
+    let end = install_modules(&modules, opts, game_dir, &mut records, |index, real_index, module|
+        install_module(module, &modda_context, &manifest, &skips, index, real_index));
+    let reached_end = reached_manifest_end(&end, &records, opts, range, &manifest.modules);
+    let end = end.and_then(|end| exec_post_install_replace(&manifest.global, game_dir, opts, reached_end).map(|_| end));
+    records.finish(end)
+}
+
+/// The range reaches the last module of the manifest, every module of the range was selected (no `--phase`
+/// or `--only`) and every selected module was installed (or skipped).
+fn reached_manifest_end(end: &Result<LoopEnd>, records: &InstallRecords, opts: &Install, range: &[Module],
+                        modules: &[Module]) -> bool {
+    matches!(end, Ok(LoopEnd::Completed)) && records.failed.is_empty() && opts.reinstall.is_empty()
+        && opts.phase.is_none() && opts.only.is_empty()
+        && opts.from_index.unwrap_or(1).saturating_sub(1) + range.len() >= modules.len()
+}
+
+/// How the installation loop ended, when no error stopped it.
+#[derive(Debug)]
+enum LoopEnd {
+    /// All the selected modules were processed
+    Completed,
+    /// A module asked for the interruption (`post_install`)
+    Stopped,
+}
+
+/// Processes the selected modules in order, `install_module` installs (or skips) one of them.<br>
+/// Every processed module is recorded, the one that stopped the installation on an error included.
+fn install_modules<F>(modules: &[(usize, &Module)], opts: &Install, game_dir: &CanonPath, records: &mut InstallRecords,
+                        mut install_module: F) -> Result<LoopEnd>
+        where F: FnMut(usize, usize, &Module) -> Result<ProcessResult> {
+    let mut game_dir_size = if opts.disk_usage { Some(dir_size(game_dir.path())?) } else { None };
+    for (index, module) in modules.iter().copied() {
+        let real_index = index + 1;
         info!("module {} - {}", real_index, module.describe());
         debug!("{:?}", module);
 
-        match check_safely_installable(module)? {
-            SafetyResult::Abort => bail!("Aborted"),
-            SafetyResult::Safe => {}
-            SafetyResult::Conflicts(matches) if matches.is_empty() => {}
-            SafetyResult::Conflicts(matches) => {
-                let list = format!("\n  - {}", matches.iter().map(|item| item.short_desc()).join("\n  - "));
-                error!("{}", Red.bold().paint(format!("Module fragment\n  {:?}\ncontains components that were already installed:{}", module, list)));
-                show_reset_help();
-                bail!("Aborting - proceeding with `install` is unsafe (could uninstall then install modules repeatedly)");
-            }
-        }
-        let process_result = match module.check_disabled(&opts.get_manifest_root(game_dir), &manifest.manifest_conditions) {
-            Ok(DisableOutCome::No(reason)) => {
-                if let Some(reason) = reason {
-                    info!("module {name} is not disabled - {reason}", name = module.get_name());
-                }
-                match module {
-                    Module::Mod { weidu_mod } =>
-                        install_weidu(weidu_mod, &modda_context, &manifest, opts, index, real_index)?,
-                    Module::Generated { gen } =>
-                        process_generated_mod(gen, &modda_context, &manifest, real_index)?,
-                }
-            }
-            Ok(DisableOutCome::Yes(reason)) => {
-                info!("module {name} is disabled - {reason}", name = module.get_name());
-                ProcessResult {
-                    stop: false,
-                    timeline: InstallTimeline::new(lwc!(&format!("{} - disabled", module.get_name())), Local::now()),
-                }
-            }
-            Err(error) => {
-                info!("disabled check for module {name} failed\n  {error}", name = module.get_name());
-                ProcessResult {
-                    stop: true,
-                    timeline: InstallTimeline::new(lwc!(&format!("{} - disable check (failed)", module.get_name())), Local::now()),
-                }
+        let start = Local::now();
+        let (process_result, error) = match install_module(index, real_index, module) {
+            Ok(result) => (result, None),
+            Err(error) if opts.continue_on_error => {
+                error!("module {name} failed\n -> {error:?}", name = module.get_name());
+                (failed_result(module, start), None)
             }
+            Err(error) => (failed_result(module, start), Some(error)),
         };
-        let ProcessResult { stop: finished, timeline } = process_result;
-        timelines.push(timeline);
+        let ProcessResult { stop: finished, mut timeline, status, skipped: skip } = process_result;
+        if let Some(before) = game_dir_size {
+            let after = dir_size(game_dir.path())?;
+            timeline.added_bytes = Some(after as i64 - before as i64);
+            game_dir_size = Some(after);
+        }
+        records.record(real_index, module, skip.as_ref(), finished, status, timeline)?;
+        if let Some(error) = error {
+            return Err(error);
+        }
 
+        if finished && opts.continue_on_error {
+            warn!("{}", Yellow.paint(format!("module {} - {} failed, continuing (--continue-on-error)",
+                                                real_index, module.get_name())));
+            records.failed.push((real_index, module.get_name().to_owned()));
+            continue;
+        }
         if finished {
             warn!("interrupted");
-            timelines.push(InstallTimeline::new(lwc!("aborted"), Local::now()));
-            handle_timeline(opts.timeline, &timelines);
             bail!("Program interrupted on error or non-whitelisted warning");
-        } else {
-            match module.exec_post_install(&module.get_name()) {
-                PostInstallOutcome::Stop => {
-                    info!("{}",  Blue.bold().paint(format!("Interruption requested for module {} - {}",
-                                                            real_index, module.describe())));
-                    return Ok(());
-                }
-                PostInstallOutcome::Continue => {}
+        }
+        match module.exec_post_install(module.get_name(), status) {
+            PostInstallOutcome::Stop => {
+                info!("{}",  Blue.bold().paint(format!("Interruption requested for module {} - {}",
+                                                        real_index, module.describe())));
+                return Ok(LoopEnd::Stopped);
             }
+            PostInstallOutcome::Continue => {}
+        }
+        if let Some(reason) = skip {
+            records.skipped.push(SkippedModule { index: real_index, name: module.get_name().to_owned(), reason });
+        }
+    }
+    Ok(LoopEnd::Completed)
+}
+
+/// Installs one selected module, unless it is skipped, and checks that all its requested components were installed.
+fn install_module(module: &Module, modda_context: &ModdaContext, manifest: &Manifest, skips: &[LwcString],
+                    index: usize, real_index: usize) -> Result<ProcessResult> {
+    let opts = modda_context.opts;
+    let game_dir = modda_context.current_dir;
+    check_weidu_log_not_locked(game_dir)?;
+    match skip_reason(module, &opts.get_manifest_root(game_dir), game_dir, &manifest.manifest_conditions, skips) {
+        Ok(None) => {
+            // reinstalling components that are already installed is the point of `--reinstall`
+            if opts.reinstall.is_empty() {
+                check_safety(module)?;
+            }
+            let result = match module {
+                Module::Mod { weidu_mod } =>
+                    install_weidu(weidu_mod, modda_context, manifest, opts, index, real_index)?,
+                Module::Generated { gen } =>
+                    process_generated_mod(gen, modda_context, manifest, real_index)?,
+            };
+            // Now check we actually installed all requested components
+            if !result.stop {
+                check_install_complete(module, &manifest.global, &modda_context.as_weidu_context())?;
+            }
+            Ok(result)
+        }
+        Ok(Some(reason)) => {
+            info!("module {name} is skipped - {reason}", name = module.get_name(), reason = reason.describe());
+            let label = match &reason {
+                SkipReason::Disabled(reason) => {
+                    modda_context.events.emit(InstallEvent::ModDisabled { mod_name: module.get_name().clone(), reason: reason.to_owned() });
+                    "disabled"
+                }
+                SkipReason::Requested => "skipped (requested)",
+                SkipReason::Filtered(_) => "filtered out",
+                SkipReason::OutOfRange => "out of range",
+            };
+            Ok(ProcessResult {
+                stop: false,
+                timeline: InstallTimeline::new(lwc!(&format!("{} - {}", module.get_name(), label)), Local::now()),
+                status: InstallStatus::Success,
+                skipped: Some(reason),
+            })
+        }
+        Err(error) => {
+            info!("disabled check for module {name} failed\n  {error}", name = module.get_name());
+            Ok(ProcessResult {
+                stop: true,
+                timeline: InstallTimeline::new(lwc!(&format!("{} - disable check (failed)", module.get_name())), Local::now()),
+                status: InstallStatus::Failed,
+                skipped: None,
+            })
+        }
+    }
+}
+
+/// Result of a module whose installation ended with an error.
+fn failed_result(module: &Module, start: DateTime<Local>) -> ProcessResult {
+    ProcessResult {
+        stop: true,
+        timeline: InstallTimeline::new(lwc!(&format!("{} - failed", module.get_name())), start),
+        status: InstallStatus::Failed,
+        skipped: None,
+    }
+}
+
+/// What the installation loop records about the processed modules, written out however the installation ends.
+struct InstallRecords<'a> {
+    opts: &'a Install,
+    summary: Option<SummaryWriter>,
+    timelines: Vec<InstallTimeline>,
+    report: Vec<ReportEntry>,
+    /// Modules that failed with `--continue-on-error` (index counting from one)
+    failed: Vec<(usize, LwcString)>,
+    skipped: Vec<SkippedModule>,
+}
+
+impl <'a> InstallRecords<'a> {
+    fn new(opts: &'a Install, skipped: Vec<SkippedModule>) -> Result<Self> {
+        let summary = match &opts.summary {
+            Some(path) => Some(SummaryWriter::create(Path::new(path))?),
+            None => None,
+        };
+        Ok(InstallRecords { opts, summary, timelines: vec![], report: vec![], failed: vec![], skipped })
+    }
+
+    fn record(&mut self, real_index: usize, module: &Module, skip: Option<&SkipReason>, finished: bool,
+                status: InstallStatus, timeline: InstallTimeline) -> Result<()> {
+        if let Some(summary) = &self.summary {
+            let (outcome, reason) = match (skip, finished, status) {
+                (Some(reason), _, _) => (SummaryOutcome::Skipped, Some(reason.describe())),
+                (None, true, _) | (None, _, InstallStatus::Failed) => (SummaryOutcome::Failed, None),
+                (None, false, InstallStatus::Warning) => (SummaryOutcome::Warning, None),
+                (None, false, InstallStatus::Success) => (SummaryOutcome::Installed, None),
+            };
+            summary.append(&SummaryRecord::new(real_index, module.get_name(), outcome, reason, timeline.start))?;
+        }
+        if self.opts.report.is_some() {
+            self.report.push(ReportEntry::new(real_index, module.get_name(), ReportStatus::new(skip, finished, status), &timeline));
+        }
+        self.timelines.push(timeline);
+        Ok(())
+    }
+
+    /// Writes the summary, timeline and report, lists the skipped modules, then returns the outcome of the installation.
+    fn finish(mut self, end: Result<LoopEnd>) -> Result<()> {
+        let label = match &end {
+            Ok(LoopEnd::Completed) => "finished",
+            Ok(LoopEnd::Stopped) => "stopped",
+            Err(_) => "aborted",
+        };
+        if matches!(end, Ok(LoopEnd::Completed)) && self.failed.is_empty() {
+            info!("Installation done with no error");
+        }
+        let compacted = match &self.summary {
+            Some(summary) => summary.compact(),
+            None => Ok(()),
+        };
+        self.timelines.push(InstallTimeline::new(lwc!(label), Local::now()));
+        handle_timeline(self.opts, &self.timelines);
+        handle_report(self.opts, &self.report);
+        log_skipped(&self.skipped);
+        end?;
+        compacted?;
+        if !self.failed.is_empty() {
+            error!("{}", Red.bold().paint(format!("failed module(s):\n  - {}", describe_failed(&self.failed))));
+            bail!("Installation done, {} module(s) failed", self.failed.len());
         }
-        // Now check we actually installed all requested components
-        // if dry_run, nothing will have been installed at all so don't check
-        if !opts.dry_run {
-            check_install_complete(&module)?
+        Ok(())
+    }
+}
+
+/// Modules that failed with `--continue-on-error` (index counting from one and name), one per line.
+fn describe_failed(failed: &[(usize, LwcString)]) -> String {
+    failed.iter().map(|(index, name)| format!("{} - {}", index, name)).join("\n  - ")
+}
+
+/// `--dry-run`: prints what would be done with each selected module, in order, without obtaining or installing anything.
+fn print_install_plan(modules: &[(usize, &Module)], manifest: &Manifest, modda_context: &ModdaContext,
+                        skipped: &[SkippedModule]) -> Result<()> {
+    let ModdaContext { current_dir: game_dir, opts, module_downloader, .. } = modda_context;
+    let weidu_context = modda_context.as_weidu_context();
+    let manifest_root = opts.get_manifest_root(game_dir);
+    info!("{}", Blue.bold().paint("Installation plan (dry run, nothing is installed)"));
+    for (index, module) in modules {
+        let skip = skip_reason(module, &manifest_root, game_dir, &manifest.manifest_conditions,
+                                &requested_skips(opts, &manifest.global))?;
+        for line in module_plan(index + 1, module, skip.as_ref(), &manifest.global, module_downloader, &weidu_context)? {
+            println!("{}", line);
         }
     }
-    info!("Installation done with no error");
-    timelines.push(InstallTimeline::new(lwc!("finished"), Local::now()));
-    handle_timeline(opts.timeline, &timelines);
+    log_skipped(skipped);
     Ok(())
 }
 
 fn install_weidu(weidu_mod: &WeiduMod, modda_context: &ModdaContext, manifest: &Manifest,
                 opts: &Install, index: usize, real_index: usize) -> Result<ProcessResult> {
-    let result = process_weidu_mod(weidu_mod, &modda_context, &manifest, real_index)?;
+    let result = process_weidu_mod(weidu_mod, modda_context, manifest, real_index)?;
     if weidu_mod.components.is_ask() {
-        if let Some(output_path) = &opts.record {
+        let output_path = if opts.save_choices { Some(&opts.manifest_path) } else { opts.record.as_ref() };
+        if let Some(output_path) = output_path {
             let manifest_path = PathBuf::from(&opts.manifest_path);
-            record_selection(index, weidu_mod, &output_path, &manifest_path, opts)?;
+            record_selection(index, weidu_mod, output_path, &manifest_path, opts)?;
+        }
+    }
+    Ok(result)
+}
+
+/// Fails when weidu.log contains components that the manifest doesn't account for (`--ignore-foreign` skips this).<br>
+/// The components of the `--reinstall` mods are handled by the reinstallation (the ones removed from the manifest
+/// are uninstalled).
+fn check_no_foreign_components(manifest: &Manifest, reinstalled: &[LwcString]) -> Result<()> {
+    let foreign = foreign_components(&extract_bare_mods()?, &manifest.modules).into_iter()
+        .filter(|component| !reinstalled.contains(&component.mod_key))
+        .collect_vec();
+    if !foreign.is_empty() {
+        bail!("weidu.log contains components that are not in the manifest (installed by another tool?):\n  - {}\nUse --ignore-foreign to install anyway",
+                foreign.iter().map(|component| component.short_desc()).join("\n  - "));
+    }
+    Ok(())
+}
+
+/// With `--no-download`, fails before installing anything if some (enabled) mods would need to be downloaded.
+fn check_archives_available(modules: &[(usize, &Module)], module_downloader: &ModuleDownload,
+                            manifest: &Manifest, manifest_root: &CanonPath, game_dir: &CanonPath) -> Result<()> {
+    let weidu_mods = modules.iter()
+        .filter(|(_, module)| !matches!(module.check_disabled(manifest_root, game_dir, &manifest.manifest_conditions),
+                                        Ok(DisableOutCome::Yes(_))))
+        .filter_map(|(_, module)| match module {
+            Module::Mod { weidu_mod } => Some(weidu_mod),
+            Module::Generated { .. } => None,
+        })
+        .collect::<Vec<_>>();
+    let missing = module_downloader.missing_archives(&weidu_mods)?;
+    if !missing.is_empty() {
+        let list = missing.iter().map(|(name, archive)| format!("{} ({:?})", name, archive)).join("\n  - ");
+        bail!("Archives not available locally, can't install with --no-download:\n  - {}", list);
+    }
+    Ok(())
+}
+
+/// Mods whose archive will be downloaded (only the first fragment of each mod, not the mods already in
+/// the game directory).
+fn prefetch_locations<'a>(modules: &[(usize, &'a Module)], module_downloader: &'a ModuleDownload,
+                            game_dir: &CanonPath) -> Vec<(&'a LwcString, &'a ConcreteLocation)> {
+    modules.iter()
+        .filter_map(|(_, module)| match module {
+            Module::Mod { weidu_mod } => Some(weidu_mod),
+            Module::Generated { .. } => None,
+        })
+        .unique_by(|weidu_mod| &weidu_mod.name)
+        .filter(|weidu_mod| find_tp2(game_dir, &weidu_mod.name).is_err())
+        .filter_map(|weidu_mod| match module_downloader.get_module_location(weidu_mod) {
+            Ok(location) if matches!(location.source, Source::Http(_) | Source::Github(_)) =>
+                Some((&weidu_mod.name, location)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// `--download-concurrency`: fills the archive cache before the installation. Failures are not fatal, the mod
+/// may be disabled or skipped later (and its download is tried again when it's installed).
+#[tokio::main]
+async fn prefetch_archives(locations: &[(&LwcString, &ConcreteLocation)], module_downloader: &ModuleDownload,
+                            concurrency: usize) {
+    info!("downloading {} archive(s), {} at a time", locations.len(), concurrency.max(1));
+    let results = futures_util::stream::iter(locations)
+        .map(|(mod_name, location)| async move {
+            (mod_name, module_downloader.retrieve_location(location, mod_name).await)
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect::<Vec<_>>()
+        .await;
+    for (mod_name, result) in results {
+        if let Err(error) = result {
+            warn!("{}", Yellow.paint(format!("Could not download archive for mod {}\n -> {:?}", mod_name, error)));
+        }
+    }
+}
+
+/// Uninstalls the components of the `--reinstall` mods that are no longer in the manifest, after confirmation.<br>
+/// The components that stay are reinstalled (in place) when the mod fragments are processed.
+fn prepare_reinstall(manifest: &Manifest, modda_context: &ModdaContext) -> Result<()> {
+    let opts = modda_context.opts;
+    let installed = extract_bare_mods()?;
+    let plans = opts.reinstall.iter()
+        .map(|mod_name| reinstall_plan(&installed, &manifest.modules, mod_name))
+        .collect::<Result<Vec<_>>>()?;
+    let summary = plans.iter().map(|plan| plan.describe()).join("\n  ");
+    if opts.dry_run {
+        println!("Dry run, would reinstall\n  {}", summary);
+    } else if !opts.assume_yes {
+        let prompt = format!("Will reinstall\n  {}\nProceed? ", summary);
+        if !dialoguer::Confirm::new().with_prompt(prompt).interact()? {
+            bail!("Aborted");
+        }
+    }
+    uninstall_removed(&plans, opts.dry_run, &modda_context.as_weidu_context())
+}
+
+/// `--resume`: shows the plan, uninstalls what diverges from the manifest, the returned options start the
+/// installation at the first module that is not installed.
+fn prepare_resume(manifest: &Manifest, opts: &Install, settings: &Config, game_dir: &CanonPath) -> Result<Install> {
+    let plan = resume_plan(&extract_bare_mods()?, &manifest.modules, &opts.get_manifest_root(game_dir), game_dir,
+                            &manifest.manifest_conditions, &requested_skips(opts, &manifest.global))?;
+    let summary = plan.describe(&manifest.modules);
+    if opts.dry_run {
+        println!("Dry run, would resume\n{}", summary);
+    } else if !opts.assume_yes {
+        let prompt = format!("Will resume\n{}\nProceed? ", summary);
+        if !dialoguer::Confirm::new().with_prompt(prompt).interact()? {
+            bail!("Aborted");
+        }
+    } else {
+        println!("Resuming\n{}", summary);
+    }
+    let weidu_context = WeiduContext { current_dir: game_dir, config: settings, debug_log: None };
+    uninstall_diverging(&plan, opts.dry_run, &weidu_context)?;
+    Ok(Install { from_index: Some(plan.from_index), ..opts.clone() })
+}
+
+/// Downloads the manifest given with `--from-manifest-url`, the returned options refer to the downloaded file.
+fn remote_manifest_opts(opts: &Install, url: &str, settings: &Config, cache: &Cache) -> Result<Install> {
+    let path = download_remote_manifest(url, settings, cache)?;
+    info!("Manifest downloaded from {} to {:?}", url, path);
+    let manifest_path = path.to_string_lossy().to_string();
+    check_remote_manifest(&Manifest::read_path(&manifest_path)?)?;
+    Ok(Install { manifest_path, from_manifest_url: None, ..opts.clone() })
+}
+
+#[tokio::main]
+async fn download_remote_manifest(url: &str, settings: &Config, cache: &Cache) -> Result<PathBuf> {
+    let downloader = Downloader::from_config(settings, EventSender::default());
+    fetch_manifest(url, &downloader, cache).await
+}
+
+/// Applies the manifest-level `post_install_replace`, only when the last module of the manifest was processed.
+fn exec_post_install_replace(global: &Global, game_dir: &CanonPath, opts: &Install, reached_end: bool) -> Result<()> {
+    if global.post_install_replace.is_empty() {
+        return Ok(());
+    }
+    if !reached_end {
+        info!("Installation stopped before the end of the manifest, `post_install_replace` is not applied");
+        return Ok(());
+    }
+    info!("Applying `post_install_replace` in game directory");
+    let get_options = GetOptions { strict_replace: opts.check_replace };
+    for spec in &global.post_install_replace {
+        if let Err(error) = spec.exec_contained(game_dir, &get_options) {
+            bail!("Manifest `post_install_replace` failed\n -> {:?}", error);
+        }
+    }
+    Ok(())
+}
+
+fn list_disabled(manifest: &Manifest, manifest_root: &CanonPath, game_dir: &CanonPath) -> Result<()> {
+    let disabled = find_disabled(&manifest.modules, manifest_root, game_dir, &manifest.manifest_conditions)?;
+    if disabled.is_empty() {
+        info!("{}", Green.paint("No disabled module"));
+    } else {
+        info!("{} disabled module(s) out of {}", disabled.len(), manifest.modules.len());
+        for (index, module, reason) in disabled {
+            info!("{} {} - {}\n    {}", Yellow.paint("disabled"), index, module.describe(), reason);
+        }
+    }
+    Ok(())
+}
+
+/// Modules to install, with their index in the manifest (counting from zero)
+type Selection<'a> = Vec<(usize, &'a Module)>;
+
+/// Applies the selection options (range, `--reinstall`, `--phase`, `--only`), returns the range and the selected modules.
+fn select_modules<'a>(modules: &'a [Module], opts: &Install) -> Result<(&'a [Module], Selection<'a>)> {
+    let range = if opts.reinstall.is_empty() { get_modules_range(modules, opts)? } else { modules };
+    let selected = if opts.reinstall.is_empty() {
+        // the range starts at `--from-index` (counting from one)
+        let start = opts.from_index.map_or(0, |from_index| from_index.saturating_sub(1));
+        range.iter().enumerate().map(|(index, module)| (start + index, module)).collect::<Vec<_>>()
+    } else {
+        select_reinstalled(modules, &opts.reinstall)?
+    };
+    let selected = match &opts.phase {
+        None => selected,
+        Some(phase) => select_phase(selected, modules, phase)?,
+    };
+    let selected = if opts.only.is_empty() { selected } else { select_only(selected, modules, &opts.only)? };
+    Ok((range, selected))
+}
+
+/// One line per selected module (for `--print-order`): index in the manifest (counting from one), name and components.
+fn install_order(selected: &[(usize, &Module)], manifest_root: &CanonPath, game_dir: &CanonPath,
+                    manifest_conditions: &ManifestConditions) -> Vec<String> {
+    selected.iter()
+        .map(|(index, module)| {
+            let line = format!("{} - {}: {}", index + 1, module.describe(), module.get_components().describe());
+            match module.check_disabled(manifest_root, game_dir, manifest_conditions) {
+                Ok(DisableOutCome::Yes(reason)) => format!("{} (disabled - {})", line, reason),
+                _ => line,
+            }
+        })
+        .collect()
+}
+
+/// Keeps the modules of the given phase, fails if no module of the manifest has this phase.
+fn select_phase<'a>(modules: Vec<(usize, &'a Module)>, all_modules: &[Module], phase: &str) -> Result<Vec<(usize, &'a Module)>> {
+    if !all_modules.iter().any(|module| module.get_phase() == Some(phase)) {
+        let phases = all_modules.iter().filter_map(|module| module.get_phase()).unique().join(", ");
+        if phases.is_empty() {
+            bail!("Unknown phase {}, no module has a phase in the manifest", phase);
+        }
+        bail!("Unknown phase {}, available phases are: {}", phase, phases);
+    }
+    Ok(modules.into_iter().filter(|(_, module)| module.get_phase() == Some(phase)).collect())
+}
+
+/// Keeps the modules with one of the given names, fails if one of the names is not in the manifest.
+fn select_only<'a>(modules: Vec<(usize, &'a Module)>, all_modules: &[Module], names: &[LwcString]) -> Result<Vec<(usize, &'a Module)>> {
+    for name in names {
+        if !all_modules.iter().any(|module| module.get_name() == name) {
+            bail!("Mod {} (given with --only) is not in the manifest", name);
+        }
+    }
+    Ok(modules.into_iter().filter(|(_, module)| names.contains(module.get_name())).collect())
+}
+
+/// Modules of the manifest left out by the selection options (range, `--phase`, `--reinstall`, `--only`), with the reason.
+fn unselected_modules(all_modules: &[Module], range: &[Module], selected: &[(usize, &Module)], opts: &Install) -> Vec<SkippedModule> {
+    all_modules.iter().enumerate()
+        .filter(|(_, module)| !selected.iter().any(|(_, selected)| std::ptr::eq(*selected, *module)))
+        .map(|(index, module)| {
+            let reason = if !range.iter().any(|in_range| std::ptr::eq(in_range, module)) {
+                SkipReason::OutOfRange
+            } else if !opts.reinstall.is_empty() {
+                SkipReason::Filtered("--reinstall".to_string())
+            } else if opts.phase.as_deref().is_none_or(|phase| module.get_phase() == Some(phase)) {
+                SkipReason::Filtered(format!("--only {}", opts.only.iter().join(",")))
+            } else {
+                SkipReason::Filtered(format!("--phase {}", opts.phase.as_deref().unwrap_or_default()))
+            };
+            SkippedModule { index: index + 1, name: module.get_name().to_owned(), reason }
+        })
+        .collect()
+}
+
+/// Mods skipped with `--skip` and the `skip` list of the manifest `global`.
+fn requested_skips(opts: &Install, global: &Global) -> Vec<LwcString> {
+    opts.skip.iter().chain(&global.skip).cloned().collect()
+}
+
+/// Decides whether a selected module is skipped, because it was asked (`skipped`) or because it is disabled.
+fn skip_reason(module: &Module, manifest_root: &CanonPath, game_dir: &CanonPath, manifest_conditions: &ManifestConditions,
+                skipped: &[LwcString]) -> Result<Option<SkipReason>> {
+    if skipped.contains(module.get_name()) {
+        return Ok(Some(SkipReason::Requested));
+    }
+    match module.check_disabled(manifest_root, game_dir, manifest_conditions)? {
+        DisableOutCome::Yes(reason) => Ok(Some(SkipReason::Disabled(reason))),
+        DisableOutCome::No(Some(reason)) => {
+            info!("module {name} is not disabled - {reason}", name = module.get_name());
+            Ok(None)
+        }
+        DisableOutCome::No(None) => Ok(None),
+    }
+}
+
+fn log_skipped(skipped: &[SkippedModule]) {
+    let (out_of_range, others): (Vec<_>, Vec<_>) = skipped.iter()
+        .partition(|skipped| skipped.reason == SkipReason::OutOfRange);
+    if !others.is_empty() {
+        info!("skipped module(s):\n  - {}", others.iter()
+            .sorted_by_key(|skipped| skipped.index)
+            .map(|skipped| format!("{} - {}: {}", skipped.index, skipped.name, skipped.reason.describe()))
+            .join("\n  - "));
+    }
+    if !out_of_range.is_empty() {
+        info!("{} module(s) out of the selected range", out_of_range.len());
+    }
+}
+
+/// Evaluates the disable condition of all modules, returns the disabled ones (with index counting from one)
+/// and the reason.
+fn find_disabled<'a>(modules: &'a [Module], manifest_root: &CanonPath, game_dir: &CanonPath,
+                        manifest_conditions: &ManifestConditions) -> Result<Vec<(usize, &'a Module, String)>> {
+    let mut result = vec![];
+    for (index, module) in modules.iter().enumerate() {
+        match module.check_disabled(manifest_root, game_dir, manifest_conditions) {
+            Ok(DisableOutCome::Yes(reason)) => result.push((index + 1, module, reason)),
+            Ok(DisableOutCome::No(_)) => {}
+            Err(error) => bail!("disabled check for module {} - {} failed\n  {}", index + 1, module.get_name(), error),
         }
     }
     Ok(result)
@@ -182,6 +739,21 @@ fn  get_modules_range<'a>(modules: &'a[Module], opts: &Install) -> Result<&'a [M
     Ok(result)
 }
 
+/// Fails if installing the module fragment would reinstall components (after confirmation for `ask`/`all`).
+fn check_safety(module: &Module) -> Result<()> {
+    match check_safely_installable(module)? {
+        SafetyResult::Abort => bail!("Aborted"),
+        SafetyResult::Safe => Ok(()),
+        SafetyResult::Conflicts(matches) if matches.is_empty() => Ok(()),
+        SafetyResult::Conflicts(matches) => {
+            let list = format!("\n  - {}", matches.iter().map(|item| item.short_desc()).join("\n  - "));
+            error!("{}", Red.bold().paint(format!("Module fragment\n  {:?}\ncontains components that were already installed:{}", module, list)));
+            show_reset_help();
+            bail!("Aborting - proceeding with `install` is unsafe (could uninstall then install modules repeatedly)");
+        }
+    }
+}
+
 fn check_safely_installable(module: &Module) -> Result<SafetyResult> {
     let installed = extract_unique_components()?;
     match module.get_components() {
@@ -209,9 +781,10 @@ fn check_safely_installable(module: &Module) -> Result<SafetyResult> {
                 Ok(SafetyResult::Safe)
             }
         }
+        Components::FromFile(file) => bail!("Components file {} was not read", file.file),
         Components::List(list) => {
-            let matches = list.iter().fold(vec![], |mut matches, current| {
-                let current = UniqueComponent { mod_key: module.get_name().to_owned(), index: current.index() };
+            let matches = list.iter().filter_map(|current| current.index()).fold(vec![], |mut matches, index| {
+                let current = UniqueComponent { mod_key: module.get_name().to_owned(), index };
                 if installed.contains(&current) {
                     matches.push(current);
                     matches
@@ -233,12 +806,45 @@ fn show_reset_help() {
     info!("You may use the `reset` subcommand")
 }
 
-fn handle_timeline(flag: bool, timelines: &[InstallTimeline]) {
-    if flag {
+fn handle_timeline(opts: &Install, timelines: &[InstallTimeline]) {
+    if opts.timeline {
         info!("timelines:\n  - {}", timelines.iter().map(|it| it.short()).join("\n  - "));
     } else{
         debug!("timelines:\n  - {}", timelines.iter().map(|it| it.short()).join("\n  - "));
     }
+    if opts.disk_usage {
+        info!("disk usage added to the game directory: {}", describe_disk_usage(timelines));
+    }
+    if let Some(trace_path) = &opts.trace_timeline {
+        // the installation result matters more than the trace
+        match write_chrome_trace(Path::new(trace_path), timelines) {
+            Ok(()) => info!("timeline trace written to {}", trace_path),
+            Err(error) => warn!("{:?}", error),
+        }
+    }
+}
+
+/// Total size added to the game directory, then the size added by each module (when it was measured).
+fn describe_disk_usage(timelines: &[InstallTimeline]) -> String {
+    let measured = timelines.iter()
+        .filter_map(|timeline| timeline.added_bytes.map(|added_bytes| (&timeline.name, added_bytes)))
+        .collect::<Vec<_>>();
+    let total = measured.iter().map(|(_, added_bytes)| added_bytes).sum();
+    let mut result = format_size_delta(total);
+    for (name, added_bytes) in measured {
+        result += &format!("\n  - {}: {}", name, format_size_delta(added_bytes));
+    }
+    result
+}
+
+fn handle_report(opts: &Install, report: &[ReportEntry]) {
+    if let Some(report_path) = &opts.report {
+        // the installation result matters more than the report
+        match write_report(Path::new(report_path), report) {
+            Ok(()) => info!("installation report written to {}", report_path),
+            Err(error) => warn!("{:?}", error),
+        }
+    }
 }
 
 pub enum SafetyResult {
@@ -258,6 +864,7 @@ fn record_selection(index: usize, module: &WeiduMod, output_file: &str, original
 
     let previous_mod = record_manifest.modules[..index].iter().rev().find(|item| match item.get_components() {
         Components::List(_) => true,
+        Components::FromFile(_) => true,
         Components::Ask => true,
         Components::All => true,
         Components::None => false,
@@ -273,11 +880,12 @@ fn record_selection(index: usize, module: &WeiduMod, output_file: &str, original
                 Components::Ask => bail!("components for previous mod fragment were not recorded"),
                 Components::All => bail!("components for previous mod fragment were not recorded"),
                 Components::None => bail!("search incorrectly returned a 'none' component list"),
+                Components::FromFile(_) => bail!("components for previous mod fragment are in a file, they can't be matched"),
             };
             let previous_name = previous.get_name();
             debug!("record_selection- previous_components={:?}, previous_name={}", previous_components, previous_name);
             let previous_match = log_rows.iter().enumerate().rev().find(|(_, row)| {
-                let result = previous_name == &row.module && previous_components.iter().any(|comp| comp.index() == row.component_index);
+                let result = previous_name == &row.module && previous_components.iter().any(|comp| comp.index() == Some(row.component_index));
                 debug!("{:?} ? {}", row, result);
                 result
             });
@@ -289,30 +897,35 @@ fn record_selection(index: usize, module: &WeiduMod, output_file: &str, original
         }
     };
     let selection = selection_rows.iter().map(|row|
-        Component::Full(FullComponent { index: row.component_index, component_name: row.component_name.to_owned() })
+        Component::Full(FullComponent { index: row.component_index, component_name: row.component_name.to_owned(), language: None })
     ).collect_vec();
 
     if confirm_record(opts.record_no_confirm, &selection_rows, &module.name)? {
-        // update manifest with new component selection
-        let components = if selection.is_empty() {
-            Components::None
-        } else{
-            Components::List(selection)
-        };
-        debug!("replace {:?} at position {}", components, index);
-        record_manifest.modules[index] = Module::Mod { weidu_mod: WeiduMod {
-            components,
-            ..module.to_owned()
-        } };
-
-        // write updated manifest to new file
-        record_manifest.write(&output_path, opts.record_with_comment_as_field)?;
-
+        save_selection(&mut record_manifest, index, module, selection, &output_path, opts.record_with_comment_as_field)?;
     }
 
     Ok(())
 }
 
+/// Replaces the components of the mod fragment at `index` with the selection and writes the manifest.
+fn save_selection(record_manifest: &mut Manifest, index: usize, module: &WeiduMod, selection: Vec<Component>,
+                    output_path: &Path, reformat_comments: bool) -> Result<()> {
+    // update manifest with new component selection
+    let components = if selection.is_empty() {
+        Components::None
+    } else{
+        Components::List(selection)
+    };
+    debug!("replace {:?} at position {}", components, index);
+    record_manifest.modules[index] = Module::Mod { weidu_mod: WeiduMod {
+        components,
+        ..module.to_owned()
+    } };
+
+    // write updated manifest to new file
+    record_manifest.write(output_path, reformat_comments)
+}
+
 fn confirm_record(no_confirm_flag: bool, selection: &[&LogRow], module_name: &LwcString) -> Result<bool> {
     if no_confirm_flag {
         Ok(true)
@@ -326,3 +939,689 @@ fn confirm_record(no_confirm_flag: bool, selection: &[&LogRow], module_name: &Lw
         }
     }
 }
+
+#[cfg(test)]
+mod test_list_disabled {
+    use std::collections::HashMap;
+
+    use crate::canon_path::CanonPath;
+    use crate::lowercase::lwc;
+    use crate::module::disable_condition::DisableCondition;
+    use crate::module::gen_mod::GeneratedMod;
+    use crate::module::manifest_conditions::ManifestConditions;
+    use crate::module::module::Module;
+    use crate::module::weidu_mod::WeiduMod;
+
+    use super::find_disabled;
+
+    fn weidu_mod(name: &str, disabled_if: Option<DisableCondition>) -> Module {
+        Module::Mod { weidu_mod: WeiduMod { name: lwc!(name), disabled_if, ..WeiduMod::default() } }
+    }
+
+    #[test]
+    fn list_only_disabled_modules() {
+        let because = |reason: &str| DisableCondition::Because { because: reason.to_string() };
+        let modules = vec![
+            weidu_mod("enabled", None),
+            weidu_mod("disabled", Some(because("not now"))),
+            weidu_mod("not_disabled", Some(DisableCondition::Not { not: Box::new(because("negated")) })),
+            Module::Generated { gen: GeneratedMod {
+                gen_mod: lwc!("generated"),
+                disabled_if: Some(DisableCondition::ManifestCondition { manifest_condition: "skip_gen".to_string() }),
+                ..GeneratedMod::default()
+            } },
+            weidu_mod("global_enabled", Some(DisableCondition::ManifestCondition { manifest_condition: "absent".to_string() })),
+        ];
+        let conditions = ManifestConditions::new(HashMap::from([
+            ("skip_gen".to_string(), because("generated mods are skipped")),
+        ]));
+
+        let disabled = find_disabled(&modules, &CanonPath::new("").unwrap(), &CanonPath::new("").unwrap(), &conditions).unwrap();
+
+        let summary = disabled.iter()
+            .map(|(index, module, reason)| (*index, module.get_name().to_string(), reason.to_owned()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (2, "disabled".to_string(), "not now".to_string()),
+                (4, "generated".to_string(), "generated mods are skipped".to_string()),
+            ]
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_skip_reason {
+    use std::collections::HashMap;
+
+    use crate::args::Install;
+    use crate::canon_path::CanonPath;
+    use crate::lowercase::lwc;
+    use crate::module::components::{Component, Components};
+    use crate::module::disable_condition::DisableCondition;
+    use crate::module::manifest_conditions::ManifestConditions;
+    use crate::module::module::Module;
+    use crate::module::weidu_mod::WeiduMod;
+    use crate::process_weidu_mod::{SkipReason, SkippedModule};
+
+    use super::{select_phase, skip_reason, unselected_modules};
+
+    fn weidu_mod(name: &str, components: &[u32], phase: Option<&str>) -> Module {
+        Module::Mod { weidu_mod: WeiduMod {
+            name: lwc!(name),
+            components: Components::List(components.iter().map(|index| Component::Simple(*index)).collect()),
+            phase: phase.map(str::to_string),
+            ..WeiduMod::default()
+        } }
+    }
+
+    fn reason(module: &Module) -> Option<SkipReason> {
+        skip_reason(module, &CanonPath::new("").unwrap(), &CanonPath::new("").unwrap(), &ManifestConditions::new(HashMap::new()),
+                    &[lwc!("skipped_mod")]).unwrap()
+    }
+
+    #[test]
+    fn requested_skip() {
+        let module = weidu_mod("Skipped_Mod", &[1], None);
+        assert_eq!(reason(&module), Some(SkipReason::Requested));
+    }
+
+    #[test]
+    fn disabled_module() {
+        let module = Module::Mod { weidu_mod: WeiduMod {
+            name: lwc!("my_mod"),
+            disabled_if: Some(DisableCondition::Because { because: "not now".to_string() }),
+            ..WeiduMod::default()
+        } };
+        assert_eq!(reason(&module), Some(SkipReason::Disabled("not now".to_string())));
+    }
+
+    #[test]
+    fn selected_module() {
+        assert_eq!(reason(&weidu_mod("my_mod", &[1, 2], None)), None);
+    }
+
+    #[test]
+    fn filtered_and_out_of_range_modules() {
+        let modules = vec![
+            weidu_mod("first", &[0], Some("early")),
+            weidu_mod("second", &[0], Some("late")),
+            weidu_mod("third", &[0], Some("early")),
+            weidu_mod("fourth", &[0], Some("early")),
+        ];
+        let range = &modules[1..3];
+        let selected = select_phase(range.iter().enumerate().collect(), &modules, "early").unwrap();
+        let opts = Install { phase: Some("early".to_string()), ..Install::default() };
+
+        assert_eq!(
+            unselected_modules(&modules, range, &selected, &opts),
+            vec![
+                SkippedModule { index: 1, name: lwc!("first"), reason: SkipReason::OutOfRange },
+                SkippedModule { index: 2, name: lwc!("second"), reason: SkipReason::Filtered("--phase early".to_string()) },
+                SkippedModule { index: 4, name: lwc!("fourth"), reason: SkipReason::OutOfRange },
+            ]
+        );
+    }
+
+    #[test]
+    fn modules_not_reinstalled() {
+        let modules = vec![weidu_mod("first", &[0], None), weidu_mod("second", &[0], None)];
+        let selected = vec![(1, &modules[1])];
+        let opts = Install { reinstall: vec![lwc!("second")], ..Install::default() };
+
+        assert_eq!(
+            unselected_modules(&modules, &modules, &selected, &opts),
+            vec![SkippedModule { index: 1, name: lwc!("first"), reason: SkipReason::Filtered("--reinstall".to_string()) }]
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_print_order {
+    use std::collections::HashMap;
+
+    use crate::args::Install;
+    use crate::canon_path::CanonPath;
+    use crate::lowercase::lwc;
+    use crate::module::components::{Component, Components};
+    use crate::module::disable_condition::DisableCondition;
+    use crate::module::manifest_conditions::ManifestConditions;
+    use crate::module::module::Module;
+    use crate::module::weidu_mod::WeiduMod;
+
+    use super::{install_order, select_modules};
+
+    fn weidu_mod(name: &str, components: Components, phase: Option<&str>) -> Module {
+        Module::Mod { weidu_mod: WeiduMod { name: lwc!(name), components, phase: phase.map(str::to_string), ..WeiduMod::default() } }
+    }
+
+    fn list(indexes: &[u32]) -> Components {
+        Components::List(indexes.iter().map(|index| Component::Simple(*index)).collect())
+    }
+
+    fn modules() -> Vec<Module> {
+        vec![
+            weidu_mod("fixpack", list(&[0]), Some("early")),
+            weidu_mod("tweaks", list(&[1, 2, 3]), Some("late")),
+            weidu_mod("ui", Components::Ask, Some("early")),
+            Module::Mod { weidu_mod: WeiduMod {
+                name: lwc!("npc"),
+                components: Components::All,
+                phase: Some("early".to_string()),
+                disabled_if: Some(DisableCondition::Because { because: "not ready".to_string() }),
+                ..WeiduMod::default()
+            } },
+            weidu_mod("final", list(&[0]), Some("early")),
+        ]
+    }
+
+    fn order(opts: &Install) -> Vec<String> {
+        let modules = modules();
+        let (_, selected) = select_modules(&modules, opts).unwrap();
+        install_order(&selected, &CanonPath::new("").unwrap(), &CanonPath::new("").unwrap(),
+                        &ManifestConditions::new(HashMap::new()))
+    }
+
+    #[test]
+    fn print_order_with_phase() {
+        let opts = Install { phase: Some("early".to_string()), ..Install::default() };
+        assert_eq!(
+            order(&opts),
+            vec![
+                "1 - fixpack: [0]".to_string(),
+                "3 - ui: ask".to_string(),
+                "4 - npc: all (disabled - not ready)".to_string(),
+                "5 - final: [0]".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn print_order_with_only() {
+        let opts = Install { only: vec![lwc!("Tweaks"), lwc!("npc")], ..Install::default() };
+        assert_eq!(order(&opts), vec!["2 - tweaks: [1, 2, 3]".to_string(), "4 - npc: all (disabled - not ready)".to_string()]);
+
+        let opts = Install { from_index: Some(2), only: vec![lwc!("fixpack"), lwc!("final")], ..Install::default() };
+        assert_eq!(order(&opts), vec!["5 - final: [0]".to_string()]);
+
+        let opts = Install { only: vec![lwc!("missing")], ..Install::default() };
+        let error = select_modules(&modules(), &opts).unwrap_err();
+        assert_eq!(error.to_string(), "Mod missing (given with --only) is not in the manifest");
+    }
+
+    #[test]
+    fn print_order_with_range_and_phase() {
+        let opts = Install { from_index: Some(2), to_index: Some(5), phase: Some("early".to_string()), ..Install::default() };
+        assert_eq!(order(&opts), vec!["3 - ui: ask".to_string(), "4 - npc: all (disabled - not ready)".to_string()]);
+
+        let opts = Install { from_index: Some(2), count: Some(1), ..Install::default() };
+        assert_eq!(order(&opts), vec!["2 - tweaks: [1, 2, 3]".to_string()]);
+    }
+}
+
+#[cfg(test)]
+mod test_phase {
+    use crate::lowercase::lwc;
+    use crate::module::gen_mod::GeneratedMod;
+    use crate::module::module::Module;
+    use crate::module::weidu_mod::WeiduMod;
+
+    use super::select_phase;
+
+    fn weidu_mod(name: &str, phase: Option<&str>) -> Module {
+        Module::Mod { weidu_mod: WeiduMod { name: lwc!(name), phase: phase.map(str::to_string), ..WeiduMod::default() } }
+    }
+
+    fn modules() -> Vec<Module> {
+        vec![
+            weidu_mod("fixpack", Some("early")),
+            weidu_mod("no_phase", None),
+            Module::Generated { gen: GeneratedMod {
+                gen_mod: lwc!("generated"),
+                phase: Some("late".to_string()),
+                ..GeneratedMod::default()
+            } },
+            weidu_mod("tweaks", Some("early")),
+            weidu_mod("final", Some("late")),
+        ]
+    }
+
+    fn names(selected: &[(usize, &Module)]) -> Vec<(usize, String)> {
+        selected.iter().map(|(index, module)| (*index, module.get_name().to_string())).collect()
+    }
+
+    #[test]
+    fn select_modules_of_phase_in_order() {
+        let modules = modules();
+        let selected = select_phase(modules.iter().enumerate().collect(), &modules, "early").unwrap();
+        assert_eq!(names(&selected), vec![(0, "fixpack".to_string()), (3, "tweaks".to_string())]);
+
+        let selected = select_phase(modules.iter().enumerate().collect(), &modules, "late").unwrap();
+        assert_eq!(names(&selected), vec![(2, "generated".to_string()), (4, "final".to_string())]);
+    }
+
+    #[test]
+    fn select_phase_inside_range() {
+        let modules = modules();
+        let range = modules.iter().enumerate().skip(2).collect();
+        let selected = select_phase(range, &modules, "early").unwrap();
+        assert_eq!(names(&selected), vec![(3, "tweaks".to_string())]);
+    }
+
+    #[test]
+    fn unknown_phase_lists_available_phases() {
+        let modules = modules();
+        let error = select_phase(modules.iter().enumerate().collect(), &modules, "mid").unwrap_err();
+        assert_eq!(error.to_string(), "Unknown phase mid, available phases are: early, late");
+    }
+
+    #[test]
+    fn deserialize_phase() {
+        let module: Module = serde_yaml::from_str(r#"
+            name: tweaks
+            components: [1]
+            phase: early
+        "#).unwrap();
+        assert_eq!(module.get_phase(), Some("early"));
+
+        let module: Module = serde_yaml::from_str(r#"
+            gen_mod: generated
+            files: []
+            phase: late
+        "#).unwrap();
+        assert_eq!(module.get_phase(), Some("late"));
+    }
+}
+
+#[cfg(test)]
+mod test_save_selection {
+    use crate::lowercase::lwc;
+    use crate::module::components::{Component, Components, FullComponent};
+    use crate::module::manifest::Manifest;
+    use crate::module::module::Module;
+
+    use super::save_selection;
+
+    #[test]
+    fn choices_are_persisted_in_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest_path = dir.path().join("manifest.yml");
+        std::fs::write(&manifest_path, r#"
+version: "1"
+global:
+  lang_dir: fr_FR
+modules:
+  - name: first
+    components: [0]
+  - name: chosen
+    components: ask
+  - name: last
+    components: ask
+"#).unwrap();
+        let mut manifest = Manifest::read_path_convert_comments(&manifest_path).unwrap();
+        let module = match &manifest.modules[1] {
+            Module::Mod { weidu_mod } => weidu_mod.clone(),
+            _ => panic!("not a weidu mod"),
+        };
+        let selection = vec![
+            Component::Full(FullComponent { index: 2, component_name: "Second component".to_string(), language: None }),
+            Component::Full(FullComponent { index: 5, component_name: "Fifth component".to_string(), language: None }),
+        ];
+
+        save_selection(&mut manifest, 1, &module, selection.clone(), &manifest_path, false).unwrap();
+
+        let saved = Manifest::read_path(manifest_path.to_str().unwrap()).unwrap();
+        assert_eq!(saved.modules.len(), 3);
+        assert_eq!(saved.modules[1].get_name(), &lwc!("chosen"));
+        assert_eq!(saved.modules[1].get_components(), Components::List(selection));
+        assert_eq!(saved.modules[0].get_components(), Components::List(vec![Component::Simple(0)]));
+        assert_eq!(saved.modules[2].get_components(), Components::Ask);
+    }
+}
+
+#[cfg(test)]
+mod test_post_install_replace {
+    use anyhow::{bail, Result};
+    use chrono::Local;
+
+    use crate::args::Install;
+    use crate::canon_path::CanonPath;
+    use crate::global::Global;
+    use crate::lowercase::lwc;
+    use crate::module::components::Components;
+    use crate::module::location::replace::ReplaceSpec;
+    use crate::module::module::Module;
+    use crate::module::weidu_mod::WeiduMod;
+    use crate::obtain::get_options::StrictReplaceAction;
+    use crate::post_install::InstallStatus;
+    use crate::process_weidu_mod::ProcessResult;
+    use crate::timeline::InstallTimeline;
+
+    use super::{exec_post_install_replace, install_modules, reached_manifest_end, select_modules, InstallRecords};
+
+    fn setup() -> (tempfile::TempDir, CanonPath, Global) {
+        let game_dir = tempfile::tempdir().unwrap();
+        for mod_name in ["mod_a", "mod_b"] {
+            std::fs::create_dir_all(game_dir.path().join(mod_name)).unwrap();
+            std::fs::write(game_dir.path().join(mod_name).join("setup.tra"), "@1 = ~teh sword~").unwrap();
+        }
+        let canon = CanonPath::new(game_dir.path()).unwrap();
+        let global = Global {
+            post_install_replace: vec![ReplaceSpec {
+                file_globs: vec!["*/*.tra".to_string()],
+                replace: "teh".to_string(),
+                // would show twice if applied twice
+                with: "the teh".to_string(),
+                max_depth: Some(2),
+                ..ReplaceSpec::default()
+            }],
+            ..Global::default()
+        };
+        (game_dir, canon, global)
+    }
+
+    fn opts() -> Install {
+        Install { check_replace: StrictReplaceAction::Fail, ..Install::default() }
+    }
+
+    fn tra_content(game_dir: &tempfile::TempDir, mod_name: &str) -> String {
+        std::fs::read_to_string(game_dir.path().join(mod_name).join("setup.tra")).unwrap()
+    }
+
+    #[test]
+    fn replace_applied_once_across_mods() {
+        let (game_dir, canon, global) = setup();
+
+        exec_post_install_replace(&global, &canon, &opts(), true).unwrap();
+
+        assert_eq!(tra_content(&game_dir, "mod_a"), "@1 = ~the teh sword~");
+        assert_eq!(tra_content(&game_dir, "mod_b"), "@1 = ~the teh sword~");
+    }
+
+    fn weidu_mod(name: &str, phase: Option<&str>) -> Module {
+        Module::Mod { weidu_mod: WeiduMod {
+            name: lwc!(name), components: Components::All, phase: phase.map(str::to_string), ..WeiduMod::default()
+        } }
+    }
+
+    fn installed(module: &Module) -> Result<ProcessResult> {
+        Ok(ProcessResult { stop: false, timeline: InstallTimeline::new(module.get_name().to_owned(), Local::now()),
+                            status: InstallStatus::Success, skipped: None })
+    }
+
+    /// Installs the modules selected by `opts` then applies the manifest-level replace, returns whether the
+    /// installation reached the end of the manifest.
+    fn install_then_replace(modules: &[Module], opts: &Install, canon: &CanonPath, global: &Global,
+                            install: &mut impl FnMut(&Module) -> Result<ProcessResult>) -> bool {
+        let (range, selected) = select_modules(modules, opts).unwrap();
+        let mut records = InstallRecords::new(opts, vec![]).unwrap();
+        let end = install_modules(&selected, opts, canon, &mut records, |_, _, module| install(module));
+        let reached_end = reached_manifest_end(&end, &records, opts, range, modules);
+        exec_post_install_replace(global, canon, opts, reached_end).unwrap();
+        reached_end
+    }
+
+    #[test]
+    fn replace_not_applied_after_a_phase() {
+        let (game_dir, canon, global) = setup();
+        let modules = [weidu_mod("fixpack", Some("early")), weidu_mod("tweaks", Some("late")), weidu_mod("final", Some("early"))];
+
+        // the last module of the manifest is in the phase, but `tweaks` was not installed
+        assert!(!install_then_replace(&modules, &Install { phase: Some("early".to_string()), ..opts() }, &canon, &global,
+                                        &mut installed));
+        assert_eq!(tra_content(&game_dir, "mod_a"), "@1 = ~teh sword~");
+    }
+
+    #[test]
+    fn replace_not_applied_after_only() {
+        let (game_dir, canon, global) = setup();
+        let modules = [weidu_mod("first", None), weidu_mod("last", None)];
+
+        assert!(!install_then_replace(&modules, &Install { only: vec![lwc!("last")], ..opts() }, &canon, &global,
+                                        &mut installed));
+        assert_eq!(tra_content(&game_dir, "mod_a"), "@1 = ~teh sword~");
+
+        assert!(install_then_replace(&modules, &opts(), &canon, &global, &mut installed));
+        assert_eq!(tra_content(&game_dir, "mod_a"), "@1 = ~the teh sword~");
+    }
+
+    #[test]
+    fn replace_applied_when_a_failed_installation_is_retried() {
+        let (game_dir, canon, global) = setup();
+        let modules = ["first", "broken", "last"].map(|name| weidu_mod(name, None));
+        let mut attempts = vec![];
+        // `broken` only fails the first time
+        let mut install = |module: &Module| {
+            let name = module.get_name().to_owned();
+            let fails = name == lwc!("broken") && !attempts.contains(&name);
+            attempts.push(name.clone());
+            if fails {
+                bail!("weidu failed");
+            }
+            Ok(ProcessResult { stop: false, timeline: InstallTimeline::new(name, Local::now()), status: InstallStatus::Success,
+                                skipped: None })
+        };
+
+        assert!(!install_then_replace(&modules, &opts(), &canon, &global, &mut install));
+        assert_eq!(tra_content(&game_dir, "mod_a"), "@1 = ~teh sword~");
+
+        // second attempt, from the failed module
+        assert!(install_then_replace(&modules, &Install { from_index: Some(2), ..opts() }, &canon, &global, &mut install));
+        assert_eq!(attempts, vec![lwc!("first"), lwc!("broken"), lwc!("broken"), lwc!("last")]);
+        assert_eq!(tra_content(&game_dir, "mod_a"), "@1 = ~the teh sword~");
+        assert_eq!(tra_content(&game_dir, "mod_b"), "@1 = ~the teh sword~");
+    }
+
+    #[test]
+    fn replace_outside_game_dir_is_refused() {
+        let (game_dir, canon, mut global) = setup();
+        global.post_install_replace[0].file_globs = vec!["../*/*.tra".to_string()];
+
+        assert!(exec_post_install_replace(&global, &canon, &opts(), true).is_err());
+        assert_eq!(tra_content(&game_dir, "mod_a"), "@1 = ~teh sword~");
+    }
+}
+
+#[cfg(test)]
+mod test_failed {
+    use crate::lowercase::lwc;
+
+    use super::describe_failed;
+
+    #[test]
+    fn failed_modules_summary() {
+        assert_eq!(describe_failed(&[(3, lwc!("my_mod")), (12, lwc!("other_mod"))]), "3 - my_mod\n  - 12 - other_mod");
+    }
+}
+
+#[cfg(test)]
+mod test_disk_usage_summary {
+    use chrono::Local;
+
+    use crate::lowercase::lwc;
+    use crate::timeline::InstallTimeline;
+
+    use super::describe_disk_usage;
+
+    #[test]
+    fn disk_usage_of_each_module_and_total() {
+        let timeline = |name: &str, added_bytes: Option<i64>| InstallTimeline {
+            added_bytes, ..InstallTimeline::new(lwc!(name), Local::now())
+        };
+        let timelines = [
+            timeline("my_mod", Some(3 * 1024 * 1024)),
+            timeline("other_mod", Some(-1024)),
+            // the last timeline marks the end of the installation, nothing measured
+            timeline("finished", None),
+        ];
+        assert_eq!(describe_disk_usage(&timelines), "+3.0 MiB\n  - my_mod: +3.0 MiB\n  - other_mod: -1.0 KiB");
+    }
+}
+
+#[cfg(test)]
+mod test_install_loop {
+    use anyhow::{bail, Result};
+    use chrono::Local;
+
+    use crate::args::Install;
+    use crate::canon_path::CanonPath;
+    use crate::install_summary::{Summary, SummaryOutcome};
+    use crate::lowercase::{lwc, LwcString};
+    use crate::module::components::Components;
+    use crate::module::module::Module;
+    use crate::module::weidu_mod::WeiduMod;
+    use crate::post_install::InstallStatus;
+    use crate::process_weidu_mod::ProcessResult;
+    use crate::timeline::InstallTimeline;
+
+    use super::{install_modules, InstallRecords, LoopEnd};
+
+    fn weidu_mod(name: &str) -> Module {
+        Module::Mod { weidu_mod: WeiduMod { name: lwc!(name), components: Components::All, ..WeiduMod::default() } }
+    }
+
+    fn modules() -> Vec<Module> {
+        vec![weidu_mod("first"), weidu_mod("broken"), weidu_mod("last")]
+    }
+
+    /// Installs every mod but `broken`, which fails with an error
+    fn install(module: &Module, installed: &mut Vec<LwcString>) -> Result<ProcessResult> {
+        installed.push(module.get_name().to_owned());
+        if module.get_name() == &lwc!("broken") {
+            bail!("weidu failed");
+        }
+        Ok(ProcessResult {
+            stop: false,
+            timeline: InstallTimeline::new(module.get_name().to_owned(), Local::now()),
+            status: InstallStatus::Success,
+            skipped: None,
+        })
+    }
+
+    fn summary_outcomes(temp_dir: &tempfile::TempDir) -> Vec<(usize, LwcString, SummaryOutcome)> {
+        let content = std::fs::read_to_string(temp_dir.path().join("summary.json")).unwrap();
+        let summary: Summary = serde_json::from_str(&content).unwrap();
+        summary.modules.into_iter().map(|record| (record.index, record.name, record.outcome)).collect()
+    }
+
+    fn opts(temp_dir: &tempfile::TempDir, continue_on_error: bool) -> Install {
+        let summary = temp_dir.path().join("summary.json").to_string_lossy().to_string();
+        Install { summary: Some(summary), continue_on_error, ..Install::default() }
+    }
+
+    #[test]
+    fn failed_module_is_recorded_when_the_installation_stops() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let game_dir = CanonPath::new(temp_dir.path()).unwrap();
+        let opts = opts(&temp_dir, false);
+        let modules = modules();
+        let selected = modules.iter().enumerate().collect::<Vec<_>>();
+        let mut records = InstallRecords::new(&opts, vec![]).unwrap();
+        let mut installed = vec![];
+
+        let end = install_modules(&selected, &opts, &game_dir, &mut records,
+                                    |_, _, module| install(module, &mut installed));
+
+        assert!(end.is_err());
+        assert_eq!(installed, vec![lwc!("first"), lwc!("broken")]);
+        assert!(records.finish(end).is_err());
+        assert_eq!(summary_outcomes(&temp_dir), vec![
+            (1, lwc!("first"), SummaryOutcome::Installed),
+            (2, lwc!("broken"), SummaryOutcome::Failed),
+        ]);
+    }
+
+    #[test]
+    fn later_modules_are_installed_after_a_failure_with_continue_on_error() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let game_dir = CanonPath::new(temp_dir.path()).unwrap();
+        let opts = opts(&temp_dir, true);
+        let modules = modules();
+        let selected = modules.iter().enumerate().collect::<Vec<_>>();
+        let mut records = InstallRecords::new(&opts, vec![]).unwrap();
+        let mut installed = vec![];
+
+        let end = install_modules(&selected, &opts, &game_dir, &mut records,
+                                    |_, _, module| install(module, &mut installed));
+
+        assert!(matches!(end, Ok(LoopEnd::Completed)));
+        assert_eq!(installed, vec![lwc!("first"), lwc!("broken"), lwc!("last")]);
+        assert_eq!(records.failed, vec![(2, lwc!("broken"))]);
+        let error = records.finish(end).unwrap_err();
+        assert_eq!(error.to_string(), "Installation done, 1 module(s) failed");
+        assert_eq!(summary_outcomes(&temp_dir), vec![
+            (1, lwc!("first"), SummaryOutcome::Installed),
+            (2, lwc!("broken"), SummaryOutcome::Failed),
+            (3, lwc!("last"), SummaryOutcome::Installed),
+        ]);
+    }
+}
+
+#[cfg(test)]
+mod test_prefetch {
+    use std::path::PathBuf;
+
+    use crate::args::Install;
+    use crate::cache::Cache;
+    use crate::canon_path::CanonPath;
+    use crate::config::Config;
+    use crate::download::Downloader;
+    use crate::global::Global;
+    use crate::install_events::EventSender;
+    use crate::lowercase::lwc;
+    use crate::module::gen_mod::GeneratedMod;
+    use crate::module::global_locations::GlobalLocations;
+    use crate::module::location::http::Http;
+    use crate::module::location::location::{ConcreteLocation, Location};
+    use crate::module::location::source::Source;
+    use crate::module::module::Module;
+    use crate::module::weidu_mod::WeiduMod;
+    use crate::obtain::get_module::ModuleDownload;
+
+    use super::prefetch_locations;
+
+    fn weidu_mod(name: &str, source: Option<Source>) -> Module {
+        Module::Mod { weidu_mod: WeiduMod {
+            name: lwc!(name),
+            location: source.map(|source| Location::Concrete { concrete: ConcreteLocation { source, ..ConcreteLocation::default() } }),
+            ..WeiduMod::default()
+        } }
+    }
+
+    fn http(name: &str) -> Option<Source> {
+        Some(Source::Http(Http::from(&format!("http://example.com/{}.zip", name))))
+    }
+
+    #[test]
+    fn only_downloaded_archives_once_per_mod() {
+        let game_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(game_dir.path().join("installed")).unwrap();
+        std::fs::write(game_dir.path().join("installed/installed.tp2"), "").unwrap();
+        let game_dir = CanonPath::new(game_dir.path()).unwrap();
+        let modules = vec![
+            weidu_mod("first", http("first")),
+            weidu_mod("local", Some(Source::Absolute { path: "/mods/local.zip".to_string(), rename: None })),
+            Module::Generated { gen: GeneratedMod { gen_mod: lwc!("generated"), ..GeneratedMod::default() } },
+            weidu_mod("installed", http("installed")),
+            weidu_mod("no_location", None),
+            weidu_mod("first", http("first")),
+            weidu_mod("second", http("second")),
+        ];
+        let selected = modules.iter().enumerate().collect::<Vec<_>>();
+
+        let config = Config::default();
+        let global = Global::default();
+        let global_locations = GlobalLocations::default();
+        let opts = Install::default();
+        let downloader = Downloader::from_config(&config, EventSender::default());
+        let cache = Cache::Path(PathBuf::from("/cache_path"));
+        let module_downloader = ModuleDownload::new(&config, &global, &global_locations, &opts,
+                                                    &downloader, &game_dir, &cache);
+
+        let names = prefetch_locations(&selected, &module_downloader, &game_dir).into_iter()
+            .map(|(name, _)| name.to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![lwc!("first"), lwc!("second")]);
+    }
+}
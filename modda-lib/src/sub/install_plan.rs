@@ -0,0 +1,191 @@
+
+use anyhow::Result;
+use itertools::Itertools;
+
+use crate::archive_extractor::ExtractionMethod;
+use crate::global::Global;
+use crate::lowercase::LwcString;
+use crate::modda_context::WeiduContext;
+use crate::module::location::location::ConcreteLocation;
+use crate::module::location::source::Source;
+use crate::module::module::Module;
+use crate::obtain::get_module::ModuleDownload;
+use crate::process_weidu_mod::SkipReason;
+use crate::run_weidu::weidu_install_plan;
+use crate::tp2::find_tp2;
+
+use super::audit::describe_source;
+
+/// What `install --dry-run` would do with a module (`index` in the manifest, counting from one): how the mod is
+/// obtained and modified, and the weidu command line.<br>
+/// Nothing is downloaded, extracted or written, the decisions are those of the installation.
+pub fn module_plan(index: usize, module: &Module, skip: Option<&SkipReason>, global: &Global,
+                    module_downloader: &ModuleDownload, weidu_context: &WeiduContext) -> Result<Vec<String>> {
+    let mut lines = vec![format!("{} - {}", index, module.describe())];
+    if let Some(reason) = skip {
+        lines.push(format!("  skipped - {}", reason.describe()));
+        return Ok(lines);
+    }
+    match module {
+        Module::Generated { gen } =>
+            lines.push(format!("  generate a mod that copies {} file definition(s) to override", gen.files.len())),
+        Module::Mod { weidu_mod } => {
+            let tp2 = match find_tp2(weidu_context.current_dir, &weidu_mod.name) {
+                Ok(tp2) => {
+                    lines.push("  already in the game directory (not obtained again)".to_string());
+                    Some(tp2.to_string_lossy().to_string())
+                }
+                Err(_) => {
+                    let location = module_downloader.get_module_location(weidu_mod)?;
+                    lines.extend(location_plan(location, &weidu_mod.name, module_downloader)?);
+                    None
+                }
+            };
+            let weidu_lines = weidu_install_plan(tp2.as_deref(), weidu_mod, module_downloader.opts, global, weidu_context)?;
+            lines.extend(weidu_lines.into_iter().map(|line| format!("  {}", line)));
+        }
+    }
+    Ok(lines)
+}
+
+fn location_plan(location: &ConcreteLocation, mod_name: &LwcString, module_downloader: &ModuleDownload) -> Result<Vec<String>> {
+    let archive = module_downloader.local_archive(location, mod_name)?;
+    let mut lines = vec![];
+    lines.push(match (&location.source, archive.exists()) {
+        (Source::Http(_) | Source::Github(_), true) => format!("  archive already downloaded in {:?}", archive),
+        (Source::Http(_) | Source::Github(_), false) =>
+            format!("  download from {} to {:?}", describe_source(&location.source), archive),
+        (source, _) => format!("  from {} ({:?})", describe_source(source), archive),
+    });
+    let name = archive.file_name().unwrap_or_default().to_string_lossy();
+    lines.push(match module_downloader.extractor.extraction_method(&archive, location)? {
+        ExtractionMethod::Directory { temp_copy: false } => "  copy the directory content".to_string(),
+        ExtractionMethod::Directory { temp_copy: true } =>
+            "  copy the directory content (to a temporary directory first, for the precopy command)".to_string(),
+        ExtractionMethod::Zip => format!("  extract {} (zip)", name),
+        ExtractionMethod::Tar(codec) => format!("  extract {} ({})", name, codec.name()),
+        ExtractionMethod::SevenZip => format!("  extract {} (7z)", name),
+        ExtractionMethod::Rar => format!("  extract {} (rar)", name),
+        ExtractionMethod::External(extractor) => format!("  extract {} with {}", name, extractor.command),
+    });
+    if let Some(precopy) = &location.precopy {
+        lines.push(format!("  run precopy command {}", std::iter::once(&precopy.command)
+            .chain(precopy.args.iter().flatten())
+            .join(" ")));
+    }
+    let patches = location.patch.iter().count() + location.patches.len();
+    if patches > 0 {
+        lines.push(format!("  apply {} patch(es)", patches));
+    }
+    match &location.replace {
+        Some(replace) if !replace.is_empty() => lines.push(format!("  apply {} replace(s)", replace.len())),
+        _ => {}
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod test_install_plan {
+    use std::path::PathBuf;
+
+    use crate::args::Install;
+    use crate::cache::Cache;
+    use crate::canon_path::CanonPath;
+    use crate::config::Config;
+    use crate::download::Downloader;
+    use crate::global::Global;
+    use crate::install_events::EventSender;
+    use crate::lowercase::lwc;
+    use crate::modda_context::WeiduContext;
+    use crate::module::components::{Component, Components, FullComponent};
+    use crate::module::gen_mod::GeneratedMod;
+    use crate::module::global_locations::GlobalLocations;
+    use crate::module::location::http::Http;
+    use crate::module::location::location::{ConcreteLocation, Location};
+    use crate::module::location::source::Source;
+    use crate::module::module::Module;
+    use crate::module::pre_copy_command::PrecopyCommand;
+    use crate::module::weidu_mod::WeiduMod;
+    use crate::obtain::get_module::ModuleDownload;
+    use crate::process_weidu_mod::SkipReason;
+
+    use super::module_plan;
+
+    #[test]
+    fn plan_of_modules() {
+        let game_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(game_dir.path().join("installed")).unwrap();
+        std::fs::write(game_dir.path().join("installed/installed.tp2"), "").unwrap();
+        let game_dir = CanonPath::new(game_dir.path()).unwrap();
+
+        let config = Config::default();
+        let global = Global { lang_preferences: Some(vec!["french".to_string()]), ..Global::default() };
+        let global_locations = GlobalLocations::default();
+        let opts = Install::default();
+        let downloader = Downloader::from_config(&config, EventSender::default());
+        let cache = Cache::Path(PathBuf::from("/cache_path"));
+        let module_downloader = ModuleDownload::new(&config, &global, &global_locations, &opts,
+                                                    &downloader, &game_dir, &cache);
+        let weidu_context = WeiduContext { current_dir: &game_dir, config: &config, debug_log: None };
+        let plan = |module: &Module, skip: Option<&SkipReason>|
+            module_plan(1, module, skip, &global, &module_downloader, &weidu_context).unwrap()[1..].to_vec();
+
+        let downloaded = Module::Mod { weidu_mod: WeiduMod {
+            name: lwc!("downloaded"),
+            language: Some(2),
+            components: Components::List(vec![
+                Component::Simple(0),
+                Component::Full(FullComponent { index: 3, component_name: String::new(), language: Some(1) }),
+            ]),
+            location: Some(Location::Concrete { concrete: ConcreteLocation {
+                source: Source::Http(Http::from("http://example.com/downloaded.zip")),
+                precopy: Some(PrecopyCommand { command: "fix.sh".to_string(), args: Some(vec!["-v".to_string()]), subdir: None }),
+                replace: Some(vec![Default::default()]),
+                ..ConcreteLocation::default()
+            } }),
+            ..WeiduMod::default()
+        } };
+        let lines = plan(&downloaded, None);
+        assert!(lines[0].starts_with("  download from http://example.com/downloaded.zip to "));
+        assert_eq!(&lines[1..], &[
+            "  extract downloaded.zip (zip)".to_string(),
+            "  run precopy command fix.sh -v".to_string(),
+            "  apply 1 replace(s)".to_string(),
+            "  weidu: components [0, 3] (the command line is known once the mod is obtained)".to_string(),
+        ]);
+
+        let installed = Module::Mod { weidu_mod: WeiduMod {
+            name: lwc!("installed"),
+            language: Some(2),
+            components: Components::List(vec![
+                Component::Simple(0),
+                Component::Full(FullComponent { index: 3, component_name: String::new(), language: Some(1) }),
+            ]),
+            ..WeiduMod::default()
+        } };
+        let lines = plan(&installed, None);
+        assert_eq!(lines[0], "  already in the game directory (not obtained again)");
+        // one weidu run per language
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("  weidu: \"weidu\" "));
+        assert!(lines[1].ends_with("\"--language\" \"2\" \"--force-install-list\" \"0\""));
+        assert!(lines[2].ends_with("\"--language\" \"1\" \"--force-install-list\" \"3\""));
+        assert_eq!(plan(&installed, Some(&SkipReason::Requested)), vec!["  skipped - skipped (requested)".to_string()]);
+
+        let asked = Module::Mod { weidu_mod: WeiduMod {
+            name: lwc!("installed"), components: Components::Ask, ..WeiduMod::default()
+        } };
+        let lines = plan(&asked, None);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("  weidu asks for the components: \"weidu\" "));
+        assert!(!lines[1].contains("--force-install-list"));
+
+        let no_components = Module::Mod { weidu_mod: WeiduMod {
+            name: lwc!("installed"), components: Components::None, ..WeiduMod::default()
+        } };
+        assert_eq!(plan(&no_components, None)[1], "  weidu is not run (no components)");
+
+        let generated = Module::Generated { gen: GeneratedMod { gen_mod: lwc!("generated"), ..GeneratedMod::default() } };
+        assert_eq!(plan(&generated, None), vec!["  generate a mod that copies 0 file definition(s) to override".to_string()]);
+    }
+}
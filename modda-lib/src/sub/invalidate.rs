@@ -1,6 +1,7 @@
 
 use crate::args::Invalidate;
-use crate::cache::Cache;
+use crate::cache::{cache_file_name, remove_name_sidecar, Cache};
+use crate::config::Config;
 use crate::lowercase::lwc;
 use crate::module::location::location::{ConcreteLocation, Location};
 use crate::module::location::source::Source;
@@ -8,10 +9,11 @@ use crate::module::manifest::Manifest;
 use crate::module::module::Module;
 use crate::module::weidu_mod::WeiduMod;
 
+
 use anyhow::{bail, Result};
 
 
-pub fn invalidate(params: &Invalidate, cache: &Cache) -> Result<()> {
+pub fn invalidate(params: &Invalidate, config: &Config, cache: &Cache) -> Result<()> {
     let manifest = match Manifest::read_path(&params.manifest_path) {
         Ok(manifest) => manifest,
         Err(error) => bail!("Could not read manifest\n -> {:?}", error),
@@ -24,14 +26,14 @@ pub fn invalidate(params: &Invalidate, cache: &Cache) -> Result<()> {
                 Module::Mod { weidu_mod } => match &weidu_mod.location {
                     None => {} // continue to search a mod with same name and a location location
                     Some(Location::Concrete { concrete }) => {
-                        clear_mod_archive(concrete, &weidu_mod, cache)?;
+                        clear_mod_archive(concrete, &weidu_mod, config, cache)?;
                         return Ok(()) // only once per name
                     }
                     Some(Location::Ref { r#ref }) => {
                         match manifest.locations.find(r#ref) {
                             None => {} // continue to search a mod with same name and a location location
                             Some(location) => {
-                                clear_mod_archive(location, &weidu_mod, cache)?;
+                                clear_mod_archive(location, &weidu_mod, config, cache)?;
                                 return Ok(()) // only once per name
                             }
                         }
@@ -44,19 +46,20 @@ pub fn invalidate(params: &Invalidate, cache: &Cache) -> Result<()> {
     bail!("Module {} not found or location not provided", mod_name);
 }
 
-fn clear_mod_archive(location: &ConcreteLocation, module :&WeiduMod, cache: &Cache) -> Result<()> {
+fn clear_mod_archive(location: &ConcreteLocation, module :&WeiduMod, config: &Config, cache: &Cache) -> Result<()> {
     match location.source {
         Source::Local {..} | Source::Absolute{..} => bail!("Can't invalidate mods with absolute or local sources"),
         _ => {}
     }
-    let dest = cache.join(location.source.save_subdir()?);
-    let save_name = location.source.save_name(&module.name)?;
-    let archive_path = dest.join(save_name);
+    let layout = config.cache_layout.unwrap_or_default();
+    let dest = cache.join(location.source.save_subdir(layout)?);
+    let save_name = location.source.save_name(&module.name, layout)?;
+    let archive_path = dest.join(cache_file_name(&dest, &save_name, config.cache_path_max_length));
     if archive_path.exists() {
-        match std::fs::remove_file(&archive_path) {
-            Ok(_) => Ok(()),
-            Err(error) => bail!("Could not remove archive {:?}\n -> {:?}", archive_path, error),
+        if let Err(error) = std::fs::remove_file(&archive_path) {
+            bail!("Could not remove archive {:?}\n -> {:?}", archive_path, error);
         }
+        remove_name_sidecar(&archive_path)
     } else {
         println!("Archive for mod {} not present.", module.name);
         Ok(())
@@ -1,5 +1,18 @@
 
 pub mod append_mod;
+pub mod audit;
+pub mod check;
 pub mod extract_manifest;
+pub mod foreign;
 pub mod install;
+pub mod install_plan;
+pub mod preview_transforms;
+pub mod prune_cache;
 pub mod invalidate;
+pub mod reinstall;
+pub mod resume;
+pub mod search;
+pub mod version;
+
+#[cfg(test)]
+mod test_fixtures;
@@ -0,0 +1,68 @@
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use log::info;
+
+use crate::apply_patch::preview_patch_module;
+use crate::archive_extractor::{Extractor, TempDirOptions};
+use crate::canon_path::CanonPath;
+use crate::config::Config;
+use crate::lowercase::LwcString;
+use crate::module::location::location::ConcreteLocation;
+use crate::module::manifest::Manifest;
+use crate::module::module::Module;
+use crate::obtain::get_module::ModuleDownload;
+use crate::transform_preview::TransformPreview;
+
+/// `install --preview-transforms <mod>`: prints what `patch`, `patches` and `replace` would change in the files
+/// of the mod, which is extracted in a temporary directory (the game directory is not modified).
+pub fn preview_transforms(manifest: &Manifest, mod_name: &LwcString, module_download: &ModuleDownload,
+                            config: &Config) -> Result<()> {
+    let weidu_mod = manifest.modules.iter()
+        .find_map(|module| match module {
+            Module::Mod { weidu_mod } if weidu_mod.name == *mod_name => Some(weidu_mod),
+            _ => None,
+        });
+    let weidu_mod = match weidu_mod {
+        Some(weidu_mod) => weidu_mod,
+        None => bail!("Mod {} (given with --preview-transforms) is not in the manifest", mod_name),
+    };
+    let location = module_download.get_module_location(weidu_mod)?;
+    if location.patch.is_none() && location.patches.is_empty() && location.replace.is_none() {
+        info!("Mod {} has no `patch`, `patches` or `replace` property, nothing to preview", mod_name);
+        return Ok(());
+    }
+    let archive = retrieve_archive(module_download, location, mod_name)?;
+
+    let temp_dir = tempfile::tempdir()?;
+    let temp_root = CanonPath::new(temp_dir.path())?;
+    let extractor = Extractor::new(&temp_root, config, TempDirOptions::default());
+    extractor.extract_files(&archive, mod_name, location)?;
+
+    let mut preview = TransformPreview::new(temp_root.clone());
+    for patch in location.patch.iter().chain(location.patches.iter()) {
+        preview_patch_module(&mut preview, module_download.game_dir, mod_name, patch,
+                                module_download.opts, module_download.global)?;
+    }
+    for spec in location.replace.iter().flatten() {
+        let count = spec.preview(&temp_root.join_path(mod_name.as_ref()), &mut preview)?;
+        info!("replace {:?} => {:?} in {:?}: {} replacement(s)", spec.replace, spec.with, spec.file_globs, count);
+    }
+
+    let diff = preview.diff();
+    if diff.is_empty() {
+        info!("The transformations of mod {} change nothing", mod_name);
+    } else {
+        println!("{}", diff);
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn retrieve_archive(module_download: &ModuleDownload, location: &ConcreteLocation, mod_name: &LwcString) -> Result<PathBuf> {
+    match module_download.retrieve_location(location, mod_name).await {
+        Ok(archive) => Ok(archive),
+        Err(error) => bail!("retrieve archive failed for module {}\n-> {:?}", mod_name, error),
+    }
+}
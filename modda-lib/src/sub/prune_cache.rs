@@ -0,0 +1,155 @@
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use log::info;
+
+use crate::args::CachePrune;
+use crate::cache::{remove_name_sidecar, Cache, ASSET_SIDECAR_EXTENSION, LOCK_EXTENSION, NAME_SIDECAR_EXTENSION};
+use crate::disk_usage::{format_size, parse_size};
+
+/// A file of the archive cache.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+pub fn prune_cache(params: &CachePrune, cache: &Cache) -> Result<()> {
+    let cache_dir = match cache {
+        Cache::Path(path) => path,
+        Cache::Tmp(_) => bail!("No archive cache configured (`archive_cache` in the global configuration), nothing to prune"),
+    };
+    if params.max_size.is_none() && params.older_than.is_none() {
+        bail!("Nothing to prune, at least one of --max-size or --older-than is needed");
+    }
+    let max_size = params.max_size.as_deref().map(parse_size).transpose()?;
+    let older_than = match params.older_than.as_deref().map(humantime::parse_duration).transpose() {
+        Ok(older_than) => older_than,
+        Err(error) => bail!("Invalid age {:?}\n -> {:?}", params.older_than, error),
+    };
+
+    let entries = cache_entries(cache_dir)?;
+    let total = entries.iter().map(|entry| entry.size).sum::<u64>();
+    let removed = prune_plan(entries, max_size, older_than, SystemTime::now());
+    let verb = if params.dry_run { "would remove" } else { "removed" };
+    for entry in &removed {
+        if !params.dry_run {
+            if let Err(error) = std::fs::remove_file(&entry.path) {
+                bail!("Could not remove cached archive {:?}\n -> {:?}", entry.path, error);
+            }
+            remove_name_sidecar(&entry.path)?;
+        }
+        info!("{} {} ({})", verb, entry.path.strip_prefix(cache_dir).unwrap_or(&entry.path).display(), format_size(entry.size));
+    }
+    let freed = removed.iter().map(|entry| entry.size).sum::<u64>();
+    info!("{} {} file(s), {} - cache size {} -> {}", verb, removed.len(), format_size(freed),
+            format_size(total), format_size(total - freed));
+    Ok(())
+}
+
+/// Files of the cache (recursively), the original name records and lock files of archives are not listed
+/// (they go with their archive).
+pub fn cache_entries(dir: &Path) -> Result<Vec<CacheEntry>> {
+    let read_dir = match std::fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(error) => bail!("Could not list cache directory {:?}\n -> {:?}", dir, error),
+    };
+    let mut entries = vec![];
+    for entry in read_dir {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            entries.extend(cache_entries(&entry.path())?);
+        } else if file_type.is_file() {
+            let path = entry.path();
+            if path.extension().is_some_and(|extension| extension == NAME_SIDECAR_EXTENSION || extension == LOCK_EXTENSION
+                                                        || extension == ASSET_SIDECAR_EXTENSION) {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            entries.push(CacheEntry { path, size: metadata.len(), modified: metadata.modified()? });
+        }
+    }
+    Ok(entries)
+}
+
+/// Entries to remove: those older than `older_than`, then the least recently modified ones until the remaining
+/// entries fit in `max_size`.
+pub fn prune_plan(entries: Vec<CacheEntry>, max_size: Option<u64>, older_than: Option<Duration>,
+                    now: SystemTime) -> Vec<CacheEntry> {
+    let mut remaining = entries.iter().map(|entry| entry.size).sum::<u64>();
+    entries.into_iter()
+        .sorted_by_key(|entry| entry.modified)
+        .filter(|entry| {
+            let too_old = older_than.is_some_and(|older_than|
+                now.duration_since(entry.modified).is_ok_and(|age| age > older_than));
+            let too_big = max_size.is_some_and(|max_size| remaining > max_size);
+            if too_old || too_big {
+                remaining -= entry.size;
+            }
+            too_old || too_big
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test_prune_cache {
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    use filetime::FileTime;
+
+    use super::{cache_entries, prune_plan, CacheEntry};
+
+    const DAY: u64 = 24 * 3600;
+
+    fn entry(name: &str, size: u64, age_days: u64, now: SystemTime) -> CacheEntry {
+        CacheEntry { path: PathBuf::from(name), size, modified: now - Duration::from_secs(age_days * DAY) }
+    }
+
+    fn names(entries: &[CacheEntry]) -> Vec<String> {
+        entries.iter().map(|entry| entry.path.to_string_lossy().to_string()).collect()
+    }
+
+    #[test]
+    fn prune_least_recently_modified_until_under_limit() {
+        let now = SystemTime::now();
+        let entries = vec![
+            entry("recent", 100, 1, now),
+            entry("oldest", 300, 30, now),
+            entry("old", 200, 10, now),
+            entry("newest", 50, 0, now),
+        ];
+        assert_eq!(names(&prune_plan(entries.clone(), Some(300), None, now)), vec!["oldest", "old"]);
+        assert_eq!(names(&prune_plan(entries.clone(), Some(1000), None, now)), Vec::<String>::new());
+        assert_eq!(names(&prune_plan(entries.clone(), None, Some(Duration::from_secs(5 * DAY)), now)), vec!["oldest", "old"]);
+        assert_eq!(names(&prune_plan(entries, Some(100), Some(Duration::from_secs(20 * DAY)), now)),
+                    vec!["oldest", "old", "recent"]);
+    }
+
+    #[test]
+    fn list_cache_entries() {
+        let cache = tempfile::tempdir().unwrap();
+        let repo = cache.path().join("github/user/repo");
+        std::fs::create_dir_all(&repo).unwrap();
+        std::fs::write(repo.join("0123abcd.zip"), vec![0u8; 10]).unwrap();
+        std::fs::write(repo.join("0123abcd.zip.name"), "long-name.zip").unwrap();
+        std::fs::write(repo.join("0123abcd.zip.lock"), "").unwrap();
+        std::fs::write(cache.path().join("mod.zip"), vec![0u8; 20]).unwrap();
+        let modified = FileTime::from_unix_time(1_600_000_000, 0);
+        filetime::set_file_mtime(cache.path().join("mod.zip"), modified).unwrap();
+
+        let entries = cache_entries(cache.path()).unwrap();
+        let mut found = entries.iter()
+            .map(|entry| (entry.path.strip_prefix(cache.path()).unwrap().to_path_buf(), entry.size))
+            .collect::<Vec<_>>();
+        found.sort();
+        assert_eq!(found, vec![(PathBuf::from("github/user/repo/0123abcd.zip"), 10), (PathBuf::from("mod.zip"), 20)]);
+        let mod_zip = entries.iter().find(|entry| entry.path.ends_with("mod.zip")).unwrap();
+        assert_eq!(FileTime::from_system_time(mod_zip.modified), modified);
+    }
+}
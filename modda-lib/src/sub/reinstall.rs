@@ -0,0 +1,169 @@
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use log::info;
+
+use crate::lowercase::LwcString;
+use crate::modda_context::WeiduContext;
+use crate::module::components::{Components, FullComponent};
+use crate::module::module::Module;
+use crate::module::weidu_mod::BareMod;
+use crate::run_weidu::uninstall_keeping_comments;
+use crate::tp2::find_tp2_str;
+
+/// What `install --reinstall <mod>` does for a mod, so that the end result matches the manifest.<br>
+/// weidu uninstalls (or reinstalls) a component in place: the components installed after it are
+/// uninstalled, then reinstalled, so the surrounding mods are not disturbed.
+#[derive(Debug, PartialEq)]
+pub struct ReinstallPlan {
+    pub mod_name: LwcString,
+    /// Installed components that are not in the manifest
+    pub uninstall: Vec<FullComponent>,
+    /// Installed components that are in the manifest, weidu reinstalls them in place
+    pub reinstall: Vec<u32>,
+    /// Components in the manifest that were not installed yet, they are installed last
+    pub added: Vec<u32>,
+}
+
+impl ReinstallPlan {
+    pub fn describe(&self) -> String {
+        format!("{}: uninstall {}, reinstall {}, add {}", self.mod_name,
+                index_list(self.uninstall.iter().map(|comp| comp.index)),
+                index_list(self.reinstall.iter().copied()),
+                index_list(self.added.iter().copied()))
+    }
+}
+
+fn index_list(mut indexes: impl Iterator<Item = u32>) -> String {
+    let list = indexes.join(", ");
+    if list.is_empty() { "-".to_string() } else { list }
+}
+
+/// Modules of the manifest that are reinstalled (with their index in the manifest, counting from zero).
+pub fn select_reinstalled<'a>(modules: &'a [Module], names: &[LwcString]) -> Result<Vec<(usize, &'a Module)>> {
+    for name in names {
+        if !modules.iter().any(|module| module.get_name() == name) {
+            bail!("Mod {} (given with --reinstall) is not in the manifest", name);
+        }
+    }
+    Ok(modules.iter().enumerate().filter(|(_, module)| names.contains(module.get_name())).collect())
+}
+
+/// Compares the components of `mod_name` in `weidu.log` (`installed`) with those of the manifest (all fragments of this mod).
+pub fn reinstall_plan(installed: &[BareMod], modules: &[Module], mod_name: &LwcString) -> Result<ReinstallPlan> {
+    let mut wanted = vec![];
+    for module in modules.iter().filter(|module| module.get_name() == mod_name) {
+        match module.get_components() {
+            Components::None => {}
+            Components::FromFile(file) => bail!("Components file {} was not read", file.file),
+            Components::Ask | Components::All =>
+                bail!("Can't reinstall mod {} which doesn't specify components explicitly (`components: ask` or `all`)", mod_name),
+            Components::List(list) => for component in list {
+                match component.index() {
+                    Some(index) => wanted.push(index),
+                    None => bail!("Can't reinstall mod {} which uses component groups, use component numbers", mod_name),
+                }
+            }
+        }
+    }
+    let installed = installed.iter()
+        .filter(|module| module.name == *mod_name)
+        .flat_map(|module| module.components.iter())
+        .collect::<Vec<_>>();
+    Ok(ReinstallPlan {
+        mod_name: mod_name.to_owned(),
+        uninstall: installed.iter()
+            .filter(|comp| !wanted.contains(&comp.index))
+            .map(|comp| (*comp).clone())
+            .collect(),
+        reinstall: wanted.iter().copied().filter(|index| installed.iter().any(|comp| comp.index == *index)).collect(),
+        added: wanted.iter().copied().filter(|index| !installed.iter().any(|comp| comp.index == *index)).collect(),
+    })
+}
+
+/// Uninstalls the components that are no longer in the manifest (the rest is done by the regular installation).
+pub fn uninstall_removed(plans: &[ReinstallPlan], dry_run: bool, weidu_context: &WeiduContext) -> Result<()> {
+    let removed = plans.iter().filter(|plan| !plan.uninstall.is_empty()).collect::<Vec<_>>();
+    if removed.is_empty() {
+        return Ok(());
+    }
+    for plan in removed {
+        let tp2 = find_tp2_str(weidu_context.current_dir, &plan.mod_name)?;
+        let bare_mod = BareMod { name: plan.mod_name.to_owned(), components: plan.uninstall.to_owned(), language: 0 };
+        info!("uninstalling {}", bare_mod.short());
+        uninstall_keeping_comments(&tp2, &bare_mod, dry_run, weidu_context)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_reinstall {
+    use crate::lowercase::lwc;
+    use crate::module::components::Components;
+    use crate::sub::test_fixtures::{bare_mod, full, list, module};
+
+    use super::{reinstall_plan, select_reinstalled, ReinstallPlan};
+
+    #[test]
+    fn plan_for_named_mod() {
+        let installed = vec![
+            bare_mod("first", &[0]),
+            bare_mod("target", &[0, 1, 2]),
+            bare_mod("other", &[10]),
+            bare_mod("target", &[5]),
+        ];
+        let modules = vec![
+            module("first", list(&[0])),
+            module("target", list(&[1, 3])),
+            module("other", list(&[10])),
+            module("target", list(&[5])),
+        ];
+
+        let plan = reinstall_plan(&installed, &modules, &lwc!("target")).unwrap();
+
+        assert_eq!(
+            plan,
+            ReinstallPlan {
+                mod_name: lwc!("target"),
+                uninstall: vec![full(0), full(2)],
+                reinstall: vec![1, 5],
+                added: vec![3],
+            }
+        );
+        assert_eq!(plan.describe(), "target: uninstall 0, 2, reinstall 1, 5, add 3");
+    }
+
+    #[test]
+    fn plan_for_mod_not_installed_yet() {
+        let modules = vec![module("target", list(&[1]))];
+
+        let plan = reinstall_plan(&[bare_mod("other", &[1])], &modules, &lwc!("target")).unwrap();
+
+        assert!(plan.uninstall.is_empty());
+        assert!(plan.reinstall.is_empty());
+        assert_eq!(plan.added, vec![1]);
+        assert_eq!(plan.describe(), "target: uninstall -, reinstall -, add 1");
+    }
+
+    #[test]
+    fn plan_requires_explicit_components() {
+        let installed = vec![bare_mod("target", &[0])];
+        assert!(reinstall_plan(&installed, &[module("target", Components::Ask)], &lwc!("target")).is_err());
+        assert!(reinstall_plan(&installed, &[module("target", Components::All)], &lwc!("target")).is_err());
+    }
+
+    #[test]
+    fn select_only_named_mods() {
+        let modules = vec![
+            module("first", list(&[0])),
+            module("target", list(&[1])),
+            module("other", list(&[10])),
+        ];
+
+        let selected = select_reinstalled(&modules, &[lwc!("target")]).unwrap();
+        assert_eq!(selected.iter().map(|(index, module)| (*index, module.get_name().to_string())).collect::<Vec<_>>(),
+                    vec![(1, "target".to_string())]);
+
+        assert!(select_reinstalled(&modules, &[lwc!("unknown")]).is_err());
+    }
+}
@@ -0,0 +1,229 @@
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use log::info;
+
+use crate::canon_path::CanonPath;
+use crate::lowercase::LwcString;
+use crate::modda_context::WeiduContext;
+use crate::module::components::{Components, FullComponent};
+use crate::module::disable_condition::DisableOutCome;
+use crate::module::manifest_conditions::ManifestConditions;
+use crate::module::module::Module;
+use crate::module::weidu_mod::BareMod;
+use crate::run_weidu::uninstall_keeping_comments;
+use crate::tp2::find_tp2_str;
+
+/// What `install --resume` does: `weidu.log` is compared with the manifest, in order.<br>
+/// The installation resumes at the first module that is not (fully) installed; the components installed after
+/// the point where `weidu.log` and the manifest diverge are uninstalled first.
+#[derive(Debug, PartialEq)]
+pub struct ResumePlan {
+    /// Index of the first module to install, counting from one (as `--from-index`)
+    pub from_index: usize,
+    /// Installed components that don't follow the manifest, in `weidu.log` order (they are uninstalled last to first)
+    pub uninstall: Vec<BareMod>,
+}
+
+impl ResumePlan {
+    pub fn describe(&self, modules: &[Module]) -> String {
+        let mut lines = vec![];
+        if !self.uninstall.is_empty() {
+            lines.push("uninstall (last to first)".to_string());
+            lines.extend(self.uninstall.iter().rev().map(|bare_mod| format!("  - {}", bare_mod.short())));
+        }
+        match modules.get(self.from_index - 1) {
+            Some(module) => lines.push(format!("install from module {} - {}", self.from_index, module.describe())),
+            None => lines.push("nothing left to install".to_string()),
+        }
+        lines.join("\n")
+    }
+}
+
+/// Components of a module, when they can be compared with `weidu.log`.
+enum Expected {
+    Known(Vec<u32>),
+    /// `ask`, `all` or component groups: the installed components of the mod are accepted as is
+    Unknown,
+}
+
+fn expected_components(module: &Module) -> Result<Expected> {
+    match module.get_components() {
+        Components::None => Ok(Expected::Known(vec![])),
+        Components::FromFile(file) => bail!("Components file {} was not read", file.file),
+        Components::Ask | Components::All => Ok(Expected::Unknown),
+        Components::List(list) => {
+            let indexes = list.iter().map(|component| component.index()).collect::<Option<Vec<_>>>();
+            Ok(indexes.map_or(Expected::Unknown, Expected::Known))
+        }
+    }
+}
+
+/// Compares the components in `weidu.log` (`installed`) with those of the manifest.<br>
+/// Disabled modules and the mods in `skipped` (`--skip`) are not expected in `weidu.log`.
+pub fn resume_plan(installed: &[BareMod], modules: &[Module], manifest_root: &CanonPath, game_dir: &CanonPath,
+                    manifest_conditions: &ManifestConditions, skipped: &[LwcString]) -> Result<ResumePlan> {
+    let rows = installed.iter()
+        .flat_map(|bare_mod| bare_mod.components.iter().map(move |component| (&bare_mod.name, component)))
+        .collect::<Vec<_>>();
+    let mut position = 0;
+    for (index, module) in modules.iter().enumerate() {
+        if position == rows.len() {
+            return Ok(ResumePlan { from_index: index + 1, uninstall: vec![] });
+        }
+        if skipped.contains(module.get_name()) {
+            continue;
+        }
+        if let DisableOutCome::Yes(_) = module.check_disabled(manifest_root, game_dir, manifest_conditions)? {
+            continue;
+        }
+        let remaining = &rows[position..];
+        let matching = match expected_components(module)? {
+            Expected::Known(components) => {
+                let matching = components.iter().zip(remaining)
+                    .take_while(|(index, (name, component))| *name == module.get_name() && component.index == **index)
+                    .count();
+                if matching < components.len() { None } else { Some(matching) }
+            }
+            Expected::Unknown => {
+                let matching = remaining.iter().take_while(|(name, _)| *name == module.get_name()).count();
+                if matching == 0 { None } else { Some(matching) }
+            }
+        };
+        match matching {
+            Some(matching) => position += matching,
+            None => return Ok(ResumePlan { from_index: index + 1, uninstall: regroup(remaining) }),
+        }
+    }
+    Ok(ResumePlan { from_index: modules.len() + 1, uninstall: regroup(&rows[position..]) })
+}
+
+/// Consecutive components of the same mod make a fragment.
+fn regroup(rows: &[(&LwcString, &FullComponent)]) -> Vec<BareMod> {
+    rows.iter()
+        .chunk_by(|(name, _)| *name)
+        .into_iter()
+        .map(|(name, group)| BareMod {
+            name: name.to_owned(),
+            components: group.map(|(_, component)| (*component).clone()).collect(),
+            language: 0,
+        })
+        .collect()
+}
+
+/// Uninstalls the components that don't follow the manifest, starting with the last one installed.
+pub fn uninstall_diverging(plan: &ResumePlan, dry_run: bool, weidu_context: &WeiduContext) -> Result<()> {
+    if plan.uninstall.is_empty() {
+        return Ok(());
+    }
+    for bare_mod in plan.uninstall.iter().rev() {
+        let tp2 = find_tp2_str(weidu_context.current_dir, &bare_mod.name)?;
+        info!("uninstalling {}", bare_mod.short());
+        uninstall_keeping_comments(&tp2, bare_mod, dry_run, weidu_context)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_resume {
+    use crate::canon_path::CanonPath;
+    use crate::lowercase::{lwc, LwcString};
+    use crate::module::components::Components;
+    use crate::module::disable_condition::DisableCondition;
+    use crate::module::module::Module;
+    use crate::module::weidu_mod::{BareMod, WeiduMod};
+    use crate::sub::test_fixtures::{bare_mod, list, module};
+
+    use super::{resume_plan, ResumePlan};
+
+    fn manifest() -> Vec<Module> {
+        vec![
+            module("first", list(&[0, 1])),
+            module("second", Components::Ask),
+            module("third", list(&[2])),
+            module("first", list(&[5])),
+        ]
+    }
+
+    fn plan(installed: &[BareMod], modules: &[Module]) -> ResumePlan {
+        plan_with_skip(installed, modules, &[])
+    }
+
+    fn plan_with_skip(installed: &[BareMod], modules: &[Module], skipped: &[LwcString]) -> ResumePlan {
+        let root = CanonPath::new(std::env::current_dir().unwrap()).unwrap();
+        resume_plan(installed, modules, &root, &root, &Default::default(), skipped).unwrap()
+    }
+
+    #[test]
+    fn skipped_mods_are_not_expected() {
+        let installed = vec![bare_mod("first", &[0, 1]), bare_mod("third", &[2])];
+        let plan = plan_with_skip(&installed, &manifest(), &[lwc!("second")]);
+        assert_eq!(plan, ResumePlan { from_index: 4, uninstall: vec![] });
+    }
+
+    #[test]
+    fn resume_after_strict_prefix() {
+        let installed = vec![bare_mod("first", &[0, 1]), bare_mod("second", &[3, 4])];
+        let plan = plan(&installed, &manifest());
+        assert_eq!(plan, ResumePlan { from_index: 3, uninstall: vec![] });
+        assert_eq!(plan.describe(&manifest()), format!("install from module 3 - {}", manifest()[2].describe()));
+    }
+
+    #[test]
+    fn resume_with_nothing_installed() {
+        assert_eq!(plan(&[], &manifest()), ResumePlan { from_index: 1, uninstall: vec![] });
+    }
+
+    #[test]
+    fn everything_installed() {
+        let installed = vec![bare_mod("first", &[0, 1]), bare_mod("second", &[0]),
+                             bare_mod("third", &[2]), bare_mod("first", &[5])];
+        let plan = plan(&installed, &manifest());
+        assert_eq!(plan, ResumePlan { from_index: 5, uninstall: vec![] });
+        assert_eq!(plan.describe(&manifest()), "nothing left to install");
+    }
+
+    #[test]
+    fn divergence_in_the_middle() {
+        let installed = vec![bare_mod("first", &[0, 1]), bare_mod("second", &[0]),
+                             bare_mod("third", &[3]), bare_mod("other", &[1, 2])];
+        let plan = plan(&installed, &manifest());
+        assert_eq!(plan, ResumePlan {
+            from_index: 3,
+            uninstall: vec![bare_mod("third", &[3]), bare_mod("other", &[1, 2])],
+        });
+        assert_eq!(
+            plan.describe(&manifest()),
+            format!("uninstall (last to first)\n  - other: 1, 2\n  - third: 3\ninstall from module 3 - {}",
+                    manifest()[2].describe())
+        );
+    }
+
+    #[test]
+    fn partially_installed_module_is_uninstalled() {
+        let installed = vec![bare_mod("first", &[0])];
+        assert_eq!(plan(&installed, &manifest()), ResumePlan { from_index: 1, uninstall: vec![bare_mod("first", &[0])] });
+    }
+
+    #[test]
+    fn extra_components_after_the_manifest() {
+        let modules = vec![module("first", list(&[0]))];
+        let installed = vec![bare_mod("first", &[0]), bare_mod("other", &[1])];
+        assert_eq!(plan(&installed, &modules), ResumePlan { from_index: 2, uninstall: vec![bare_mod("other", &[1])] });
+    }
+
+    #[test]
+    fn disabled_modules_are_ignored() {
+        let modules = vec![
+            module("first", list(&[0])),
+            Module::Mod { weidu_mod: WeiduMod {
+                name: lwc!("disabled"), components: list(&[0]),
+                disabled_if: Some(DisableCondition::Because { because: "not now".to_string() }),
+                ..WeiduMod::default()
+            } },
+            module("third", list(&[2])),
+        ];
+        let installed = vec![bare_mod("first", &[0]), bare_mod("third", &[2])];
+        assert_eq!(plan(&installed, &modules), ResumePlan { from_index: 4, uninstall: vec![] });
+    }
+}
@@ -0,0 +1,99 @@
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use itertools::Itertools;
+
+use crate::lowercase::LwcString;
+use crate::module::global_locations::GlobalLocations;
+use crate::module::location::location::{ConcreteLocation, Location};
+use crate::module::manifest::Manifest;
+use crate::module::module::Module;
+
+#[derive(Debug, PartialEq)]
+pub enum SearchHitKind {
+    /// A module of the manifest, with its index (counting from one)
+    Module { index: usize },
+    /// A key of the global locations (`entries` or external registries)
+    LocationKey,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SearchHit<'a> {
+    pub score: i64,
+    pub name: &'a LwcString,
+    pub kind: SearchHitKind,
+    /// Where the module would be obtained from, `None` for generated mods or unresolved locations
+    pub location: Option<&'a ConcreteLocation>,
+    pub description: Option<&'a str>,
+}
+
+/// Modules and location keys whose name fuzzy-matches `pattern`, best matches first (then in manifest order).
+/// The manifest is expected to be assembled, so that the locations of the external registries are included.
+pub fn fuzzy_search<'a>(manifest: &'a Manifest, pattern: &str) -> Vec<SearchHit<'a>> {
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let modules = manifest.modules.iter().enumerate()
+        .filter_map(|(index, module)| {
+            let score = matcher.fuzzy_match(module.get_name().as_ref(), pattern)?;
+            Some(SearchHit {
+                score,
+                name: module.get_name(),
+                kind: SearchHitKind::Module { index: index + 1 },
+                location: module_location(module, &manifest.locations),
+                description: module.get_description().as_deref(),
+            })
+        });
+    let locations = manifest.locations.entries.iter()
+        .sorted_by(|(left, _), (right, _)| left.as_ref().cmp(right.as_ref()))
+        .filter_map(|(key, location)| {
+            let score = matcher.fuzzy_match(key.as_ref(), pattern)?;
+            Some(SearchHit { score, name: key, kind: SearchHitKind::LocationKey, location: Some(location), description: None })
+        });
+    // stable sort, so equal scores keep modules first, in manifest order
+    modules.chain(locations)
+        .sorted_by(|left, right| right.score.cmp(&left.score))
+        .collect()
+}
+
+fn module_location<'a>(module: &'a Module, locations: &'a GlobalLocations) -> Option<&'a ConcreteLocation> {
+    match module {
+        Module::Generated { .. } => None,
+        Module::Mod { weidu_mod } => match &weidu_mod.location {
+            None => locations.find(&weidu_mod.name),
+            Some(Location::Concrete { concrete }) => Some(concrete),
+            Some(Location::Ref { r#ref: reference }) => locations.find(reference),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_search {
+    use crate::canon_path::CanonPath;
+    use crate::lowercase::lwc;
+    use crate::module::location::source::Source;
+    use crate::module::manifest::Manifest;
+
+    use super::{fuzzy_search, SearchHitKind};
+
+    #[test]
+    fn fuzzy_search_modules_and_locations() {
+        let manifest_root = CanonPath::new(format!("{}/resources/test/search", env!("CARGO_MANIFEST_DIR"))).unwrap();
+        let manifest_path = manifest_root.join_path("manifest.yml");
+        let manifest = Manifest::assemble_from_path(manifest_path.to_str().unwrap(), &manifest_root).unwrap();
+
+        let hits = fuzzy_search(&manifest, "ascen");
+        let found = hits.iter().map(|hit| (hit.name.as_ref().as_str(), &hit.kind)).collect::<Vec<_>>();
+        assert_eq!(found, vec![
+            ("ascension", &SearchHitKind::Module { index: 2 }),
+            ("ascension", &SearchHitKind::LocationKey),
+            ("ascension_fixes", &SearchHitKind::LocationKey),
+        ]);
+        assert_eq!(hits[0].description, Some("the main one"));
+        assert_eq!(hits[0].location.unwrap().source, Source::Local { local: "mods/ascension.zip".to_string(), rename: None });
+
+        let hits = fuzzy_search(&manifest, "TWEAKS");
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].name, &lwc!("tweaks"));
+        assert_eq!(hits[0].location.unwrap().source, Source::Local { local: "mods/tweaks-v2.zip".to_string(), rename: None });
+
+        assert_eq!(fuzzy_search(&manifest, "zzz"), vec![]);
+    }
+}
@@ -0,0 +1,28 @@
+//! Manifest modules and `weidu.log` content shared by the tests of the subcommands.
+
+use crate::lowercase::lwc;
+use crate::module::components::{Component, Components, FullComponent};
+use crate::module::module::Module;
+use crate::module::weidu_mod::{BareMod, WeiduMod};
+
+/// A mod installed with these components, as read in `weidu.log`.
+pub fn bare_mod(name: &str, components: &[u32]) -> BareMod {
+    BareMod {
+        name: lwc!(name),
+        components: components.iter().map(|index| full(*index)).collect(),
+        language: 0,
+    }
+}
+
+pub fn full(index: u32) -> FullComponent {
+    FullComponent { index, component_name: format!("component {}", index), language: None }
+}
+
+/// A manifest module fragment for a weidu mod.
+pub fn module(name: &str, components: Components) -> Module {
+    Module::Mod { weidu_mod: WeiduMod { name: lwc!(name), components, ..WeiduMod::default() } }
+}
+
+pub fn list(components: &[u32]) -> Components {
+    Components::List(components.iter().map(|index| Component::Simple(*index)).collect())
+}
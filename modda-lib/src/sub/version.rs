@@ -0,0 +1,116 @@
+
+use std::fmt::Display;
+
+use anyhow::Result;
+
+use crate::args::Version;
+use crate::game_detect::{detect_game, GameFlavor};
+use crate::modda_context::WeiduContext;
+use crate::module::manifest::MANIFEST_VERSION;
+use crate::progname::PROGNAME;
+use crate::run_weidu::weidu_version;
+use crate::weidu_conf::read_weidu_conf_lang_dir;
+
+/// What is useful to know about the environment when reporting a bug.
+#[derive(Debug)]
+pub struct VersionReport {
+    pub modda_version: String,
+    /// weidu executable and version, or why they could not be obtained
+    pub weidu: Result<(String, String), String>,
+    pub game: Option<GameFlavor>,
+    /// `lang_dir` from `weidu.conf`
+    pub game_language: Option<String>,
+    pub manifest_version: &'static str,
+}
+
+impl Display for VersionReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} version: {}", PROGNAME, self.modda_version)?;
+        match &self.weidu {
+            Ok((path, version)) => {
+                writeln!(f, "weidu path: {}", path)?;
+                writeln!(f, "weidu version: {}", version)?;
+            }
+            Err(error) => writeln!(f, "weidu: not available ({})", error)?,
+        }
+        match &self.game {
+            Some(game) => writeln!(f, "game: {}", game)?,
+            None => writeln!(f, "game: not detected")?,
+        }
+        writeln!(f, "game language: {}", self.game_language.as_deref().unwrap_or("unknown"))?;
+        write!(f, "manifest format version: {}", self.manifest_version)
+    }
+}
+
+pub fn version(params: &Version, modda_version: &str, weidu_context: &WeiduContext) -> Result<()> {
+    if params.full {
+        println!("{}", version_report(modda_version, weidu_context));
+    } else {
+        println!("{} {}", PROGNAME, modda_version);
+    }
+    Ok(())
+}
+
+pub fn version_report(modda_version: &str, weidu_context: &WeiduContext) -> VersionReport {
+    let game_dir = weidu_context.current_dir;
+    VersionReport {
+        modda_version: modda_version.to_owned(),
+        weidu: weidu_version(weidu_context).map_err(|error| error.to_string()),
+        game: detect_game(game_dir.path()).unwrap_or(None),
+        game_language: read_weidu_conf_lang_dir(game_dir).unwrap_or(None),
+        manifest_version: MANIFEST_VERSION,
+    }
+}
+
+#[cfg(test)]
+mod test_version_report {
+    use crate::canon_path::CanonPath;
+    use crate::config::Config;
+    use crate::game_detect::GameFlavor;
+    use crate::modda_context::WeiduContext;
+
+    use super::version_report;
+
+    #[cfg(unix)]
+    #[test]
+    fn report_contains_stub_weidu_version() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let game_dir = tempfile::tempdir().unwrap();
+        std::fs::write(game_dir.path().join("chitin.key"), "").unwrap();
+        std::fs::write(game_dir.path().join("engine.lua"), "").unwrap();
+        let weidu = game_dir.path().join("weidu-stub");
+        std::fs::write(&weidu, "#!/bin/sh\necho\necho '[weidu-stub] WeiDU version 24900'\n").unwrap();
+        std::fs::set_permissions(&weidu, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let config = Config { weidu_path: Some(weidu.to_string_lossy().to_string()), ..Config::default() };
+        let current_dir = CanonPath::new(game_dir.path()).unwrap();
+        let weidu_context = WeiduContext { config: &config, current_dir: &current_dir, debug_log: None };
+
+        let report = version_report("1.2.3", &weidu_context);
+
+        assert_eq!(report.weidu, Ok((weidu.to_string_lossy().to_string(), "[weidu-stub] WeiDU version 24900".to_string())));
+        assert_eq!(report.game, Some(GameFlavor::Bgee));
+        let printed = report.to_string();
+        assert!(printed.contains("version: 1.2.3"));
+        assert!(printed.contains("weidu version: [weidu-stub] WeiDU version 24900"));
+        assert!(printed.contains("manifest format version: 1"));
+    }
+
+    #[test]
+    fn report_without_weidu() {
+        let game_dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            weidu_path: Some(game_dir.path().join("no-weidu-here").to_string_lossy().to_string()),
+            ..Config::default()
+        };
+        let current_dir = CanonPath::new(game_dir.path()).unwrap();
+        let weidu_context = WeiduContext { config: &config, current_dir: &current_dir, debug_log: None };
+
+        let report = version_report("1.2.3", &weidu_context);
+
+        assert!(report.weidu.is_err());
+        assert_eq!(report.game, None);
+        assert!(report.to_string().contains("weidu: not available"));
+    }
+}
@@ -1,7 +1,26 @@
 
+use std::path::Path;
+
+use anyhow::{bail, Result};
 use chrono::{DateTime, Local, Duration};
+use lazy_static::lazy_static;
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+
+use crate::disk_usage::format_size_delta;
 
 use crate::lowercase::LwcString;
+use crate::run_weidu::read_debug_log_since;
+
+/// When modda's measured install time exceeds weidu's own by more than this, weidu was probably
+/// waiting for input.
+const STALL_MIN_GAP_SECONDS: i64 = 60;
+
+lazy_static! {
+    static ref WEIDU_INSTALL_TIME_REGEX: Regex =
+        RegexBuilder::new(r"^SUCCESSFULLY INSTALLED\b.*\bin ([0-9]+(?:\.[0-9]+)?) seconds?\s*$")
+            .case_insensitive(true).multi_line(true).build().unwrap();
+}
 
 
 #[derive(Default, Debug, Clone)]
@@ -12,9 +31,14 @@ pub struct InstallTimeline {
     pub downloaded: Option<DateTime<Local>>,
     pub copied: Option<DateTime<Local>>,
     pub patched: Option<DateTime<Local>>,
+    pub replaced: Option<DateTime<Local>>,
     pub configured: Option<DateTime<Local>>,
     pub start_install: Option<DateTime<Local>>,
     pub installed: Option<DateTime<Local>>,
+    /// Size difference of the game directory (only measured with `--disk-usage`)
+    pub added_bytes: Option<i64>,
+    /// Install time reported by weidu in its debug log
+    pub weidu_reported: Option<Duration>,
 }
 
 impl InstallTimeline {
@@ -31,9 +55,30 @@ impl InstallTimeline {
         self.downloaded = setup.downloaded;
         self.copied = setup.copied;
         self.patched = setup.patched;
+        self.replaced = setup.replaced;
         self.configured = setup.configured;
     }
 
+    /// Steps of the mod installation that have both a start and an end, for the trace export.
+    fn spans(&self) -> Vec<(&'static str, DateTime<Local>, DateTime<Local>)> {
+        let steps = [
+            ("download", self.start_download, self.downloaded),
+            ("extract", self.downloaded, self.copied),
+            ("patch", self.copied, self.patched),
+            ("replace", self.patched, self.replaced),
+            ("configure", self.replaced.or(self.patched), self.configured),
+            ("weidu", self.start_install, self.installed),
+        ];
+        steps.into_iter()
+            .filter_map(|(name, start, end)| Some((name, start?, end?)))
+            .collect()
+    }
+
+    fn end(&self) -> Option<DateTime<Local>> {
+        [self.start_download, self.downloaded, self.copied, self.patched, self.replaced,
+            self.configured, self.start_install, self.installed].into_iter().flatten().max()
+    }
+
     pub fn short(&self) -> String {
         let mut result = format!("({}) {}" , self.start.format("%H:%M:%S"), self.name);
             result +=" download: ";
@@ -60,8 +105,36 @@ impl InstallTimeline {
         } else {
             result += "-"
         }
+        if let Some(weidu_reported) = self.weidu_reported {
+            result += &format!(" (weidu: {})", format_duration(weidu_reported));
+        }
+        if let Some(added_bytes) = self.added_bytes {
+            result += &format!(" disk: {}", format_size_delta(added_bytes));
+        }
         result
     }
+
+    /// Difference between the measured install time and the time reported by weidu, when it is large
+    /// enough to mean that weidu was waiting (most likely on a prompt).
+    pub fn install_stall(&self) -> Option<Duration> {
+        let measured = self.installed? - self.start_install?;
+        let reported = self.weidu_reported?;
+        let gap = measured - reported;
+        if gap > Duration::seconds(STALL_MIN_GAP_SECONDS) && measured > reported * 2 {
+            Some(gap)
+        } else {
+            None
+        }
+    }
+}
+
+/// Total install time reported by weidu (`SUCCESSFULLY INSTALLED ... in N seconds` lines) in a debug log excerpt.
+pub fn parse_weidu_install_time(debug_log: &str) -> Option<Duration> {
+    let millis = WEIDU_INSTALL_TIME_REGEX.captures_iter(debug_log)
+        .filter_map(|captures| captures.get(1)?.as_str().parse::<f64>().ok())
+        .map(|seconds| (seconds * 1000.0).round() as i64)
+        .reduce(|total, item| total + item)?;
+    Some(Duration::milliseconds(millis))
 }
 
 fn format_duration(duration: Duration) -> String {
@@ -77,4 +150,198 @@ pub struct SetupTimeline {
     pub patched: Option<DateTime<Local>>,
     pub replaced: Option<DateTime<Local>>,
     pub configured: Option<DateTime<Local>>,
+    /// Output of the `precopy` command, for the modda log
+    pub precopy_output: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct TraceEvent {
+    name: String,
+    cat: &'static str,
+    /// `X` for a span, `i` for an instant event
+    ph: &'static str,
+    /// Microseconds since the start of the first timeline
+    ts: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dur: Option<i64>,
+    /// Scope of instant events (`g` = global)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    s: Option<&'static str>,
+    pid: u32,
+    tid: u32,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Trace {
+    trace_events: Vec<TraceEvent>,
+    display_time_unit: &'static str,
+}
+
+/// Timelines in the Chrome tracing JSON format (can be opened with `chrome://tracing` or Perfetto).<br>
+/// Each mod is a span, containing a span for each step (download, extract, patch, replace, configure, weidu).
+pub fn chrome_trace(timelines: &[InstallTimeline]) -> Result<String> {
+    let origin = match timelines.iter().map(|timeline| timeline.start).min() {
+        None => return Ok(serde_json::to_string(&Trace { trace_events: vec![], display_time_unit: "ms" })?),
+        Some(origin) => origin,
+    };
+    let micros = |date: DateTime<Local>| (date - origin).num_microseconds().unwrap_or_default();
+    let mut events = vec![];
+    for timeline in timelines {
+        let start = micros(timeline.start);
+        match timeline.end() {
+            None => events.push(TraceEvent {
+                name: timeline.name.to_string(), cat: "mod", ph: "i", ts: start, dur: None, s: Some("g"), pid: 1, tid: 1,
+            }),
+            Some(end) => events.push(TraceEvent {
+                name: timeline.name.to_string(), cat: "mod", ph: "X", ts: start, dur: Some(micros(end) - start), s: None, pid: 1, tid: 1,
+            }),
+        }
+        for (name, step_start, step_end) in timeline.spans() {
+            events.push(TraceEvent {
+                name: name.to_string(), cat: "step", ph: "X", ts: micros(step_start),
+                dur: Some(micros(step_end) - micros(step_start)), s: None, pid: 1, tid: 1,
+            });
+        }
+    }
+    Ok(serde_json::to_string_pretty(&Trace { trace_events: events, display_time_unit: "ms" })?)
+}
+
+pub fn write_chrome_trace(path: &Path, timelines: &[InstallTimeline]) -> Result<()> {
+    if let Err(error) = std::fs::write(path, chrome_trace(timelines)?) {
+        bail!("Could not write timeline trace to {:?}\n -> {:?}", path, error);
+    }
+    Ok(())
+}
+
+/// Install time reported by weidu in the part of its debug log written after `offset`.
+pub fn read_weidu_install_time(debug_log: &Path, offset: u64) -> Option<Duration> {
+    parse_weidu_install_time(&read_debug_log_since(debug_log, offset)?)
+}
+
+#[cfg(test)]
+mod test_weidu_install_time {
+    use chrono::{Duration, Local};
+
+    use crate::lowercase::lwc;
+
+    use super::{parse_weidu_install_time, read_weidu_install_time, InstallTimeline};
+
+    #[test]
+    fn parse_install_time() {
+        let log = "Installing [My component] [v1]\n\
+                   Copying and patching 12 files ...\n\
+                   SUCCESSFULLY INSTALLED      My component in 12.5 seconds\n";
+        assert_eq!(parse_weidu_install_time(log), Some(Duration::milliseconds(12_500)));
+    }
+
+    #[test]
+    fn parse_install_time_of_several_components() {
+        let log = "SUCCESSFULLY INSTALLED      First component in 3 seconds\n\
+                   Installing [Second component] [v1]\n\
+                   SUCCESSFULLY INSTALLED      Second component in 1 second\r\n";
+        assert_eq!(parse_weidu_install_time(log), Some(Duration::seconds(4)));
+    }
+
+    #[test]
+    fn read_only_the_last_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let debug_log = dir.path().join("setup-my_mod.debug");
+        std::fs::write(&debug_log, "SUCCESSFULLY INSTALLED      My component in 30 seconds\n").unwrap();
+        let offset = std::fs::metadata(&debug_log).unwrap().len();
+        let mut content = std::fs::read_to_string(&debug_log).unwrap();
+        content.push_str("SUCCESSFULLY INSTALLED      My component in 2 seconds\n");
+        std::fs::write(&debug_log, content).unwrap();
+
+        assert_eq!(read_weidu_install_time(&debug_log, offset), Some(Duration::seconds(2)));
+        assert_eq!(read_weidu_install_time(&debug_log, 0), Some(Duration::seconds(32)));
+        assert_eq!(read_weidu_install_time(&dir.path().join("missing.debug"), 0), None);
+    }
+
+    #[test]
+    fn no_install_time() {
+        assert_eq!(parse_weidu_install_time(""), None);
+        assert_eq!(parse_weidu_install_time("SUCCESSFULLY INSTALLED      My component\n"), None);
+        assert_eq!(parse_weidu_install_time("NOT INSTALLED DUE TO ERRORS My component in 2 seconds\n"), None);
+    }
+
+    #[test]
+    fn detect_stall() {
+        let start = Local::now();
+        let timeline = |measured: i64, reported: i64| InstallTimeline {
+            start_install: Some(start),
+            installed: Some(start + Duration::seconds(measured)),
+            weidu_reported: Some(Duration::seconds(reported)),
+            ..InstallTimeline::new(lwc!("my_mod"), start)
+        };
+        assert_eq!(timeline(300, 20).install_stall(), Some(Duration::seconds(280)));
+        assert_eq!(timeline(25, 20).install_stall(), None);
+        // long installs can have a large (relative to the threshold) but proportionally small gap
+        assert_eq!(timeline(1300, 1200).install_stall(), None);
+        assert_eq!(InstallTimeline::new(lwc!("my_mod"), start).install_stall(), None);
+    }
+}
+
+#[cfg(test)]
+mod test_chrome_trace {
+    use chrono::{Duration, Local};
+    use serde_json::{json, Value};
+
+    use crate::lowercase::lwc;
+
+    use super::{chrome_trace, InstallTimeline};
+
+    #[test]
+    fn spans_for_each_mod_and_step() {
+        let origin = Local::now();
+        let at = |seconds: i64| Some(origin + Duration::seconds(seconds));
+        let timelines = vec![
+            InstallTimeline {
+                start_download: at(0),
+                downloaded: at(10),
+                copied: at(12),
+                patched: at(13),
+                replaced: at(13),
+                configured: at(14),
+                start_install: at(14),
+                installed: at(40),
+                ..InstallTimeline::new(lwc!("first_mod"), origin)
+            },
+            // already in the game directory: nothing downloaded
+            InstallTimeline {
+                start_install: at(41),
+                installed: at(45),
+                ..InstallTimeline::new(lwc!("second_mod"), origin + Duration::seconds(40))
+            },
+            InstallTimeline::new(lwc!("finished"), origin + Duration::seconds(45)),
+        ];
+
+        let trace: Value = serde_json::from_str(&chrome_trace(&timelines).unwrap()).unwrap();
+
+        let events = trace["traceEvents"].as_array().unwrap().iter()
+            .map(|event| json!([event["name"], event["ph"], event["ts"], event["dur"]]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                json!(["first_mod", "X", 0, 40_000_000]),
+                json!(["download", "X", 0, 10_000_000]),
+                json!(["extract", "X", 10_000_000, 2_000_000]),
+                json!(["patch", "X", 12_000_000, 1_000_000]),
+                json!(["replace", "X", 13_000_000, 0]),
+                json!(["configure", "X", 13_000_000, 1_000_000]),
+                json!(["weidu", "X", 14_000_000, 26_000_000]),
+                json!(["second_mod", "X", 40_000_000, 5_000_000]),
+                json!(["weidu", "X", 41_000_000, 4_000_000]),
+                json!(["finished", "i", 45_000_000, null]),
+            ]
+        );
+        assert_eq!(trace["displayTimeUnit"], "ms");
+    }
+
+    #[test]
+    fn empty_trace() {
+        let trace: Value = serde_json::from_str(&chrome_trace(&[]).unwrap()).unwrap();
+        assert_eq!(trace["traceEvents"], json!([]));
+    }
 }
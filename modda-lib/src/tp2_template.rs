@@ -14,12 +14,12 @@ const TP2_TEMPLATE: &str ="
  * TP2 generated by modda
  * {{date}}
 */
-BACKUP ~weidu_external/backup/{{mod_name}}~
+BACKUP ~{{{backup}}}~
 AUTHOR ~generated by modda~
 {{description}}
 BEGIN ~{{component_name}}~
 DESIGNATED ~{{index}}~
-
+{{{flags}}}
 COPY ~{{mod_name}}/data~ ~override~
 
 ";
@@ -32,6 +32,15 @@ pub fn generate_tp2(gen: &GeneratedMod, date: DateTime<Utc>) -> Result<String> {
         Some(s) if s.is_empty() => gen.gen_mod.to_string(),
         Some(name) => name.to_owned(),
     };
+    let backup = match &gen.backup {
+        None => format!("weidu_external/backup/{}", gen.gen_mod),
+        Some(backup) if backup.trim().is_empty() || backup.contains('~') =>
+            bail!("Invalid `backup` value {:?} for generated mod {}", backup, gen.gen_mod),
+        Some(backup) => backup.to_owned(),
+    };
+    if let Some(flag) = gen.component.flags.iter().find(|flag| flag.contains('\n')) {
+        bail!("Component flag {:?} of generated mod {} must be on a single line", flag, gen.gen_mod);
+    }
     let result = registry.render_template(
         TP2_TEMPLATE,
         &json!({
@@ -39,6 +48,8 @@ pub fn generate_tp2(gen: &GeneratedMod, date: DateTime<Utc>) -> Result<String> {
             "mod_name": &gen.gen_mod,
             "component_name": comp_name,
             "index": gen.component.index,
+            "backup": backup,
+            "flags": gen.component.flags.iter().map(|flag| format!("{flag}\n")).collect::<String>(),
             "description": match &gen.description {
                 Some(desc) => format!("\n// {desc}"),
                 None => "".to_string(),
@@ -86,11 +97,15 @@ mod tests {
                 FileModuleOrigin::Local { local: "my_subdir".to_string(), glob: None },
             ],
             description: None,
-            component: GenModComponent { index: 0, name: Some("my component".to_string()) },
+            component: GenModComponent { index: 0, name: Some("my component".to_string()), flags: vec![] },
             post_install: None,
             ignore_warnings: true,
             allow_overwrite: true,
-            disabled_if: None
+            overwrite: None,
+            backup_on_overwrite: None,
+            disabled_if: None,
+            backup: None,
+            phase: None,
         };
         let date_time = DateTime::from_naive_utc_and_offset(
             NaiveDateTime::new(
@@ -126,11 +141,15 @@ COPY ~ccc/data~ ~override~
                 FileModuleOrigin::Local { local: "my_subdir".to_string(), glob: None },
             ],
             description: Some("Very detailed description".to_string()),
-            component: GenModComponent { index: 0, name: Some("my component".to_string()) },
+            component: GenModComponent { index: 0, name: Some("my component".to_string()), flags: vec![] },
             post_install: None,
             ignore_warnings: true,
             allow_overwrite: true,
+            overwrite: None,
+            backup_on_overwrite: None,
             disabled_if: None,
+            backup: None,
+            phase: None,
         };
         let date_time = DateTime::from_naive_utc_and_offset(
             NaiveDateTime::new(
@@ -158,4 +177,57 @@ COPY ~ccc/data~ ~override~
 "#
         )
     }
+
+    #[test]
+    fn generate_tp2_with_backup_and_flags() {
+        let gen_mod = GeneratedMod {
+            gen_mod: lwc!("ccc"),
+            files: vec![
+                FileModuleOrigin::Local { local: "my_subdir".to_string(), glob: None },
+            ],
+            component: GenModComponent {
+                index: 3,
+                name: Some("my component".to_string()),
+                flags: vec!["NO_LOG_RECORD".to_string(), "LABEL ~ccc-main~".to_string()],
+            },
+            backup: Some("ccc/backup".to_string()),
+            ..GeneratedMod::default()
+        };
+        let date_time = DateTime::from_naive_utc_and_offset(
+            NaiveDateTime::new(
+                NaiveDate::from_ymd_opt(2024, 05, 18).unwrap(),
+                NaiveTime::from_hms_opt(12, 13, 14).unwrap(),
+            ),
+            Utc
+        );
+        assert_eq!(
+            generate_tp2(&gen_mod,date_time).unwrap(),
+            r#"
+/*
+ * TP2 generated by modda
+ * 2024-05-18 12:13:14 UTC
+*/
+BACKUP ~ccc/backup~
+AUTHOR ~generated by modda~
+
+BEGIN ~my component~
+DESIGNATED ~3~
+NO_LOG_RECORD
+LABEL ~ccc-main~
+
+COPY ~ccc/data~ ~override~
+
+"#
+        )
+    }
+
+    #[test]
+    fn generate_tp2_with_invalid_backup() {
+        let gen_mod = GeneratedMod {
+            gen_mod: lwc!("ccc"),
+            backup: Some("~bad~".to_string()),
+            ..GeneratedMod::default()
+        };
+        assert!(generate_tp2(&gen_mod, Utc::now()).is_err());
+    }
 }
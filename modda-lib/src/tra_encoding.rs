@@ -0,0 +1,147 @@
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use chardetng::EncodingDetector;
+use encoding_rs::Encoding;
+use globwalk::GlobWalkerBuilder;
+
+use crate::patch_source::PatchEncoding;
+
+/// Above this ratio of replacement characters, the declared encoding is considered wrong.
+const MAX_REPLACEMENT_RATIO: f64 = 0.002;
+/// Only the biggest tra files are checked, they have the most text.
+const MAX_CHECKED_FILES: usize = 10;
+
+/// A tra file that could not be decoded correctly with the declared encoding.
+#[derive(Debug, PartialEq)]
+pub struct EncodingMismatch {
+    pub file: PathBuf,
+    pub replacement_ratio: f64,
+    /// Encoding guessed from the file content
+    pub guessed: &'static Encoding,
+}
+
+impl EncodingMismatch {
+    /// Value of the `encoding` property matching the guessed encoding, if it is supported.
+    pub fn suggestion(&self) -> String {
+        match from_encoding(self.guessed) {
+            Some(encoding) => format!("{} (`encoding: {:?}`)", self.guessed.name(), encoding),
+            None => self.guessed.name().to_string(),
+        }
+    }
+}
+
+/// Decodes the tra files of a mod with its declared encoding and reports those with a high ratio
+/// of replacement characters (`�`), which means weidu would install garbled text.
+pub fn check_tra_encoding(mod_dir: &Path, encoding: PatchEncoding) -> Result<Vec<EncodingMismatch>> {
+    let mut mismatches = vec![];
+    for file in key_tra_files(mod_dir)? {
+        let bytes = match std::fs::read(&file) {
+            Ok(bytes) => bytes,
+            Err(error) => bail!("Could not read tra file {:?}\n -> {:?}", file, error),
+        };
+        let (decoded, _, _) = encoding.decode(&bytes);
+        let replacement_ratio = replacement_ratio(&decoded);
+        if replacement_ratio > MAX_REPLACEMENT_RATIO {
+            mismatches.push(EncodingMismatch { file, replacement_ratio, guessed: guess_encoding(&bytes) });
+        }
+    }
+    Ok(mismatches)
+}
+
+fn key_tra_files(mod_dir: &Path) -> Result<Vec<PathBuf>> {
+    let glob = match GlobWalkerBuilder::from_patterns(mod_dir, &["**/*.tra"])
+            .case_insensitive(true)
+            .build() {
+        Err(error) => bail!("Could not search tra files in {:?}\n -> {:?}", mod_dir, error),
+        Ok(glob) => glob,
+    };
+    let mut files = glob.into_iter()
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let size = entry.metadata().ok()?.len();
+            Some((size, entry.into_path()))
+        })
+        .collect::<Vec<_>>();
+    files.sort_by(|(size1, path1), (size2, path2)| size2.cmp(size1).then(path1.cmp(path2)));
+    Ok(files.into_iter().take(MAX_CHECKED_FILES).map(|(_, path)| path).collect())
+}
+
+pub fn replacement_ratio(decoded: &str) -> f64 {
+    let total = decoded.chars().count();
+    if total == 0 {
+        return 0.0;
+    }
+    let replaced = decoded.chars().filter(|c| *c == char::REPLACEMENT_CHARACTER).count();
+    replaced as f64 / total as f64
+}
+
+fn guess_encoding(bytes: &[u8]) -> &'static Encoding {
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    detector.guess(None, true)
+}
+
+fn from_encoding(encoding: &'static Encoding) -> Option<PatchEncoding> {
+    let found = if encoding == encoding_rs::UTF_8 {
+        PatchEncoding::UTF8
+    } else if encoding == encoding_rs::WINDOWS_1252 {
+        PatchEncoding::WIN1252
+    } else if encoding == encoding_rs::WINDOWS_1251 {
+        PatchEncoding::WIN1251
+    } else if encoding == encoding_rs::ISO_8859_15 {
+        PatchEncoding::ISO8859_15
+    } else if encoding == encoding_rs::GBK || encoding == encoding_rs::GB18030 {
+        PatchEncoding::CP936
+    } else if encoding == encoding_rs::BIG5 {
+        PatchEncoding::CP950
+    } else if encoding == encoding_rs::WINDOWS_1250 {
+        PatchEncoding::CP1250
+    } else if encoding == encoding_rs::SHIFT_JIS {
+        PatchEncoding::CP932
+    } else if encoding == encoding_rs::EUC_KR {
+        PatchEncoding::CP949
+    } else {
+        return None
+    };
+    Some(found)
+}
+
+#[cfg(test)]
+mod test_tra_encoding {
+    use std::path::PathBuf;
+
+    use crate::patch_source::PatchEncoding;
+
+    use super::{check_tra_encoding, replacement_ratio};
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/tra_encoding").join(name)
+    }
+
+    #[test]
+    fn wrong_encoding_is_detected() {
+        let mismatches = check_tra_encoding(&fixture("latin_mod"), PatchEncoding::UTF8).unwrap();
+
+        assert_eq!(mismatches.len(), 1);
+        let mismatch = &mismatches[0];
+        assert_eq!(mismatch.file, fixture("latin_mod").join("french").join("setup.tra"));
+        assert!(mismatch.replacement_ratio > 0.01);
+        assert_eq!(mismatch.guessed, encoding_rs::WINDOWS_1252);
+        assert_eq!(mismatch.suggestion(), "windows-1252 (`encoding: WIN1252`)");
+    }
+
+    #[test]
+    fn right_encoding_passes() {
+        assert!(check_tra_encoding(&fixture("latin_mod"), PatchEncoding::WIN1252).unwrap().is_empty());
+        assert!(check_tra_encoding(&fixture("utf8_mod"), PatchEncoding::UTF8).unwrap().is_empty());
+    }
+
+    #[test]
+    fn ratio_of_replacement_chars() {
+        assert_eq!(replacement_ratio(""), 0.0);
+        assert_eq!(replacement_ratio("abcd"), 0.0);
+        assert_eq!(replacement_ratio("ab\u{FFFD}\u{FFFD}"), 0.5);
+    }
+}
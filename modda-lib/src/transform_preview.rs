@@ -0,0 +1,272 @@
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+
+use crate::canon_path::CanonPath;
+
+/// Number of unchanged lines shown around the changes.
+const DIFF_CONTEXT: usize = 3;
+
+/// Mod files as they would be after `patch`/`patches`/`replace`, kept in memory (nothing is written).
+pub struct TransformPreview {
+    root: CanonPath,
+    /// Content before any change, `None` if the file didn't exist
+    original: BTreeMap<PathBuf, Option<String>>,
+    current: BTreeMap<PathBuf, Option<String>>,
+}
+
+impl TransformPreview {
+    pub fn new(root: CanonPath) -> Self {
+        Self { root, original: BTreeMap::new(), current: BTreeMap::new() }
+    }
+
+    /// Files are given relative to this directory in the diff.
+    pub fn root(&self) -> &CanonPath {
+        &self.root
+    }
+
+    /// Current (maybe already transformed) content of the file, `read` is used the first time the file is seen.
+    pub fn content(&mut self, path: &CanonPath, read: impl FnOnce(&CanonPath) -> Result<String>) -> Result<Option<String>> {
+        let key = self.key(path)?;
+        if let Some(content) = self.current.get(&key) {
+            return Ok(content.clone());
+        }
+        let content = if path.path().is_file() { Some(read(path)?) } else { None };
+        self.original.insert(key.clone(), content.clone());
+        self.current.insert(key, content.clone());
+        Ok(content)
+    }
+
+    /// Changes the content of the file, `None` removes it.
+    pub fn set(&mut self, path: &CanonPath, content: Option<String>) -> Result<()> {
+        let key = self.key(path)?;
+        if !self.original.contains_key(&key) {
+            let original = match std::fs::read(path) {
+                Ok(bytes) => Some(String::from_utf8_lossy(&bytes).into_owned()),
+                Err(_) => None,
+            };
+            self.original.insert(key.clone(), original);
+        }
+        self.current.insert(key, content);
+        Ok(())
+    }
+
+    /// Unified diff of all changed files (empty if nothing changed).
+    pub fn diff(&self) -> String {
+        self.original.iter()
+            .filter_map(|(key, original)| {
+                let current = self.current.get(key).unwrap_or(original);
+                if current == original {
+                    return None;
+                }
+                let name = key.components()
+                    .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                Some(unified_diff(&name, original.as_deref(), current.as_deref()))
+            })
+            .collect()
+    }
+
+    fn key(&self, path: &CanonPath) -> Result<PathBuf> {
+        match path.path().strip_prefix(self.root.path()) {
+            Ok(relative) => Ok(relative.to_path_buf()),
+            Err(_) => bail!("{:?} is not inside {:?}", path, self.root),
+        }
+    }
+}
+
+fn unified_diff(name: &str, old: Option<&str>, new: Option<&str>) -> String {
+    let old_lines = old.unwrap_or_default().lines().collect::<Vec<_>>();
+    let new_lines = new.unwrap_or_default().lines().collect::<Vec<_>>();
+    let lines = diff::slice(&old_lines, &new_lines);
+    let changes = lines.iter().enumerate()
+        .filter(|(_, line)| !matches!(line, diff::Result::Both(..)))
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+
+    let mut result = format!(
+        "--- {}\n+++ {}\n",
+        old.map(|_| format!("a/{}", name)).unwrap_or("/dev/null".to_string()),
+        new.map(|_| format!("b/{}", name)).unwrap_or("/dev/null".to_string()),
+    );
+    let mut hunks: Vec<(usize, usize)> = vec![];
+    for change in changes {
+        let start = change.saturating_sub(DIFF_CONTEXT);
+        let end = (change + DIFF_CONTEXT + 1).min(lines.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    for (start, end) in hunks {
+        let is_old = |line: &&diff::Result<&&str>| !matches!(line, diff::Result::Right(_));
+        let is_new = |line: &&diff::Result<&&str>| !matches!(line, diff::Result::Left(_));
+        let old_start = lines[..start].iter().filter(is_old).count();
+        let old_len = lines[start..end].iter().filter(is_old).count();
+        let new_start = lines[..start].iter().filter(is_new).count();
+        let new_len = lines[start..end].iter().filter(is_new).count();
+        result.push_str(&format!("@@ -{} +{} @@\n", hunk_range(old_start, old_len), hunk_range(new_start, new_len)));
+        for line in &lines[start..end] {
+            let (prefix, line) = match line {
+                diff::Result::Both(line, _) => (' ', line),
+                diff::Result::Left(line) => ('-', line),
+                diff::Result::Right(line) => ('+', line),
+            };
+            result.push_str(&format!("{}{}\n", prefix, line));
+        }
+    }
+    result
+}
+
+/// `start` is the number of lines before the hunk (unified diffs count from 1, or give the line before an empty range).
+fn hunk_range(start: usize, len: usize) -> String {
+    if len == 0 {
+        format!("{},0", start)
+    } else {
+        format!("{},{}", start + 1, len)
+    }
+}
+
+#[cfg(test)]
+mod test_transform_preview {
+    use std::path::PathBuf;
+
+    use indoc::indoc;
+
+    use crate::apply_patch::preview_patch_module;
+    use crate::args::Install;
+    use crate::canon_path::CanonPath;
+    use crate::global::Global;
+    use crate::lowercase::lwc;
+    use crate::module::location::replace::ReplaceSpec;
+    use crate::patch_source::{PatchDesc, PatchEncoding, PatchSource};
+
+    use super::TransformPreview;
+
+    const TP2: &str = indoc!(r#"
+        BACKUP ~my_mod/backup~
+        AUTHOR ~me~
+        VERSION ~1.0~
+        BEGIN ~first component~
+        COPY ~my_mod/a.itm~ ~override~
+        BEGIN ~second component~
+        COPY ~my_mod/b.itm~ ~override~
+    "#);
+
+    const TRA: &str = "@1 = ~Teh sword~\n@2 = ~Teh shield~\n";
+
+    fn setup() -> (tempfile::TempDir, CanonPath) {
+        let game_dir = tempfile::tempdir().unwrap();
+        let canon = CanonPath::new(game_dir.path()).unwrap();
+        std::fs::create_dir_all(game_dir.path().join("my_mod/tra")).unwrap();
+        std::fs::write(game_dir.path().join("my_mod/my_mod.tp2"), TP2).unwrap();
+        std::fs::write(game_dir.path().join("my_mod/tra/setup.tra"), TRA).unwrap();
+        (game_dir, canon)
+    }
+
+    #[test]
+    fn preview_patch_and_replace() {
+        let (game_dir, canon) = setup();
+        let patch = PatchDesc {
+            patch_source: PatchSource::Inline {
+                inline: indoc!(r#"
+                    --- my_mod/my_mod.tp2
+                    +++ my_mod/my_mod.tp2
+                    @@ -1,3 +1,3 @@
+                     BACKUP ~my_mod/backup~
+                     AUTHOR ~me~
+                    -VERSION ~1.0~
+                    +VERSION ~1.1~
+                "#).to_string(),
+            },
+            encoding: PatchEncoding::UTF8,
+        };
+        let replace = ReplaceSpec {
+            file_globs: vec!["tra/*.tra".to_string()],
+            replace: "Teh".to_string(),
+            with: "The".to_string(),
+            max_depth: Some(2),
+            ..ReplaceSpec::default()
+        };
+
+        let mut preview = TransformPreview::new(canon.clone());
+        preview_patch_module(&mut preview, &canon, &lwc!("my_mod"), &patch, &Install::default(), &Global::default()).unwrap();
+        let count = replace.preview(&game_dir.path().join("my_mod"), &mut preview).unwrap();
+
+        assert_eq!(count, 2);
+        assert_eq!(
+            preview.diff(),
+            indoc!(r#"
+                --- a/my_mod/my_mod.tp2
+                +++ b/my_mod/my_mod.tp2
+                @@ -1,6 +1,6 @@
+                 BACKUP ~my_mod/backup~
+                 AUTHOR ~me~
+                -VERSION ~1.0~
+                +VERSION ~1.1~
+                 BEGIN ~first component~
+                 COPY ~my_mod/a.itm~ ~override~
+                 BEGIN ~second component~
+                --- a/my_mod/tra/setup.tra
+                +++ b/my_mod/tra/setup.tra
+                @@ -1,2 +1,2 @@
+                -@1 = ~Teh sword~
+                -@2 = ~Teh shield~
+                +@1 = ~The sword~
+                +@2 = ~The shield~
+            "#)
+        );
+        // nothing was written
+        assert_eq!(std::fs::read_to_string(game_dir.path().join("my_mod/my_mod.tp2")).unwrap(), TP2);
+        assert_eq!(std::fs::read_to_string(game_dir.path().join("my_mod/tra/setup.tra")).unwrap(), TRA);
+        let files = std::fs::read_dir(game_dir.path().join("my_mod")).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(files.len(), 2);
+    }
+
+    #[test]
+    fn replace_sees_patched_content() {
+        let (game_dir, canon) = setup();
+        let first = ReplaceSpec {
+            file_globs: vec!["my_mod.tp2".to_string()],
+            replace: "~1.0~".to_string(),
+            with: "~1.1~".to_string(),
+            max_depth: Some(1),
+            ..ReplaceSpec::default()
+        };
+        let second = ReplaceSpec {
+            file_globs: vec!["my_mod.tp2".to_string()],
+            replace: "~1.1~".to_string(),
+            with: "~1.2~".to_string(),
+            max_depth: Some(1),
+            ..ReplaceSpec::default()
+        };
+
+        let mut preview = TransformPreview::new(canon.clone());
+        first.preview(&game_dir.path().join("my_mod"), &mut preview).unwrap();
+        second.preview(&game_dir.path().join("my_mod"), &mut preview).unwrap();
+
+        let diff = preview.diff();
+        assert!(diff.contains("-VERSION ~1.0~\n+VERSION ~1.2~\n"), "{}", diff);
+        assert_eq!(std::fs::read_to_string(game_dir.path().join("my_mod/my_mod.tp2")).unwrap(), TP2);
+    }
+
+    #[test]
+    fn no_change_no_diff() {
+        let (game_dir, canon) = setup();
+        let replace = ReplaceSpec {
+            file_globs: vec!["**/*.tra".to_string()],
+            replace: "not there".to_string(),
+            with: "whatever".to_string(),
+            max_depth: Some(2),
+            ..ReplaceSpec::default()
+        };
+        let mut preview = TransformPreview::new(canon);
+        assert_eq!(replace.preview(&PathBuf::from(game_dir.path()).join("my_mod"), &mut preview).unwrap(), 0);
+        assert_eq!(preview.diff(), "");
+    }
+}
@@ -0,0 +1,63 @@
+
+use std::path::{Path, PathBuf};
+
+/// Finds the executable that would be run for `command`: an existing file if it is a path,
+/// otherwise the first match in the directories of `PATH`.
+pub fn find_command(command: &str) -> Option<PathBuf> {
+    let path = Path::new(command);
+    if path.is_absolute() || path.components().count() > 1 {
+        return if path.is_file() { Some(path.to_path_buf()) } else { None };
+    }
+    let paths = std::env::var_os("PATH")?;
+    let extensions = executable_extensions();
+    std::env::split_paths(&paths)
+        .flat_map(|dir| extensions.iter().map(move |extension| dir.join(format!("{}{}", command, extension))))
+        .find(|candidate| candidate.is_file())
+}
+
+/// True when `command` is a path relative to the directory it is run from (which may not exist yet).
+pub fn is_relative_command_path(command: &str) -> bool {
+    let path = Path::new(command);
+    !path.is_absolute() && path.components().count() > 1
+}
+
+#[cfg(windows)]
+fn executable_extensions() -> Vec<String> {
+    let pathext = std::env::var("PATHEXT").unwrap_or(".COM;.EXE;.BAT;.CMD".to_string());
+    std::iter::once(String::new())
+        .chain(pathext.split(';').filter(|extension| !extension.is_empty()).map(str::to_string))
+        .collect()
+}
+
+#[cfg(not(windows))]
+fn executable_extensions() -> Vec<String> {
+    vec![String::new()]
+}
+
+#[cfg(all(test, unix))]
+mod test_find_command {
+    use super::{find_command, is_relative_command_path};
+
+    #[test]
+    fn command_on_path() {
+        assert!(find_command("sh").is_some());
+        assert!(find_command("modda-no-such-command").is_none());
+    }
+
+    #[test]
+    fn command_as_path() {
+        let work_dir = tempfile::tempdir().unwrap();
+        let script = work_dir.path().join("extract.sh");
+        std::fs::write(&script, "#!/bin/sh").unwrap();
+        assert_eq!(find_command(script.to_str().unwrap()), Some(script.clone()));
+        assert!(find_command(work_dir.path().join("missing.sh").to_str().unwrap()).is_none());
+    }
+
+    #[test]
+    fn relative_command_path() {
+        assert!(is_relative_command_path("./fix.sh"));
+        assert!(is_relative_command_path("scripts/fix.sh"));
+        assert!(!is_relative_command_path("sh"));
+        assert!(!is_relative_command_path("/bin/sh"));
+    }
+}
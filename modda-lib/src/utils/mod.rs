@@ -1,4 +1,5 @@
 
 pub mod bufread_raw;
+pub mod command_path;
 pub mod pathext;
 pub mod read_all;
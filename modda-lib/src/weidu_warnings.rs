@@ -0,0 +1,78 @@
+
+use anyhow::{bail, Result};
+use regex::{Regex, RegexBuilder};
+
+/// Warnings emitted by weidu in a debug log excerpt (`WARNING: ...` lines).
+pub fn extract_warnings(debug_log: &str) -> Vec<String> {
+    debug_log.lines()
+        .map(|line| line.trim())
+        .filter(|line| line.starts_with("WARNING"))
+        .map(|line| line.to_owned())
+        .collect()
+}
+
+/// Warnings that match none of the `allowed` patterns (regular expressions, case-insensitive).
+pub fn unmatched_warnings<'a>(warnings: &'a [String], allowed: &[String]) -> Result<Vec<&'a String>> {
+    let patterns = allowed.iter()
+        .map(|pattern| match RegexBuilder::new(pattern).case_insensitive(true).build() {
+            Ok(regex) => Ok(regex),
+            Err(error) => bail!("Invalid `allowed_warnings` pattern {}\n -> {:?}", pattern, error),
+        })
+        .collect::<Result<Vec<Regex>>>()?;
+    Ok(warnings.iter()
+        .filter(|warning| !patterns.iter().any(|pattern| pattern.is_match(warning)))
+        .collect())
+}
+
+#[cfg(test)]
+mod test_weidu_warnings {
+    use std::path::PathBuf;
+
+    use super::{extract_warnings, unmatched_warnings};
+
+    fn debug_log() -> String {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/test/weidu_warnings/setup-rr.debug");
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn extract_warnings_from_debug_log() {
+        assert_eq!(
+            extract_warnings(&debug_log()),
+            vec![
+                "WARNING: no effects altered on MISC2P.ITM".to_string(),
+                "WARNING: no effects altered on SW1H54.ITM".to_string(),
+                "WARNING: [override/spcl131.spl] is not a valid SPL file".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn all_warnings_allowed() {
+        let warnings = extract_warnings(&debug_log());
+        let allowed = vec![
+            "no effects altered on".to_string(),
+            r"spcl131\.spl.*not a valid".to_string(),
+        ];
+        assert!(unmatched_warnings(&warnings, &allowed).unwrap().is_empty());
+    }
+
+    #[test]
+    fn some_warnings_not_allowed() {
+        let warnings = extract_warnings(&debug_log());
+        let allowed = vec!["no effects altered on misc2p.itm".to_string()];
+        assert_eq!(
+            unmatched_warnings(&warnings, &allowed).unwrap(),
+            vec![
+                "WARNING: no effects altered on SW1H54.ITM",
+                "WARNING: [override/spcl131.spl] is not a valid SPL file",
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        let warnings = extract_warnings(&debug_log());
+        assert!(unmatched_warnings(&warnings, &["(unclosed".to_string()]).is_err());
+    }
+}